@@ -1,5 +1,11 @@
 use std::{
-    borrow::Cow, collections::BTreeMap, ffi::OsString, fmt::Debug, fs, path::Path, str::FromStr,
+    borrow::Cow,
+    collections::BTreeMap,
+    ffi::OsString,
+    fmt::{Debug, Write as _},
+    fs,
+    path::PathBuf,
+    str::FromStr,
     sync::LazyLock,
 };
 
@@ -14,28 +20,86 @@ const PM_IMPL_DIR: &str = "src/pm/";
 
 // We have to specify the length there (the elision is blocked by https://github.com/rust-lang/rfcs/pull/2545).
 // TODO: Fix this when the issue is resolved.
-const METHODS: [&str; 31] = [
-    "q", "qc", "qe", "qi", "qii", "qk", "ql", "qm", "qo", "qp", "qs", "qu", "r", "rn", "rns", "rs",
-    "rss", "s", "sc", "scc", "sccc", "sg", "si", "sii", "sl", "ss", "su", "suy", "sw", "sy", "u",
+const METHODS: [&str; 32] = [
+    "q", "qc", "qe", "qi", "qii", "qk", "qkk", "ql", "qm", "qo", "qp", "qs", "qu", "r", "rn",
+    "rns", "rs", "rss", "s", "sc", "scc", "sccc", "sg", "si", "sii", "sl", "ss", "su", "suy", "sw",
+    "sy", "u",
 ];
 
 /// Checks the implementation status of `pacman` commands in a specific file
 /// (eg. `homebrew.rs`).
-fn check_methods(file: &Path) -> anyhow::Result<BTreeMap<String, bool>> {
-    let bytes = fs::read(file)?;
-    let contents = String::from_utf8(bytes)?;
-
+fn check_methods(contents: &str) -> anyhow::Result<BTreeMap<String, bool>> {
     METHODS
         .iter()
         .map(|&method| {
             // A function definition (rg. `rs`) is written as follows:
             // `(async) fn rs(..) {..}`
-            let found = Regex::new(&format!(r"fn\s+{method}\s*\("))?.is_match(&contents);
+            let found = Regex::new(&format!(r"fn\s+{method}\s*\("))?.is_match(contents);
             Ok((method.to_owned(), found))
         })
         .try_collect()
 }
 
+/// A package manager implemented in a file under [`PM_IMPL_DIR`].
+struct Backend {
+    /// The absolute path of the implementation.
+    path: PathBuf,
+
+    /// The name of the package manager, as returned by `Pm::name`.
+    name: String,
+
+    /// The implementation status of each of the [`METHODS`].
+    methods: BTreeMap<String, bool>,
+}
+
+/// Returns the package manager implemented in `contents`, i.e. the string
+/// literal returned by its `Pm::name`, if any.
+fn pm_name(contents: &str) -> Option<String> {
+    static IMPL: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"impl\s+Pm\s+for\s").unwrap());
+    static NAME: LazyLock<Regex> = LazyLock::new(|| {
+        Regex::new(r#"fn\s+name\s*\(\s*&self\s*\)\s*->\s*&'static\s+str\s*\{\s*"([^"]+)""#).unwrap()
+    });
+    if !IMPL.is_match(contents) {
+        return None;
+    }
+    Some(NAME.captures(contents)?[1].to_owned())
+}
+
+/// Collects the [`Backend`]s under [`PM_IMPL_DIR`] by their file names, which
+/// is the single source of truth of both [`compat_table_impl`] and
+/// [`compat_data_impl`].
+fn backends() -> anyhow::Result<BTreeMap<OsString, Backend>> {
+    let paths: Vec<fs::DirEntry> = fs::read_dir(PM_IMPL_DIR)
+        .context("failed while reading PM_IMPL_DIR")?
+        .map(|entry| entry.context("error while reading path"))
+        .try_collect()?;
+
+    // The mock and the fallback backends are not actual package managers.
+    let excluded_names = ["mock.rs", "unknown.rs"];
+    paths
+        .iter()
+        .filter(|entry| !excluded_names.iter().any(|&ex| ex == entry.file_name()))
+        .filter_map(|entry| {
+            let backend = || {
+                let path = fs::canonicalize(entry.path())?;
+                let contents = String::from_utf8(fs::read(&path)?)?;
+                let Some(name) = pm_name(&contents) else {
+                    return Ok(None);
+                };
+                let methods = check_methods(&contents)?;
+                anyhow::Ok(Some(Backend {
+                    path,
+                    name,
+                    methods,
+                }))
+            };
+            backend()
+                .transpose()
+                .map(|backend| backend.map(|backend| (entry.file_name(), backend)))
+        })
+        .try_collect()
+}
+
 struct CompatRow {
     fields: Vec<String>,
 }
@@ -56,17 +120,7 @@ impl Tabled for CompatRow {
 }
 
 fn make_table() -> anyhow::Result<String> {
-    let paths: Vec<fs::DirEntry> = fs::read_dir(PM_IMPL_DIR)
-        .context("failed while reading PM_IMPL_DIR")?
-        .map(|entry| entry.context("error while reading path"))
-        .try_collect()?;
-
-    let excluded_names = ["mod.rs", "unknown.rs"];
-    let impls: BTreeMap<OsString, BTreeMap<String, bool>> = paths
-        .iter()
-        .filter(|entry| !excluded_names.iter().any(|&ex| ex == entry.file_name()))
-        .map(|entry| check_methods(&entry.path()).map(|impl_| (entry.file_name(), impl_)))
-        .try_collect()?;
+    let impls = backends()?;
 
     let make_row = |name, data| {
         let fields = chain!([name], data).map_into().collect_vec();
@@ -75,9 +129,9 @@ fn make_table() -> anyhow::Result<String> {
 
     let data: Vec<_> = impls
         .iter()
-        .map(|(file, items)| {
+        .map(|(file, backend)| {
             let data = METHODS.map(|method| {
-                items
+                (backend.methods)
                     .get(method)
                     .expect("implementation details not registered")
                     .then(|| "*")
@@ -96,14 +150,42 @@ fn make_table() -> anyhow::Result<String> {
     ))
 }
 
-#[allow(clippy::module_name_repetitions)]
-pub fn compat_table_impl() -> Result<TokenStream> {
-    fn throw(e: &dyn Debug) -> Error {
-        let msg = format!("{e:?}");
-        Error::new(Span::call_site(), msg)
+/// Makes the Rust expression of the compatibility table as a slice of
+/// `(pm, method, implemented)` tuples.
+///
+/// The implementations are also included (and then discarded) so that the
+/// expression is regenerated whenever any of them changes.
+fn make_data() -> anyhow::Result<String> {
+    let impls = backends()?;
+    let mut data = String::from("{ const _: &[&str] = &[");
+    for backend in impls.values() {
+        let path =
+            (backend.path.to_str()).context("failed to convert `path: PathBuf` to `&str`")?;
+        write!(data, "include_str!({path:?}),")?;
+    }
+    data += "]; &[";
+    for backend in impls.values() {
+        for (method, found) in &backend.methods {
+            write!(data, "({:?}, {method:?}, {found}),", backend.name)?;
+        }
     }
+    data += "] }";
+    Ok(data)
+}
+
+fn throw(e: &dyn Debug) -> Error {
+    let msg = format!("{e:?}");
+    Error::new(Span::call_site(), msg)
+}
 
+#[allow(clippy::module_name_repetitions)]
+pub fn compat_table_impl() -> Result<TokenStream> {
     let table = make_table().map_err(|e| throw(&e))?;
     let docstring = format!(r##"r#"{table}"#"##);
     Ok(TokenStream::from_str(&docstring)?)
 }
+
+pub fn compat_data_impl() -> Result<TokenStream> {
+    let data = make_data().map_err(|e| throw(&e))?;
+    Ok(TokenStream::from_str(&data)?)
+}
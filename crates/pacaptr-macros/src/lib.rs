@@ -5,7 +5,7 @@ mod test_dsl;
 use anyhow::Result;
 use proc_macro::TokenStream;
 
-use crate::compat_table::compat_table_impl;
+use crate::compat_table::{compat_data_impl, compat_table_impl};
 #[cfg(feature = "test")]
 use crate::test_dsl::test_dsl_impl;
 
@@ -20,6 +20,29 @@ use crate::test_dsl::test_dsl_impl;
 ///   on Windows).
 /// - `ou` item: Check the output of the **last** `in` or `in !` item above
 ///   against a **regex** pattern.
+/// - `ou !` item: Check that a **regex** pattern matches none of the lines of
+///   the output of the **last** `in` or `in !` item above.
+/// - `ou` and `ou !` items can take modifiers right after `ou`, written as an
+///   optional `^` followed by an optional `/` and some flags:
+///   - `^`: The pattern must match a whole line, e.g. `ou^ Done.`.
+///   - `/i`: The pattern is case-insensitive, e.g. `ou/i error`.
+///   - `/e`: The pattern is checked against stderr instead of stdout, e.g.
+///     `ou/e ! warning`.
+///
+///   The modifiers can be combined, e.g. `ou^/ie Error: .*`.
+/// - `ec` item: Check the exit code of the **last** `in` or `in !` item above,
+///   which otherwise must succeed.
+/// - `to` item: Set the time allowed for the **next** `in` or `in !` item below
+///   in seconds, after which it is killed and the test fails (5 minutes by
+///   default), e.g. `to 120`.
+/// - `si` item: Write a line to the stdin of the **last** `in` or `in !` item
+///   above, e.g. `si yes` to answer a prompt, or a bare `si` to accept its
+///   default answer. The lines are written in order, after which the stdin is
+///   closed.
+/// - `ev` item: Set an environment variable for **all** the `in` and `in !`
+///   items below, e.g. `ev NO_COLOR=1` (where the value extends to the end of
+///   the line and might be empty), or unset it with `ev -KEY`, e.g. `ev -HOME`.
+///   Only the spawned processes are affected.
 ///
 /// A comment in this DSL starts with a `#`.
 ///
@@ -39,6 +62,7 @@ use crate::test_dsl::test_dsl_impl;
 ///        # Verify installation.
 ///        in ! which screen
 ///        ou ^/usr/bin/screen
+///        ou/e ! not found
 ///
 ///        # Remove `screen` and verify the removal.
 ///        in -R screen --yes
@@ -80,6 +104,18 @@ pub fn compat_table(input: TokenStream) -> TokenStream {
     res_token_stream(res)
 }
 
+/// Generates the compatibility table as a slice of `(pm, method,
+/// implemented)` tuples, from the same data as [`compat_table!`].
+#[proc_macro]
+pub fn compat_data(input: TokenStream) -> TokenStream {
+    if !input.is_empty() {
+        return syn::Error::new(proc_macro2::Span::call_site(), "expected no argument")
+            .to_compile_error()
+            .into();
+    }
+    res_token_stream(compat_data_impl())
+}
+
 fn res_token_stream(res: Result<impl Into<TokenStream>, syn::Error>) -> TokenStream {
     res.map_or_else(|e| e.to_compile_error().into(), Into::into)
 }
@@ -3,29 +3,134 @@ use proc_macro2::{Literal, Span, TokenStream};
 use quote::quote;
 use syn::{Error, Result};
 
+/// The modifiers of an `ou` item, written right after `ou`, e.g. `ou^/ie`.
+#[derive(Debug, Default, PartialEq, Eq)]
+struct OuMods {
+    /// `^`: The pattern must match a whole line.
+    line: bool,
+    /// `/i`: The pattern is case-insensitive.
+    case_insensitive: bool,
+    /// `/e`: The pattern is checked against stderr instead of stdout.
+    stderr: bool,
+}
+
+impl OuMods {
+    fn try_from_str(mods: &str) -> Result<Self> {
+        let err = || {
+            let msg = format!(
+                "invalid `ou` modifiers `{mods}`, expected an optional `^` followed by an \
+                 optional `/` and some of `i` and `e`"
+            );
+            Error::new(Span::call_site(), msg)
+        };
+        let (line, rest) = mods
+            .strip_prefix('^')
+            .map_or((false, mods), |rest| (true, rest));
+        let mut res = Self {
+            line,
+            ..Self::default()
+        };
+        if rest.is_empty() {
+            return Ok(res);
+        }
+        let flags = rest
+            .strip_prefix('/')
+            .filter(|f| !f.is_empty())
+            .ok_or_else(err)?;
+        for flag in flags.chars() {
+            let slot = match flag {
+                'i' => &mut res.case_insensitive,
+                'e' => &mut res.stderr,
+                _ => return Err(err()),
+            };
+            if *slot {
+                return Err(err());
+            }
+            *slot = true;
+        }
+        Ok(res)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
 enum TestDslItem {
     In(Vec<String>),
     InBang(Vec<String>),
-    Ou(String),
+    Ou {
+        pattern: String,
+        forbid: bool,
+        mods: OuMods,
+    },
+    Ec(i32),
+    To(u64),
+    /// Sets an environment variable, or unsets it if the value is `None`.
+    Ev(String, Option<String>),
+    /// Writes a line to the stdin of the last input, e.g. to answer a prompt.
+    Si(String),
 }
 
 impl TestDslItem {
     fn try_from_line(ln: &str) -> Result<Self> {
         let in_bang = "in ! ";
         let in_ = "in ";
+        let ou_bang = "ou ! ";
         let ou = "ou ";
+        let ec = "ec ";
+        let to = "to ";
+        let ev = "ev ";
+        let si = "si ";
         let tokenize = |s: &str| s.split_whitespace().map_into().collect();
         #[allow(clippy::option_if_let_else)]
         if let Some(rest) = ln.strip_prefix(in_bang) {
             Ok(Self::InBang(tokenize(rest)))
         } else if let Some(rest) = ln.strip_prefix(in_) {
             Ok(Self::In(tokenize(rest)))
-        } else if let Some(rest) = ln.strip_prefix(ou) {
-            Ok(Self::Ou(rest.into()))
+        } else if let Some((mods, rest)) = ln
+            .strip_prefix(ou.trim_end())
+            .and_then(|rest| rest.split_once(' '))
+        {
+            let mods = OuMods::try_from_str(mods)?;
+            let (forbid, pattern) = rest
+                .strip_prefix("! ")
+                .map_or((false, rest), |pattern| (true, pattern));
+            Ok(Self::Ou {
+                pattern: pattern.into(),
+                forbid,
+                mods,
+            })
+        } else if let Some(rest) = ln.strip_prefix(ec) {
+            rest.trim().parse().map(Self::Ec).map_err(|_| {
+                let msg = format!("`ec` expects an integer exit code, found `{rest}`");
+                Error::new(Span::call_site(), msg)
+            })
+        } else if let Some(rest) = ln.strip_prefix(to) {
+            rest.trim().parse().map(Self::To).map_err(|_| {
+                let msg = format!("`to` expects a timeout in seconds, found `{rest}`");
+                Error::new(Span::call_site(), msg)
+            })
+        } else if let Some(rest) = ln.strip_prefix(ev) {
+            let var = rest.strip_prefix('-').map_or_else(
+                || (rest.split_once('=')).map(|(key, val)| (key, Some(val.into()))),
+                |key| Some((key, None)),
+            );
+            match var {
+                Some((key, val)) if !key.is_empty() && !key.contains(['=', ' ']) => {
+                    Ok(Self::Ev(key.into(), val))
+                }
+                _ => {
+                    let msg = format!("`ev` expects `KEY=value` or `-KEY`, found `{rest}`");
+                    Err(Error::new(Span::call_site(), msg))
+                }
+            }
+        } else if let Some(rest) = ln.strip_prefix(si) {
+            Ok(Self::Si(rest.into()))
+        } else if ln == si.trim_end() {
+            // An empty line, e.g. to accept the default answer of a prompt.
+            Ok(Self::Si(String::new()))
         } else {
             let msg = format!(
                 "Item must start with one of the following: {}, found `{}`",
-                [in_bang, in_, ou,]
+                [in_bang, in_, ou_bang, ou, ec, to, ev, si]
                     .iter()
                     .map(|s| format!("`{}`", s.trim_end()))
                     .join(", "),
@@ -45,9 +150,54 @@ impl TestDslItem {
                 let i = i.iter().map(|s| Literal::string(s)).collect_vec();
                 quote! { .exec(&[ #(#i),* ], &[]) }
             }
-            Self::Ou(o) => {
-                let o = Literal::string(o);
-                quote! { .output(&[ #o ]) }
+            Self::Ou {
+                pattern,
+                forbid,
+                mods:
+                    OuMods {
+                        line,
+                        case_insensitive,
+                        stderr,
+                    },
+            } => {
+                let pattern = Literal::string(pattern);
+                let stream = if *stderr {
+                    quote! { Stream::Stderr }
+                } else {
+                    quote! { Stream::Stdout }
+                };
+                quote! {
+                    .pattern(Pattern {
+                        regex: #pattern,
+                        forbid: #forbid,
+                        line: #line,
+                        case_insensitive: #case_insensitive,
+                        stream: #stream,
+                    })
+                }
+            }
+            Self::Ec(code) => {
+                let code = Literal::i32_unsuffixed(*code);
+                quote! { .exit_code(#code) }
+            }
+            Self::To(secs) => {
+                let secs = Literal::u64_unsuffixed(*secs);
+                quote! { .timeout(#secs) }
+            }
+            Self::Ev(key, val) => {
+                let key = Literal::string(key);
+                let val = val.as_deref().map_or_else(
+                    || quote! { None },
+                    |val| {
+                        let val = Literal::string(val);
+                        quote! { Some(#val) }
+                    },
+                );
+                quote! { .env(#key, #val) }
+            }
+            Self::Si(line) => {
+                let line = Literal::string(line);
+                quote! { .stdin(#line) }
             }
         }
     }
@@ -64,3 +214,125 @@ pub fn test_dsl_impl(input: &str) -> Result<TokenStream> {
         .try_collect()?;
     Ok(quote! { Test::new() #(#items)* .run()})
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ou(pattern: &str, forbid: bool, mods: OuMods) -> TestDslItem {
+        TestDslItem::Ou {
+            pattern: pattern.into(),
+            forbid,
+            mods,
+        }
+    }
+
+    #[test]
+    fn parse_items() {
+        let cases = [
+            (
+                "in -S  screen",
+                TestDslItem::In(vec!["-S".into(), "screen".into()]),
+            ),
+            (
+                "in ! which screen",
+                TestDslItem::InBang(vec!["which".into(), "screen".into()]),
+            ),
+            (
+                "ou ^Status: ok",
+                ou("^Status: ok", false, OuMods::default()),
+            ),
+            ("ou ! not found", ou("not found", true, OuMods::default())),
+            (
+                "ou/i error",
+                ou(
+                    "error",
+                    false,
+                    OuMods {
+                        case_insensitive: true,
+                        ..OuMods::default()
+                    },
+                ),
+            ),
+            (
+                "ou^ Done.",
+                ou(
+                    "Done.",
+                    false,
+                    OuMods {
+                        line: true,
+                        ..OuMods::default()
+                    },
+                ),
+            ),
+            (
+                "ou^/ei ! warning: .*",
+                ou(
+                    "warning: .*",
+                    true,
+                    OuMods {
+                        line: true,
+                        case_insensitive: true,
+                        stderr: true,
+                    },
+                ),
+            ),
+            (
+                "ou/e  two spaces",
+                ou(
+                    " two spaces",
+                    false,
+                    OuMods {
+                        stderr: true,
+                        ..OuMods::default()
+                    },
+                ),
+            ),
+            ("ec 5", TestDslItem::Ec(5)),
+            ("to 120", TestDslItem::To(120)),
+            (
+                "ev NO_COLOR=1",
+                TestDslItem::Ev("NO_COLOR".into(), Some("1".into())),
+            ),
+            (
+                "ev FOO=a=b c",
+                TestDslItem::Ev("FOO".into(), Some("a=b c".into())),
+            ),
+            (
+                "ev EMPTY=",
+                TestDslItem::Ev("EMPTY".into(), Some(String::new())),
+            ),
+            ("ev -HOME", TestDslItem::Ev("HOME".into(), None)),
+            ("si yes", TestDslItem::Si("yes".into())),
+            ("si", TestDslItem::Si(String::new())),
+        ];
+        for (ln, expected) in cases {
+            assert_eq!(TestDslItem::try_from_line(ln).unwrap(), expected, "{ln}");
+        }
+    }
+
+    #[test]
+    fn reject_items() {
+        for ln in [
+            "ou",
+            "ouch",
+            "ou/ pattern",
+            "ou/x pattern",
+            "ou/ii pattern",
+            "ou/i^ pattern",
+            "ec five",
+            "to -1",
+            "ev FOO",
+            "ev =bar",
+            "ev -",
+            "ev -FOO=bar",
+            "out pattern",
+            "sip",
+        ] {
+            assert!(
+                TestDslItem::try_from_line(ln).is_err(),
+                "`{ln}` should be rejected"
+            );
+        }
+    }
+}
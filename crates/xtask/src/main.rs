@@ -0,0 +1,238 @@
+//! Development tasks of `pacaptr`, run with `cargo xtask`.
+//!
+//! - `cargo xtask test --pm apt`: Runs the Test DSL file `tests/apt.rs` in a
+//!   container of the corresponding distro (see [`BACKENDS`]), including the
+//!   ignored tests, as done in CI.
+
+use std::{
+    env, fs,
+    io::{BufRead, BufReader},
+    path::{Path, PathBuf},
+    process::{Command, ExitCode, Stdio},
+};
+
+use anyhow::{bail, ensure, Context, Result};
+use clap::{builder::PossibleValuesParser, Parser, Subcommand};
+use serde_json::Value;
+
+/// A backend whose tests are run in a container.
+#[derive(Debug)]
+struct Backend {
+    /// The name of the package manager, which is also the name of its Test DSL
+    /// file in `tests`.
+    pm: &'static str,
+
+    /// The container image providing the package manager.
+    image: &'static str,
+
+    /// The shell commands to be run in the container before the tests.
+    setup: &'static str,
+
+    /// Whether the tests should be run one at a time.
+    serial: bool,
+}
+
+/// The backends whose tests can be run in a container, in the same setup as
+/// in CI.
+const BACKENDS: &[Backend] = &[
+    Backend {
+        pm: "apk",
+        image: "alpine:latest",
+        setup: "apk update",
+        serial: false,
+    },
+    Backend {
+        pm: "apt",
+        image: "ubuntu:latest",
+        setup: "apt-get update",
+        serial: false,
+    },
+    Backend {
+        pm: "dnf",
+        image: "fedora:latest",
+        setup: "",
+        serial: false,
+    },
+    Backend {
+        pm: "emerge",
+        image: "gentoo/stage3:latest",
+        // `pacaptr -Ss` might fail without this line.
+        setup: "emerge --sync || true",
+        serial: false,
+    },
+    Backend {
+        pm: "xbps",
+        image: "ghcr.io/void-linux/void-glibc-full:latest",
+        setup: "xbps-install -y -Su || (xbps-install -y -u xbps && xbps-install -y -Su)",
+        serial: false,
+    },
+    Backend {
+        pm: "zypper",
+        image: "registry.opensuse.org/opensuse/tumbleweed:latest",
+        setup: "",
+        serial: true,
+    },
+];
+
+/// The directory where the binaries are mounted in the container.
+const MOUNT_POINT: &str = "/pacaptr";
+
+#[derive(Debug, Parser)]
+#[command(bin_name = "cargo xtask", about = "Development tasks of pacaptr.")]
+struct Xtask {
+    #[command(subcommand)]
+    task: Task,
+}
+
+#[derive(Debug, Subcommand)]
+enum Task {
+    /// Run the Test DSL file of a package manager in a container.
+    Test {
+        /// The package manager to be tested.
+        #[arg(long, value_parser = PossibleValuesParser::new(BACKENDS.iter().map(|b| b.pm)))]
+        pm: String,
+
+        /// The container image to be used instead of the default one.
+        #[arg(long)]
+        image: Option<String>,
+
+        /// The container engine to be used, e.g. `podman`.
+        #[arg(long, default_value = "docker")]
+        engine: String,
+
+        /// The target to build the binaries for, which should be statically
+        /// linked to run on any distro.
+        #[arg(long, default_value_t = format!("{}-unknown-linux-musl", env::consts::ARCH))]
+        target: String,
+
+        /// Extra arguments passed to the test binary, e.g. a test name filter.
+        #[arg(last = true)]
+        test_args: Vec<String>,
+    },
+}
+
+/// The executables built for the tests of a backend.
+#[derive(Debug)]
+struct Artifacts {
+    /// The `pacaptr` executable.
+    pacaptr: PathBuf,
+
+    /// The test executable of the Test DSL file.
+    test: PathBuf,
+}
+
+/// Builds `pacaptr` and the test executable of `tests/{pm}.rs` for `target`
+/// with `cargo`, returning their paths.
+fn build(pm: &str, target: &str) -> Result<Artifacts> {
+    let cargo = env::var("CARGO").unwrap_or_else(|_| "cargo".into());
+    let mut child = Command::new(cargo)
+        .args(["test", "--no-run", "--features=test", "--test", pm])
+        .args([
+            "--target",
+            target,
+            "--message-format=json-render-diagnostics",
+        ])
+        .stdout(Stdio::piped())
+        .spawn()
+        .context("failed to run `cargo`")?;
+    let stdout = child.stdout.take().context("failed to capture `cargo`")?;
+    let (mut pacaptr, mut test) = (None, None);
+    for line in BufReader::new(stdout).lines() {
+        let msg: Value = serde_json::from_str(&line?)?;
+        if msg["reason"] != "compiler-artifact" {
+            continue;
+        }
+        let Some(exe) = msg["executable"].as_str().map(PathBuf::from) else {
+            continue;
+        };
+        let is_kind = |kind| {
+            msg["target"]["kind"]
+                .as_array()
+                .is_some_and(|k| k.contains(&kind))
+        };
+        match msg["target"]["name"].as_str() {
+            Some("pacaptr") if is_kind("bin".into()) => pacaptr = Some(exe),
+            Some(name) if name == pm && is_kind("test".into()) => test = Some(exe),
+            _ => (),
+        }
+    }
+    ensure!(
+        child.wait()?.success(),
+        "failed to build the tests of `{pm}`"
+    );
+    Ok(Artifacts {
+        pacaptr: pacaptr.context("`pacaptr` has not been built")?,
+        test: test.with_context(|| format!("`tests/{pm}.rs` has not been built"))?,
+    })
+}
+
+/// Copies the `artifacts` into `dir` under fixed names, so that they can be
+/// mounted into the container.
+fn stage(artifacts: &Artifacts, dir: &Path) -> Result<()> {
+    fs::create_dir_all(dir)?;
+    for (src, name) in [(&artifacts.pacaptr, "pacaptr"), (&artifacts.test, "test")] {
+        fs::copy(src, dir.join(name))
+            .with_context(|| format!("failed to copy `{}`", src.display()))?;
+    }
+    Ok(())
+}
+
+/// Runs the staged test executable in `dir` in a container of `image`, with
+/// the output streamed as is.
+fn run_in_container(
+    backend: &Backend,
+    image: &str,
+    engine: &str,
+    dir: &Path,
+    test_args: &[String],
+) -> Result<bool> {
+    let mut test_cmd = vec![format!("{MOUNT_POINT}/test"), "--include-ignored".into()];
+    if backend.serial {
+        test_cmd.push("--test-threads=1".into());
+    }
+    test_cmd.extend(test_args.iter().cloned());
+    // The test command is passed as the positional parameters of the script,
+    // so that its arguments need no quoting.
+    let script = match backend.setup {
+        "" => r#"exec "$0" "$@""#.to_owned(),
+        setup => format!(r#"{setup} && exec "$0" "$@""#),
+    };
+    let volume = format!("{}:{MOUNT_POINT}:ro", dir.canonicalize()?.display());
+    let status = Command::new(engine)
+        .args(["run", "--rm", "--volume", &volume])
+        .args(["--env", &format!("PACAPTR_BIN={MOUNT_POINT}/pacaptr")])
+        .args([image, "sh", "-c", &script])
+        .args(test_cmd)
+        .status()
+        .with_context(|| format!("failed to run `{engine}`"))?;
+    Ok(status.success())
+}
+
+fn main() -> Result<ExitCode> {
+    let Xtask { task } = Xtask::parse();
+    match task {
+        Task::Test {
+            pm,
+            image,
+            engine,
+            target,
+            test_args,
+        } => {
+            let Some(backend) = BACKENDS.iter().find(|b| b.pm == pm) else {
+                bail!("no container is known for `{pm}`");
+            };
+            let artifacts = build(&pm, &target)?;
+            let dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+                .join("../../target/xtask")
+                .join(&pm);
+            stage(&artifacts, &dir)?;
+            let image = image.as_deref().unwrap_or(backend.image);
+            let ok = run_in_container(backend, image, &engine, &dir, &test_args)?;
+            Ok(if ok {
+                ExitCode::SUCCESS
+            } else {
+                ExitCode::FAILURE
+            })
+        }
+    }
+}
@@ -0,0 +1,94 @@
+//! Drives a query of the detected package manager programmatically, as a
+//! front-end embedding `pacaptr` would do.
+//!
+//! Nothing is printed out to the terminal by `pacaptr` itself: the installed
+//! packages matching the given keywords are collected as [`Pkg`]s, and the rest
+//! of the output is forwarded to a callback.
+//!
+//! ```sh
+//! cargo run --example query -- curl
+//! ```
+
+use std::{
+    env,
+    sync::{Arc, Mutex},
+};
+
+use pacaptr::{
+    config::Config,
+    error::Result,
+    exec::context::{self, Context, OutputSink, PromptHandler, Stream},
+    pkg::Pkg,
+    pm::{self, BoxPm},
+    print::{self, OutputFormat, PkgSink},
+};
+
+/// Collects the text printed out by `pacaptr` and its commands.
+#[derive(Debug, Default)]
+struct Log(Mutex<Vec<String>>);
+
+impl OutputSink for Log {
+    fn print(&self, text: &str) {
+        self.0.lock().unwrap().push(text.trim_end().into());
+    }
+
+    fn cmd_output(&self, stream: Stream, chunk: &[u8]) {
+        let text = String::from_utf8_lossy(chunk);
+        self.0
+            .lock()
+            .unwrap()
+            .push(format!("[{stream:?}] {}", text.trim_end()));
+    }
+}
+
+/// Declines all the prompts, as a query should not need any.
+#[derive(Debug)]
+struct Decline;
+
+impl PromptHandler for Decline {
+    fn prompt(
+        &self,
+        _prompt: &str,
+        question: &str,
+        expected: &[&str],
+        _default: usize,
+    ) -> Result<usize> {
+        eprintln!("declining to proceed {question}");
+        Ok(expected.len() - 1)
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let kws = env::args().skip(1).collect::<Vec<_>>();
+    let kws = kws.iter().map(String::as_str).collect::<Vec<_>>();
+
+    let cfg = Config {
+        default_pm: Some(pm::detect_pm_str().into()),
+        output_format: Some(OutputFormat::Json),
+        ..Config::default()
+    };
+    let pm = BoxPm::from(cfg);
+    println!("using `{}`", pm.name());
+
+    let log = Arc::new(Log::default());
+    let ctx = Context::new(Arc::clone(&log) as _, Arc::new(Decline));
+    let sink = PkgSink::default();
+    let query = print::capture_pkgs(Arc::clone(&sink), pm.qs(&kws, &[]));
+    let res = context::scope(ctx, query).await;
+
+    for line in log.0.lock().unwrap().iter() {
+        println!("log: {line}");
+    }
+    res?;
+    let pkgs: Vec<Pkg> = sink.lock().unwrap().clone();
+    for Pkg {
+        name,
+        version,
+        source,
+    } in &pkgs
+    {
+        println!("{name} {version} ({source})");
+    }
+    Ok(())
+}
@@ -16,15 +16,29 @@
 //!    `.suy()`, according to the combination of flags and options obtained
 //!    above.
 
+use std::{
+    collections::BTreeMap,
+    io::{self, IsTerminal},
+    mem,
+    path::PathBuf,
+    sync::{Arc, PoisonError},
+};
+
 use clap::{self, ArgAction, Parser};
 use figment::Figment;
-use itertools::Itertools;
+use itertools::{chain, Either, Itertools};
 use pacaptr::{
-    config::Config,
-    error::{Error, Result},
+    config::{Config, ContainerMode, MacosPm, PromptDefault, SearchSort},
+    error::{self, Error, ExitKind, Result},
+    exec::{self, context},
     methods,
-    pm::BoxPm,
-    print::{println, prompt},
+    pkg::Pkg,
+    pm::{self, BoxPm, Pm},
+    print::{
+        self, println, prompt,
+        theme::{Theme, ThemeConfig},
+        OutputFormat, PkgSink, Progress,
+    },
 };
 use tap::prelude::*;
 use tokio::task;
@@ -43,6 +57,7 @@ fn version() -> &'static str {
     author = clap::crate_authors!(),
     about = clap::crate_description!(),
     before_help = format!("{} {}", clap::crate_name!(), version()),
+    after_help = ExitKind::table(),
     subcommand_required = true,
     arg_required_else_help = true,
 )]
@@ -62,6 +77,26 @@ pub struct Pacaptr {
     )]
     using: Option<String>,
 
+    /// Run `-Q` or `-Ss` with both `brew` and `port` (if installed),
+    /// labeling the results by package manager (macOS only).
+    #[arg(global = true, long, conflicts_with = "using")]
+    all_managers: bool,
+
+    /// Run the queries of `--all-managers` concurrently, merging the results
+    /// by package and noting the package managers they come from.
+    #[arg(global = true, long, requires = "all_managers")]
+    concurrent_pm_queries: bool,
+
+    /// Buffer the output of each of the concurrent queries, printing it out in
+    /// the order of the package managers instead of interleaving it.
+    #[arg(global = true, long, requires = "concurrent_pm_queries")]
+    ordered_output: bool,
+
+    /// Install the package manager with its official install script if it is
+    /// missing, after asking for confirmation (`brew` and `scoop` only).
+    #[arg(global = true, long, conflicts_with = "all_managers")]
+    bootstrap: bool,
+
     /// Perform a dry run.
     #[arg(global = true, long, visible_alias = "dryrun")]
     dry_run: bool,
@@ -79,6 +114,71 @@ pub struct Pacaptr {
     )]
     no_confirm: bool,
 
+    /// Whether to behave as if running in a container, where `sudo` is only
+    /// used if available and questions are answered with yes without a TTY.
+    #[arg(global = true, long, value_name = "MODE")]
+    container_mode: Option<ContainerMode>,
+
+    /// The answer to the confirmation prompts selected by an empty input.
+    #[arg(global = true, long, value_name = "ANSWER")]
+    prompt_default: Option<PromptDefault>,
+
+    /// Answer yes to every question only for the given operation(s),
+    /// separated by commas, e.g. `s,su,sy`.
+    #[arg(global = true, long, value_name = "OPS", value_delimiter = ',')]
+    assume_yes_for: Vec<String>,
+
+    /// Ask for confirmation before each step of a multi-step transaction
+    /// (e.g. refreshing and then upgrading with `-Suy`), aborting the
+    /// remaining steps on a "no".
+    #[arg(global = true, long, visible_alias = "ask-before-each-step")]
+    step: bool,
+
+    /// Drop the installed packages from the results of `-Ss` (currently `apt`,
+    /// `brew` and `dnf` only).
+    #[arg(global = true, long, conflicts_with = "only_installed")]
+    exclude_installed: bool,
+
+    /// Keep only the installed packages in the results of `-Ss` (currently
+    /// `apt`, `brew` and `dnf` only).
+    #[arg(global = true, long)]
+    only_installed: bool,
+
+    /// Show at most the given number of results of `-Ss` (currently `apt`,
+    /// `brew`, `choco`, `dnf`, `port` and `winget` only).
+    #[arg(global = true, long = "limit", value_name = "N")]
+    search_limit: Option<usize>,
+
+    /// Sort the results of `-Ss` by name instead of relevance (currently
+    /// `apt`, `brew`, `choco`, `dnf` and `port` only).
+    #[arg(global = true, long = "sort", value_name = "KEY")]
+    search_sort: Option<SearchSort>,
+
+    /// Show the dependency trees of the packages with `-Qi` or `-Si`
+    /// (currently `apt`, `brew`, `conda`, `dnf` and `pip` only).
+    #[arg(global = true, long)]
+    tree: bool,
+
+    /// Show the installed packages requiring the packages with `-Qi`, i.e.
+    /// their local reverse dependencies.
+    #[arg(global = true, long)]
+    required_by: bool,
+
+    /// Install the development version of the packages with `-S`, and check
+    /// it for updates with `-Qu` and `-Su` (currently `brew` only).
+    #[arg(global = true, long)]
+    head: bool,
+
+    /// Build the packages from source with `-S` (currently `brew` and `port`
+    /// only).
+    #[arg(global = true, long)]
+    source_build: bool,
+
+    /// Rebuild the packages that are already up to date (currently `emerge`
+    /// only).
+    #[arg(global = true, long)]
+    force: bool,
+
     /// Remove cache after installation.
     #[arg(global = true, long, visible_alias = "nocache")]
     no_cache: bool,
@@ -87,6 +187,172 @@ pub struct Pacaptr {
     #[arg(global = true, long, conflicts_with = "dry_run")]
     quiet: Option<bool>,
 
+    /// Stop at the first keyword that fails, instead of going on and reporting
+    /// all the failures in the end.
+    #[arg(global = true, long)]
+    fail_fast: bool,
+
+    /// Install, upgrade, download or remove the given packages one by one,
+    /// going on after a package has failed and reporting all the failures in
+    /// the end.
+    #[arg(global = true, long, conflicts_with = "fail_fast")]
+    keep_going: bool,
+
+    /// Run every command in the `C` locale, instead of only the ones whose
+    /// output is parsed by pacaptr.
+    #[arg(global = true, long)]
+    force_c_locale: bool,
+
+    /// Strip the ANSI escape sequences (e.g. colors) from the output of the
+    /// commands relayed by pacaptr, for the backends that emit them even when
+    /// piped.
+    #[arg(global = true, long)]
+    strip_ansi: bool,
+
+    /// Summarize the dependency conflicts when an installation fails because
+    /// of them (currently `apt` and `dnf` only).
+    #[arg(global = true, long)]
+    explain_conflicts: bool,
+
+    /// Check for dependency conflicts with a dry run of the resolver before
+    /// installing packages, and abort if any is found (currently `pip` and
+    /// `conda` only).
+    #[arg(global = true, long)]
+    check_deps: bool,
+
+    /// Show the size of each file and the total with `-Ql` (currently `apt`,
+    /// `dnf` and `zypper` only).
+    #[arg(global = true, long)]
+    size: bool,
+
+    /// List the installed packages by size with `-Q`, show their sizes with
+    /// `-Qi`, and report the transaction size with `-S` and `-Su` (currently
+    /// `apk`, `apt`, `brew` and `dnf` only).
+    #[arg(global = true, long)]
+    sizes: bool,
+
+    /// Show the package manager's own plan (if available) before asking for
+    /// confirmation.
+    #[arg(global = true, long, visible_alias = "preview-only")]
+    preview: bool,
+
+    /// Print query results in the stable `name<TAB>version<TAB>source`
+    /// format, as a shorthand for `--output-format porcelain`.
+    #[arg(global = true, long, conflicts_with = "output_format")]
+    porcelain: bool,
+
+    /// Print query results as JSON Lines, streaming each package as soon as
+    /// it is parsed, as a shorthand for `--output-format json-lines`.
+    #[arg(
+        global = true,
+        long,
+        conflicts_with_all = ["output_format", "porcelain"]
+    )]
+    json_lines: bool,
+
+    /// Print query results in the given format, where formats other than
+    /// `plain` are only available if the output can be parsed.
+    #[arg(global = true, long, value_name = "FORMAT")]
+    output_format: Option<OutputFormat>,
+
+    /// Always print out commands in full.
+    #[arg(global = true, short, long)]
+    verbose: bool,
+
+    /// Exit with the exit code of the failed subprocess instead of `5`.
+    #[arg(global = true, long)]
+    propagate_exit_code: bool,
+
+    /// Reflow pacaptr's own output to the given width [default: terminal
+    /// width].
+    #[arg(global = true, long, value_name = "N")]
+    wrap_width: Option<usize>,
+
+    /// Print out the effective configuration of the given operation (after
+    /// merging the config file, the environment and the CLI flags) as TOML,
+    /// then exit.
+    #[arg(global = true, long)]
+    dump_config: bool,
+
+    /// Retry up to N times, with an exponential backoff, when a lock of the
+    /// package manager is held by another process (currently `dnf` only).
+    #[arg(global = true, long, value_name = "N")]
+    max_retries_on_lock: Option<u32>,
+
+    /// Load environment variables for every command from the given
+    /// dotenv-style file.
+    #[arg(global = true, long, value_name = "PATH")]
+    env_file: Option<PathBuf>,
+
+    /// Go through the given proxy for both HTTP and HTTPS requests, overriding
+    /// the proxies in the config.
+    #[arg(global = true, long, value_name = "URL")]
+    proxy: Option<String>,
+
+    /// Skip upgrading the given package(s), separated by commas (currently
+    /// `apt`, `choco` and `winget` only).
+    #[arg(global = true, long, value_name = "PKGS", value_delimiter = ',')]
+    ignore: Vec<String>,
+
+    /// Skip upgrading with `-Su` or removing with `-R` the packages (or glob
+    /// patterns) listed in the given file, one per line.
+    #[arg(global = true, long, value_name = "FILE")]
+    exclude_from: Option<PathBuf>,
+
+    /// Skip upgrading the packages from the given source(s), separated by
+    /// commas (currently `winget` only).
+    #[arg(global = true, long, value_name = "SOURCES", value_delimiter = ',')]
+    exclude_source: Vec<String>,
+
+    /// Allow downgrading packages when upgrading, e.g. after switching mirrors
+    /// (currently `dnf` and `zypper` only).
+    #[arg(global = true, long)]
+    allow_downgrade: bool,
+
+    /// Install, upgrade and show packages from the given release, e.g.
+    /// `bookworm-backports` (currently `apt` and `dnf` only).
+    #[arg(global = true, long, value_name = "REL")]
+    target_release: Option<String>,
+
+    /// Enable the given repository for the sync operations, e.g. a COPR
+    /// repository (currently `dnf` and `zypper` only, which expects a URI).
+    #[arg(global = true, long, value_name = "REPO")]
+    enable_repo: Vec<String>,
+
+    /// Disable the given repository for the sync operations (currently `dnf`
+    /// only).
+    #[arg(global = true, long, value_name = "REPO")]
+    disable_repo: Vec<String>,
+
+    /// Send a desktop notification on completion of `-S`, `-Su` or `-Suy`.
+    #[arg(global = true, long)]
+    notify: bool,
+
+    /// Report the data downloaded by `-S`, `-Su`, `-Suy` or `-Sw` (currently
+    /// `apt` and `dnf` only).
+    #[arg(global = true, long)]
+    measure_network: bool,
+
+    /// Refuse the operations needing the network, unless the package manager
+    /// can run them from its cache instead (currently `apt` and `dnf` only).
+    #[arg(global = true, long)]
+    offline: bool,
+
+    /// Verify the package files downloaded by `-Sw` against the checksums
+    /// published by the repositories (currently `apt` and `dnf` only).
+    #[arg(global = true, long)]
+    verify_checksums: bool,
+
+    /// Run the given shell command if the operation fails, with the operation
+    /// and the exit code in `$PACAPTR_FAILED_OP` and `$PACAPTR_EXIT_CODE`.
+    #[arg(global = true, long, value_name = "CMD")]
+    on_failure: Option<String>,
+
+    /// Install packages into the given directory instead of the default
+    /// location (currently `pip` and `stack` only).
+    #[arg(global = true, long, value_name = "DIR")]
+    install_into: Option<PathBuf>,
+
     /// Package name or (sometimes) regex.
     #[arg(global = true, name = "KEYWORDS")]
     keywords: Vec<String>,
@@ -116,9 +382,9 @@ enum Operations {
         i: u8,
 
         /// Check that all files owned by the given package(s) are present on
-        /// the system.
-        #[arg(short, long = "check")]
-        k: bool,
+        /// the system. Specify this option twice to list every altered file.
+        #[arg(short, long = "check", action(ArgAction::Count))]
+        k: u8,
 
         /// List all files owned by a given package.
         #[arg(short, long = "list")]
@@ -211,6 +477,102 @@ enum Operations {
         y: bool,
     },
 
+    /// Show all the packages that are no longer required by any other
+    /// installed package, then remove them after confirmation.
+    Autoremove {
+        /// Choose which of the packages to remove in a wizard showing their
+        /// sizes, instead of removing all of them.
+        #[arg(
+            short,
+            long = "interactive",
+            visible_alias = "interactive-remove-orphans"
+        )]
+        i: bool,
+
+        /// Only print the targets instead of performing the actual operation.
+        #[arg(short, long = "print")]
+        p: bool,
+    },
+
+    /// Try to repair a system in a broken or half-configured state, e.g. after
+    /// an interrupted upgrade.
+    #[command(visible_alias = "fix")]
+    Repair {
+        /// Only print the targets instead of performing the actual operation.
+        #[arg(short, long = "print")]
+        p: bool,
+    },
+
+    /// Report the installed files that differ from the packaged ones, and
+    /// whether the installed version matches the one in the repositories.
+    Verify {
+        /// Only print the commands instead of performing the actual operation.
+        #[arg(short, long = "print")]
+        p: bool,
+    },
+
+    /// Refresh the keys used to verify the signatures of the repositories and
+    /// their packages.
+    RefreshKeys {
+        /// Only print the commands instead of performing the actual operation.
+        #[arg(short, long = "print")]
+        p: bool,
+    },
+
+    /// Install an older version of package(s), i.e. the one given as
+    /// `name=version`, or the previous one (if supported) otherwise.
+    Downgrade {
+        /// Only print the targets instead of performing the actual operation.
+        #[arg(short, long = "print")]
+        p: bool,
+    },
+
+    /// Undo the transaction given by its ID in the history of the package
+    /// manager, or the last one otherwise.
+    Undo {
+        /// Only print the commands instead of performing the actual operation.
+        #[arg(short, long = "print")]
+        p: bool,
+    },
+
+    /// Manage the module streams (currently `dnf` only), listing the ones
+    /// matching the given pattern(s) if no action is given.
+    Module {
+        /// Enable the given module stream(s), e.g. `nodejs:18`.
+        #[arg(short, long = "enable", group = "action")]
+        e: bool,
+
+        /// Install the given module stream(s), e.g. `nodejs:18/development`,
+        /// switching from the stream enabled so far if needed.
+        #[arg(short, long = "install", group = "action")]
+        i: bool,
+
+        /// Reset the given module(s), so that none of their streams is
+        /// enabled or disabled.
+        #[arg(short, long = "reset", group = "action")]
+        r: bool,
+
+        /// Only print the commands instead of performing the actual operation.
+        #[arg(short, long = "print")]
+        p: bool,
+    },
+
+    /// Manage the repository index services (currently `zypper` only),
+    /// listing them if no action is given.
+    Service {
+        /// Add the service given by its URI and its alias.
+        #[arg(short, long = "add", group = "action")]
+        a: bool,
+
+        /// Remove the given service(s), along with their repositories.
+        #[arg(short, long = "remove", group = "action")]
+        r: bool,
+
+        /// Only print the commands instead of performing the actual operation.
+        #[arg(short, long = "print")]
+        p: bool,
+    },
+
     /// Upgrade or add package(s) to the system and install the required
     /// dependencies from sync repositories.
     #[command(short_flag = 'U', long_flag = "update")]
@@ -219,6 +581,69 @@ enum Operations {
         #[arg(short, long = "print")]
         p: bool,
     },
+
+    /// Update pacaptr itself to the latest release on GitHub.
+    #[cfg(feature = "self-update")]
+    SelfUpdate {
+        /// Only print what would be downloaded instead of performing the
+        /// actual operation.
+        #[arg(short, long = "print")]
+        p: bool,
+    },
+}
+
+impl Operations {
+    /// Returns the full name of this operation if it is not from `pacman`,
+    /// which is also the name of the corresponding [`Pm`](crate::pm::Pm)
+    /// method.
+    const fn full_name(&self) -> Option<&'static str> {
+        match self {
+            Self::Autoremove { i: true, .. } => Some("autoremove_interactive"),
+            Self::Autoremove { .. } => Some("autoremove"),
+            Self::Repair { .. } => Some("repair"),
+            Self::Verify { .. } => Some("verify"),
+            Self::RefreshKeys { .. } => Some("refresh_keys"),
+            Self::Downgrade { .. } => Some("downgrade"),
+            Self::Undo { .. } => Some("undo"),
+            Self::Module { e: true, .. } => Some("module_enable"),
+            Self::Module { i: true, .. } => Some("module_install"),
+            Self::Module { r: true, .. } => Some("module_reset"),
+            Self::Module { .. } => Some("module_list"),
+            Self::Service { a: true, .. } => Some("service_add"),
+            Self::Service { r: true, .. } => Some("service_remove"),
+            Self::Service { .. } => Some("service_list"),
+            #[cfg(feature = "self-update")]
+            Self::SelfUpdate { .. } => Some("self_update"),
+            _ => None,
+        }
+    }
+
+    /// Returns whether this operation might change the packages installed on
+    /// the system, rather than merely querying them.
+    const fn is_transaction(&self) -> bool {
+        matches!(
+            self,
+            Self::Remove { .. }
+                | Self::Update { .. }
+                | Self::Autoremove { .. }
+                | Self::Repair { .. }
+                | Self::RefreshKeys { .. }
+                | Self::Downgrade { .. }
+                | Self::Undo { .. }
+                | Self::Module { e: true, .. }
+                | Self::Module { i: true, .. }
+                | Self::Module { r: true, .. }
+                | Self::Service { a: true, .. }
+                | Self::Service { r: true, .. }
+                | Self::Sync {
+                    g: false,
+                    i: 0,
+                    l: false,
+                    s: false,
+                    ..
+                }
+        )
+    }
 }
 
 impl Pacaptr {
@@ -229,9 +654,73 @@ impl Pacaptr {
             dry_run: self.dry_run,
             needed: self.needed,
             no_confirm: self.no_confirm,
+            step: self.step,
+            assume_yes_for: self.assume_yes_for.clone(),
+            container_mode: self.container_mode,
+            prompt_default: self.prompt_default,
+            exclude_installed: self.exclude_installed,
+            only_installed: self.only_installed,
+            search_limit: self.search_limit,
+            search_sort: self.search_sort,
+            tree: self.tree,
+            required_by: self.required_by,
+            head: self.head,
+            source_build: self.source_build,
+            force: self.force,
             no_cache: self.no_cache,
             quiet: self.quiet,
+            fail_fast: self.fail_fast,
+            keep_going: self.keep_going,
+            force_c_locale: self.force_c_locale,
+            strip_ansi: self.strip_ansi,
+            explain_conflicts: self.explain_conflicts,
+            check_deps: self.check_deps,
+            check_required_by: false,
+            size: self.size,
+            sizes: self.sizes,
+            preview: self.preview,
+            porcelain: self.porcelain,
+            json_lines: self.json_lines,
+            output_format: self.output_format,
+            verbose: self.verbose,
+            propagate_exit_code: self.propagate_exit_code,
+            echo_limit: None,
+            wrap_width: self.wrap_width,
+            ignore: self.ignore.clone(),
+            exclude_from: self.exclude_from.clone(),
+            allow_downgrade: self.allow_downgrade,
+            target_release: self.target_release.clone(),
+            enable_repo: self.enable_repo.clone(),
+            disable_repo: self.disable_repo.clone(),
+            notify: self.notify,
+            measure_network: self.measure_network,
+            offline: self.offline,
+            verify_checksums: self.verify_checksums,
+            on_failure: self.on_failure.clone(),
+            exclude_source: self.exclude_source.clone(),
+            env_file: self.env_file.clone(),
+            // The proxy given here overrides the specific ones in the config too.
+            proxy: self.proxy.clone(),
+            http_proxy: self.proxy.clone(),
+            https_proxy: self.proxy.clone(),
+            no_proxy: None,
+            forward_proxy_env: false,
+            max_retries_on_lock: self.max_retries_on_lock,
+            parallel_downloads: None,
+            install_into: self.install_into.clone(),
+            stack_resolver: None,
+            pip_strategy: None,
+            apt_noninteractive_dpkg_opts: false,
             default_pm: self.using.clone(),
+            macos_default: None,
+            all_managers: self.all_managers,
+            concurrent_pm_queries: self.concurrent_pm_queries,
+            ordered_output: self.ordered_output,
+            bootstrap: self.bootstrap,
+            theme: ThemeConfig::default(),
+            check_updates: None,
+            record_snapshots: None,
+            query_cache_ttl: None,
         }
     }
 
@@ -250,14 +739,14 @@ impl Pacaptr {
         /// stay coherent with Rust coding style the method name should be
         /// `suy`.
         macro_rules! collect_options {(
-            $( $op:ident {
+            $( $( #[$attr:meta] )* $op:ident {
                 $( mappings: [$( $key:ident -> $val:ident ), *], )?
                 $( flags: [$( $flag:ident ), *], )?
             }, )*
         ) => {{
             let mut options = String::new();
             match self.ops {
-                $( Operations::$op {
+                $( $( #[$attr] )* Operations::$op {
                     $( $( $key, )* )?
                     $( $( $flag, )* )?
                 } => {
@@ -273,14 +762,7 @@ impl Pacaptr {
             options.chars().sorted_unstable().pipe(String::from_iter)
         }};}
 
-        // Ensure that the cursor is not hidden when `Ctrl-C` is used.
-        // See: https://github.com/console-rs/dialoguer/issues/77#issuecomment-669986406
-        _ = ctrlc::set_handler(move || {
-            let term = console::Term::stdout();
-            _ = term.show_cursor();
-        })
-        .tap_err(|e| println(&*prompt::INFO, e));
-
+        show_cursor_on_ctrlc();
         let options = collect_options! {
             Query {
                 flags: [c, e, i, k, l, m, o, p, s, u],
@@ -296,18 +778,157 @@ impl Pacaptr {
             Update {
                 mappings: [p -> dry_run],
             },
+            Autoremove {
+                mappings: [p -> dry_run],
+                flags: [i],
+            },
+            Repair {
+                mappings: [p -> dry_run],
+            },
+            Verify {
+                mappings: [p -> dry_run],
+            },
+            RefreshKeys {
+                mappings: [p -> dry_run],
+            },
+            Downgrade {
+                mappings: [p -> dry_run],
+            },
+            Undo {
+                mappings: [p -> dry_run],
+            },
+            Module {
+                mappings: [p -> dry_run],
+                flags: [e, i, r],
+            },
+            Service {
+                mappings: [p -> dry_run],
+                flags: [a, r],
+            },
+            #[cfg(feature = "self-update")]
+            SelfUpdate {
+                mappings: [p -> dry_run],
+            },
         };
 
+        let method = self
+            .ops
+            .full_name()
+            .map_or_else(|| options.to_lowercase(), String::from);
+        let on_failure = cfg.on_failure.clone().filter(|_| !self.dump_config);
+        let dry_run = cfg.dry_run;
+        let res = self.dispatch_method(cfg, &method, &options).await;
+        if let (Err(e), Some(script)) = (&res, on_failure) {
+            let op = chain!([self.op_name(&options)], self.keywords.clone()).join(" ");
+            exec::hook::on_failure(&script, &op, e.exit_code(), dry_run).await;
+        }
+        res
+    }
+
+    /// Runs `method` (whose flags are given by `options`) with the given
+    /// [`Config`], reporting on its result as requested.
+    ///
+    /// # Errors
+    /// See [`Error`](crate::error::Error) for a list of possible errors.
+    async fn dispatch_method(&self, mut cfg: Config, method: &str, options: &str) -> Result<()> {
+        apply_globals(&cfg)?;
+        if self.dump_config {
+            return dump_config(cfg, method);
+        }
+        #[cfg(feature = "self-update")]
+        if let Operations::SelfUpdate { .. } = self.ops {
+            return pacaptr::self_update::self_update(&cfg).await;
+        }
+        if cfg.all_managers {
+            return self.dispatch_all_managers(&cfg, method).await;
+        }
+        restrict_to_method(&mut cfg, method)?;
+        let summarize = self.ops.is_transaction() && !cfg.dry_run && !cfg.quiet();
         let pm = cfg.conv::<BoxPm>();
+        pm::bootstrap(&*pm).await?;
+        check_pm(&*pm, method)?;
+
+        let res = check_declined(self.run_method(&pm, method, options).await);
+        if self.ops.is_transaction() && !pm.cfg().dry_run {
+            pm::query_cache::invalidate(pm.name());
+        }
+        if pm.cfg().measure_network && !pm.cfg().dry_run {
+            print_download(pm.name(), pm.cfg().query_format());
+        }
+        if summarize {
+            self.print_summary(options, pm.name(), &res);
+        }
+        if pm.cfg().notify && NOTIFY_METHODS.contains(&method) {
+            self.notify(options, pm.name(), &res, pm.cfg().dry_run)
+                .await;
+        }
+        res
+    }
 
+    /// Runs `method` (whose flags are given by `options`) on `pm` with the
+    /// keywords and extra flags given by the user, once per keyword with
+    /// `--keep-going`.
+    ///
+    /// The installed packages are listed beforehand for `undo`, and saved
+    /// afterwards unless the operation has been declined, see
+    /// [`pm::snapshot::record`].
+    ///
+    /// # Errors
+    /// See [`Pacaptr::call_method`] and [`pm::for_each_kw`].
+    async fn run_method(&self, pm: &BoxPm<'_>, method: &str, options: &str) -> Result<()> {
         let kws = self.keywords.iter().map(AsRef::as_ref).collect_vec();
+        let kws = exclude_removals(pm.cfg(), method, kws)?;
+        if kws.is_empty() && !self.keywords.is_empty() {
+            return Ok(());
+        }
         let flags = self.extra_flags.iter().map(AsRef::as_ref).collect_vec();
+        let op = chain!([self.op_name(options)], self.keywords.clone()).join(" ");
+        let snapshot = pm::snapshot::record(&**pm, method, &op).await;
+        let keep_going = pm.cfg().keep_going && KEEP_GOING_METHODS.contains(&method);
+        let res = if !keep_going || kws.len() <= 1 {
+            self.call_method(pm, method, options, &kws, &flags).await
+        } else {
+            let progress = Progress::new(kws.len(), pm.cfg());
+            pm::for_each_kw(pm.cfg(), &kws, |kw| {
+                progress.step(kw);
+                let flags = &flags;
+                async move { self.call_method(pm, method, options, &[kw], flags).await }
+            })
+            .await
+        };
+        if let Some(snapshot) = snapshot {
+            pm::snapshot::commit(&**pm, &snapshot);
+        }
+        res
+    }
+
+    /// Calls `method` (whose flags are given by `options`) on `pm` with the
+    /// given keywords and extra flags.
+    ///
+    /// # Errors
+    /// Returns an [`Error::ArgParseError`] if `method` is invalid or
+    /// `autoremove` is given keywords, and any error returned by `pm`
+    /// otherwise.
+    async fn call_method(
+        &self,
+        pm: &BoxPm<'_>,
+        method: &str,
+        options: &str,
+        kws: &[&str],
+        flags: &[&str],
+    ) -> Result<()> {
+        // `-Qi --required-by` shows the local reverse dependencies as `-Qii` does.
+        let method = if method == "qi" && pm.cfg().required_by {
+            "qii"
+        } else {
+            method
+        };
 
         /// Call the method indicated by `options` on `pm`. That is:
         ///
         /// ```rust
         /// match options.to_lowercase().as_ref() {
-        ///     "q" => pm.q(&kws, &flags).await,
+        ///     "q" => pm.q(kws, flags).await,
         ///     ..
         /// }
         /// ```
@@ -317,20 +938,142 @@ impl Pacaptr {
                 async fn $method:ident;
             )* }]
         ) => {
-            match options.to_lowercase().as_ref() {
-                $(stringify!($method) => pm.$method(&kws, &flags).await,)*
+            match method {
+                $(stringify!($method) => pm.$method(kws, flags).await,)*
                 _ => Err(Error::ArgParseError {
                     msg: format!("invalid flag combination `-{options}`"),
                 }),
             }
         };}
 
-        // Send `methods!()` to `dispatch_match`. That is,
-        // `dispatch_match!( methods = [{ q qc qe .. }] )`.
-        tt_call! {
-            macro = [{ methods }]
-            ~~> dispatch_match
+        // The operations that are not from `pacman` are dispatched on their own.
+        match self.ops {
+            // The orphans are found by the package manager, so there is nothing to
+            // restrict the removal to.
+            Operations::Autoremove { .. } if !kws.is_empty() => Err(Error::ArgParseError {
+                msg: "`autoremove` takes no packages, use `--interactive` to choose the ones to remove".into(),
+            }),
+            Operations::Autoremove { i: true, .. } => pm.autoremove_interactive(kws, flags).await,
+            Operations::Autoremove { .. } => pm.autoremove(kws, flags).await,
+            Operations::Repair { .. } => pm.repair(kws, flags).await,
+            Operations::Verify { .. } => pm.verify(kws, flags).await,
+            Operations::RefreshKeys { .. } => pm.refresh_keys(kws, flags).await,
+            Operations::Downgrade { .. } => pm.downgrade(kws, flags).await,
+            Operations::Undo { .. } if pm.supports_undo() => pm.undo(kws, flags).await,
+            Operations::Undo { .. } => pm::snapshot::undo(&**pm, kws, flags).await,
+            Operations::Module { e: true, .. } => pm.module_enable(kws, flags).await,
+            Operations::Module { i: true, .. } => pm.module_install(kws, flags).await,
+            Operations::Module { r: true, .. } => pm.module_reset(kws, flags).await,
+            Operations::Module { .. } => pm.module_list(kws, flags).await,
+            Operations::Service { a: true, .. } => pm.service_add(kws, flags).await,
+            Operations::Service { r: true, .. } => pm.service_remove(kws, flags).await,
+            Operations::Service { .. } => pm.service_list(kws, flags).await,
+            // Send `methods!()` to `dispatch_match`. That is,
+            // `dispatch_match!( methods = [{ q qc qe .. }] )`.
+            _ => tt_call! {
+                macro = [{ methods }]
+                ~~> dispatch_match
+            },
+        }
+    }
+
+    /// Runs the read-only query `method` (`q` or `ss`) with every [`MacosPm`]
+    /// installed, printing out the name of each package manager before its
+    /// results.
+    ///
+    /// With `--concurrent-pm-queries`, the queries are run concurrently
+    /// instead, see [`query_concurrently`].
+    ///
+    /// # Errors
+    /// Returns an [`Error::ArgParseError`] if `--all-managers` is unavailable
+    /// for `method` or on the current platform, and an
+    /// [`Error::PmNotFoundError`] if none of the package managers is
+    /// installed.
+    async fn dispatch_all_managers(&self, cfg: &Config, method: &str) -> Result<()> {
+        let arg_err = |msg: &str| Error::ArgParseError { msg: msg.into() };
+        if !matches!(method, "q" | "ss") {
+            return Err(arg_err(
+                "`--all-managers` is only available for `-Q` and `-Ss`",
+            ));
+        }
+        if !cfg!(target_os = "macos") {
+            return Err(arg_err("`--all-managers` is only available on macOS"));
+        }
+        let structured = cfg.query_format().is_structured();
+        if structured && !cfg.concurrent_pm_queries {
+            return Err(Error::StructuredOutputUnsupportedError {
+                pm: "--all-managers".into(),
+            });
+        }
+        let (pms, missing): (Vec<_>, Vec<_>) = MacosPm::ALL
+            .into_iter()
+            .map(|pm| {
+                let cfg = Config {
+                    default_pm: Some(pm.name().into()),
+                    // The results can only be merged when they are parsed.
+                    output_format: if cfg.concurrent_pm_queries && !structured {
+                        Some(OutputFormat::Porcelain)
+                    } else {
+                        cfg.output_format
+                    },
+                    ..cfg.clone()
+                };
+                let pm = cfg.conv::<BoxPm>();
+                pm::ensure_known(&*pm).map(|()| pm)
+            })
+            .partition_result();
+        if let (true, Some(e)) = (pms.is_empty(), missing.into_iter().next()) {
+            return Err(e);
         }
+        let kws = self.keywords.iter().map(AsRef::as_ref).collect_vec();
+        let flags = self.extra_flags.iter().map(AsRef::as_ref).collect_vec();
+        if cfg.concurrent_pm_queries {
+            return query_concurrently(cfg, method, &pms, &kws, &flags).await;
+        }
+        for pm in pms {
+            println(&*prompt::INFO, format_args!("results from `{}`", pm.name()));
+            if method == "q" {
+                pm.q(&kws, &flags).await?;
+            } else {
+                pm.ss(&kws, &flags).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Prints out the summary of the operation given by `options`, done by the
+    /// package manager `pm` with the result `res`.
+    fn print_summary(&self, options: &str, pm: &str, res: &Result<()>) {
+        let stats = exec::exec_stats();
+        let code = res.as_ref().map_or_else(Error::status_code, |()| Some(0));
+        print::println_summary(&self.op_name(options), pm, stats.cmds, stats.elapsed, code);
+    }
+
+    /// Sends a desktop notification summarizing the operation given by
+    /// `options`, done by the package manager `pm` with the result `res`, or
+    /// only prints out the notifier command if `dry_run` is set.
+    async fn notify(&self, options: &str, pm: &str, res: &Result<()>, dry_run: bool) {
+        let stats = exec::exec_stats();
+        let code = res.as_ref().map_or_else(Error::status_code, |()| Some(0));
+        let outcome = match code {
+            Some(0) => "done".into(),
+            Some(code) => format!("exited with code {code}"),
+            None => "failed".into(),
+        };
+        let plural = if stats.cmds == 1 { "" } else { "s" };
+        let title = format!("pacaptr: `{}` {outcome}", self.op_name(options));
+        let body = format!(
+            "`{pm}` ran {} command{plural} in {:.1}s",
+            stats.cmds,
+            stats.elapsed.as_secs_f64(),
+        );
+        exec::desktop::notify(&title, &body, dry_run).await;
+    }
+
+    /// Returns the name of the operation given by `options` as typed by the
+    /// user, e.g. `-Syu` or `autoremove`.
+    fn op_name(&self, options: &str) -> String {
+        (self.ops.full_name()).map_or_else(|| format!("-{options}"), String::from)
     }
 
     /// Runs [`dispatch_from`](Pacaptr::dispatch_from) with automatically
@@ -341,6 +1084,11 @@ impl Pacaptr {
     /// - Environment variables;
     /// - The config file.
     ///
+    /// The resulting [`Config`] is then checked with [`Config::validate`].
+    ///
+    /// A new release of [`pacaptr`](crate) is also checked for in the
+    /// background, and notified of once the job is done successfully.
+    ///
     /// # Errors
     /// See [`Error`](crate::error::Error) for a list of possible errors.
     pub async fn dispatch(&self) -> Result<()> {
@@ -350,26 +1098,572 @@ impl Pacaptr {
                 .join(Config::file_provider())
                 .extract::<Config>()
         })?);
+        cfg.validate()?;
+        #[cfg(feature = "self-update")]
+        if !self.dump_config && !matches!(self.ops, Operations::SelfUpdate { .. }) {
+            let check = pacaptr::self_update::notify::UpdateCheck::spawn(&cfg);
+            let res = self.dispatch_from(cfg).await;
+            if let (Ok(()), Some(check)) = (&res, check) {
+                check.notify().await;
+            }
+            return res;
+        }
         self.dispatch_from(cfg).await
     }
 }
 
-#[cfg(all(test, feature = "test"))]
+/// The operations run once per keyword with `--keep-going`, i.e. the ones
+/// installing, upgrading, downloading or removing the given packages.
+const KEEP_GOING_METHODS: &[&str] = &["downgrade", "r", "rn", "rns", "rs", "rss", "s", "su", "sw"];
+
+/// The upgrade operations skipping the packages in `--ignore` and
+/// `--exclude-from`.
+const IGNORE_METHODS: &[&str] = &["su", "suy"];
+
+/// The removal operations skipping the packages in `--exclude-from`.
+const REMOVAL_METHODS: &[&str] = &["r", "rn", "rns", "rs", "rss"];
+
+/// The operations sending a desktop notification on completion with
+/// `--notify`, i.e. the ones installing or upgrading packages.
+const NOTIFY_METHODS: &[&str] = &["s", "su", "suy"];
+
+/// The operations whose downloads are reported with `--measure-network`.
+const MEASURE_NETWORK_METHODS: &[&str] = &["s", "su", "suy", "sw"];
+
+/// The operations reporting the sizes of the packages with `--sizes`.
+const SIZE_METHODS: &[&str] = &["q", "qi", "s", "su"];
+
+/// The operations showing the dependency trees of the packages with `--tree`.
+const TREE_METHODS: &[&str] = &["qi", "si"];
+
+/// The operations choosing the versions of the packages from
+/// `--target-release`.
+const TARGET_RELEASE_METHODS: &[&str] = &["s", "si", "su", "suy"];
+
+/// The sync operations reading the repositories selected with
+/// `--enable-repo` and `--disable-repo`.
+const REPO_METHODS: &[&str] = &["s", "si", "sii", "sl", "ss", "su", "suy", "sw", "sy"];
+
+/// The maximum number of package managers queried at once with
+/// `--concurrent-pm-queries`.
+const MAX_CONCURRENT_PM_QUERIES: usize = 4;
+
+/// Runs the read-only query `method` (`q` or `ss`) with each of `pms`
+/// concurrently, then prints out their results merged by [`merge_pkgs`].
+///
+/// With `--ordered-output`, the output of each query is kept apart from the
+/// others, see [`context::run_concurrently`].
+///
+/// A package manager failing is reported after the results instead of
+/// stopping the others.
+///
+/// # Errors
+/// Returns the error of the first package manager if all of them have failed.
+async fn query_concurrently(
+    cfg: &Config,
+    method: &str,
+    pms: &[BoxPm<'_>],
+    kws: &[&str],
+    flags: &[&str],
+) -> Result<()> {
+    let queries = pms.iter().map(|pm| async move {
+        let sink = PkgSink::default();
+        let query = async {
+            if method == "q" {
+                pm.q(kws, flags).await
+            } else {
+                pm.ss(kws, flags).await
+            }
+        };
+        let res = print::capture_pkgs(Arc::clone(&sink), query).await;
+        let pkgs = mem::take(&mut *sink.lock().unwrap_or_else(PoisonError::into_inner));
+        (pm.name(), res.map(|()| pkgs))
+    });
+    let results =
+        context::run_concurrently(queries, MAX_CONCURRENT_PM_QUERIES, cfg.ordered_output).await;
+
+    let (found, failed): (Vec<_>, Vec<_>) =
+        results.into_iter().partition_map(|(pm, res)| match res {
+            Ok(pkgs) => Either::Left((pm, pkgs)),
+            Err(e) => Either::Right((pm, e)),
+        });
+    if found.is_empty() {
+        if let Some((_, e)) = failed.into_iter().next() {
+            return Err(e);
+        }
+        return Ok(());
+    }
+    // The raw output has no counterpart here, so it falls back to a table.
+    let format = match cfg.query_format() {
+        OutputFormat::Plain => OutputFormat::Table,
+        format => format,
+    };
+    print::println_pkgs(&merge_pkgs(found), format);
+    for (pm, e) in failed {
+        println(&*prompt::WARNING, format_args!("`{pm}` failed: {e}"));
+    }
+    Ok(())
+}
+
+/// Merges the [`Pkg`]s found by each package manager into one list sorted by
+/// name, where the versions and the package managers (as the source) of a
+/// package found more than once are joined by `, `.
+fn merge_pkgs(found: Vec<(&str, Vec<Pkg>)>) -> Vec<Pkg> {
+    let mut merged = BTreeMap::<_, (Vec<_>, Vec<_>)>::new();
+    for (pm, pkgs) in found {
+        for Pkg { name, version, .. } in pkgs {
+            let (versions, pms) = merged.entry(name).or_default();
+            if !version.is_empty() && !versions.contains(&version) {
+                versions.push(version);
+            }
+            if !pms.contains(&pm) {
+                pms.push(pm);
+            }
+        }
+    }
+    merged
+        .into_iter()
+        .map(|(name, (versions, pms))| Pkg::new(name, versions.join(", "), pms.join(", ")))
+        .collect()
+}
+
+/// Applies the process-wide settings of `cfg`, e.g. the [`Theme`] of the
+/// output.
+///
+/// # Errors
+/// Returns an [`Error::EnvFileError`] if `--env-file` cannot be loaded.
+fn apply_globals(cfg: &Config) -> Result<()> {
+    exec::set_container_mode(cfg.in_container());
+    exec::set_force_c_locale(cfg.force_c_locale);
+    exec::set_strip_ansi(cfg.strip_ansi);
+    exec::set_prompt_default(cfg.prompt_default.unwrap_or_default());
+    // The proxies in the config take precedence over the ones in the env file.
+    exec::set_env_vars(chain!(cfg.env_vars()?, cfg.proxy_env_vars()).collect());
+    error::set_propagate_exit_code(cfg.propagate_exit_code);
+    print::set_theme(Theme::from(&cfg.theme));
+    print::set_cmd_echo_limit(cfg.echo_limit());
+    print::set_wrap_width(cfg.wrap_width);
+    Ok(())
+}
+
+/// Prints out `cfg` as TOML for `--dump-config`, as it would be used by the
+/// operation `method`.
+///
+/// # Errors
+/// Returns an [`Error::OtherError`] if `cfg` cannot be serialized.
+fn dump_config(mut cfg: Config, method: &str) -> Result<()> {
+    cfg.no_confirm |= should_assume_yes(&cfg, method);
+    if !cfg.all_managers {
+        pm::resolve_default_pm(&mut cfg);
+    }
+    print!("{}", cfg.dump()?);
+    Ok(())
+}
+
+/// Checks if `pm` is ready for the operation `method`.
+///
+/// The modifiers that `pm` cannot honor are rejected if they would change
+/// the outcome of `method`, e.g. which packages are installed or which
+/// results are listed, and only warned about if they would merely change how
+/// it is shown.
+///
+/// # Errors
+/// See [`pm::ensure_known`], [`check_offline`], [`check_install_into`],
+/// [`check_target_release`], [`check_repos`], [`check_build_options`],
+/// [`check_installed_filter`], [`check_allow_downgrade`] and
+/// [`check_verify_checksums`].
+fn check_pm(pm: &(impl Pm + ?Sized), method: &str) -> Result<()> {
+    pm::ensure_known(pm)?;
+    check_offline(pm, method)?;
+    check_install_into(pm, method)?;
+    check_target_release(pm, method)?;
+    check_repos(pm, method)?;
+    check_build_options(pm, method)?;
+    check_installed_filter(pm, method)?;
+    check_allow_downgrade(pm, method)?;
+    check_verify_checksums(pm, method)?;
+    check_search_options(pm, method);
+    check_tree(pm, method);
+    check_sizes(pm, method);
+    check_preview(pm, method);
+    check_parallel_downloads(pm, method);
+    Ok(())
+}
+
+/// Returns the [`Error::ArgParseError`] of a `flag` that `pm` has no
+/// equivalent of.
+fn unsupported_flag(pm: &(impl Pm + ?Sized), flag: &str) -> Error {
+    Error::ArgParseError {
+        msg: format!("`{flag}` is unsupported by `{}`", pm.name()),
+    }
+}
+
+/// Checks that the operation `method` can be run by `pm` with `--offline`.
+///
+/// # Errors
+/// Returns an [`Error::OfflineError`] if `method` is one of the
+/// [`pm::NETWORK_METHODS`] that `pm` cannot run from its cache.
+fn check_offline(pm: &(impl Pm + ?Sized), method: &str) -> Result<()> {
+    if pm.cfg().offline
+        && pm::NETWORK_METHODS.contains(&method)
+        && !pm.offline_methods().contains(&method)
+    {
+        return Err(Error::OfflineError {
+            op: method.into(),
+            pm: pm.name().into(),
+        });
+    }
+    Ok(())
+}
+
+/// Checks the directory given by `--install-into`, and that `pm` can install
+/// packages into it when running `method`.
+///
+/// # Errors
+/// Returns an [`Error::ArgParseError`] if the given path exists but is not a
+/// directory, or if `pm` has no equivalent of `--install-into`.
+fn check_install_into(pm: &(impl Pm + ?Sized), method: &str) -> Result<()> {
+    let Some(dir) = &pm.cfg().install_into else {
+        return Ok(());
+    };
+    if dir.exists() && !dir.is_dir() {
+        return Err(Error::ArgParseError {
+            msg: format!(
+                "`--install-into` expects a directory, found `{}`",
+                dir.display()
+            ),
+        });
+    }
+    if ["s", "su", "suy"].contains(&method) && !pm.supports_install_into() {
+        return Err(unsupported_flag(pm, "--install-into"));
+    }
+    Ok(())
+}
+
+/// Checks that `--target-release` can be honored by `pm` when running
+/// `method`.
+///
+/// # Errors
+/// Returns an [`Error::ArgParseError`] if `pm` has no equivalent of it.
+fn check_target_release(pm: &(impl Pm + ?Sized), method: &str) -> Result<()> {
+    if pm.cfg().target_release.is_some()
+        && TARGET_RELEASE_METHODS.contains(&method)
+        && !pm.supports_target_release()
+    {
+        return Err(unsupported_flag(pm, "--target-release"));
+    }
+    Ok(())
+}
+
+/// Checks that `--enable-repo` and `--disable-repo` can be honored by `pm`
+/// when running `method`.
+///
+/// # Errors
+/// Returns an [`Error::ArgParseError`] if `pm` has no equivalent of any of
+/// them.
+fn check_repos(pm: &(impl Pm + ?Sized), method: &str) -> Result<()> {
+    if !REPO_METHODS.contains(&method) {
+        return Ok(());
+    }
+    let cfg = pm.cfg();
+    let repo_flags = [
+        ("--enable-repo", &cfg.enable_repo, pm.supports_enable_repo()),
+        (
+            "--disable-repo",
+            &cfg.disable_repo,
+            pm.supports_disable_repo(),
+        ),
+    ];
+    for (flag, repos, supported) in repo_flags {
+        if !repos.is_empty() && !supported {
+            return Err(unsupported_flag(pm, flag));
+        }
+    }
+    Ok(())
+}
+
+/// Checks that the [`BuildOption`](pm::BuildOption)s given (e.g. `--head`) can
+/// be honored by `pm` when running `method`.
+///
+/// # Errors
+/// Returns an [`Error::ArgParseError`] if `pm` has no equivalent of any of
+/// them.
+fn check_build_options(pm: &(impl Pm + ?Sized), method: &str) -> Result<()> {
+    match pm::unsupported_build_option(pm) {
+        Some(opt) if method == "s" => Err(unsupported_flag(pm, opt.flag())),
+        _ => Ok(()),
+    }
+}
+
+/// Checks that `--exclude-installed` and `--only-installed` can be honored by
+/// `pm` when running `method`.
+///
+/// # Errors
+/// Returns an [`Error::ArgParseError`] if `pm` cannot filter the search
+/// results.
+fn check_installed_filter(pm: &(impl Pm + ?Sized), method: &str) -> Result<()> {
+    let cfg = pm.cfg();
+    let flag = match (cfg.exclude_installed, cfg.only_installed) {
+        (true, _) => "--exclude-installed",
+        (_, true) => "--only-installed",
+        _ => return Ok(()),
+    };
+    if method == "ss" && !pm.supports_installed_filter() {
+        return Err(unsupported_flag(pm, flag));
+    }
+    Ok(())
+}
+
+/// Reports the data downloaded by the package manager `pm` with
+/// `--measure-network`, as a JSON object in the JSON [`OutputFormat`]s.
+fn print_download(pm: &str, format: OutputFormat) {
+    let downloaded = exec::download::downloaded();
+    if matches!(format, OutputFormat::Json | OutputFormat::JsonLines) {
+        print::print_raw(exec::download::format_json(downloaded));
+        return;
+    }
+    match downloaded {
+        Some(downloaded) => println(&*prompt::INFO, format_args!("downloaded {downloaded}")),
+        None => println(
+            &*prompt::INFO,
+            format_args!("downloaded size unknown, as it has not been reported by `{pm}`"),
+        ),
+    }
+}
+
+/// Warns if `--limit` or `--sort` is going to be ignored by `pm` when running
+/// `method`.
+fn check_search_options(pm: &(impl Pm + ?Sized), method: &str) {
+    if method != "ss" {
+        return;
+    }
+    let cfg = pm.cfg();
+    let unsupported = [
+        (cfg.search_limit.is_some() && !pm.supports_search_limit()).then_some("--limit"),
+        (cfg.search_sort == Some(SearchSort::Name) && !pm.supports_search_sort())
+            .then_some("--sort"),
+    ];
+    for flag in unsupported.into_iter().flatten() {
+        println(
+            &*prompt::WARNING,
+            format_args!(
+                "`{flag}` is unsupported by `{}` and will be ignored",
+                pm.name()
+            ),
+        );
+    }
+}
+
+/// Warns if `--tree` is going to be ignored by `pm` when running `method`.
+fn check_tree(pm: &(impl Pm + ?Sized), method: &str) {
+    if pm.cfg().tree && TREE_METHODS.contains(&method) && !pm.dep_tree_methods().contains(&method) {
+        println(
+            &*prompt::WARNING,
+            format_args!(
+                "`--tree` is unsupported by `{}` and will be ignored",
+                pm.name()
+            ),
+        );
+    }
+}
+
+/// Warns if `--sizes` is going to be ignored by `pm` when running `method`.
+fn check_sizes(pm: &(impl Pm + ?Sized), method: &str) {
+    if pm.cfg().sizes && !pm.pkg_size_methods().contains(&method) {
+        println(
+            &*prompt::WARNING,
+            format_args!(
+                "`--sizes` is unsupported by `{}` and will be ignored",
+                pm.name()
+            ),
+        );
+    }
+}
+
+/// Warns if `--preview` is going to be ignored by `pm` when running `method`.
+fn check_preview(pm: &(impl Pm + ?Sized), method: &str) {
+    if pm.cfg().preview && !pm.preview_methods().contains(&method) {
+        println(
+            &*prompt::WARNING,
+            format_args!(
+                "`--preview` is unsupported by `{}` and will be ignored",
+                pm.name()
+            ),
+        );
+    }
+}
+
+/// Checks that `--allow-downgrade` can be honored by `pm` when running the
+/// upgrade `method`.
+///
+/// # Errors
+/// Returns an [`Error::ArgParseError`] if `pm` cannot downgrade packages while
+/// upgrading.
+fn check_allow_downgrade(pm: &(impl Pm + ?Sized), method: &str) -> Result<()> {
+    if pm.cfg().allow_downgrade && ["su", "suy"].contains(&method) && !pm.supports_allow_downgrade()
+    {
+        return Err(unsupported_flag(pm, "--allow-downgrade"));
+    }
+    Ok(())
+}
+
+/// Notes with `--verbose` that `parallel_downloads` is going to be ignored by
+/// `pm` when running the sync `method`.
+///
+/// Unlike the flags, this is usually set once and for all in the config file,
+/// so it is not worth a warning on every operation.
+fn check_parallel_downloads(pm: &(impl Pm + ?Sized), method: &str) {
+    let cfg = pm.cfg();
+    if cfg.verbose
+        && cfg.parallel_downloads.is_some()
+        && ["s", "su", "suy", "sw"].contains(&method)
+        && !pm.supports_parallel_downloads()
+    {
+        println(
+            &*prompt::INFO,
+            format_args!(
+                "`parallel_downloads` is unsupported by `{}` and will be ignored",
+                pm.name()
+            ),
+        );
+    }
+}
+
+/// Checks that `--verify-checksums` can be honored by `pm` when running
+/// `-Sw`.
+///
+/// # Errors
+/// Returns an [`Error::ArgParseError`] if `pm` cannot verify the downloaded
+/// packages.
+fn check_verify_checksums(pm: &(impl Pm + ?Sized), method: &str) -> Result<()> {
+    if pm.cfg().verify_checksums && method == "sw" && !pm.supports_verify_checksums() {
+        return Err(unsupported_flag(pm, "--verify-checksums"));
+    }
+    Ok(())
+}
+
+/// Turns a successful `res` into an [`Error::PromptDeclinedError`] if any
+/// prompt has been declined: that only skips the command in question, but
+/// this should still be reflected in the exit code.
+fn check_declined(res: Result<()>) -> Result<()> {
+    res.and_then(|()| {
+        (!exec::prompt_declined())
+            .then_some(())
+            .ok_or(Error::PromptDeclinedError)
+    })
+}
+
+/// Ensures that the cursor is not hidden when `Ctrl-C` is used.
+///
+/// See: <https://github.com/console-rs/dialoguer/issues/77#issuecomment-669986406>
+fn show_cursor_on_ctrlc() {
+    _ = ctrlc::set_handler(move || {
+        let term = console::Term::stdout();
+        _ = term.show_cursor();
+    })
+    .tap_err(|e| println(&*prompt::INFO, e));
+}
+
+/// Adjusts `cfg` to the operation `method`, turning off the settings that do
+/// not apply to it and adding the packages in `--exclude-from` to the ignored
+/// ones of an upgrade.
+///
+/// # Errors
+/// Returns an [`Error::ExcludeFileError`] if `--exclude-from` cannot be
+/// loaded.
+fn restrict_to_method(cfg: &mut Config, method: &str) -> Result<()> {
+    cfg.no_confirm |= should_assume_yes(cfg, method);
+    cfg.measure_network &= MEASURE_NETWORK_METHODS.contains(&method);
+    cfg.sizes &= SIZE_METHODS.contains(&method);
+    if IGNORE_METHODS.contains(&method) {
+        let excludes = cfg.excludes()?;
+        cfg.ignore.extend(excludes);
+    }
+    Ok(())
+}
+
+/// Drops the keywords `kws` of the removal `method` matching the packages (or
+/// glob patterns) in `--exclude-from`.
+///
+/// # Errors
+/// Returns an [`Error::ExcludeFileError`] if `--exclude-from` cannot be
+/// loaded.
+fn exclude_removals<'a>(cfg: &Config, method: &str, kws: Vec<&'a str>) -> Result<Vec<&'a str>> {
+    if !REMOVAL_METHODS.contains(&method) || cfg.exclude_from.is_none() {
+        return Ok(kws);
+    }
+    let patterns = exec::glob_patterns(&cfg.excludes()?)?;
+    let (skipped, kws): (Vec<_>, Vec<_>) = kws.into_iter().partition(|kw| patterns.is_match(kw));
+    if !skipped.is_empty() {
+        println(
+            &*prompt::INFO,
+            format_args!(
+                "skipping removal of {}, as excluded by `--exclude-from`",
+                skipped.join(", ")
+            ),
+        );
+    }
+    Ok(kws)
+}
+
+/// Checks if every question should be answered with yes when running the
+/// operation `method`, either because of `--assume-yes-for` or because of
+/// running in a container without a TTY.
+fn should_assume_yes(cfg: &Config, method: &str) -> bool {
+    cfg.assume_yes_for.iter().any(|op| op_method(op) == method)
+        || (cfg.in_container() && !io::stdin().is_terminal())
+}
+
+/// Normalizes an operation given by the user (e.g. `-Syu`) into the name of
+/// the corresponding [`Pm`](crate::pm::Pm) method (e.g. `suy`).
+fn op_method(op: &str) -> String {
+    let op = op.trim_start_matches('-').to_lowercase();
+    match op.as_str() {
+        "autoremove" | "repair" | "verify" | "downgrade" | "undo" => return op,
+        "fix" => return "repair".into(),
+        "autoremove-interactive" | "autoremove_interactive" => {
+            return "autoremove_interactive".into();
+        }
+        "refresh-keys" | "refresh_keys" => return "refresh_keys".into(),
+        op if op.starts_with("module") || op.starts_with("service") => {
+            return op.replace('-', "_");
+        }
+        _ => (),
+    }
+    let mut chars = op.chars();
+    chars
+        .next()
+        .into_iter()
+        .chain(chars.sorted_unstable())
+        .collect()
+}
+
+#[cfg(all(test, feature = "mock"))]
 mod tests {
     use std::sync::LazyLock;
 
+    use pacaptr::pm::mock::{capture_output, MockLog};
     use tokio::test;
 
     use super::*;
 
     static MOCK_CFG: LazyLock<Config> = LazyLock::new(|| Config {
-        default_pm: Some("mockpm".into()),
+        default_pm: Some("mock".into()),
+        container_mode: Some(ContainerMode::Off),
+        quiet: Some(true),
         ..Config::default()
     });
 
+    /// Dispatches `opt` to the `mock` package manager, returning the commands
+    /// that it would have run.
+    async fn dispatch_mock(opt: &Pacaptr) -> Result<Vec<String>> {
+        let log = MockLog::new();
+        log.scope(opt.dispatch_from(opt.cfg().join(MOCK_CFG.clone())))
+            .await?;
+        Ok(log.lines())
+    }
+
     #[test]
-    #[should_panic(expected = "should run: suy")]
-    #[allow(clippy::semicolon_if_nothing_returned)]
     async fn simple_syu() {
         let opt = dbg!(Pacaptr::parse_from(["pacaptr", "-Syu"]));
         let subcmd = &opt.ops;
@@ -377,12 +1671,10 @@ mod tests {
         assert!(matches!(subcmd, &Operations::Sync{ u, y, .. } if y && u));
         assert!(opt.keywords.is_empty());
 
-        opt.dispatch_from(MOCK_CFG.clone()).await.unwrap();
+        assert_eq!(dispatch_mock(&opt).await.unwrap(), ["mock suy"]);
     }
 
     #[test]
-    #[should_panic(expected = "should run: suy")]
-    #[allow(clippy::semicolon_if_nothing_returned)]
     async fn long_syu() {
         let opt = dbg!(Pacaptr::parse_from([
             "pacaptr",
@@ -395,12 +1687,42 @@ mod tests {
         assert!(matches!(subcmd, &Operations::Sync { u, y, .. } if y && u));
         assert!(opt.keywords.is_empty());
 
-        opt.dispatch_from(MOCK_CFG.clone()).await.unwrap();
+        assert_eq!(dispatch_mock(&opt).await.unwrap(), ["mock suy"]);
+    }
+
+    #[test]
+    async fn recursive_r() {
+        for (args, method) in [
+            (&["-R", "fish"][..], "r"),
+            (&["-Rs", "fish"], "rs"),
+            (&["-Rss", "fish"], "rss"),
+            (&["-R", "--recursive", "-s", "fish"], "rss"),
+            (&["-Rsn", "fish"], "rns"),
+        ] {
+            let opt = Pacaptr::parse_from(chain!(["pacaptr"], args.iter().copied()));
+            assert_eq!(
+                dispatch_mock(&opt).await.unwrap(),
+                [format!("mock {method} fish")]
+            );
+        }
+    }
+
+    #[test]
+    async fn check_qk() {
+        for (args, method) in [
+            (&["-Qk", "fish"][..], "qk"),
+            (&["-Qkk", "fish"], "qkk"),
+            (&["-Q", "--check", "--check", "fish"], "qkk"),
+        ] {
+            let opt = Pacaptr::parse_from(chain!(["pacaptr"], args.iter().copied()));
+            assert_eq!(
+                dispatch_mock(&opt).await.unwrap(),
+                [format!("mock {method} fish")]
+            );
+        }
     }
 
     #[test]
-    #[should_panic(expected = r#"should run: sw ["curl", "wget"]"#)]
-    #[allow(clippy::semicolon_if_nothing_returned)]
     async fn simple_sw() {
         let opt = dbg!(Pacaptr::parse_from(["pacaptr", "-Sw", "curl", "wget"]));
         let subcmd = &opt.ops;
@@ -408,12 +1730,10 @@ mod tests {
         assert!(matches!(subcmd, &Operations::Sync { w, .. } if w));
         assert_eq!(opt.keywords, &["curl", "wget"]);
 
-        opt.dispatch_from(MOCK_CFG.clone()).await.unwrap();
+        assert_eq!(dispatch_mock(&opt).await.unwrap(), ["mock sw curl wget"]);
     }
 
     #[test]
-    #[should_panic(expected = r#"should run: s ["docker"]"#)]
-    #[allow(clippy::semicolon_if_nothing_returned)]
     async fn other_flags() {
         let opt = dbg!(Pacaptr::parse_from([
             "pacaptr", "-S", "--dryrun", "--yes", "docker"
@@ -425,12 +1745,11 @@ mod tests {
         assert!(matches!(subcmd, &Operations::Sync { .. }));
         assert_eq!(opt.keywords, &["docker"]);
 
-        opt.dispatch_from(MOCK_CFG.clone()).await.unwrap();
+        // Nothing is run in a dry run.
+        assert!(dispatch_mock(&opt).await.unwrap().is_empty());
     }
 
     #[test]
-    #[should_panic(expected = r#"should run: s ["docker", "--proxy=localhost:1234"]"#)]
-    #[allow(clippy::semicolon_if_nothing_returned)]
     async fn extra_flags() {
         let opt = dbg!(Pacaptr::parse_from([
             "pacaptr",
@@ -447,17 +1766,18 @@ mod tests {
         assert_eq!(opt.keywords, &["docker"]);
         assert_eq!(opt.extra_flags, &["--proxy=localhost:1234"]);
 
-        opt.dispatch_from(MOCK_CFG.clone()).await.unwrap();
+        assert_eq!(
+            dispatch_mock(&opt).await.unwrap(),
+            ["mock s --proxy=localhost:1234 --yes docker"]
+        );
     }
 
     #[test]
-    #[should_panic(expected = r#"should run: si ["docker", "--proxy=localhost:1234"]"#)]
-    #[allow(clippy::semicolon_if_nothing_returned)]
     async fn using() {
         let opt = dbg!(Pacaptr::parse_from([
             "pacaptr",
             "--pm",
-            "mockpm",
+            "mock",
             "-Si",
             "--yes",
             "docker",
@@ -471,6 +1791,494 @@ mod tests {
         assert_eq!(opt.keywords, &["docker"]);
         assert_eq!(opt.extra_flags, &["--proxy=localhost:1234"]);
 
-        opt.dispatch_from(MOCK_CFG.clone()).await.unwrap();
+        let log = MockLog::new();
+        log.scope(opt.dispatch_from(opt.cfg())).await.unwrap();
+        assert_eq!(log.lines(), ["mock si --proxy=localhost:1234 --yes docker"]);
+    }
+
+    #[test]
+    async fn keep_going() {
+        let opt = Pacaptr::parse_from([
+            "pacaptr",
+            "-S",
+            "--keep-going",
+            "--yes",
+            "curl",
+            "nope",
+            "wget",
+        ]);
+        let log = MockLog::new().fail("mock s --yes nope", 100);
+        let res = (log.scope(opt.dispatch_from(opt.cfg().join(MOCK_CFG.clone())))).await;
+        assert_eq!(
+            log.lines(),
+            [
+                "mock s --yes curl",
+                "mock s --yes nope",
+                "mock s --yes wget"
+            ]
+        );
+        let Err(Error::MultiError(e)) = res else {
+            panic!("expected a `MultiError`, got {res:?}");
+        };
+        assert_eq!((e.failures.len(), e.total), (1, 3));
+        assert_eq!(e.failures[0].0, "nope");
+
+        // The queries are run at once as usual.
+        let opt = Pacaptr::parse_from(["pacaptr", "-Ss", "--keep-going", "curl", "wget"]);
+        assert_eq!(dispatch_mock(&opt).await.unwrap(), ["mock ss curl wget"]);
+    }
+
+    #[test]
+    async fn op_methods() {
+        assert_eq!(op_method("-Syu"), "suy");
+        assert_eq!(op_method("sy"), "sy");
+        assert_eq!(op_method("Rns"), "rns");
+        assert_eq!(op_method("fix"), "repair");
+        assert_eq!(op_method("verify"), "verify");
+        assert_eq!(op_method("refresh-keys"), "refresh_keys");
+        assert_eq!(op_method("module-install"), "module_install");
+    }
+
+    #[test]
+    async fn assume_yes_for() {
+        let opt = dbg!(Pacaptr::parse_from([
+            "pacaptr",
+            "-Su",
+            "--assume-yes-for",
+            "sy,-Su",
+            "docker",
+        ]));
+
+        assert!(!opt.no_confirm);
+        assert_eq!(opt.assume_yes_for, &["sy", "-Su"]);
+
+        assert_eq!(dispatch_mock(&opt).await.unwrap(), ["mock su --yes docker"]);
+    }
+
+    #[test]
+    async fn non_pacman_ops() {
+        for (args, expected) in [
+            (&["autoremove"][..], "mock autoremove"),
+            (&["autoremove", "-i"], "mock autoremove_interactive"),
+            (
+                &["autoremove", "--interactive-remove-orphans"],
+                "mock autoremove_interactive",
+            ),
+            (&["fix"], "mock repair"),
+            (&["verify", "curl"], "mock verify curl"),
+            (&["refresh-keys"], "mock refresh_keys"),
+            (&["downgrade", "curl=7.81.0"], "mock downgrade curl=7.81.0"),
+            (&["undo"], "mock undo"),
+            (&["undo", "42"], "mock undo 42"),
+            (&["module", "nodejs"], "mock module_list nodejs"),
+            (
+                &["module", "--install", "nodejs:18"],
+                "mock module_install nodejs:18",
+            ),
+            (&["module", "-r", "nodejs"], "mock module_reset nodejs"),
+            (
+                &["service", "--add", "https://example.com/repo", "example"],
+                "mock service_add https://example.com/repo example",
+            ),
+            (&["service", "-r", "example"], "mock service_remove example"),
+        ] {
+            let opt = Pacaptr::parse_from(chain!(["pacaptr"], args.iter().copied()));
+            assert_eq!(dispatch_mock(&opt).await.unwrap(), [expected]);
+        }
+        // Only one action can be taken at a time.
+        assert!(Pacaptr::try_parse_from(["pacaptr", "module", "-e", "-i", "nodejs:18"]).is_err());
+        // `autoremove` takes no packages.
+        for args in [&["autoremove", "curl"][..], &["autoremove", "-i", "curl"]] {
+            let opt = Pacaptr::parse_from(chain!(["pacaptr"], args.iter().copied()));
+            let res = dispatch_mock(&opt).await;
+            assert!(
+                matches!(&res, Err(Error::ArgParseError { msg }) if msg.contains("takes no packages")),
+                "{res:?}"
+            );
+        }
+    }
+
+    #[test]
+    async fn print_forces_dry_run() {
+        for args in [
+            &["-Rp", "curl"][..],
+            &["-Sp", "curl"],
+            &["repair", "--print"],
+        ] {
+            let opt = Pacaptr::parse_from(chain!(["pacaptr"], args.iter().copied()));
+            assert!(!opt.dry_run);
+            assert!(dispatch_mock(&opt).await.unwrap().is_empty(), "{args:?}");
+        }
+    }
+
+    #[test]
+    async fn measure_network_json() {
+        let args = ["pacaptr", "--measure-network", "--output-format", "json"];
+        let opt = Pacaptr::parse_from(chain!(args, ["-S", "curl"]));
+        assert_eq!(dispatch_mock(&opt).await.unwrap(), ["mock s curl"]);
+        // The format does not affect the other operations.
+        let opt = Pacaptr::parse_from(chain!(args, ["-R", "curl"]));
+        assert_eq!(dispatch_mock(&opt).await.unwrap(), ["mock r curl"]);
+    }
+
+    #[test]
+    async fn structured_transactions() {
+        for (args, expected) in [
+            (&["-S", "foo"][..], "mock s foo"),
+            (&["-R", "foo"], "mock r foo"),
+            (&["-Su"], "mock su"),
+        ] {
+            let opt = Pacaptr::parse_from(chain!(
+                ["pacaptr", "--output-format", "json"],
+                args.iter().copied()
+            ));
+            assert_eq!(dispatch_mock(&opt).await.unwrap(), [expected], "{args:?}");
+        }
+    }
+
+    #[test]
+    async fn exclude_from() {
+        let path = std::env::temp_dir().join(format!("pacaptr-exclude-{}", std::process::id()));
+        let file = path.to_str().unwrap();
+        std::fs::write(&path, "# Kernels\nlinux-image-*\n").unwrap();
+        let args = ["pacaptr", "--exclude-from", file, "-R"];
+        let opt = Pacaptr::parse_from(chain!(args, ["curl", "linux-image-amd64"]));
+        assert_eq!(dispatch_mock(&opt).await.unwrap(), ["mock r curl"]);
+        // Nothing is left to be removed.
+        let opt = Pacaptr::parse_from(chain!(args, ["linux-image-amd64"]));
+        assert!(dispatch_mock(&opt).await.unwrap().is_empty());
+        std::fs::remove_file(&path).unwrap();
+
+        let opt = Pacaptr::parse_from(["pacaptr", "--exclude-from", file, "-Su"]);
+        let res = dispatch_mock(&opt).await;
+        assert!(matches!(res, Err(Error::ExcludeFileError { .. })));
+    }
+
+    #[test]
+    async fn required_by() {
+        let opt = Pacaptr::parse_from(["pacaptr", "-Qi", "--required-by", "curl"]);
+        assert_eq!(dispatch_mock(&opt).await.unwrap(), ["mock qii curl"]);
+        let opt = Pacaptr::parse_from(["pacaptr", "-Si", "--required-by", "curl"]);
+        assert_eq!(dispatch_mock(&opt).await.unwrap(), ["mock si curl"]);
+    }
+
+    #[test]
+    async fn offline() {
+        let opt = Pacaptr::parse_from(["pacaptr", "-S", "--offline", "curl"]);
+        let res = dispatch_mock(&opt).await;
+        assert!(
+            matches!(&res, Err(Error::OfflineError { op, .. }) if op == "s"),
+            "{res:?}"
+        );
+        // So are the extra operations fetching packages or module metadata.
+        for (args, method) in [
+            (&["downgrade", "curl=7.81.0"][..], "downgrade"),
+            (&["module", "-e", "nodejs:18"], "module_enable"),
+            (&["module", "-i", "nodejs:18"], "module_install"),
+        ] {
+            let opt = Pacaptr::parse_from([&["pacaptr", "--offline"], args].concat());
+            let res = dispatch_mock(&opt).await;
+            assert!(
+                matches!(&res, Err(Error::OfflineError { op, .. }) if op == method),
+                "{res:?}"
+            );
+        }
+        // The local operations are run as usual.
+        let opt = Pacaptr::parse_from(["pacaptr", "-Q", "--offline"]);
+        assert_eq!(dispatch_mock(&opt).await.unwrap(), ["mock q"]);
+        let opt = Pacaptr::parse_from(["pacaptr", "-R", "--offline", "curl"]);
+        assert_eq!(dispatch_mock(&opt).await.unwrap(), ["mock r curl"]);
+    }
+
+    #[test]
+    #[cfg(not(windows))]
+    async fn on_failure() {
+        let opt = Pacaptr::parse_from(["pacaptr", "-S", "--on-failure", "cleanup", "curl"]);
+        let dispatch = || opt.dispatch_from(opt.cfg().join(MOCK_CFG.clone()));
+        let log = MockLog::new().fail("mock s", 100);
+        let res = log.scope(dispatch()).await;
+        assert_eq!(log.lines(), ["mock s curl", "sh -c cleanup"]);
+        assert_eq!(
+            log.cmds()[1].env,
+            [
+                ("PACAPTR_FAILED_OP".into(), "-S curl".into()),
+                ("PACAPTR_EXIT_CODE".into(), "5".into()),
+            ]
+        );
+        assert_eq!(res.unwrap_err().status_code(), Some(100));
+
+        // The hook failing does not mask the original error.
+        let log = MockLog::new().fail("mock s", 100).fail("sh -c", 1);
+        let res = log.scope(dispatch()).await;
+        assert_eq!(log.lines(), ["mock s curl", "sh -c cleanup"]);
+        assert_eq!(res.unwrap_err().status_code(), Some(100));
+
+        // Nor is the hook run on success.
+        assert_eq!(dispatch_mock(&opt).await.unwrap(), ["mock s curl"]);
+    }
+
+    #[test]
+    async fn container_mode() {
+        let opt = dbg!(Pacaptr::parse_from([
+            "pacaptr",
+            "-S",
+            "--container-mode=off",
+            "docker",
+        ]));
+        assert_eq!(opt.container_mode, Some(ContainerMode::Off));
+        assert!(!opt.cfg().in_container());
+        let opt = Pacaptr::parse_from(["pacaptr", "-S", "--container-mode", "on"]);
+        assert!(opt.cfg().in_container());
+    }
+
+    #[test]
+    async fn prompt_default() {
+        let opt = Pacaptr::parse_from(["pacaptr", "-R", "--prompt-default", "no", "curl"]);
+        let file = Config {
+            prompt_default: Some(PromptDefault::Yes),
+            ..Config::default()
+        };
+        assert_eq!(
+            opt.cfg().join(file.clone()).prompt_default,
+            Some(PromptDefault::No)
+        );
+        let opt = Pacaptr::parse_from(["pacaptr", "-R", "curl"]);
+        assert_eq!(
+            opt.cfg().join(file).prompt_default,
+            Some(PromptDefault::Yes)
+        );
+    }
+
+    #[test]
+    async fn proxy_overrides_config() {
+        let opt = Pacaptr::parse_from(["pacaptr", "-S", "--proxy", "http://cli:3128", "curl"]);
+        let file = Config {
+            http_proxy: Some("http://file:8080".into()),
+            no_proxy: Some("localhost".into()),
+            ..Config::default()
+        };
+        let cfg = opt.cfg().join(file);
+        assert_eq!(cfg.http_proxy(), Some("http://cli:3128"));
+        assert_eq!(cfg.https_proxy(), Some("http://cli:3128"));
+        assert_eq!(cfg.no_proxy.as_deref(), Some("localhost"));
+    }
+
+    #[test]
+    async fn unknown_pm() {
+        let opt = Pacaptr::parse_from(["pacaptr", "--using", "foo", "-S", "docker"]);
+        let res = opt.dispatch_from(opt.cfg()).await;
+        assert!(matches!(res, Err(Error::PmNotFoundError { name, .. }) if name == "foo"));
+    }
+
+    #[cfg(not(windows))]
+    #[test(flavor = "multi_thread")]
+    async fn bootstrap_declined() {
+        use std::sync::Mutex;
+
+        use pacaptr::exec::context::{self, Context, OutputSink, PromptHandler, Stream};
+
+        #[derive(Default)]
+        struct Log(Mutex<String>);
+
+        impl OutputSink for Log {
+            fn print(&self, text: &str) {
+                self.0.lock().unwrap().push_str(text);
+            }
+
+            fn cmd_output(&self, _stream: Stream, _chunk: &[u8]) {}
+        }
+
+        struct Decline;
+
+        impl PromptHandler for Decline {
+            fn prompt(&self, _: &str, _: &str, expected: &[&str], _: usize) -> Result<usize> {
+                Ok(expected.len() - 1)
+            }
+        }
+
+        let opt = Pacaptr::parse_from(["pacaptr", "--using", "scoop", "--bootstrap", "-S", "git"]);
+        let log = Arc::new(Log::default());
+        let ctx = Context::new(Arc::clone(&log) as _, Arc::new(Decline));
+        let res = context::scope(ctx, opt.dispatch_from(opt.cfg())).await;
+        assert!(matches!(res, Err(Error::PmNotFoundError { name, .. }) if name == "scoop"));
+        assert!(log
+            .0
+            .lock()
+            .unwrap()
+            .contains("powershell -NoProfile -Command 'irm get.scoop.sh | iex'"));
+    }
+
+    #[test]
+    async fn install_into_file() {
+        let opt = Pacaptr::parse_from(["pacaptr", "-S", "curl"]);
+        let cfg = Config {
+            install_into: Some(concat!(env!("CARGO_MANIFEST_DIR"), "/Cargo.toml").into()),
+            ..MOCK_CFG.clone()
+        };
+        let res = opt.dispatch_from(cfg).await;
+        assert!(matches!(res, Err(Error::ArgParseError { msg }) if msg.contains("--install-into")));
+    }
+
+    #[test]
+    async fn target_release_unsupported() {
+        let cfg = Config {
+            target_release: Some("bookworm-backports".into()),
+            ..MOCK_CFG.clone()
+        };
+        let opt = Pacaptr::parse_from(["pacaptr", "-S", "curl"]);
+        let res = opt.dispatch_from(cfg.clone()).await;
+        assert!(
+            matches!(res, Err(Error::ArgParseError { msg }) if msg.contains("--target-release"))
+        );
+        let opt = Pacaptr::parse_from(["pacaptr", "-Q"]);
+        assert!(MockLog::new().scope(opt.dispatch_from(cfg)).await.is_ok());
+    }
+
+    #[test]
+    async fn repos_unsupported() {
+        let cfg = Config {
+            disable_repo: vec!["updates".into()],
+            ..MOCK_CFG.clone()
+        };
+        let opt = Pacaptr::parse_from(["pacaptr", "-Ss", "curl"]);
+        let res = opt.dispatch_from(cfg).await;
+        assert!(matches!(res, Err(Error::ArgParseError { msg }) if msg.contains("--disable-repo")));
+    }
+
+    #[test]
+    async fn build_options_unsupported() {
+        let cfg = Config {
+            head: true,
+            ..MOCK_CFG.clone()
+        };
+        let opt = Pacaptr::parse_from(["pacaptr", "-S", "curl"]);
+        let res = opt.dispatch_from(cfg).await;
+        assert!(matches!(res, Err(Error::ArgParseError { msg }) if msg.contains("--head")));
+    }
+
+    #[test]
+    async fn unsupported_modifiers() {
+        let rejected = [
+            (
+                &["-S", "curl"][..],
+                "--install-into",
+                Config {
+                    install_into: Some(std::env::temp_dir()),
+                    ..MOCK_CFG.clone()
+                },
+            ),
+            (
+                &["-Ss", "curl"],
+                "--exclude-installed",
+                Config {
+                    exclude_installed: true,
+                    ..MOCK_CFG.clone()
+                },
+            ),
+            (
+                &["-Su"],
+                "--allow-downgrade",
+                Config {
+                    allow_downgrade: true,
+                    ..MOCK_CFG.clone()
+                },
+            ),
+            (
+                &["-Sw", "curl"],
+                "--verify-checksums",
+                Config {
+                    verify_checksums: true,
+                    ..MOCK_CFG.clone()
+                },
+            ),
+        ];
+        for (args, flag, cfg) in rejected {
+            let opt = Pacaptr::parse_from([&["pacaptr"], args].concat());
+            let res = opt.dispatch_from(cfg).await;
+            assert!(
+                matches!(&res, Err(Error::ArgParseError { msg }) if msg.contains(flag)),
+                "{args:?}: {res:?}"
+            );
+        }
+
+        // The ones only changing how the results are shown are merely warned about.
+        let cfg = Config {
+            sizes: true,
+            tree: true,
+            search_limit: Some(3),
+            ..MOCK_CFG.clone()
+        };
+        for args in [&["-S", "curl"][..], &["-Qi", "curl"], &["-Ss", "curl"]] {
+            let opt = Pacaptr::parse_from([&["pacaptr"], args].concat());
+            let res = MockLog::new().scope(opt.dispatch_from(cfg.clone())).await;
+            assert!(res.is_ok(), "{args:?}: {res:?}");
+        }
+        let opt = Pacaptr::parse_from(["pacaptr", "-S", "--preview", "curl"]);
+        let log = MockLog::new();
+        let (res, out) = capture_output(log.scope(Box::pin(
+            opt.dispatch_from(opt.cfg().join(MOCK_CFG.clone())),
+        )))
+        .await;
+        assert!(res.is_ok(), "{res:?}");
+        assert!(
+            out.contains("`--preview` is unsupported by `mock` and will be ignored"),
+            "{out}"
+        );
+        assert_eq!(log.lines(), ["mock s curl"]);
+    }
+
+    #[test]
+    async fn all_managers() {
+        let dispatch = |args: &[&str]| {
+            let opt = Pacaptr::parse_from([&["pacaptr", "--all-managers"], args].concat());
+            async move { opt.dispatch_from(opt.cfg()).await }
+        };
+        let res = dispatch(&["-S", "curl"]).await;
+        assert!(matches!(res, Err(Error::ArgParseError { msg }) if msg.contains("`-Q` and `-Ss`")));
+        if !cfg!(target_os = "macos") {
+            let res = dispatch(&["-Ss", "curl"]).await;
+            assert!(matches!(res, Err(Error::ArgParseError { msg }) if msg.contains("macOS")));
+        }
+        assert!(
+            Pacaptr::try_parse_from(["pacaptr", "-Q", "--all-managers", "--using", "brew"])
+                .is_err()
+        );
+        assert!(Pacaptr::try_parse_from(["pacaptr", "-Q", "--concurrent-pm-queries"]).is_err());
+        assert!(Pacaptr::try_parse_from([
+            "pacaptr",
+            "-Q",
+            "--all-managers",
+            "--concurrent-pm-queries"
+        ])
+        .is_ok());
+    }
+
+    #[test]
+    async fn merged_pkgs() {
+        let found = vec![
+            (
+                "brew",
+                vec![
+                    Pkg::new("wget", "1.24.5", "brew"),
+                    Pkg::new("ripgrep", "14.1.1", "brew"),
+                ],
+            ),
+            (
+                "port",
+                vec![
+                    Pkg::new("ripgrep", "14.1.1", "port"),
+                    Pkg::new("curl", "8.5.0_0", "port"),
+                    Pkg::new("wget", "1.21.4_0", "port"),
+                ],
+            ),
+        ];
+        assert_eq!(
+            merge_pkgs(found),
+            [
+                Pkg::new("curl", "8.5.0_0", "port"),
+                Pkg::new("ripgrep", "14.1.1", "brew, port"),
+                Pkg::new("wget", "1.24.5, 1.21.4_0", "brew, port"),
+            ]
+        );
     }
 }
@@ -1,16 +1,18 @@
 //! Basic error definitions specific to this crate.
 
 use std::{
-    fmt::{self, Debug},
+    fmt::{self, Debug, Display, Write},
     process::{ExitCode, Termination},
+    sync::atomic::{AtomicBool, Ordering},
 };
 
+use itertools::Itertools;
 use thiserror::Error;
 use thiserror_ext::AsReport;
 use tokio::{io, task::JoinError};
 
 use crate::{
-    exec::{Output, StatusCode},
+    exec::{network::NetworkIssue, Mode, Output, StatusCode},
     print,
 };
 
@@ -31,6 +33,16 @@ pub enum Error {
     #[error("failed to parse config")]
     ConfigError(#[from] figment::Error),
 
+    /// Error when loading the file given by `--env-file`.
+    #[allow(missing_docs)]
+    #[error("failed to load env file `{path}`: {msg}")]
+    EnvFileError { path: String, msg: String },
+
+    /// Error when loading the file given by `--exclude-from`.
+    #[allow(missing_docs)]
+    #[error("failed to load exclude file `{path}`: {msg}")]
+    ExcludeFileError { path: String, msg: String },
+
     /// A [`Cmd`](crate::exec::Cmd) failed to finish.
     #[error("failed to get exit code of subprocess")]
     CmdJoinError(#[from] JoinError),
@@ -50,14 +62,47 @@ pub enum Error {
     CmdWaitError(#[source] io::Error),
 
     /// A [`Cmd`](crate::exec::Cmd) exited with an error.
+    ///
+    /// Apart from the captured `output`, the rendered `cmd` and the `mode` it
+    /// has been run in are kept as well, to be shown in the error report.
     #[allow(missing_docs)]
     #[error("subprocess exited with code {code}")]
-    CmdStatusCodeError { code: StatusCode, output: Output },
+    CmdStatusCodeError {
+        code: StatusCode,
+        output: Output,
+        cmd: String,
+        mode: Mode,
+    },
+
+    /// A [`Cmd`](crate::exec::Cmd) failed because of a network issue, as
+    /// detected in its output.
+    #[allow(missing_docs)]
+    #[error("network failure: {issue}")]
+    NetworkError {
+        issue: NetworkIssue,
+        #[source]
+        source: Box<Self>,
+    },
+
+    /// A [`Cmd`](crate::exec::Cmd) failed because some of the requested
+    /// packages were not found, as detected in its output.
+    #[allow(missing_docs)]
+    #[error("package not found: {}", .pkgs.iter().map(|pkg| format!("`{pkg}`")).join(", "))]
+    PkgNotFoundError {
+        pkgs: Vec<String>,
+        #[source]
+        source: Box<Self>,
+    },
 
     /// A [`Cmd`](crate::exec::Cmd) was interrupted by a signal.
     #[error("subprocess interrupted by signal")]
     CmdInterruptedError,
 
+    /// The user has declined to run (at least) one of the
+    /// [`Cmd`](crate::exec::Cmd)s.
+    #[error("some commands were skipped as the prompt was declined")]
+    PromptDeclinedError,
+
     /// Error while converting a [`Vec<u8>`] to a [`String`].
     #[error(transparent)]
     FromUtf8Error(#[from] std::string::FromUtf8Error),
@@ -70,19 +115,280 @@ pub enum Error {
     #[error(transparent)]
     IoError(#[from] io::Error),
 
+    /// The requested [`Pm`](crate::pm::Pm) is unsupported or not installed, or
+    /// no supported one has been detected.
+    #[allow(missing_docs)]
+    #[error("package manager `{name}` is unsupported or not found")]
+    PmNotFoundError { name: String, hint: Option<String> },
+
     /// A [`Pm`](crate::pm::Pm) operation is not implemented.
     #[allow(missing_docs)]
     #[error("operation `{op}` is unimplemented for `{pm}`")]
     OperationUnimplementedError { op: String, pm: String },
 
+    /// A [`Pm`](crate::pm::Pm) operation cannot provide structured output,
+    /// e.g. for `--output-format json`.
+    #[allow(missing_docs)]
+    #[error("structured output is unsupported by `{pm}` for this operation")]
+    StructuredOutputUnsupportedError { pm: String },
+
+    /// A [`Pm`](crate::pm::Pm) operation needs the network, which is
+    /// unavailable with `--offline`.
+    #[allow(missing_docs)]
+    #[error("operation `{op}` needs the network, so it is refused by `{pm}` with `--offline`")]
+    OfflineError { op: String, pm: String },
+
+    /// The dependency conflicts found by `--check-deps` before an installation.
+    #[allow(missing_docs)]
+    #[error("found {count} dependency conflict(s), so nothing has been installed")]
+    DepConflictError { count: usize },
+
+    /// The downloaded package files failing the verification of
+    /// `--verify-checksums`.
+    #[allow(missing_docs)]
+    #[error("found {count} downloaded package file(s) not matching their checksums")]
+    ChecksumMismatchError { count: usize },
+
+    /// Error when updating the `pacaptr` executable itself.
+    #[cfg(feature = "self-update")]
+    #[allow(missing_docs)]
+    #[error("failed to update pacaptr: {msg}")]
+    SelfUpdateError { msg: String },
+
+    /// An operation looping over multiple keywords failed for some of them.
+    #[error(transparent)]
+    MultiError(#[from] MultiError),
+
     /// Miscellaneous other error.
     #[error("{0}")]
     OtherError(String),
 }
 
+impl Error {
+    /// Returns the hint on how to fix this error, if any.
+    #[must_use]
+    pub fn hint(&self) -> Option<&str> {
+        match self {
+            Self::PmNotFoundError { hint, .. } => hint.as_deref(),
+            Self::NetworkError { issue, .. } => Some(issue.hint()),
+            Self::PkgNotFoundError { .. } => Some(
+                "check the spelling of the package names, or refresh the package database with \
+                 `-Sy`",
+            ),
+            Self::OfflineError { .. } => {
+                Some("run it again without `--offline` once the network is available")
+            }
+            Self::DepConflictError { .. } => Some(
+                "change the requested versions to resolve the conflicts above, or run without \
+                 `--check-deps` to let the package manager decide",
+            ),
+            Self::ChecksumMismatchError { .. } => Some(
+                "remove the files above from the cache of the package manager, and download \
+                 them again with `-Sw`",
+            ),
+            _ => None,
+        }
+    }
+
+    /// Returns the exit code of the failed subprocess behind this error, if
+    /// any.
+    #[must_use]
+    pub fn status_code(&self) -> Option<StatusCode> {
+        match self {
+            Self::CmdStatusCodeError { code, .. } => Some(*code),
+            Self::NetworkError { source, .. } | Self::PkgNotFoundError { source, .. } => {
+                source.status_code()
+            }
+            Self::MultiError(e) if !e.is_partial() => e.failures.first()?.1.status_code(),
+            _ => None,
+        }
+    }
+
+    /// Returns the [`Error::CmdStatusCodeError`] behind this error, if any.
+    #[must_use]
+    pub fn cmd_failure(&self) -> Option<&Self> {
+        match self {
+            Self::CmdStatusCodeError { .. } => Some(self),
+            Self::NetworkError { source, .. } | Self::PkgNotFoundError { source, .. } => {
+                source.cmd_failure()
+            }
+            _ => None,
+        }
+    }
+
+    /// Checks if this error might go away by simply trying again, e.g. when a
+    /// host name could not be resolved.
+    #[must_use]
+    pub const fn is_transient(&self) -> bool {
+        matches!(self, Self::NetworkError { issue, .. } if issue.is_transient())
+    }
+
+    /// Returns the [`ExitKind`] of this error.
+    #[must_use]
+    pub fn exit_kind(&self) -> ExitKind {
+        match self {
+            Self::ArgParseError { .. }
+            | Self::ConfigError(_)
+            | Self::EnvFileError { .. }
+            | Self::ExcludeFileError { .. } => ExitKind::Usage,
+            Self::PmNotFoundError { .. } => ExitKind::PmNotFound,
+            Self::OperationUnimplementedError { .. }
+            | Self::StructuredOutputUnsupportedError { .. }
+            | Self::OfflineError { .. } => ExitKind::Unsupported,
+            Self::CmdStatusCodeError { .. } => ExitKind::CmdFailed,
+            Self::NetworkError { source, .. } => source.exit_kind(),
+            Self::PkgNotFoundError { .. } => ExitKind::PkgNotFound,
+            Self::MultiError(e) if e.is_partial() => ExitKind::Partial,
+            Self::MultiError(e) => {
+                (e.failures.first()).map_or(ExitKind::Generic, |(_, e)| e.exit_kind())
+            }
+            Self::PromptDeclinedError => ExitKind::Declined,
+            Self::CmdInterruptedError => ExitKind::Interrupted,
+            _ => ExitKind::Generic,
+        }
+    }
+
+    /// Returns the exit code of `pacaptr` for this error, i.e. the one of its
+    /// [`ExitKind`], or the one of the failed subprocess with
+    /// `--propagate-exit-code`.
+    #[must_use]
+    pub fn exit_code(&self) -> u8 {
+        #[allow(clippy::cast_sign_loss, clippy::cast_possible_truncation)]
+        match self.status_code() {
+            Some(code) if PROPAGATE_EXIT_CODE.load(Ordering::Relaxed) => code as u8,
+            _ => self.exit_kind().code(),
+        }
+    }
+}
+
+/// The failures of an operation looping over multiple keywords, which goes on
+/// after a keyword has failed unless `--fail-fast` is set.
+#[derive(Debug)]
+pub struct MultiError {
+    /// The keywords that have failed, along with their errors, in order.
+    pub failures: Vec<(String, Error)>,
+
+    /// The total number of keywords.
+    pub total: usize,
+}
+
+impl MultiError {
+    /// Checks if the operation has succeeded for some of the keywords.
+    #[must_use]
+    pub const fn is_partial(&self) -> bool {
+        self.failures.len() < self.total
+    }
+}
+
+impl Display for MultiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "failed for {} of {} keywords: ",
+            self.failures.len(),
+            self.total
+        )?;
+        for (i, (kw, e)) in self.failures.iter().enumerate() {
+            let sep = if i == 0 { "" } else { "; " };
+            write!(f, "{sep}`{kw}` ({})", e.as_report())?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for MultiError {}
+
+/// The classes of outcomes of [`pacaptr`](crate), each of which comes with a
+/// stable exit code.
+///
+/// This is the single source of truth for the exit codes, from which both
+/// [`MainError`] and the `Exit codes` section of `pacaptr --help` are derived.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[repr(u8)]
+#[allow(missing_docs)]
+pub enum ExitKind {
+    Success = 0,
+    Generic = 1,
+    Usage = 2,
+    PmNotFound = 3,
+    Unsupported = 4,
+    CmdFailed = 5,
+    Declined = 6,
+    Partial = 7,
+    PkgNotFound = 8,
+    Interrupted = 130,
+}
+
+impl ExitKind {
+    /// All the [`ExitKind`]s, in the ascending order of their exit codes.
+    pub const ALL: [Self; 10] = [
+        Self::Success,
+        Self::Generic,
+        Self::Usage,
+        Self::PmNotFound,
+        Self::Unsupported,
+        Self::CmdFailed,
+        Self::Declined,
+        Self::Partial,
+        Self::PkgNotFound,
+        Self::Interrupted,
+    ];
+
+    /// Returns the exit code of this kind.
+    #[must_use]
+    pub const fn code(self) -> u8 {
+        self as u8
+    }
+
+    /// Returns a short description of this kind.
+    #[must_use]
+    pub const fn description(self) -> &'static str {
+        match self {
+            Self::Success => "success",
+            Self::Generic => "generic error",
+            Self::Usage => "invalid arguments or config",
+            Self::PmNotFound => "package manager unsupported or not found",
+            Self::Unsupported => "operation unsupported by the package manager",
+            Self::CmdFailed => {
+                "subprocess failed (its own code is used instead with `--propagate-exit-code`)"
+            }
+            Self::Declined => "prompt declined by the user",
+            Self::Partial => "operation failed for some of the keywords only",
+            Self::PkgNotFound => "package not found",
+            Self::Interrupted => "interrupted",
+        }
+    }
+
+    /// Returns the table of all exit codes, as shown in `pacaptr --help`.
+    #[must_use]
+    pub fn table() -> String {
+        Self::ALL
+            .iter()
+            .fold(String::from("Exit codes:"), |mut table, kind| {
+                _ = write!(table, "\n  {:>3}  {}", kind.code(), kind.description());
+                table
+            })
+    }
+}
+
+/// Whether [`MainError`] should exit with the exit code of the failed
+/// subprocess instead of [`ExitKind::CmdFailed`].
+///
+/// This is a standalone flag, so `Ordering::Relaxed` is fine.
+static PROPAGATE_EXIT_CODE: AtomicBool = AtomicBool::new(false);
+
+/// Sets whether [`MainError`] should exit with the exit code of the failed
+/// subprocess instead of [`ExitKind::CmdFailed`].
+pub fn set_propagate_exit_code(propagate: bool) {
+    PROPAGATE_EXIT_CODE.store(propagate, Ordering::Relaxed);
+}
+
 /// A simple [`enum@Error`] wrapper designed to be returned in the `main`
 /// function. It delegates its [`Debug`] implementation to the
 /// [`std::fmt::Display`] implementation of its underlying error.
+///
+/// When reported as a [`Termination`], the error is printed to `stderr`, and
+/// the exit code is chosen according to its [`ExitKind`].
 #[allow(clippy::module_name_repetitions)]
 pub struct MainError(Error);
 
@@ -96,17 +402,109 @@ impl Debug for MainError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         // Erase the default "Error: " message header.
         write!(f, "\r")?;
-        print::write_err(f, &*print::prompt::ERROR, self.0.as_report())
+        print::write_err(f, &*print::prompt::ERROR, self.0.as_report())?;
+        if let Some(Error::CmdStatusCodeError {
+            cmd, mode, output, ..
+        }) = self.0.cmd_failure()
+        {
+            print::write_cmd_failure(f, cmd, mode, output)?;
+        }
+        self.0
+            .hint()
+            .map_or(Ok(()), |hint| print::write_hint(f, hint))
     }
 }
 
 impl Termination for MainError {
     fn report(self) -> ExitCode {
-        #[allow(clippy::cast_sign_loss, clippy::cast_possible_truncation)]
-        match self.0 {
-            Error::CmdStatusCodeError { code, .. } => code as u8,
-            _ => 1,
+        eprintln!("{self:?}");
+        self.0.exit_code().into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Makes a [`MultiError`] where the first `failed` of `total` keywords
+    /// have failed.
+    fn multi_error(failed: usize, total: usize) -> Error {
+        let failures = (1..=failed)
+            .map(|i| {
+                let e = Error::CmdStatusCodeError {
+                    code: 100,
+                    output: vec![],
+                    cmd: format!("apt install pkg{i}"),
+                    mode: Mode::default(),
+                };
+                (format!("pkg{i}"), e)
+            })
+            .collect();
+        MultiError { failures, total }.into()
+    }
+
+    #[test]
+    fn exit_kinds() {
+        let cases = [
+            (Error::ArgParseError { msg: String::new() }, 2),
+            (
+                Error::PmNotFoundError {
+                    name: "foo".into(),
+                    hint: None,
+                },
+                3,
+            ),
+            (
+                Error::OperationUnimplementedError {
+                    op: "si".into(),
+                    pm: "foo".into(),
+                },
+                4,
+            ),
+            (
+                Error::StructuredOutputUnsupportedError { pm: "foo".into() },
+                4,
+            ),
+            (
+                Error::NetworkError {
+                    issue: NetworkIssue::Dns,
+                    source: Box::new(Error::CmdStatusCodeError {
+                        code: 100,
+                        output: vec![],
+                        cmd: "apt update".into(),
+                        mode: Mode::default(),
+                    }),
+                },
+                5,
+            ),
+            (Error::PromptDeclinedError, 6),
+            (multi_error(2, 3), 7),
+            (multi_error(2, 2), 5),
+            (Error::CmdInterruptedError, 130),
+            (Error::OtherError(String::new()), 1),
+        ];
+        for (err, code) in cases {
+            assert_eq!(err.exit_kind().code(), code, "{err:?}");
         }
-        .into()
+    }
+
+    #[test]
+    fn multi_errors() {
+        let partial = multi_error(2, 3);
+        assert_eq!(partial.status_code(), None);
+        assert_eq!(
+            partial.to_string(),
+            "failed for 2 of 3 keywords: `pkg1` (subprocess exited with code 100); `pkg2` \
+             (subprocess exited with code 100)"
+        );
+        assert_eq!(multi_error(2, 2).status_code(), Some(100));
+    }
+
+    #[test]
+    fn exit_code_table() {
+        let table = ExitKind::table();
+        assert!(table.starts_with("Exit codes:\n    0  success\n"));
+        assert!(table.ends_with("\n  130  interrupted"));
+        assert_eq!(table.lines().count(), ExitKind::ALL.len() + 1);
     }
 }
@@ -1,12 +1,31 @@
 //! APIs for spawning subprocesses and handling their results.
 
+pub mod context;
+pub mod desktop;
+pub mod download;
+pub mod hook;
+pub mod network;
+pub mod not_found;
+pub mod result_class;
+
 use std::{
+    borrow::Cow,
+    env,
+    fmt::{self, Display},
+    fs,
+    io::{IsTerminal, Write},
+    mem,
+    path::{Path, PathBuf},
     process::Stdio,
-    sync::atomic::{AtomicBool, Ordering},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        LazyLock, Mutex, PoisonError,
+    },
+    time::{Duration, Instant},
 };
 
 use bytes::{Bytes, BytesMut};
-use dialoguer::FuzzySelect;
+use dialoguer::{theme::Theme, FuzzySelect, MultiSelect};
 use futures::prelude::*;
 use indoc::indoc;
 use itertools::{chain, Itertools};
@@ -21,13 +40,13 @@ use tokio::{
 use tokio_util::{
     codec::{BytesCodec, FramedRead},
     compat::*,
-    either::Either,
 };
 use which::which;
 
 use crate::{
+    config::PromptDefault,
     error::{Error, Result},
-    print::{println_quoted, prompt, question_theme},
+    print::{self, println_cmd, prompt, question_theme},
 };
 
 /// Different ways in which a [`Cmd`] shall be dealt with.
@@ -67,26 +86,104 @@ pub enum Mode {
     Prompt,
 }
 
+impl Display for Mode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::PrintCmd => "print only",
+            Self::Mute => "muted, capturing stdout and stderr",
+            Self::CheckAll { .. } => "capturing stdout and stderr",
+            Self::CheckErr { .. } => "capturing stderr",
+            Self::Prompt => "prompted, capturing stderr",
+        })
+    }
+}
+
 /// The status code type returned by a [`Cmd`],
 pub type StatusCode = i32;
 
 /// Returns a [`Result`] for a [`Cmd`] according to if its exit status code
 /// indicates an error.
 ///
+/// The rendered `cmd` and the `mode` it has been run in are kept in the error
+/// for the sake of debugging.
+///
 /// # Errors
 /// This function might return one of the following errors:
 ///
 /// - [`Error::CmdStatusCodeError`], when `status` is `Some(n)` where `n != 0`.
 /// - [`Error::CmdInterruptedError`], when `status` is `None`.
 #[allow(clippy::missing_const_for_fn)]
-fn exit_result(code: Option<StatusCode>, output: Output) -> Result<Output> {
+fn exit_result(
+    code: Option<StatusCode>,
+    output: Output,
+    cmd: String,
+    mode: Mode,
+) -> Result<Output> {
     match code {
         Some(0) => Ok(output),
-        Some(code) => Err(Error::CmdStatusCodeError { code, output }),
+        Some(code) => Err(Error::CmdStatusCodeError {
+            code,
+            output,
+            cmd,
+            mode,
+        }),
         None => Err(Error::CmdInterruptedError),
     }
 }
 
+/// Statistics of the [`Cmd`]s executed so far.
+#[derive(Copy, Clone, Default, Debug, PartialEq, Eq)]
+pub struct ExecStats {
+    /// The number of commands executed.
+    pub cmds: usize,
+
+    /// The total time spent on executing the commands.
+    pub elapsed: Duration,
+}
+
+/// The [`ExecStats`] of the current process.
+static EXEC_STATS: Mutex<ExecStats> = Mutex::new(ExecStats {
+    cmds: 0,
+    elapsed: Duration::ZERO,
+});
+
+tokio::task_local! {
+    /// Set within [`unmeasured`].
+    static UNMEASURED: ();
+}
+
+/// Returns the [`ExecStats`] of the [`Cmd`]s executed so far.
+#[must_use]
+pub fn exec_stats() -> ExecStats {
+    *EXEC_STATS.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Runs `fut` without measuring the [`Cmd`]s it executes.
+///
+/// They are left out of the [`ExecStats`] and of the downloads measured with
+/// `--measure-network`, e.g. for the bookkeeping done by [`pacaptr`](crate) on
+/// its own.
+pub async fn unmeasured<F: Future>(fut: F) -> F::Output {
+    UNMEASURED.scope((), fut).await
+}
+
+/// Checks if the current task is within [`unmeasured`].
+#[must_use]
+pub fn is_unmeasured() -> bool {
+    UNMEASURED.try_with(|()| ()).is_ok()
+}
+
+/// Records a [`Cmd`] that has been running for `elapsed` in [`EXEC_STATS`],
+/// unless within [`unmeasured`].
+fn record_exec(elapsed: Duration) {
+    if is_unmeasured() {
+        return;
+    }
+    let mut stats = EXEC_STATS.lock().unwrap_or_else(PoisonError::into_inner);
+    stats.cmds += 1;
+    stats.elapsed += elapsed;
+}
+
 /// The type for captured `stdout`, and if set to [`Mode::CheckAll`], mixed with
 /// captured `stderr`.
 pub type Output = Vec<u8>;
@@ -107,6 +204,10 @@ pub struct Cmd {
 
     /// The "keywords" part of the command string, e.g. `curl fish`.
     pub kws: Vec<String>,
+
+    /// The extra environment variables of this command, which take
+    /// precedence over the ones set with [`set_env_vars`].
+    pub env: Vec<(String, String)>,
 }
 
 impl Cmd {
@@ -136,6 +237,18 @@ impl Cmd {
         self
     }
 
+    /// Adds an environment variable to [`env`](field@Cmd::env).
+    pub(crate) fn env(mut self, key: impl Into<String>, val: impl Into<String>) -> Self {
+        self.env.push((key.into(), val.into()));
+        self
+    }
+
+    /// Runs this command in the `C` locale, so that its output is in English
+    /// and stable enough to be parsed.
+    pub(crate) fn c_locale(self) -> Self {
+        self.env("LC_ALL", "C").env("LANG", "C")
+    }
+
     /// Overrides the value of [`sudo`](field@Cmd::sudo).
     pub(crate) const fn sudo(mut self, sudo: bool) -> Self {
         self.sudo = sudo;
@@ -147,23 +260,69 @@ impl Cmd {
     /// If a **normal admin** needs to run it with `sudo`, and we are not
     /// `root`, then this is the case.
     #[must_use]
-    fn should_sudo(&self) -> bool {
-        self.sudo && !is_root()
+    pub(crate) fn should_sudo(&self) -> bool {
+        // `sudo` is usually not installed in containers.
+        self.sudo && !is_root() && (!CONTAINER_MODE.load(Ordering::Relaxed) || is_exe("sudo", ""))
+    }
+
+    /// Prints out this command after the given prompt, with the words quoted
+    /// by [`shell_quote`].
+    ///
+    /// See [`println_cmd`] for more info.
+    pub(crate) fn echo(&self, prompt: impl Display) {
+        let should_sudo = if print::is_capturing_echoes() {
+            self.sudo
+        } else {
+            self.should_sudo()
+        };
+        let sudo = should_sudo.then_some(["sudo", "-S"]);
+        let head = chain!(
+            sudo.iter().flatten().copied(),
+            self.cmd.iter().map(String::as_str),
+            self.flags.iter().map(String::as_str),
+        )
+        .map(shell_quote);
+        let kws = self.kws.iter().map(|kw| shell_quote(kw)).collect_vec();
+        let user = should_sudo.then_some("root");
+        println_cmd(prompt, user, head, &kws);
     }
 
     /// Converts a [`Cmd`] object into an [`Exec`].
     #[must_use]
     fn build(self) -> Exec {
+        let vars = ENV_VARS
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone();
+        let sudo = self.should_sudo();
+        self.build_with(&vars, sudo)
+    }
+
+    /// Converts a [`Cmd`] object into an [`Exec`], setting the given
+    /// environment variables under the ones of this command, and running it
+    /// with `sudo` if `sudo` is set (see [`Cmd::should_sudo`]).
+    #[must_use]
+    fn build_with(self, vars: &[(String, String)], sudo: bool) -> Exec {
+        // The variables of this command are set last, so that they take precedence.
+        let env = vars
+            .iter()
+            .chain(&self.env)
+            .map(|(key, val)| (key.clone(), val.clone()))
+            .collect_vec();
         // ! Special fix for `zypper`: `zypper install -y curl` is accepted,
         // ! but not `zypper install curl -y`.
         // ! So we place the flags first, and then keywords.
-        if self.should_sudo() {
+        if sudo {
             Exec::new("sudo").tap_mut(|builder| {
-                builder
-                    .arg("-S")
-                    .args(&self.cmd)
-                    .args(&self.flags)
-                    .args(&self.kws);
+                builder.arg("-S");
+                // `sudo` resets the environment, so the variables are passed with `env`
+                // instead.
+                if !env.is_empty() {
+                    builder
+                        .arg("env")
+                        .args(env.iter().map(|(key, val)| format!("{key}={val}")));
+                }
+                builder.args(&self.cmd).args(&self.flags).args(&self.kws);
             })
         } else {
             let (cmd, subcmd) = self
@@ -171,7 +330,11 @@ impl Cmd {
                 .split_first()
                 .expect("failed to build Cmd, command is empty");
             Exec::new(cmd).tap_mut(|builder| {
-                builder.args(subcmd).args(&self.flags).args(&self.kws);
+                builder
+                    .args(subcmd)
+                    .args(&self.flags)
+                    .args(&self.kws)
+                    .envs(env);
             })
         }
     }
@@ -226,33 +389,49 @@ impl Cmd {
     /// The exact behavior depends on the [`Mode`] passed in (see the definition
     /// of [`Mode`] for more info).
     #[doc = docs_errors_exec!()]
-    pub(crate) async fn exec(self, mode: Mode) -> Result<Output> {
+    pub(crate) async fn exec(mut self, mode: Mode) -> Result<Output> {
+        // The output of a muted command is never shown as is, but parsed instead.
+        if matches!(mode, Mode::Mute) || FORCE_C_LOCALE.load(Ordering::Relaxed) {
+            self = self.c_locale();
+        }
         match mode {
             Mode::PrintCmd => {
-                println_quoted(&*prompt::CANCELED, &self);
+                self.echo(&*prompt::CANCELED);
                 Ok(Output::default())
             }
-            Mode::Mute => self.exec_checkall(true).await,
+            Mode::Mute => self.exec_checkall(mode).await,
             Mode::CheckAll { quiet } => {
                 if !quiet {
-                    println_quoted(&*prompt::RUNNING, &self);
+                    self.echo(&*prompt::RUNNING);
                 }
-                self.exec_checkall(false).await
+                self.exec_checkall(mode).await
             }
             Mode::CheckErr { quiet } => {
                 if !quiet {
-                    println_quoted(&*prompt::RUNNING, &self);
+                    self.echo(&*prompt::RUNNING);
                 }
-                self.exec_checkerr(false).await
+                self.exec_checkerr(mode).await
             }
-            Mode::Prompt => self.exec_prompt(false).await,
+            Mode::Prompt => self.exec_prompt(None).await,
         }
     }
 
+    /// Executes a [`Cmd`] in [`Mode::Prompt`], running the given `preview`
+    /// command first to show its output right above the prompt.
+    #[doc = docs_errors_exec!()]
+    pub(crate) async fn exec_prompt_with_preview(self, preview: Self) -> Result<Output> {
+        self.exec_prompt(Some(preview)).await
+    }
+
     /// Inner implementation of [`Cmd::exec_checkerr`] (if `merge` is `false`)
     /// and [`Cmd::exec_checkall`] (otherwise).
+    ///
+    /// The output is printed out as well with the
+    /// [`OutputSink`](context::OutputSink) of the current
+    /// [`Context`](context::Context), unless in [`Mode::Mute`], and is
+    /// stripped of ANSI escape sequences with `--strip-ansi`.
     #[doc = docs_errors_exec!()]
-    async fn exec_check_output(self, mute: bool, merge: bool) -> Result<Output> {
+    async fn exec_check_output(self, mode: Mode, merge: bool) -> Result<Output> {
         use tokio_stream::StreamExt;
         use Error::{CmdJoinError, CmdNoHandleError, CmdSpawnError, CmdWaitError};
 
@@ -265,123 +444,465 @@ impl Cmd {
             })
         }
 
+        fn strip_reader(
+            reader: impl Stream<Item = io::Result<Bytes>>,
+        ) -> impl Stream<Item = io::Result<Bytes>> {
+            if STRIP_ANSI.load(Ordering::Relaxed) {
+                let mut stripper = AnsiStripper::default();
+                (reader.map_ok(move |chunk| Bytes::from(stripper.strip(&chunk)))).left_stream()
+            } else {
+                reader.right_stream()
+            }
+        }
+
+        #[cfg(any(test, feature = "mock"))]
+        if let Some((output, code)) = crate::pm::mock::intercept(&self) {
+            return exit_result(Some(code), output, self.to_string(), mode);
+        }
+
+        let ctx = context::current();
+        let mute = matches!(mode, Mode::Mute);
+        // Outside of the console, the `stdout` cannot be inherited, so it is
+        // forwarded to the output sink as well.
+        let forward_stdout = !merge && !mute && !ctx.output().is_console();
+        let rendered = self.to_string();
         let mut child = self
             .build()
             .stderr(Stdio::piped())
             .tap_deref_mut(|cmd| {
-                if merge {
+                if merge || forward_stdout {
                     cmd.stdout(Stdio::piped());
                 }
             })
             .spawn()
             .map_err(CmdSpawnError)?;
+        let start = Instant::now();
 
         let stderr_reader = make_reader(child.stderr.take(), "stderr")?;
-        let mut reader = if merge {
+        let (reader, stdout_reader) = if merge {
             let stdout_reader = make_reader(child.stdout.take(), "stdout")?;
-            StreamExt::merge(stdout_reader, stderr_reader).left_stream()
+            (
+                StreamExt::merge(stdout_reader, stderr_reader).left_stream(),
+                None,
+            )
+        } else if forward_stdout {
+            let stdout_reader = make_reader(child.stdout.take(), "stdout")?;
+            (
+                stderr_reader.right_stream(),
+                Some(strip_reader(stdout_reader)),
+            )
         } else {
-            stderr_reader.right_stream()
+            (stderr_reader.right_stream(), None)
         };
+        let mut reader = strip_reader(reader);
 
-        let mut out = if merge {
-            Either::Left(io::stdout())
+        let mut out = ctx.writer(if merge {
+            context::Stream::Stdout
         } else {
-            Either::Right(io::stderr())
-        };
+            context::Stream::Stderr
+        });
 
         let code: JoinHandle<Result<Option<i32>>> = tokio::spawn(async move {
-            let status = child.wait().await.map_err(CmdWaitError)?;
-            Ok(status.code())
+            let status = child.wait().await;
+            record_exec(start.elapsed());
+            Ok(status.map_err(CmdWaitError)?.code())
         });
 
-        let output = exec_tee(&mut reader, (!mute).then_some(&mut out)).await?;
+        let forwarded = async {
+            match stdout_reader {
+                Some(reader) => {
+                    let out = ctx.writer(context::Stream::Stdout);
+                    exec_tee(reader, Some(out)).await.map(drop)
+                }
+                None => Ok(()),
+            }
+        };
+        let (output, forwarded) = tokio::join!(
+            exec_tee(&mut reader, (!mute).then_some(&mut out)),
+            forwarded,
+        );
+        let (output, ()) = (output?, forwarded?);
         let code = code.await.map_err(CmdJoinError)??;
-        exit_result(code, output)
+        exit_result(code, output, rendered, mode)
     }
 
     /// Executes a [`Cmd`] and returns its `stdout` and `stderr`.
     ///
-    /// If `mute` is `false`, then normal `stdout/stderr` output will be printed
+    /// Unless in [`Mode::Mute`], normal `stdout/stderr` output will be printed
     /// to `stdout` too.
     #[doc = docs_errors_exec!()]
-    async fn exec_checkall(self, mute: bool) -> Result<Output> {
-        self.exec_check_output(mute, true).await
+    async fn exec_checkall(self, mode: Mode) -> Result<Output> {
+        self.exec_check_output(mode, true).await
     }
 
     /// Executes a [`Cmd`] and collects its `stderr`.
     ///
-    /// If `mute` is `false`, then its `stderr` output will be printed to
+    /// Unless in [`Mode::Mute`], its `stderr` output will be printed to
     /// `stderr` too.
     #[doc = docs_errors_exec!()]
-    async fn exec_checkerr(self, mute: bool) -> Result<Output> {
-        self.exec_check_output(mute, false).await
+    async fn exec_checkerr(self, mode: Mode) -> Result<Output> {
+        self.exec_check_output(mode, false).await
     }
 
-    /// Executes a [`Cmd`] and collects its `stderr`.
-    ///
-    /// If `mute` is `false`, then its `stderr` output will be printed to
+    /// Executes a [`Cmd`] and collects its `stderr`, which is printed to
     /// `stderr` too.
     ///
     /// This function behaves just like [`exec_checkerr`](Cmd::exec_checkerr),
     /// but in addition, the user will be prompted if (s)he wishes to
     /// continue with the command execution.
+    ///
+    /// If a `preview` command is given, it will be run before the prompt, so
+    /// that its output (e.g. the package manager's own plan) is shown right
+    /// above the question.
     #[doc = docs_errors_exec!()]
-    async fn exec_prompt(self, mute: bool) -> Result<Output> {
-        /// If the user has skipped all the prompts with `yes`.
-        static ALL: AtomicBool = AtomicBool::new(false);
-
-        // The answer obtained from the prompt.
-        // The only Atomic* we're dealing with is `ALL`, so `Ordering::Relaxed` is fine.
-        // See: <https://marabos.nl/atomics/memory-ordering.html#relaxed>
-        let proceed = ALL.load(Ordering::Relaxed) || {
-            println_quoted(&*prompt::PENDING, &self);
-            let answer = tokio::task::block_in_place(move || {
-                prompt(
-                    "Proceed",
-                    "with the previous command?",
-                    &["Yes", "All", "No"],
-                )
-            })?;
-            match answer {
-                // The default answer is `Yes`.
-                0 => true,
-                // You can also say `All` to answer `Yes` to all the other questions that follow.
-                1 => {
-                    ALL.store(true, Ordering::Relaxed);
-                    true
-                }
-                // Or you can say `No`.
-                2 => false,
-                // ! I didn't put a `None` option because you can just Ctrl-C it if you want.
-                _ => unreachable!(),
+    async fn exec_prompt(self, preview: Option<Self>) -> Result<Output> {
+        let proceed = context::current().prompt_all() || {
+            if let Some(preview) = preview {
+                preview.echo(&*prompt::PREVIEW);
+                preview
+                    .exec_checkall(Mode::CheckAll { quiet: true })
+                    .await?;
             }
+            self.echo(&*prompt::PENDING);
+            ask_proceed("with the previous command?")?
         };
         if !proceed {
             return Ok(Output::default());
         }
-        println_quoted(&*prompt::RUNNING, &self);
-        self.exec_checkerr(mute).await
+        self.echo(&*prompt::RUNNING);
+        self.exec_checkerr(Mode::Prompt).await
     }
 }
 
-impl std::fmt::Display for Cmd {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl Cmd {
+    /// Executes a [`Cmd`] silently, calling `f` on each line of its `stdout`
+    /// as soon as it is complete, instead of collecting the whole output.
+    ///
+    /// Only the `stderr` is collected, to be reported if the command fails.
+    #[doc = docs_errors_exec!()]
+    pub(crate) async fn exec_lines(self, mut f: impl FnMut(&str) + Send) -> Result<()> {
+        use Error::{CmdJoinError, CmdNoHandleError, CmdSpawnError, CmdWaitError};
+
+        // The output is parsed, just like in `Mode::Mute`.
+        let cmd = self.c_locale();
+        let rendered = cmd.to_string();
+
+        #[cfg(any(test, feature = "mock"))]
+        if let Some((output, code)) = crate::pm::mock::intercept(&cmd) {
+            let mut decoder = LineDecoder::default();
+            decoder
+                .push(&output)
+                .iter()
+                .chain(&decoder.finish())
+                .for_each(|ln| f(ln));
+            return exit_result(Some(code), Output::new(), rendered, Mode::Mute).map(drop);
+        }
+
+        let mut child = cmd
+            .build()
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(CmdSpawnError)?;
+        let start = Instant::now();
+        let no_handle = |handle: &str| CmdNoHandleError {
+            handle: handle.into(),
+        };
+        let mut stdout = into_bytes(child.stdout.take().ok_or_else(|| no_handle("stdout"))?);
+        let stderr = into_bytes(child.stderr.take().ok_or_else(|| no_handle("stderr"))?);
+        // The `stderr` is drained alongside, so that the command never blocks on it.
+        let stderr: JoinHandle<Result<Output>> = tokio::spawn(exec_tee(stderr, None::<io::Sink>));
+
+        let mut decoder = LineDecoder::default();
+        while let Some(chunk) = stdout.try_next().await? {
+            decoder.push(&chunk).iter().for_each(|ln| f(ln));
+        }
+        decoder.finish().iter().for_each(|ln| f(ln));
+
+        let status = child.wait().await.map_err(CmdWaitError)?;
+        record_exec(start.elapsed());
+        let output = stderr.await.map_err(CmdJoinError)??;
+        exit_result(status.code(), output, rendered, Mode::Mute).map(drop)
+    }
+}
+
+impl Display for Cmd {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let sudo: &str = if self.should_sudo() { "sudo -S " } else { "" };
-        let cmd = chain!(&self.cmd, &self.flags, &self.kws).join(" ");
+        let cmd = chain!(&self.cmd, &self.flags, &self.kws)
+            .map(|word| shell_quote(word))
+            .join(" ");
         write!(f, "{sudo}{cmd}")
     }
 }
 
-/// Gives a prompt and returns the index of the user choice.
-fn prompt(prompt: &str, question: &str, expected: &[&str]) -> Result<usize> {
+/// Quotes `word` for POSIX shells if it contains any special character, so
+/// that the commands printed out can be copied and run as is.
+#[must_use]
+pub fn shell_quote(word: &str) -> Cow<'_, str> {
+    let plain = |c: char| c.is_ascii_alphanumeric() || "-_./:=@%+,^~".contains(c);
+    // A leading `~` would be subject to tilde expansion.
+    if !word.is_empty() && !word.starts_with('~') && word.chars().all(plain) {
+        return word.into();
+    }
+    format!("'{}'", word.replace('\'', r"'\''")).into()
+}
+
+/// Whether an empty answer to [`ask_proceed`] means `No` instead of `Yes`.
+///
+/// This is a standalone flag, so `Ordering::Relaxed` is fine.
+static PROMPT_DEFAULT_NO: AtomicBool = AtomicBool::new(false);
+
+/// Sets the answer selected by default in the confirmation prompts, see
+/// [`Config::prompt_default`](crate::config::Config::prompt_default).
+pub fn set_prompt_default(default: PromptDefault) {
+    PROMPT_DEFAULT_NO.store(default == PromptDefault::No, Ordering::Relaxed);
+}
+
+/// Checks if the user has declined (at least) one of the prompts so far in
+/// the current [`Context`](context::Context).
+#[must_use]
+pub fn prompt_declined() -> bool {
+    context::current().prompt_declined()
+}
+
+/// Asks the user if (s)he wishes to proceed with the `question` with the
+/// [`PromptHandler`](context::PromptHandler) of the current
+/// [`Context`](context::Context), and returns the answer.
+fn ask_proceed(question: &str) -> Result<bool> {
+    let ctx = context::current();
+    // The default answer is `Yes`, unless set otherwise with `--prompt-default`.
+    let default = if PROMPT_DEFAULT_NO.load(Ordering::Relaxed) {
+        2
+    } else {
+        0
+    };
+    let answer = tokio::task::block_in_place(|| {
+        (ctx.prompt_handler()).prompt("Proceed", question, &["Yes", "All", "No"], default)
+    })?;
+    Ok(match answer {
+        0 => true,
+        // You can also say `All` to answer `Yes` to all the other questions that follow.
+        1 => {
+            ctx.set_prompt_all();
+            true
+        }
+        // Or you can say `No`.
+        2 => {
+            ctx.set_prompt_declined();
+            false
+        }
+        // ! I didn't put a `None` option because you can just Ctrl-C it if you want.
+        _ => unreachable!(),
+    })
+}
+
+/// Prints out the given [`Cmd`]s, then asks the user for one confirmation
+/// before running all of them.
+///
+/// Returns `true` if the user wishes to proceed.
+///
+/// # Errors
+/// Returns an [`Error::DialogError`] if the prompt fails.
+pub(crate) fn confirm_all(cmds: &[Cmd]) -> Result<bool> {
+    if context::current().prompt_all() {
+        return Ok(true);
+    }
+    for cmd in cmds {
+        cmd.echo(&*prompt::PENDING);
+    }
+    ask_proceed(if cmds.len() == 1 {
+        "with the previous command?"
+    } else {
+        "with the previous commands?"
+    })
+}
+
+/// Asks for the `sudo` password (if necessary) upfront, so that the following
+/// [`Cmd`]s run with `sudo` can share the cached credentials.
+#[doc = docs_errors_exec!()]
+pub(crate) async fn validate_sudo() -> Result<()> {
+    Cmd::new(["sudo", "-S", "-v"])
+        .exec(Mode::CheckErr { quiet: true })
+        .await
+        .map(drop)
+}
+
+/// Gives a prompt and returns the index of the user choice, where the
+/// `default` one is preselected.
+///
+/// Without a terminal (e.g. when the answers are piped in), see
+/// [`prompt_line`] instead.
+fn prompt(prompt: &str, question: &str, expected: &[&str], default: usize) -> Result<usize> {
+    if !(std::io::stdin().is_terminal() && std::io::stderr().is_terminal()) {
+        return prompt_line(prompt, question, expected, default);
+    }
     Ok(FuzzySelect::with_theme(&question_theme(prompt))
         .with_prompt(question)
         .items(expected)
-        .default(0)
+        .default(default)
+        .interact()?)
+}
+
+/// Gives a prompt without a terminal, reading the answer as a line from
+/// `stdin`, and returns the index of the user choice.
+///
+/// The expected answers are listed after the question, where only the
+/// `default` one is capitalized, e.g. `[yes/all/No]`. Any prefix of an
+/// expected answer selects it regardless of case (e.g. `y` for `Yes`), and an
+/// empty line selects the default one. The question is asked again until a
+/// valid answer is given.
+///
+/// # Errors
+/// Returns an [`Error::DialogError`] if `stdin` is closed before any valid
+/// answer is given.
+fn prompt_line(prompt: &str, question: &str, expected: &[&str], default: usize) -> Result<usize> {
+    let mut rendered = String::new();
+    _ = question_theme(prompt).format_select_prompt(&mut rendered, question);
+    let rendered = rendered.trim_end();
+    let answers = format_answers(expected, default);
+    let (stdin, mut stderr) = (std::io::stdin(), std::io::stderr());
+    loop {
+        _ = write!(stderr, "{rendered} [{answers}] ");
+        _ = stderr.flush();
+        let mut answer = String::new();
+        if stdin.read_line(&mut answer)? == 0 {
+            _ = writeln!(stderr);
+            let eof = std::io::Error::new(
+                std::io::ErrorKind::UnexpectedEof,
+                "stdin closed before answering the prompt",
+            );
+            return Err(dialoguer::Error::IO(eof).into());
+        }
+        // The answer is not echoed without a terminal.
+        _ = writeln!(stderr, "{}", answer.trim());
+        if let Some(i) = match_answer(&answer, expected, default) {
+            return Ok(i);
+        }
+    }
+}
+
+/// Formats the capitalized `expected` answers of [`prompt_line`], where all
+/// but the `default` one are put in lowercase.
+fn format_answers(expected: &[&str], default: usize) -> String {
+    (expected.iter().enumerate())
+        .map(|(i, &it)| {
+            if i == default {
+                it.into()
+            } else {
+                it.to_lowercase()
+            }
+        })
+        .join("/")
+}
+
+/// Returns the index of the `expected` answer selected by the line `answer`,
+/// as done in [`prompt_line`].
+fn match_answer(answer: &str, expected: &[&str], default: usize) -> Option<usize> {
+    let answer = answer.trim().to_lowercase();
+    if answer.is_empty() {
+        return Some(default);
+    }
+    (expected.iter()).position(|it| it.to_lowercase().starts_with(&answer))
+}
+
+/// Asks the user to choose among the `items` with the
+/// [`PromptHandler`](context::PromptHandler) of the current
+/// [`Context`](context::Context), and returns the indices of the chosen ones.
+///
+/// # Errors
+/// Returns an [`Error::DialogError`] if the prompt fails.
+pub(crate) fn ask_select(question: &str, items: &[String]) -> Result<Vec<usize>> {
+    let ctx = context::current();
+    tokio::task::block_in_place(|| (ctx.prompt_handler()).select("Select", question, items))
+}
+
+/// Gives a multi-selection prompt and returns the indices of the user choices,
+/// where none of the `items` is preselected.
+///
+/// Without a terminal (e.g. when the answers are piped in), see
+/// [`select_line`] instead.
+fn select(prompt: &str, question: &str, items: &[String]) -> Result<Vec<usize>> {
+    if !(std::io::stdin().is_terminal() && std::io::stderr().is_terminal()) {
+        return select_line(prompt, question, items);
+    }
+    Ok(MultiSelect::with_theme(&question_theme(prompt))
+        .with_prompt(question)
+        .items(items)
         .interact()?)
 }
 
+/// Gives a multi-selection prompt without a terminal, listing the numbered
+/// `items` before reading the answer as a line from `stdin`, and returns the
+/// indices of the user choices.
+///
+/// See [`parse_selection`] for the accepted answers. The question is asked
+/// again until a valid answer is given.
+///
+/// # Errors
+/// Returns an [`Error::DialogError`] if `stdin` is closed before any valid
+/// answer is given.
+fn select_line(prompt: &str, question: &str, items: &[String]) -> Result<Vec<usize>> {
+    let mut rendered = String::new();
+    _ = question_theme(prompt).format_select_prompt(&mut rendered, question);
+    let rendered = rendered.trim_end();
+    let (stdin, mut stderr) = (std::io::stdin(), std::io::stderr());
+    for (i, item) in items.iter().enumerate() {
+        _ = writeln!(stderr, "{:>4} {item}", i + 1);
+    }
+    loop {
+        _ = write!(stderr, "{rendered} [e.g. 1 3 5-7, all/None] ");
+        _ = stderr.flush();
+        let mut answer = String::new();
+        if stdin.read_line(&mut answer)? == 0 {
+            _ = writeln!(stderr);
+            let eof = std::io::Error::new(
+                std::io::ErrorKind::UnexpectedEof,
+                "stdin closed before answering the prompt",
+            );
+            return Err(dialoguer::Error::IO(eof).into());
+        }
+        // The answer is not echoed without a terminal.
+        _ = writeln!(stderr, "{}", answer.trim());
+        if let Some(chosen) = parse_selection(&answer, items.len()) {
+            return Ok(chosen);
+        }
+    }
+}
+
+/// Parses the line `answer` choosing among `len` items into the sorted
+/// indices of the chosen ones, or returns `None` if it is invalid.
+///
+/// The items are numbered from `1`, and can be chosen one by one or as an
+/// inclusive range, separated by spaces or commas, e.g. `1 3 5-7`. `all`
+/// chooses all of them, while `none` or an empty line chooses none of them.
+fn parse_selection(answer: &str, len: usize) -> Option<Vec<usize>> {
+    let answer = answer.trim().to_lowercase();
+    match answer.as_str() {
+        "" | "n" | "none" => return Some(vec![]),
+        "a" | "all" => return Some((0..len).collect()),
+        _ => (),
+    }
+    let index = |it: &str| {
+        it.trim()
+            .parse::<usize>()
+            .ok()
+            .filter(|&i| (1..=len).contains(&i))
+    };
+    let mut chosen = vec![];
+    for token in answer.split([' ', ',']).filter(|it| !it.is_empty()) {
+        let (start, end) = match token.split_once('-') {
+            Some((start, end)) => (index(start)?, index(end)?),
+            None => (index(token)?, index(token)?),
+        };
+        if start > end {
+            return None;
+        }
+        chosen.extend(start - 1..end);
+    }
+    Some(chosen.into_iter().sorted_unstable().dedup().collect())
+}
+
 macro_rules! docs_errors_grep {
     () => {
         indoc! {"
@@ -398,16 +919,78 @@ macro_rules! docs_errors_grep {
 /// An error message will be returned if this is not the case.
 #[doc = docs_errors_grep!()]
 pub fn grep<'t>(text: &'t str, patterns: &[&str]) -> Result<Vec<&'t str>> {
-    let patterns: RegexSet = RegexSetBuilder::new(patterns)
-        .case_insensitive(true)
-        .build()
-        .map_err(|e| Error::OtherError(format!("ill-formed patterns found: {e:?}")))?;
+    let patterns = grep_patterns(patterns)?;
     Ok(text
         .lines()
         .filter(|line| patterns.matches(line).into_iter().count() == patterns.len())
         .collect())
 }
 
+/// Compiles the case-insensitive regex `patterns` of [`grep`], which a line
+/// should match all of.
+#[doc = docs_errors_grep!()]
+pub fn grep_patterns(patterns: &[&str]) -> Result<RegexSet> {
+    RegexSetBuilder::new(patterns)
+        .case_insensitive(true)
+        .build()
+        .map_err(|e| Error::OtherError(format!("ill-formed patterns found: {e:?}")))
+}
+
+/// Checks if `pattern` is a glob pattern, i.e. if it contains any of the
+/// wildcards `*`, `?` or `[`.
+#[must_use]
+pub fn is_glob(pattern: &str) -> bool {
+    pattern.contains(['*', '?', '['])
+}
+
+/// Converts the glob `pattern` into an anchored regex.
+///
+/// `*` matches any string, `?` matches any character, and `[..]` (or `[!..]`)
+/// matches any character (not) in the brackets. Everything else is taken
+/// literally.
+#[must_use]
+pub fn glob_to_regex(pattern: &str) -> String {
+    let mut res = String::from("^");
+    let mut rest = pattern;
+    while let Some(c) = rest.chars().next() {
+        rest = &rest[c.len_utf8()..];
+        match c {
+            '*' => res.push_str(".*"),
+            '?' => res.push('.'),
+            '[' if rest.contains(']') => {
+                let (class, after) = rest.split_once(']').unwrap_or_default();
+                rest = after;
+                res.push('[');
+                let class = class.strip_prefix(['!', '^']).map_or(class, |class| {
+                    res.push('^');
+                    class
+                });
+                for c in class.chars() {
+                    if matches!(c, '\\' | '[' | '&' | '~') {
+                        res.push('\\');
+                    }
+                    res.push(c);
+                }
+                res.push(']');
+            }
+            c => res.push_str(&regex::escape(c.encode_utf8(&mut [0; 4]))),
+        }
+    }
+    res.push('$');
+    res
+}
+
+/// Compiles the glob `patterns` into a case-insensitive [`RegexSet`] with
+/// [`glob_to_regex`], which a name should match any of.
+#[doc = docs_errors_grep!()]
+pub fn glob_patterns(patterns: &[impl AsRef<str>]) -> Result<RegexSet> {
+    let patterns = patterns.iter().map(|pat| glob_to_regex(pat.as_ref()));
+    RegexSetBuilder::new(patterns)
+        .case_insensitive(true)
+        .build()
+        .map_err(|e| Error::OtherError(format!("ill-formed patterns found: {e:?}")))
+}
+
 /// Prints the result of [`grep`] line by line.
 #[doc = docs_errors_grep!()]
 pub fn grep_print(text: &str, patterns: &[&str]) -> Result<()> {
@@ -421,21 +1004,234 @@ pub fn grep_print(text: &str, patterns: &[&str]) -> Result<()> {
 #[doc = docs_errors_grep!()]
 pub fn grep_print_with_header(text: &str, patterns: &[&str], header_lines: usize) -> Result<()> {
     let lns = text.lines().collect_vec();
-    let (header, rest) = lns.split_at(header_lines);
+    let (header, rest) = lns.split_at(header_lines.min(lns.len()));
     header
         .iter()
         .copied()
         .chain(grep(&rest.join("\n"), patterns)?)
-        .for_each(|ln| println!("{ln}"));
+        .for_each(|ln| print::print_raw(format_args!("{ln}\n")));
     Ok(())
 }
 
+/// Whether some output has been decoded with invalid UTF-8 bytes replaced.
+static LOSSY_DECODED: AtomicBool = AtomicBool::new(false);
+
+/// Decodes the output of a command as UTF-8, replacing the invalid bytes with
+/// `U+FFFD`.
+///
+/// Such outputs are common with localized messages or odd package
+/// descriptions, so this never fails, but a warning is printed out the first
+/// time it happens.
+/// ANSI escape sequences, e.g. the color codes emitted by some package
+/// managers even when their output is piped, are stripped beforehand, so that
+/// they never get in the way of [`grep`] or the parsers.
+#[must_use]
+pub fn decode(out: &[u8]) -> Cow<'_, str> {
+    if out.contains(&ESC) {
+        return Cow::Owned(decode(&strip_ansi(out)).into_owned());
+    }
+    let text = String::from_utf8_lossy(out);
+    if matches!(text, Cow::Owned(_)) && !LOSSY_DECODED.swap(true, Ordering::Relaxed) {
+        print::println(
+            &*prompt::WARNING,
+            "the output contains invalid UTF-8, which has been replaced with `\u{FFFD}`",
+        );
+    }
+    text
+}
+
+/// The escape character starting an ANSI escape sequence.
+const ESC: u8 = 0x1B;
+
+/// The bell character, which might end an OSC sequence.
+const BEL: u8 = 0x07;
+
+/// The state of an [`AnsiStripper`] between two chunks of output.
+#[derive(Copy, Clone, Default, Debug, PartialEq, Eq)]
+enum AnsiState {
+    /// Outside of any escape sequence.
+    #[default]
+    Text,
+
+    /// Right after an `ESC`.
+    Esc,
+
+    /// In the intermediate bytes of a two-character sequence, e.g. `ESC ( B`.
+    EscIntermediate,
+
+    /// In a CSI sequence, e.g. `ESC [ 1 ; 31 m`.
+    Csi,
+
+    /// In a string sequence (OSC, DCS, etc.), which is ended by `BEL` or
+    /// `ESC \`.
+    Str,
+
+    /// Right after an `ESC` in a string sequence.
+    StrEsc,
+}
+
+/// Strips the ANSI escape sequences from a stream of output, which might be
+/// split in the middle of a sequence.
+#[derive(Copy, Clone, Default, Debug)]
+pub struct AnsiStripper {
+    state: AnsiState,
+}
+
+impl AnsiStripper {
+    /// Returns the given `chunk` of output with the ANSI escape sequences
+    /// removed, carrying any unfinished sequence over to the next chunk.
+    pub fn strip(&mut self, chunk: &[u8]) -> Vec<u8> {
+        use AnsiState::{Csi, Esc, EscIntermediate, Str, StrEsc, Text};
+        let mut res = Vec::with_capacity(chunk.len());
+        for &b in chunk {
+            self.state = match (self.state, b) {
+                (Str | StrEsc, ESC) => StrEsc,
+                (Str, BEL) | (StrEsc, b'\\') => Text,
+                (Str | StrEsc, _) => Str,
+                (_, ESC) => Esc,
+                (Text, _) => {
+                    res.push(b);
+                    Text
+                }
+                (Esc, b'[') | (Csi, 0x20..=0x3F) => Csi,
+                (Esc, b']' | b'P' | b'X' | b'^' | b'_') => Str,
+                (Esc | EscIntermediate, 0x20..=0x2F) => EscIntermediate,
+                // The final byte of the sequence.
+                (Esc | EscIntermediate | Csi, _) => Text,
+            };
+        }
+        res
+    }
+}
+
+/// Splits a stream of output into lines, which might be split across the
+/// chunks of the stream.
+#[derive(Clone, Default, Debug)]
+pub struct LineDecoder {
+    /// The unfinished line carried over from the previous chunks.
+    partial: Vec<u8>,
+}
+
+impl LineDecoder {
+    /// Returns the lines completed by the given `chunk` of output, decoded
+    /// with [`decode`] and without their line endings, carrying the unfinished
+    /// one over to the next chunk.
+    pub fn push(&mut self, chunk: &[u8]) -> Vec<String> {
+        let mut lines = vec![];
+        let mut rest = chunk;
+        while let Some(i) = rest.iter().position(|&b| b == b'\n') {
+            self.partial.extend_from_slice(&rest[..i]);
+            lines.push(self.take());
+            rest = &rest[i + 1..];
+        }
+        self.partial.extend_from_slice(rest);
+        lines
+    }
+
+    /// Returns the last line, if the output has not ended with a line ending.
+    #[must_use]
+    pub fn finish(mut self) -> Option<String> {
+        (!self.partial.is_empty()).then(|| self.take())
+    }
+
+    /// Takes the current line out, decoded.
+    fn take(&mut self) -> String {
+        let line = mem::take(&mut self.partial);
+        decode(line.strip_suffix(b"\r").unwrap_or(&line)).into_owned()
+    }
+}
+
+/// Removes the ANSI escape sequences (colors, cursor movements, hyperlinks,
+/// etc.) from the given output.
+#[must_use]
+pub fn strip_ansi(out: &[u8]) -> Vec<u8> {
+    AnsiStripper::default().strip(out)
+}
+
+/// Whether the output of the commands relayed by `pacaptr` is stripped of ANSI
+/// escape sequences.
+///
+/// This is a standalone flag, so `Ordering::Relaxed` is fine.
+static STRIP_ANSI: AtomicBool = AtomicBool::new(false);
+
+/// Sets whether the output of the commands relayed by `pacaptr` is stripped of
+/// ANSI escape sequences, see [`strip_ansi`].
+pub fn set_strip_ansi(strip: bool) {
+    STRIP_ANSI.store(strip, Ordering::Relaxed);
+}
+
 /// Checks if an executable exists by name (consult `$PATH`) or by path.
 ///
 /// To check by one parameter only, pass `""` to the other one.
 #[must_use]
 pub fn is_exe(name: &str, path: &str) -> bool {
-    (!path.is_empty() && which(path).is_ok()) || (!name.is_empty() && which(name).is_ok())
+    find_exe(name, path).is_some()
+}
+
+/// Returns the path of an executable found by path, or else by name (consult
+/// `$PATH`).
+///
+/// To look up by one parameter only, pass `""` to the other one.
+#[must_use]
+pub fn find_exe(name: &str, path: &str) -> Option<PathBuf> {
+    let find = |exe: &str| (!exe.is_empty()).then(|| which(exe).ok()).flatten();
+    find(path).or_else(|| find(name))
+}
+
+/// The environment variables to be set for every [`Cmd`], e.g. the ones loaded
+/// from `--env-file`.
+static ENV_VARS: Mutex<Vec<(String, String)>> = Mutex::new(Vec::new());
+
+/// Sets the environment variables to be set for every [`Cmd`], under the ones
+/// given with [`Cmd::env`].
+pub fn set_env_vars(vars: Vec<(String, String)>) {
+    *ENV_VARS.lock().unwrap_or_else(PoisonError::into_inner) = vars;
+}
+
+/// Whether every command is run in the `C` locale, instead of only the
+/// muted ones whose output is parsed.
+///
+/// This is a standalone flag, so `Ordering::Relaxed` is fine.
+static FORCE_C_LOCALE: AtomicBool = AtomicBool::new(false);
+
+/// Sets whether every command is run in the `C` locale, see [`Cmd::c_locale`].
+pub fn set_force_c_locale(force: bool) {
+    FORCE_C_LOCALE.store(force, Ordering::Relaxed);
+}
+
+/// Whether the commands are run in the container mode.
+///
+/// This is a standalone flag, so `Ordering::Relaxed` is fine.
+static CONTAINER_MODE: AtomicBool = AtomicBool::new(false);
+
+/// Sets whether the commands are run in the container mode, where `sudo` is
+/// only used if it's available.
+///
+/// See [`Config::in_container`](crate::config::Config::in_container) for more
+/// info.
+pub fn set_container_mode(container: bool) {
+    CONTAINER_MODE.store(container, Ordering::Relaxed);
+}
+
+/// Checks if the current process is running in a container (e.g. Docker or
+/// Podman).
+#[must_use]
+pub fn is_container() -> bool {
+    static IS_CONTAINER: LazyLock<bool> = LazyLock::new(|| {
+        ["/.dockerenv", "/run/.containerenv"]
+            .iter()
+            .any(|path| Path::new(path).exists())
+            || env::var_os("container").is_some()
+            || fs::read_to_string("/proc/1/cgroup").is_ok_and(|cgroup| is_container_cgroup(&cgroup))
+    });
+    *IS_CONTAINER
+}
+
+/// Checks if the given content of `/proc/1/cgroup` hints at a container.
+fn is_container_cgroup(cgroup: &str) -> bool {
+    ["docker", "kubepods", "containerd", "libpod", "lxc"]
+        .iter()
+        .any(|hint| cgroup.contains(hint))
 }
 
 /// Checks if the current user is root or admin.
@@ -452,9 +1248,439 @@ pub fn is_root() -> bool {
     nix::unistd::Uid::current().is_root()
 }
 
+/// Returns the disk space available to the current user (in bytes) on the
+/// filesystem containing `path`, or `None` if it cannot be determined.
+#[cfg(unix)]
+#[must_use]
+pub fn free_space(path: &str) -> Option<u64> {
+    let stat = nix::sys::statvfs::statvfs(path).ok()?;
+    #[allow(clippy::useless_conversion)]
+    u64::from(stat.blocks_available()).checked_mul(stat.fragment_size().into())
+}
+
+/// Returns the disk space available to the current user (in bytes) on the
+/// filesystem containing `path`, or `None` if it cannot be determined.
+#[cfg(windows)]
+#[must_use]
+pub const fn free_space(_path: &str) -> Option<u64> {
+    None
+}
+
 /// Turns an [`AsyncRead`] into a [`Stream`].
 ///
 /// _Shamelessly copied from [`StackOverflow`](https://stackoverflow.com/a/59327560)._
 fn into_bytes(reader: impl AsyncRead) -> impl Stream<Item = io::Result<Bytes>> {
     FramedRead::new(reader, BytesCodec::new()).map_ok(BytesMut::freeze)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn container_cgroups() {
+        assert!(is_container_cgroup("12:pids:/docker/3f4a1b2c\n"));
+        assert!(is_container_cgroup("0::/kubepods/besteffort/pod1234\n"));
+        assert!(!is_container_cgroup("0::/init.scope\n"));
+    }
+
+    #[test]
+    fn matched_answers() {
+        let expected = ["Yes", "All", "No"];
+        for (answer, i) in [
+            ("\n", Some(0)),
+            ("y\n", Some(0)),
+            ("  ALL\n", Some(1)),
+            ("n", Some(2)),
+            ("nope", None),
+            ("x", None),
+        ] {
+            assert_eq!(match_answer(answer, &expected, 0), i, "{answer:?}");
+        }
+        assert_eq!(match_answer("\n", &expected, 2), Some(2));
+        assert_eq!(match_answer("y", &expected, 2), Some(0));
+        assert_eq!(format_answers(&expected, 0), "Yes/all/no");
+        assert_eq!(format_answers(&expected, 2), "yes/all/No");
+    }
+
+    #[test]
+    fn parsed_selections() {
+        for (answer, chosen) in [
+            ("\n", Some(vec![])),
+            ("None\n", Some(vec![])),
+            ("all\n", Some(vec![0, 1, 2, 3, 4])),
+            ("2\n", Some(vec![1])),
+            ("5 1,3\n", Some(vec![0, 2, 4])),
+            ("1-3 2\n", Some(vec![0, 1, 2])),
+            ("0\n", None),
+            ("6\n", None),
+            ("3-1\n", None),
+            ("1-\n", None),
+            ("curl\n", None),
+        ] {
+            assert_eq!(parse_selection(answer, 5), chosen, "{answer:?}");
+        }
+    }
+
+    #[test]
+    fn globs_to_regexes() {
+        for (glob, regex) in [
+            ("curl", "^curl$"),
+            ("linux-image-*", "^linux\\-image\\-.*$"),
+            ("python3.1?", "^python3\\.1.$"),
+            ("lib[!a-c]x", "^lib[^a-c]x$"),
+            ("g++", "^g\\+\\+$"),
+            ("[oops", "^\\[oops$"),
+        ] {
+            assert_eq!(glob_to_regex(glob), regex, "{glob:?}");
+        }
+        let patterns = glob_patterns(&["linux-*", "python3.1?", "lib[ab]x"]).unwrap();
+        for (name, matched) in [
+            ("linux-image-6.1.0-18-amd64", true),
+            ("Linux-Headers", true),
+            ("python3.11", true),
+            ("python3.1", false),
+            ("python311", false),
+            ("libax", true),
+            ("libcx", false),
+            ("util-linux", false),
+        ] {
+            assert_eq!(patterns.is_match(name), matched, "{name:?}");
+        }
+        assert!(is_glob("linux-*") && is_glob("lib[ab]x") && !is_glob("g++"));
+    }
+
+    #[test]
+    fn shell_quoted_words() {
+        for (word, quoted) in [
+            ("apt", "apt"),
+            (
+                "--setopt=proxy=http://localhost:8080",
+                "--setopt=proxy=http://localhost:8080",
+            ),
+            ("*foo*", "'*foo*'"),
+            ("foo bar", "'foo bar'"),
+            ("it's", r"'it'\''s'"),
+            ("~", "'~'"),
+            ("foo~", "foo~"),
+            ("", "''"),
+        ] {
+            assert_eq!(shell_quote(word), quoted);
+        }
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn env_file_round_trip() {
+        use crate::config::Config;
+
+        let path = env::temp_dir().join(format!("pacaptr-env-{}", std::process::id()));
+        fs::write(&path, "FOO='from file'\nBAR=\"from file\"\n").unwrap();
+        let cfg = Config {
+            env_file: Some(path.clone()),
+            ..Config::default()
+        };
+        set_env_vars(cfg.env_vars().unwrap());
+        fs::remove_file(&path).unwrap();
+
+        let out = Cmd::new(["sh", "-c", "echo \"$FOO, $BAR\""])
+            .env("BAR", "from cmd")
+            .exec(Mode::Mute)
+            .await
+            .unwrap();
+        set_env_vars(vec![]);
+        assert_eq!(String::from_utf8(out).unwrap(), "from file, from cmd\n");
+    }
+
+    #[test]
+    fn proxy_env_through_sudo() {
+        use crate::config::Config;
+
+        let cfg = Config {
+            proxy: Some("http://proxy:3128".into()),
+            ..Config::default()
+        };
+        let vars = cfg.proxy_env_vars();
+        let build = |sudo| {
+            let cmd = Cmd::with_sudo(["apt", "install"]).kws(["curl"]);
+            cmd.build_with(&vars, sudo)
+        };
+        let argv = |exec: &Exec| {
+            let exec = exec.as_std();
+            chain!([exec.get_program()], exec.get_args())
+                .map(|arg| arg.to_string_lossy().into_owned())
+                .collect_vec()
+        };
+
+        // `sudo` resets the environment, so the variables go through `env`.
+        assert_eq!(
+            argv(&build(true)),
+            [
+                "sudo",
+                "-S",
+                "env",
+                "http_proxy=http://proxy:3128",
+                "HTTP_PROXY=http://proxy:3128",
+                "https_proxy=http://proxy:3128",
+                "HTTPS_PROXY=http://proxy:3128",
+                "apt",
+                "install",
+                "curl",
+            ]
+        );
+
+        // Without `sudo`, e.g. as `root`, they are set directly.
+        let exec = build(false);
+        assert_eq!(argv(&exec), ["apt", "install", "curl"]);
+        let envs = (exec.as_std().get_envs())
+            .filter_map(|(key, val)| Some((key.to_str()?, val?.to_str()?)))
+            .collect_vec();
+        assert!(
+            envs.contains(&("HTTPS_PROXY", "http://proxy:3128")),
+            "{envs:?}"
+        );
+    }
+
+    #[test]
+    fn line_decoder() {
+        let mut decoder = LineDecoder::default();
+        assert_eq!(decoder.push(b"curl/noble 8.5"), [""; 0]);
+        assert_eq!(
+            decoder.push(b".0\r\nwget/noble 1.21\n\nfi"),
+            ["curl/noble 8.5.0", "wget/noble 1.21", ""]
+        );
+        assert_eq!(decoder.push(b"sh"), [""; 0]);
+        assert_eq!(decoder.finish().as_deref(), Some("fish"));
+        assert_eq!(LineDecoder::default().finish(), None);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn exec_lines() {
+        let mut lines = vec![];
+        Cmd::new(["sh", "-c", "printf 'foo\\nbar'; echo oops >&2"])
+            .exec_lines(|ln| lines.push(ln.to_owned()))
+            .await
+            .unwrap();
+        assert_eq!(lines, ["foo", "bar"]);
+
+        let res = Cmd::new(["sh", "-c", "echo foo; echo oops >&2; exit 3"])
+            .exec_lines(|_| ())
+            .await;
+        assert!(matches!(
+            res,
+            Err(Error::CmdStatusCodeError { code: 3, output, .. }) if output == b"oops\n"
+        ));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn custom_context() {
+        use std::sync::Arc;
+
+        use context::{Context, OutputSink, PromptHandler, Stream};
+
+        #[derive(Default)]
+        struct Log(Mutex<Vec<String>>);
+
+        impl OutputSink for Log {
+            fn print(&self, text: &str) {
+                let text = console::strip_ansi_codes(text).trim().to_owned();
+                self.0.lock().unwrap().push(text);
+            }
+
+            fn cmd_output(&self, stream: Stream, chunk: &[u8]) {
+                let text = String::from_utf8_lossy(chunk).trim().to_owned();
+                self.0.lock().unwrap().push(format!("{stream:?}: {text}"));
+            }
+        }
+
+        struct Decline;
+
+        impl PromptHandler for Decline {
+            fn prompt(
+                &self,
+                _prompt: &str,
+                _question: &str,
+                expected: &[&str],
+                _default: usize,
+            ) -> Result<usize> {
+                Ok(expected.len() - 1)
+            }
+        }
+
+        let log = Arc::new(Log::default());
+        let ctx = Context::new(Arc::clone(&log) as _, Arc::new(Decline));
+        let cmd = || Cmd::new(["sh", "-c", "echo out; echo err >&2"]);
+        let run = async {
+            let out = cmd().exec(Mode::CheckErr { quiet: true }).await.unwrap();
+            assert_eq!(out, b"err\n");
+            let out = cmd().exec(Mode::Prompt).await.unwrap();
+            assert!(out.is_empty());
+            prompt_declined()
+        };
+        assert!(context::scope(ctx, run).await);
+        assert!(!prompt_declined());
+
+        let mut lines = log.0.lock().unwrap().clone();
+        // The two streams of the first command might come in any order.
+        lines[..2].sort_unstable();
+        assert_eq!(
+            lines,
+            [
+                "Stderr: err",
+                "Stdout: out",
+                "Pending `sh -c 'echo out; echo err >&2'`",
+            ]
+        );
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn muted_in_c_locale() {
+        let cmd = Cmd::new(["sh", "-c", "echo \"$LC_ALL $LANG\""]).env("LANG", "de_DE.UTF-8");
+        let out = cmd.exec(Mode::Mute).await.unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "C C\n");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn invalid_utf8_output() {
+        let out = Cmd::new(["printf", r"foo\377bar\nbaz\n"])
+            .exec(Mode::Mute)
+            .await
+            .unwrap();
+        let text = decode(&out);
+        assert_eq!(text, "foo\u{FFFD}bar\nbaz\n");
+        assert_eq!(grep(&text, &["bar"]).unwrap(), ["foo\u{FFFD}bar"]);
+        assert!(LOSSY_DECODED.load(Ordering::Relaxed));
+        assert_eq!(decode(b"baz"), "baz");
+    }
+
+    #[test]
+    fn ansi_stripped() {
+        let colored = b"\x1b[1;32mfish\x1b[0m/jammy 3.3.1 \x1b]8;;https://fishshell.com\x07[link]\x1b]8;;\x1b\\ \x1b(Bdone\x1b[K\n";
+        let plain = "fish/jammy 3.3.1 [link] done\n";
+        assert_eq!(strip_ansi(colored), plain.as_bytes());
+        assert_eq!(strip_ansi(plain.as_bytes()), plain.as_bytes());
+        // The sequences might be split across the chunks of a stream.
+        for i in 0..colored.len() {
+            let mut stripper = AnsiStripper::default();
+            let (head, tail) = colored.split_at(i);
+            assert_eq!(
+                [stripper.strip(head), stripper.strip(tail)].concat(),
+                plain.as_bytes()
+            );
+        }
+        assert_eq!(decode(colored), plain);
+        assert_eq!(
+            grep(&decode(colored), &["^fish/"]).unwrap(),
+            [plain.trim_end()]
+        );
+    }
+
+    /// Property tests on how a [`Cmd`] is built and printed out, with the
+    /// displayed form read back by [`shell_words`] (or a real shell) as the
+    /// oracle.
+    mod props {
+        use std::process::Command;
+
+        use proptest::{collection::vec, prelude::*};
+
+        use super::*;
+
+        /// Generates a word, with a bias towards the empty string and the
+        /// characters special to shells.
+        fn word() -> impl Strategy<Value = String> {
+            prop_oneof![
+                Just(String::new()),
+                r#"[-a-z0-9 '"\\$`*?!~#=:,./@%+^(){}<>|;&\t\n]{1,12}"#,
+                "[^\0]{0,12}",
+            ]
+        }
+
+        /// Generates a [`Cmd`] out of at most `len` words in each part, along
+        /// with the words of its parts in order.
+        fn cmd(len: usize) -> impl Strategy<Value = (Cmd, Vec<String>)> {
+            (
+                vec(word(), 1..=len),
+                vec(word(), 0..=len),
+                vec(word(), 0..=len),
+                vec(("[A-Z_]{1,5}", word()), 0..3),
+                any::<bool>(),
+            )
+                .prop_map(|(cmd, flags, kws, env, sudo)| {
+                    let words = chain!(&cmd, &flags, &kws).cloned().collect();
+                    let cmd = env.into_iter().fold(
+                        Cmd::new(cmd).flags(flags).kws(kws).sudo(sudo),
+                        |cmd, (key, val)| cmd.env(key, val),
+                    );
+                    (cmd, words)
+                })
+        }
+
+        /// Returns the words to be prepended to `cmd` as `sudo` is needed.
+        fn sudo_prefix(cmd: &Cmd) -> Vec<String> {
+            let sudo = cmd.should_sudo().then_some(["sudo", "-S"]);
+            sudo.into_iter().flatten().map(String::from).collect()
+        }
+
+        /// Returns the program and the arguments of `exec`.
+        fn argv(exec: &Exec) -> Vec<String> {
+            let exec = exec.as_std();
+            chain!([exec.get_program()], exec.get_args())
+                .map(|arg| arg.to_string_lossy().into_owned())
+                .collect()
+        }
+
+        proptest! {
+            #[test]
+            fn shell_quote_round_trip(word in word()) {
+                prop_assert_eq!(shell_words::split(&shell_quote(&word)).unwrap(), [word]);
+            }
+
+            #[test]
+            fn display_round_trip((cmd, words) in cmd(4)) {
+                let expected = chain!(sudo_prefix(&cmd), words).collect_vec();
+                prop_assert_eq!(shell_words::split(&cmd.to_string()).unwrap(), expected);
+            }
+
+            #[test]
+            fn argv_order((cmd, words) in cmd(4)) {
+                let prefix = sudo_prefix(&cmd);
+                let argv = argv(&cmd.build());
+                // The flags are always placed before the keywords, see `Cmd::build`.
+                prop_assert!(argv.ends_with(&words), "{argv:?}");
+                if prefix.is_empty() {
+                    prop_assert_eq!(argv, words);
+                    return Ok(());
+                }
+                // The variables might be passed with `env` in between.
+                prop_assert!(argv.starts_with(&prefix), "{argv:?}");
+                let sudos = |words: &[String]| words.iter().filter(|w| *w == "sudo").count();
+                prop_assert_eq!(sudos(&argv), sudos(&words) + 1);
+            }
+        }
+
+        #[cfg(unix)]
+        proptest! {
+            #![proptest_config(ProptestConfig::with_cases(32))]
+
+            #[test]
+            fn display_in_shell((cmd, words) in cmd(3)) {
+                prop_assume!(!cmd.should_sudo());
+                // Each word is printed out by `printf` with a trailing NUL instead.
+                let line = cmd.to_string();
+                let script = format!("printf '%s\\0' {line}");
+                let out = Command::new("sh").args(["-c", &script]).output().unwrap();
+                prop_assert!(out.status.success(), "{line}");
+                let got = out
+                    .stdout
+                    .split(|&b| b == 0)
+                    .map(|w| String::from_utf8_lossy(w).into_owned())
+                    .collect_vec();
+                prop_assert_eq!(&got[..got.len() - 1], &words[..], "{}", line);
+            }
+        }
+    }
+}
@@ -0,0 +1,446 @@
+//! The execution context of `pacaptr`, i.e. where its output goes and how its
+//! prompts are answered.
+//!
+//! By default, everything is printed out to the console, and the prompts are
+//! answered on the terminal (or `stdin`). A library consumer can instead run
+//! any [`Pm`](crate::pm::Pm) operation within [`scope`] with a [`Context`] of
+//! its own, so as to receive the output and to answer the prompts with
+//! callbacks, as shown in `examples/query.rs`.
+//!
+//! The packages of the queries supporting the structured
+//! [`OutputFormat`](crate::print::OutputFormat)s can also be collected as is
+//! with [`capture_pkgs`](crate::print::capture_pkgs).
+//!
+//! Concurrent operations can be run with [`run_concurrently`], which can
+//! keep their outputs apart with a [`BufferedSink`] each.
+
+use std::{
+    fmt::{self, Debug},
+    future::Future,
+    io::{self, Write},
+    mem,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, LazyLock, Mutex, PoisonError,
+    },
+    task::{self, Poll},
+};
+
+use futures::{stream, StreamExt};
+use tokio::io::AsyncWrite;
+
+use super::download::Download;
+use crate::error::Result;
+
+/// The standard stream of a command that some output comes from.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Stream {
+    /// The `stdout` of the command.
+    Stdout,
+
+    /// The `stderr` of the command.
+    Stderr,
+}
+
+/// A receiver of the output of `pacaptr`.
+pub trait OutputSink: Send + Sync {
+    /// Receives some text printed out by `pacaptr` itself, e.g. a command
+    /// echo, a message or the results of a query in the requested
+    /// [`OutputFormat`](crate::print::OutputFormat).
+    fn print(&self, text: &str);
+
+    /// Receives a chunk of the output of a command run by `pacaptr` from its
+    /// `stream`, unless the command is run in
+    /// [`Mode::Mute`](super::Mode::Mute).
+    fn cmd_output(&self, stream: Stream, chunk: &[u8]);
+
+    /// Checks if this sink is the console itself, in which case the commands
+    /// are allowed to write to `stdout` directly, so as to keep their colors
+    /// and progress bars.
+    ///
+    /// Otherwise, the `stdout` of the commands is always captured and
+    /// forwarded to [`OutputSink::cmd_output`].
+    fn is_console(&self) -> bool {
+        false
+    }
+}
+
+/// A handler of the prompts of `pacaptr`, e.g. the confirmation before running
+/// a command.
+pub trait PromptHandler: Send + Sync {
+    /// Asks the `question` after the `prompt`, and returns the index of the
+    /// answer chosen among the `expected` ones, where the one at `default` is
+    /// selected by default.
+    ///
+    /// # Errors
+    /// Returns an error if the question cannot be answered, which aborts the
+    /// current operation.
+    fn prompt(
+        &self,
+        prompt: &str,
+        question: &str,
+        expected: &[&str],
+        default: usize,
+    ) -> Result<usize>;
+
+    /// Asks the `question` after the `prompt`, and returns the indices of the
+    /// `items` chosen, in ascending order.
+    ///
+    /// None of them is chosen by default.
+    ///
+    /// # Errors
+    /// Returns an error if the question cannot be answered, which aborts the
+    /// current operation.
+    fn select(&self, _prompt: &str, _question: &str, _items: &[String]) -> Result<Vec<usize>> {
+        Ok(vec![])
+    }
+}
+
+/// The default [`OutputSink`] and [`PromptHandler`], printing out to `stdout`
+/// and `stderr` and asking on the terminal.
+#[derive(Copy, Clone, Default, Debug)]
+pub struct Console;
+
+impl OutputSink for Console {
+    fn print(&self, text: &str) {
+        print!("{text}");
+    }
+
+    fn cmd_output(&self, stream: Stream, chunk: &[u8]) {
+        fn write_all(mut out: impl Write, chunk: &[u8]) -> io::Result<()> {
+            out.write_all(chunk)?;
+            // The output is flushed right away, so that progress bars are shown.
+            out.flush()
+        }
+
+        _ = match stream {
+            Stream::Stdout => write_all(io::stdout().lock(), chunk),
+            Stream::Stderr => write_all(io::stderr().lock(), chunk),
+        };
+    }
+
+    fn is_console(&self) -> bool {
+        true
+    }
+}
+
+impl PromptHandler for Console {
+    fn prompt(
+        &self,
+        prompt: &str,
+        question: &str,
+        expected: &[&str],
+        default: usize,
+    ) -> Result<usize> {
+        super::prompt(prompt, question, expected, default)
+    }
+
+    fn select(&self, prompt: &str, question: &str, items: &[String]) -> Result<Vec<usize>> {
+        super::select(prompt, question, items)
+    }
+}
+
+/// An [`OutputSink`] keeping the output in memory, until it is flushed to
+/// another one with [`BufferedSink::flush`].
+#[derive(Default, Debug)]
+pub struct BufferedSink {
+    /// The chunks received so far, along with the stream of the command that
+    /// they come from (if any).
+    chunks: Mutex<Vec<(Option<Stream>, Vec<u8>)>>,
+}
+
+impl BufferedSink {
+    /// Forwards the output received so far to `output`, in the same order.
+    pub fn flush(&self, output: &dyn OutputSink) {
+        let chunks = mem::take(&mut *self.chunks.lock().unwrap_or_else(PoisonError::into_inner));
+        for (stream, chunk) in chunks {
+            match stream {
+                Some(stream) => output.cmd_output(stream, &chunk),
+                None => output.print(&String::from_utf8_lossy(&chunk)),
+            }
+        }
+    }
+
+    fn push(&self, stream: Option<Stream>, chunk: &[u8]) {
+        (self.chunks.lock().unwrap_or_else(PoisonError::into_inner)).push((stream, chunk.into()));
+    }
+}
+
+impl OutputSink for BufferedSink {
+    fn print(&self, text: &str) {
+        self.push(None, text.as_bytes());
+    }
+
+    fn cmd_output(&self, stream: Stream, chunk: &[u8]) {
+        self.push(Some(stream), chunk);
+    }
+}
+
+/// The answers given so far in a [`Context`].
+///
+/// These are standalone flags, so `Ordering::Relaxed` is fine.
+/// See: <https://marabos.nl/atomics/memory-ordering.html#relaxed>
+#[derive(Default, Debug)]
+struct Answers {
+    /// If the user has skipped all the prompts with `All`.
+    all: AtomicBool,
+
+    /// If the user has declined (at least) one of the prompts.
+    declined: AtomicBool,
+}
+
+/// The execution context of `pacaptr`, see the [module-level
+/// documentation](self).
+#[derive(Clone)]
+pub struct Context {
+    output: Arc<dyn OutputSink>,
+    prompt: Arc<dyn PromptHandler>,
+    answers: Arc<Answers>,
+    downloaded: Arc<Mutex<Option<Download>>>,
+}
+
+impl Context {
+    /// Makes a new [`Context`] with the given `output` and `prompt` handlers,
+    /// in which no prompt has been answered and nothing has been downloaded
+    /// yet.
+    #[must_use]
+    pub fn new(output: Arc<dyn OutputSink>, prompt: Arc<dyn PromptHandler>) -> Self {
+        Self {
+            output,
+            prompt,
+            answers: Arc::default(),
+            downloaded: Arc::default(),
+        }
+    }
+
+    /// Returns the [`OutputSink`] of this context.
+    #[must_use]
+    pub fn output(&self) -> &dyn OutputSink {
+        &*self.output
+    }
+
+    /// Returns the [`PromptHandler`] of this context.
+    #[must_use]
+    pub fn prompt_handler(&self) -> &dyn PromptHandler {
+        &*self.prompt
+    }
+
+    /// Checks if the user has answered `All` to one of the prompts so far, so
+    /// that the following ones should be skipped.
+    #[must_use]
+    pub fn prompt_all(&self) -> bool {
+        self.answers.all.load(Ordering::Relaxed)
+    }
+
+    /// Checks if the user has declined (at least) one of the prompts so far.
+    #[must_use]
+    pub fn prompt_declined(&self) -> bool {
+        self.answers.declined.load(Ordering::Relaxed)
+    }
+
+    /// Records that the user has answered `All` to a prompt.
+    pub(crate) fn set_prompt_all(&self) {
+        self.answers.all.store(true, Ordering::Relaxed);
+    }
+
+    /// Records that the user has declined a prompt.
+    pub(crate) fn set_prompt_declined(&self) {
+        self.answers.declined.store(true, Ordering::Relaxed);
+    }
+
+    /// Returns the total [`Download`] recorded so far in this context, or
+    /// `None` if none of the commands has reported one.
+    #[must_use]
+    pub fn downloaded(&self) -> Option<Download> {
+        *self
+            .downloaded
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Adds `download` to the total recorded in this context.
+    pub(crate) fn add_download(&self, download: Download) {
+        let mut total = self
+            .downloaded
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        *total = Some(total.unwrap_or_default() + download);
+    }
+
+    /// Makes a copy of this context with its output going to `output`
+    /// instead, where the prompts are still answered (and the downloads still
+    /// added up) as in this one.
+    #[must_use]
+    pub fn with_output(&self, output: Arc<dyn OutputSink>) -> Self {
+        Self {
+            output,
+            ..self.clone()
+        }
+    }
+
+    /// Returns a writer forwarding the output of a command from `stream` to
+    /// the [`OutputSink`] of this context.
+    pub(crate) fn writer(&self, stream: Stream) -> SinkWriter {
+        SinkWriter {
+            output: Arc::clone(&self.output),
+            stream,
+        }
+    }
+}
+
+impl Default for Context {
+    /// Returns a new console context, see [`Console`].
+    fn default() -> Self {
+        Self::new(Arc::new(Console), Arc::new(Console))
+    }
+}
+
+impl Debug for Context {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Context")
+            .field("console", &self.output.is_console())
+            .field("answers", &self.answers)
+            .field("downloaded", &self.downloaded())
+            .finish_non_exhaustive()
+    }
+}
+
+/// An [`AsyncWrite`] adapter of [`OutputSink::cmd_output`].
+pub(crate) struct SinkWriter {
+    output: Arc<dyn OutputSink>,
+    stream: Stream,
+}
+
+impl Debug for SinkWriter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SinkWriter")
+            .field("stream", &self.stream)
+            .finish_non_exhaustive()
+    }
+}
+
+impl AsyncWrite for SinkWriter {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        _cx: &mut task::Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        self.output.cmd_output(self.stream, buf);
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut task::Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut task::Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// The console context, used outside of any [`scope`].
+static CONSOLE: LazyLock<Context> = LazyLock::new(Context::default);
+
+tokio::task_local! {
+    /// The [`Context`] of the current task, if any.
+    static CONTEXT: Context;
+}
+
+/// Runs `fut` within the given [`Context`] instead of the console one.
+///
+/// Just like other task-local values, the context is not inherited by the
+/// tasks spawned in `fut`.
+pub async fn scope<F: Future>(ctx: Context, fut: F) -> F::Output {
+    CONTEXT.scope(ctx, fut).await
+}
+
+/// Returns the [`Context`] of the current task, or the console one outside of
+/// any [`scope`].
+#[must_use]
+pub fn current() -> Context {
+    CONTEXT
+        .try_with(Context::clone)
+        .unwrap_or_else(|_| CONSOLE.clone())
+}
+
+/// Runs the `futs` concurrently, `limit` at a time at most, returning their
+/// results in order.
+///
+/// The outputs of the futures are interleaved as they come by default. If
+/// `ordered` is set, the output of each future is buffered with a
+/// [`BufferedSink`] instead, and flushed to the current [`Context`] once the
+/// future and all the previous ones have completed, so that the outputs come
+/// in the same order as the futures.
+pub async fn run_concurrently<F: Future>(
+    futs: impl IntoIterator<Item = F>,
+    limit: usize,
+    ordered: bool,
+) -> Vec<F::Output> {
+    let ctx = current();
+    stream::iter(futs)
+        .map(|fut| {
+            let buf = Arc::new(BufferedSink::default());
+            let ctx = if ordered {
+                ctx.with_output(Arc::clone(&buf) as _)
+            } else {
+                ctx.clone()
+            };
+            async move { (scope(ctx, fut).await, buf) }
+        })
+        .buffered(limit)
+        .map(|(res, buf)| {
+            buf.flush(ctx.output());
+            res
+        })
+        .collect()
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::time;
+
+    use super::*;
+
+    /// Collects the lines printed out.
+    #[derive(Default)]
+    struct Log(Mutex<Vec<String>>);
+
+    impl OutputSink for Log {
+        fn print(&self, text: &str) {
+            (self.0.lock().unwrap()).extend(text.lines().map(String::from));
+        }
+
+        fn cmd_output(&self, _stream: Stream, _chunk: &[u8]) {}
+    }
+
+    #[tokio::test]
+    async fn ordered_output() {
+        let run = |ordered| async move {
+            let log = Arc::new(BufferedSink::default());
+            let ctx = Context::default().with_output(Arc::clone(&log) as _);
+            let futs = [("slow", 50), ("fast", 0)].map(|(name, delay)| async move {
+                crate::print::print_raw(format_args!("{name} started\n"));
+                time::sleep(Duration::from_millis(delay)).await;
+                crate::print::print_raw(format_args!("{name} done\n"));
+                name
+            });
+            let res = scope(ctx, run_concurrently(futs, 2, ordered)).await;
+            assert_eq!(res, ["slow", "fast"]);
+            let out = Log::default();
+            log.flush(&out);
+            out.0.into_inner().unwrap()
+        };
+        assert_eq!(
+            run(false).await,
+            ["slow started", "fast started", "fast done", "slow done"]
+        );
+        assert_eq!(
+            run(true).await,
+            ["slow started", "slow done", "fast started", "fast done"]
+        );
+    }
+}
@@ -0,0 +1,106 @@
+//! Desktop notifications sent with the native notifier of each platform, e.g.
+//! on completion of a long operation with `--notify`.
+
+use super::{Cmd, Mode};
+use crate::print::{self, prompt};
+
+/// The notifier of the current platform.
+#[cfg(target_os = "macos")]
+const NOTIFIER: &str = "osascript";
+
+/// The notifier of the current platform.
+#[cfg(windows)]
+const NOTIFIER: &str = "powershell";
+
+/// The notifier of the current platform.
+#[cfg(not(any(target_os = "macos", windows)))]
+const NOTIFIER: &str = "notify-send";
+
+/// The application ID under which the toasts are shown on Windows, borrowed
+/// from PowerShell since `pacaptr` itself is not registered.
+#[cfg(windows)]
+const TOAST_APP_ID: &str =
+    r"{1AC14E77-02E7-4E5D-B744-2EB1AE5198B7}\WindowsPowerShell\v1.0\powershell.exe";
+
+/// Returns the command showing a desktop notification with the given `title`
+/// and `body` with [`NOTIFIER`].
+#[cfg(target_os = "macos")]
+fn notify_cmd(title: &str, body: &str) -> Cmd {
+    let quote = |s: &str| format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""));
+    let script = format!(
+        "display notification {} with title {}",
+        quote(body),
+        quote(title)
+    );
+    Cmd::new([NOTIFIER, "-e", &script])
+}
+
+/// Returns the command showing a desktop notification with the given `title`
+/// and `body` with [`NOTIFIER`].
+#[cfg(windows)]
+fn notify_cmd(title: &str, body: &str) -> Cmd {
+    let quote = |s: &str| format!("'{}'", s.replace('\'', "''"));
+    let script = indoc::formatdoc! {"
+        $null = [Windows.UI.Notifications.ToastNotificationManager, Windows.UI.Notifications, ContentType = WindowsRuntime]
+        $xml = [Windows.UI.Notifications.ToastNotificationManager]::GetTemplateContent([Windows.UI.Notifications.ToastTemplateType]::ToastText02)
+        $text = $xml.GetElementsByTagName('text')
+        $null = $text.Item(0).AppendChild($xml.CreateTextNode({title}))
+        $null = $text.Item(1).AppendChild($xml.CreateTextNode({body}))
+        [Windows.UI.Notifications.ToastNotificationManager]::CreateToastNotifier({app_id}).Show([Windows.UI.Notifications.ToastNotification]::new($xml))
+    ",
+        title = quote(title),
+        body = quote(body),
+        app_id = quote(TOAST_APP_ID),
+    };
+    Cmd::new([
+        NOTIFIER,
+        "-NoProfile",
+        "-NonInteractive",
+        "-Command",
+        &script,
+    ])
+}
+
+/// Returns the command showing a desktop notification with the given `title`
+/// and `body` with [`NOTIFIER`].
+#[cfg(not(any(target_os = "macos", windows)))]
+fn notify_cmd(title: &str, body: &str) -> Cmd {
+    Cmd::new([NOTIFIER, "--app-name=pacaptr", title, body])
+}
+
+/// Shows a desktop notification with the given `title` and `body`, or only
+/// prints out the command that would be run if `dry_run` is set.
+///
+/// This is a best-effort operation: if the notifier is not installed or has
+/// failed, a warning is printed out instead.
+pub async fn notify(title: &str, body: &str, dry_run: bool) {
+    if !dry_run && !super::is_exe(NOTIFIER, "") {
+        print::println(
+            &*prompt::WARNING,
+            format_args!("`{NOTIFIER}` is not found, skipping the desktop notification"),
+        );
+        return;
+    }
+    let mode = if dry_run { Mode::PrintCmd } else { Mode::Mute };
+    if let Err(e) = notify_cmd(title, body).exec(mode).await {
+        print::println(
+            &*prompt::WARNING,
+            format_args!("failed to send the desktop notification: {e}"),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(not(any(target_os = "macos", windows)))]
+    fn notify_send() {
+        let cmd = notify_cmd("pacaptr: `-Suy` done", "3 commands in 1.5s");
+        assert_eq!(
+            cmd.to_string(),
+            "notify-send --app-name=pacaptr 'pacaptr: `-Suy` done' '3 commands in 1.5s'"
+        );
+    }
+}
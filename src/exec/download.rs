@@ -0,0 +1,265 @@
+//! Measurement of the data downloaded by [`Cmd`](super::Cmd)s.
+//!
+//! With [`Config::measure_network`](crate::config::Config::measure_network),
+//! the captured output of each command is scanned for the download summary of
+//! the corresponding package manager, e.g. `Fetched 568 kB in 2s (284 kB/s)`
+//! with `apt`. The downloads are added up with [`record`], so that they can be
+//! reported once the operation is done.
+
+use std::{
+    fmt::{self, Display},
+    ops::Add,
+    sync::LazyLock,
+    time::Duration,
+};
+
+use regex::Regex;
+use serde_json::json;
+
+use super::context;
+use crate::print;
+
+/// The data downloaded by one or more commands.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Download {
+    /// The number of bytes downloaded.
+    pub bytes: u64,
+
+    /// The time spent on downloading them.
+    pub elapsed: Duration,
+}
+
+impl Download {
+    /// Returns the average download rate in bytes per second, unless no time
+    /// has been spent at all.
+    #[must_use]
+    #[allow(
+        clippy::cast_precision_loss,
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss
+    )]
+    pub fn rate(self) -> Option<u64> {
+        let secs = self.elapsed.as_secs_f64();
+        (secs > 0.).then(|| (self.bytes as f64 / secs).round() as u64)
+    }
+}
+
+impl Add for Download {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self {
+            bytes: self.bytes + other.bytes,
+            elapsed: self.elapsed + other.elapsed,
+        }
+    }
+}
+
+impl Display for Download {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} in {:.1}s",
+            print::format_size(self.bytes),
+            self.elapsed.as_secs_f64()
+        )?;
+        if let Some(rate) = self.rate() {
+            write!(f, " ({}/s)", print::format_size(rate))?;
+        }
+        Ok(())
+    }
+}
+
+/// Parses a size such as `1,172 kB` into bytes, where the units are
+/// multiples of `base`.
+#[allow(
+    clippy::cast_precision_loss,
+    clippy::cast_possible_truncation,
+    clippy::cast_sign_loss
+)]
+pub(crate) fn parse_size(num: &str, unit: &str, base: u64) -> Option<u64> {
+    let num: f64 = num.replace(',', "").parse().ok()?;
+    let exp = match unit.chars().next()? {
+        'B' => 0,
+        'k' | 'K' => 1,
+        'M' => 2,
+        'G' => 3,
+        'T' => 4,
+        _ => return None,
+    };
+    Some((num * base.pow(exp) as f64).round() as u64)
+}
+
+/// Parses the download summaries of `apt`, e.g.
+/// `Fetched 412 MB in 1min 5s (6,339 kB/s)`, where the sizes are in SI units.
+fn parse_apt(output: &str) -> Option<Download> {
+    static FETCHED: LazyLock<Regex> = LazyLock::new(|| {
+        Regex::new(r"(?m)^Fetched ([\d.,]+) ([kMGT]?B) in ((?:\d+(?:d|h|min|s) ?)+)")
+            .expect("pattern should be valid")
+    });
+    static SPAN: LazyLock<Regex> =
+        LazyLock::new(|| Regex::new(r"(\d+)(d|h|min|s)").expect("pattern should be valid"));
+
+    FETCHED
+        .captures_iter(output)
+        .map(|caps| {
+            let bytes = parse_size(&caps[1], &caps[2], 1000)?;
+            let secs = SPAN
+                .captures_iter(&caps[3])
+                .map(|span| {
+                    let n: u64 = span[1].parse().unwrap_or_default();
+                    n * match &span[2] {
+                        "d" => 86400,
+                        "h" => 3600,
+                        "min" => 60,
+                        _ => 1,
+                    }
+                })
+                .sum();
+            Some(Download {
+                bytes,
+                elapsed: Duration::from_secs(secs),
+            })
+        })
+        .reduce(|a, b| Some(a? + b?))?
+}
+
+/// Parses the download progress lines of `dnf`, e.g.
+/// `Total   1.0 MB/s | 1.2 MB   00:01` (`dnf4`) or
+/// `[2/2] Total   100% | 305.4 KiB/s | 421.1 KiB |  00m01s` (`dnf5`), where the
+/// sizes are in binary units.
+///
+/// The lines of the packages are only added up if there is no total, i.e. if
+/// a single package has been downloaded. The lines of the repository metadata
+/// are always added up.
+fn parse_dnf(output: &str) -> Option<Download> {
+    static SIZE: LazyLock<Regex> = LazyLock::new(|| {
+        Regex::new(r"([\d.]+)\s*([kKMGT]?i?B|[kMGT])(?:\s|\||$)").expect("pattern should be valid")
+    });
+    static TIME: LazyLock<Regex> = LazyLock::new(|| {
+        Regex::new(r"(?:(\d+)[:h])?(\d+)[:m](\d+)s?\s*$").expect("pattern should be valid")
+    });
+
+    let lines = output
+        .lines()
+        .filter_map(|line| {
+            let (head, rest) = line.split_once('|')?;
+            let size = SIZE.captures(rest)?;
+            let time = TIME.captures(rest)?;
+            let secs = [1, 2, 3]
+                .map(|i| time.get(i).map_or(0, |m| m.as_str().parse().unwrap_or(0)))
+                .into_iter()
+                .fold(0, |acc, n: u64| acc * 60 + n);
+            let download = Download {
+                bytes: parse_size(&size[1], &size[2], 1024)?,
+                elapsed: Duration::from_secs(secs),
+            };
+            let head = head.trim_start();
+            let is_pkg = head.starts_with('(') || head.starts_with('[');
+            Some((head.contains("Total"), is_pkg, download))
+        })
+        .collect::<Vec<_>>();
+    let has_total = lines.iter().any(|&(total, ..)| total);
+    lines
+        .into_iter()
+        .filter(|&(total, is_pkg, _)| total || !(is_pkg && has_total))
+        .map(|(.., download)| download)
+        .reduce(Add::add)
+}
+
+/// Parses the download summary in the `output` of a command run by the
+/// package manager `pm`, if any.
+///
+/// Currently only `apt` and `dnf` report one.
+#[must_use]
+pub fn parse(pm: &str, output: &str) -> Option<Download> {
+    match pm {
+        "apt" => parse_apt(output),
+        "dnf" => parse_dnf(output),
+        _ => None,
+    }
+}
+
+/// Adds the download reported in the `output` of a command run by the package
+/// manager `pm` (if any) to the total of the current
+/// [`Context`](super::context::Context).
+pub fn record(pm: &str, output: &[u8]) {
+    if let Some(download) = parse(pm, &String::from_utf8_lossy(output)) {
+        context::current().add_download(download);
+    }
+}
+
+/// Returns the total [`Download`] recorded so far in the current
+/// [`Context`](super::context::Context), or `None` if none of the commands has
+/// reported one.
+#[must_use]
+pub fn downloaded() -> Option<Download> {
+    context::current().downloaded()
+}
+
+/// Formats `download` as a JSON object with the `bytes`, `seconds` and
+/// `bytes_per_second` keys, which are `null` if it is unknown.
+#[must_use]
+pub fn format_json(download: Option<Download>) -> String {
+    json!({
+        "bytes": download.map(|d| d.bytes),
+        "seconds": download.map(|d| d.elapsed.as_secs_f64()),
+        "bytes_per_second": download.and_then(Download::rate),
+    })
+    .to_string()
+        + "\n"
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path};
+
+    use super::*;
+
+    #[test]
+    fn parse_captured_outputs() {
+        let download = |bytes, secs| {
+            Some(Download {
+                bytes,
+                elapsed: Duration::from_secs(secs),
+            })
+        };
+        let cases = [
+            ("apt", "apt", download(568_000, 2)),
+            ("apt-upgrade", "apt", download(413_172_000, 66)),
+            ("dnf", "dnf", download(1_258_291, 1)),
+            ("dnf-single", "dnf", download(809_984, 0)),
+            ("dnf5", "dnf", download(13_957_836, 4)),
+            ("dnf-cached", "dnf", None),
+            // The summaries of other package managers are not taken into account.
+            ("apt", "brew", None),
+        ];
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden/download");
+        for (name, pm, expected) in cases {
+            let output = fs::read_to_string(dir.join(format!("{name}.txt"))).unwrap();
+            assert_eq!(parse(pm, &output), expected, "`{name}` misparsed");
+        }
+    }
+
+    #[test]
+    fn format_download() {
+        let download = Download {
+            bytes: 413_172_000,
+            elapsed: Duration::from_secs(66),
+        };
+        assert_eq!(download.to_string(), "394.0 MiB in 66.0s (6.0 MiB/s)");
+        assert_eq!(
+            format_json(Some(download)),
+            "{\"bytes\":413172000,\"bytes_per_second\":6260182,\"seconds\":66.0}\n"
+        );
+        assert_eq!(
+            format_json(None),
+            "{\"bytes\":null,\"bytes_per_second\":null,\"seconds\":null}\n"
+        );
+        let instant = Download {
+            bytes: 809_984,
+            elapsed: Duration::ZERO,
+        };
+        assert_eq!(instant.to_string(), "791.0 KiB in 0.0s");
+    }
+}
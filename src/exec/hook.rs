@@ -0,0 +1,60 @@
+//! The hooks run around an operation, e.g. the shell command given by
+//! `--on-failure` once the operation has failed.
+
+use super::{Cmd, Mode};
+use crate::print::{self, prompt};
+
+/// The shell running the hooks on the current platform.
+#[cfg(windows)]
+const SHELL: &[&str] = &["cmd", "/C"];
+
+/// The shell running the hooks on the current platform.
+#[cfg(not(windows))]
+const SHELL: &[&str] = &["sh", "-c"];
+
+/// Returns the command running the `script` of the `--on-failure` hook with
+/// [`SHELL`], after the operation `op` has failed with the exit `code`.
+fn on_failure_cmd(script: &str, op: &str, code: u8) -> Cmd {
+    Cmd::new(SHELL.iter().copied().chain([script]))
+        .env("PACAPTR_FAILED_OP", op)
+        .env("PACAPTR_EXIT_CODE", code.to_string())
+}
+
+/// Runs the `script` of the `--on-failure` hook after the operation `op` has
+/// failed with the exit `code`, or only prints out the command that would be
+/// run if `dry_run` is set.
+///
+/// The hook failing is only reported with a warning, so that the error of
+/// the operation itself is kept.
+pub async fn on_failure(script: &str, op: &str, code: u8, dry_run: bool) {
+    let mode = if dry_run {
+        Mode::PrintCmd
+    } else {
+        Mode::CheckErr { quiet: false }
+    };
+    if let Err(e) = on_failure_cmd(script, op, code).exec(mode).await {
+        print::println(
+            &*prompt::WARNING,
+            format_args!("the `--on-failure` hook has failed: {e}"),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(not(windows))]
+    fn on_failure_env() {
+        let cmd = on_failure_cmd("echo \"$PACAPTR_FAILED_OP\"", "-S curl", 5);
+        assert_eq!(cmd.to_string(), "sh -c 'echo \"$PACAPTR_FAILED_OP\"'");
+        assert_eq!(
+            cmd.env,
+            [
+                ("PACAPTR_FAILED_OP".into(), "-S curl".into()),
+                ("PACAPTR_EXIT_CODE".into(), "5".into()),
+            ]
+        );
+    }
+}
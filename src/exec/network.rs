@@ -0,0 +1,203 @@
+//! Classification of network failures of [`Cmd`](super::Cmd)s.
+//!
+//! When a command fails, its captured output is scanned for the well-known
+//! signatures of network failures of the corresponding package manager, so
+//! that a hint can be shown instead of leaving the user alone with the raw
+//! output.
+
+use std::fmt::{self, Display};
+
+use crate::error::Error;
+
+/// A kind of network failure.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum NetworkIssue {
+    /// A host name could not be resolved.
+    Dns,
+
+    /// A server could not be connected to, or the connection timed out.
+    Connection,
+
+    /// The proxy could not be connected to, or it rejected the request.
+    Proxy,
+
+    /// The mirror failed to serve a file, e.g. with a `404`.
+    Mirror,
+}
+
+impl NetworkIssue {
+    /// Returns a one-line hint on how to fix this issue.
+    #[must_use]
+    pub const fn hint(self) -> &'static str {
+        match self {
+            Self::Dns => "check your network connection and DNS settings",
+            Self::Connection => {
+                "check your network connection, or the proxy environment variables (e.g. \
+                 `https_proxy`) if you are behind a proxy"
+            }
+            Self::Proxy => {
+                "check the proxy environment variables (e.g. `http_proxy` and `https_proxy`), \
+                 which can also be set with `--env-file`"
+            }
+            Self::Mirror => {
+                "the mirror might be down or out of sync, refresh the package database or try \
+                 another mirror"
+            }
+        }
+    }
+
+    /// Checks if this issue might go away by simply trying again.
+    #[must_use]
+    pub const fn is_transient(self) -> bool {
+        matches!(self, Self::Dns | Self::Connection)
+    }
+}
+
+impl Display for NetworkIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Dns => "failed to resolve a host name",
+            Self::Connection => "failed to connect to a server",
+            Self::Proxy => "failed to go through the proxy",
+            Self::Mirror => "failed to download from the mirror",
+        })
+    }
+}
+
+/// The signatures of network failures in the output of each package manager,
+/// in the form of `(pm, signature, issue)`.
+///
+/// The entries are checked in order, so the more specific signatures of a
+/// package manager should come first.
+const SIGNATURES: &[(&str, &str, NetworkIssue)] = &[
+    ("apt", "Temporary failure resolving", NetworkIssue::Dns),
+    ("apt", "Could not resolve", NetworkIssue::Dns),
+    ("apt", "Proxy Authentication Required", NetworkIssue::Proxy),
+    ("apt", "Could not connect to", NetworkIssue::Connection),
+    ("apt", "Connection timed out", NetworkIssue::Connection),
+    ("apt", "404  Not Found", NetworkIssue::Mirror),
+    ("brew", "Could not resolve proxy", NetworkIssue::Proxy),
+    ("brew", "Could not resolve host", NetworkIssue::Dns),
+    ("brew", "Failed to connect", NetworkIssue::Connection),
+    ("choco", "No such host is known", NetworkIssue::Dns),
+    (
+        "choco",
+        "remote name could not be resolved",
+        NetworkIssue::Dns,
+    ),
+    ("choco", "Unable to connect", NetworkIssue::Connection),
+    ("dnf", "Curl error (5)", NetworkIssue::Proxy),
+    ("dnf", "Curl error (6)", NetworkIssue::Dns),
+    ("dnf", "Curl error (7)", NetworkIssue::Connection),
+    ("dnf", "Curl error (28)", NetworkIssue::Connection),
+    (
+        "dnf",
+        "Failed to download metadata for repo",
+        NetworkIssue::Mirror,
+    ),
+    ("pip", "ProxyError", NetworkIssue::Proxy),
+    (
+        "pip",
+        "Temporary failure in name resolution",
+        NetworkIssue::Dns,
+    ),
+    (
+        "pip",
+        "Failed to establish a new connection",
+        NetworkIssue::Connection,
+    ),
+    ("zypper", "Could not resolve proxy", NetworkIssue::Proxy),
+    ("zypper", "Could not resolve host", NetworkIssue::Dns),
+    ("zypper", "Connection timed out", NetworkIssue::Connection),
+];
+
+/// Finds the [`NetworkIssue`] of the package manager `pm` whose signature
+/// appears in `output`, if any.
+#[must_use]
+pub fn detect(pm: &str, output: &str) -> Option<NetworkIssue> {
+    SIGNATURES
+        .iter()
+        .find(|(name, signature, _)| *name == pm && output.contains(signature))
+        .map(|&(.., issue)| issue)
+}
+
+/// Wraps `err` in an [`Error::NetworkError`] if it is a failure of a command
+/// run by the package manager `pm`, whose output contains a known signature of
+/// network failures.
+#[must_use]
+pub fn classify(pm: &str, err: Error) -> Error {
+    let Error::CmdStatusCodeError { output, .. } = &err else {
+        return err;
+    };
+    match detect(pm, &String::from_utf8_lossy(output)) {
+        Some(issue) => Error::NetworkError {
+            issue,
+            source: Box::new(err),
+        },
+        None => err,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path};
+
+    use super::*;
+    use crate::exec::Mode;
+
+    #[test]
+    fn detect_captured_outputs() {
+        let cases = [
+            ("apt-dns", "apt", Some(NetworkIssue::Dns)),
+            ("apt-proxy", "apt", Some(NetworkIssue::Proxy)),
+            ("apt-mirror", "apt", Some(NetworkIssue::Mirror)),
+            ("apt-not-found", "apt", None),
+            ("brew-connection", "brew", Some(NetworkIssue::Connection)),
+            ("brew-dns", "brew", Some(NetworkIssue::Dns)),
+            ("choco-dns", "choco", Some(NetworkIssue::Dns)),
+            ("dnf-dns", "dnf", Some(NetworkIssue::Dns)),
+            ("dnf-timeout", "dnf", Some(NetworkIssue::Connection)),
+            ("pip-dns", "pip", Some(NetworkIssue::Dns)),
+            ("zypper-dns", "zypper", Some(NetworkIssue::Dns)),
+        ];
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden/network");
+        for (name, pm, expected) in cases {
+            let output = fs::read_to_string(dir.join(format!("{name}.txt"))).unwrap();
+            assert_eq!(detect(pm, &output), expected, "`{name}` misclassified");
+        }
+    }
+
+    #[test]
+    fn classify_status_code_errors_only() {
+        let output = b"E: Failed to fetch http://deb.debian.org/debian/dists/bookworm/InRelease  \
+            Temporary failure resolving 'deb.debian.org'\n";
+        let err = Error::CmdStatusCodeError {
+            code: 100,
+            output: output.to_vec(),
+            cmd: "apt update".into(),
+            mode: Mode::Mute,
+        };
+        assert!(matches!(
+            classify("apt", err),
+            Error::NetworkError {
+                issue: NetworkIssue::Dns,
+                ..
+            }
+        ));
+        // The signatures of other package managers are not taken into account.
+        let err = Error::CmdStatusCodeError {
+            code: 1,
+            output: output.to_vec(),
+            cmd: "dnf check-update".into(),
+            mode: Mode::Mute,
+        };
+        assert!(matches!(
+            classify("dnf", err),
+            Error::CmdStatusCodeError { .. }
+        ));
+        assert!(matches!(
+            classify("apt", Error::CmdInterruptedError),
+            Error::CmdInterruptedError
+        ));
+    }
+}
@@ -0,0 +1,119 @@
+//! Classification of "package not found" failures of [`Cmd`](super::Cmd)s.
+//!
+//! Each package manager reports a package missing from its repositories in
+//! its own words, so the captured output of a failed command is scanned for
+//! the corresponding signature, which also names the missing package.
+
+use std::sync::LazyLock;
+
+use itertools::Itertools;
+use regex::Regex;
+
+use crate::error::Error;
+
+/// The signatures of "package not found" failures in the output of each
+/// package manager, in the form of `(pm, pattern)`, where the first capture
+/// group of `pattern` is the name of the missing package.
+const SIGNATURES: &[(&str, &str)] = &[
+    ("apk", r"(?m)^\s+(\S+) \(no such package\):"),
+    ("apt", r"Unable to locate package (\S+)"),
+    (
+        "brew",
+        r#"No available (?:formula|cask)(?: or cask)? with the name "([^"]+)""#,
+    ),
+    ("brew", r"(?m)No formulae or casks found for (\S+?)\.?$"),
+    (
+        "choco",
+        r"(?m)^(\S+) not installed\. The package was not found",
+    ),
+    ("dnf", r"No match for argument: (\S+)"),
+    ("pip", r"No matching distribution found for (\S+)"),
+    ("zypper", r"No provider of '([^']+)' found"),
+    ("zypper", r"Package '([^']+)' not found"),
+];
+
+/// The compiled [`SIGNATURES`].
+static MATCHERS: LazyLock<Vec<(&str, Regex)>> = LazyLock::new(|| {
+    SIGNATURES
+        .iter()
+        .map(|&(pm, pattern)| (pm, Regex::new(pattern).expect("signature should be valid")))
+        .collect()
+});
+
+/// Finds the packages of the package manager `pm` reported as missing in
+/// `output`, in order and without duplicates.
+#[must_use]
+pub fn detect(pm: &str, output: &str) -> Vec<String> {
+    MATCHERS
+        .iter()
+        .filter(|(name, _)| *name == pm)
+        .flat_map(|(_, re)| re.captures_iter(output))
+        .map(|caps| caps[1].to_owned())
+        .unique()
+        .collect()
+}
+
+/// Wraps `err` in an [`Error::PkgNotFoundError`] if it is a failure of a
+/// command run by the package manager `pm`, whose output reports some
+/// packages as missing.
+#[must_use]
+pub fn classify(pm: &str, err: Error) -> Error {
+    let Error::CmdStatusCodeError { output, .. } = &err else {
+        return err;
+    };
+    let pkgs = detect(pm, &String::from_utf8_lossy(output));
+    if pkgs.is_empty() {
+        return err;
+    }
+    Error::PkgNotFoundError {
+        pkgs,
+        source: Box::new(err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path};
+
+    use super::*;
+    use crate::exec::Mode;
+
+    #[test]
+    fn detect_captured_outputs() {
+        let cases: [(&str, &str, &[&str]); 9] = [
+            ("apk", "apk", &["no-such-package"]),
+            ("apt", "apt", &["no-such-package", "nor-this-one"]),
+            ("brew", "brew", &["no-such-formula"]),
+            ("choco", "choco", &["no-such-package"]),
+            ("dnf", "dnf", &["no-such-package"]),
+            ("pip", "pip", &["no-such-package"]),
+            ("zypper", "zypper", &["no-such-package"]),
+            // The signatures of other package managers are not taken into account.
+            ("apt", "dnf", &[]),
+            // Unrelated failures are left alone.
+            ("dnf-conflict", "dnf", &[]),
+        ];
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden/not_found");
+        for (name, pm, expected) in cases {
+            let output = fs::read_to_string(dir.join(format!("{name}.txt"))).unwrap();
+            assert_eq!(detect(pm, &output), expected, "`{name}` misclassified");
+        }
+    }
+
+    #[test]
+    fn classify_status_code_errors_only() {
+        let err = Error::CmdStatusCodeError {
+            code: 100,
+            output: b"E: Unable to locate package foo\n".to_vec(),
+            cmd: "sudo -S apt install foo".into(),
+            mode: Mode::Mute,
+        };
+        let err = classify("apt", err);
+        assert_eq!(err.to_string(), "package not found: `foo`");
+        assert!(matches!(err, Error::PkgNotFoundError { pkgs, .. } if pkgs == ["foo"]));
+        assert!(matches!(
+            classify("apt", Error::CmdInterruptedError),
+            Error::CmdInterruptedError
+        ));
+    }
+}
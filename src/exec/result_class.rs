@@ -0,0 +1,266 @@
+//! Normalized classification of the results of [`Cmd`](super::Cmd)s.
+//!
+//! Each package manager has its own exit codes and messages for a package
+//! being missing or already installed, or for a transaction having failed.
+//! These are mapped to a [`ResultClass`], which other features can rely on
+//! instead of matching the output of every package manager by themselves.
+
+use std::fmt::{self, Display};
+
+use super::{network, not_found, StatusCode};
+
+/// A normalized class of the result of a command run by a package manager.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ResultClass {
+    /// The command has succeeded and done something.
+    Success,
+
+    /// The command has succeeded, but there was nothing to do, e.g. no
+    /// package to upgrade.
+    NothingToDo,
+
+    /// The command has succeeded, but the requested packages were already
+    /// installed.
+    AlreadyInstalled,
+
+    /// The command has failed as some of the requested packages were not
+    /// found, see [`not_found::detect`].
+    PkgNotFound,
+
+    /// The command has failed because of a network issue, see
+    /// [`network::detect`].
+    Network,
+
+    /// The command has failed while resolving or committing the transaction,
+    /// e.g. because of a dependency conflict.
+    TransactionFailed,
+
+    /// The command has failed for any other reason.
+    Failed,
+}
+
+impl ResultClass {
+    /// Checks if this class is one of a successful command.
+    #[must_use]
+    pub const fn is_success(self) -> bool {
+        matches!(
+            self,
+            Self::Success | Self::NothingToDo | Self::AlreadyInstalled
+        )
+    }
+}
+
+impl Display for ResultClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Success => "success",
+            Self::NothingToDo => "nothing to do",
+            Self::AlreadyInstalled => "already installed",
+            Self::PkgNotFound => "package not found",
+            Self::Network => "network failure",
+            Self::TransactionFailed => "transaction failed",
+            Self::Failed => "failed",
+        })
+    }
+}
+
+/// The signatures of the result classes in the output of each package
+/// manager, in the form of `(pm, signature, class)`.
+///
+/// A signature is only taken into account if the success of its class agrees
+/// with the exit code. The entries are checked in order, so the more specific
+/// signatures of a package manager should come first.
+const SIGNATURES: &[(&str, &str, ResultClass)] = &[
+    (
+        "apk",
+        "ERROR: unable to select packages",
+        ResultClass::TransactionFailed,
+    ),
+    (
+        "apt",
+        "is already the newest version",
+        ResultClass::AlreadyInstalled,
+    ),
+    (
+        "apt",
+        "0 upgraded, 0 newly installed, 0 to remove",
+        ResultClass::NothingToDo,
+    ),
+    (
+        "apt",
+        "E: Unmet dependencies",
+        ResultClass::TransactionFailed,
+    ),
+    (
+        "apt",
+        "E: Sub-process /usr/bin/dpkg returned an error code",
+        ResultClass::TransactionFailed,
+    ),
+    (
+        "brew",
+        "is already installed",
+        ResultClass::AlreadyInstalled,
+    ),
+    ("choco", "already installed", ResultClass::AlreadyInstalled),
+    ("choco", "NOT successful", ResultClass::TransactionFailed),
+    ("dnf", "is already installed", ResultClass::AlreadyInstalled),
+    ("dnf", "Nothing to do", ResultClass::NothingToDo),
+    ("dnf", "Problem: ", ResultClass::TransactionFailed),
+    (
+        "dnf",
+        "Transaction check error",
+        ResultClass::TransactionFailed,
+    ),
+    (
+        "dnf",
+        "Transaction test error",
+        ResultClass::TransactionFailed,
+    ),
+    (
+        "pip",
+        "Requirement already satisfied",
+        ResultClass::AlreadyInstalled,
+    ),
+    (
+        "pip",
+        "ResolutionImpossible",
+        ResultClass::TransactionFailed,
+    ),
+    (
+        "pip",
+        "Could not install packages",
+        ResultClass::TransactionFailed,
+    ),
+    (
+        "zypper",
+        "is already installed",
+        ResultClass::AlreadyInstalled,
+    ),
+    ("zypper", "Nothing to do", ResultClass::NothingToDo),
+    ("zypper", "Problem: ", ResultClass::TransactionFailed),
+    (
+        "zypper",
+        "Problem occurred during or after installation",
+        ResultClass::TransactionFailed,
+    ),
+];
+
+/// Classifies the result of a command run by the package manager `pm` with
+/// the exit `code` and the captured `output`.
+///
+/// A failure is first checked for missing packages and network issues, and
+/// then any result for the [`SIGNATURES`] of `pm`.
+#[must_use]
+pub fn classify(pm: &str, code: StatusCode, output: &[u8]) -> ResultClass {
+    let output = String::from_utf8_lossy(output);
+    let succeeded = code == 0;
+    if !succeeded {
+        if !not_found::detect(pm, &output).is_empty() {
+            return ResultClass::PkgNotFound;
+        }
+        if network::detect(pm, &output).is_some() {
+            return ResultClass::Network;
+        }
+    }
+    SIGNATURES
+        .iter()
+        .find(|&&(name, signature, class)| {
+            name == pm && class.is_success() == succeeded && output.contains(signature)
+        })
+        .map_or(
+            if succeeded {
+                ResultClass::Success
+            } else {
+                ResultClass::Failed
+            },
+            |&(.., class)| class,
+        )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path};
+
+    use super::*;
+
+    #[test]
+    fn classify_captured_outputs() {
+        let cases = [
+            (
+                "result/apk-conflict",
+                "apk",
+                1,
+                ResultClass::TransactionFailed,
+            ),
+            (
+                "result/apt-installed",
+                "apt",
+                0,
+                ResultClass::AlreadyInstalled,
+            ),
+            ("result/apt-nothing", "apt", 0, ResultClass::NothingToDo),
+            (
+                "result/apt-dpkg",
+                "apt",
+                100,
+                ResultClass::TransactionFailed,
+            ),
+            ("not_found/apt", "apt", 100, ResultClass::PkgNotFound),
+            ("network/apt-dns", "apt", 100, ResultClass::Network),
+            (
+                "result/brew-installed",
+                "brew",
+                0,
+                ResultClass::AlreadyInstalled,
+            ),
+            ("not_found/brew", "brew", 1, ResultClass::PkgNotFound),
+            (
+                "result/choco-failed",
+                "choco",
+                1,
+                ResultClass::TransactionFailed,
+            ),
+            (
+                "result/dnf-installed",
+                "dnf",
+                0,
+                ResultClass::AlreadyInstalled,
+            ),
+            ("result/dnf-nothing", "dnf", 0, ResultClass::NothingToDo),
+            (
+                "not_found/dnf-conflict",
+                "dnf",
+                1,
+                ResultClass::TransactionFailed,
+            ),
+            ("network/dnf-timeout", "dnf", 1, ResultClass::Network),
+            (
+                "result/pip-installed",
+                "pip",
+                0,
+                ResultClass::AlreadyInstalled,
+            ),
+            ("not_found/pip", "pip", 1, ResultClass::PkgNotFound),
+            (
+                "result/zypper-nothing",
+                "zypper",
+                0,
+                ResultClass::NothingToDo,
+            ),
+            ("not_found/zypper", "zypper", 104, ResultClass::PkgNotFound),
+            // The signatures of other package managers are not taken into account.
+            ("result/dnf-nothing", "apt", 0, ResultClass::Success),
+            // Nor are the signatures disagreeing with the exit code.
+            ("result/apt-installed", "apt", 100, ResultClass::Failed),
+        ];
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden");
+        for (name, pm, code, expected) in cases {
+            let output = fs::read(dir.join(format!("{name}.txt"))).unwrap();
+            assert_eq!(
+                classify(pm, code, &output),
+                expected,
+                "`{name}` misclassified"
+            );
+        }
+    }
+}
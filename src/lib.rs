@@ -21,5 +21,8 @@
 pub mod config;
 pub mod error;
 pub mod exec;
+pub mod pkg;
 pub mod pm;
 pub mod print;
+#[cfg(feature = "self-update")]
+pub mod self_update;
@@ -4,13 +4,17 @@ mod _built {
     include!(concat!(env!("OUT_DIR"), "/built.rs"));
 }
 
+use std::process::{ExitCode, Termination};
+
 use clap::Parser;
 use pacaptr::error::MainError;
 
 use crate::cmd::Pacaptr;
 
 #[tokio::main]
-async fn main() -> Result<(), MainError> {
-    Pacaptr::parse().dispatch().await?;
-    Ok(())
+async fn main() -> ExitCode {
+    match Pacaptr::parse().dispatch().await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => MainError::from(e).report(),
+    }
 }
@@ -0,0 +1,533 @@
+//! Structured package records parsed from the output of package managers.
+//!
+//! # Output Formats
+//!
+//! With `--output-format`, supported query operations print the parsed
+//! package records instead of the output of the package manager:
+//! - `plain` (the default) prints the output of the package manager as is;
+//! - `table` prints aligned columns of name, version and source;
+//! - `json` prints a JSON array of objects with the `name`, `version` and
+//!   `source` keys;
+//! - `porcelain` (or `--porcelain`) prints one package per line in the format
+//!   below.
+//!
+//! In each format, `source` is the repository or channel the package comes
+//! from if the package manager reports it, or the name of the package manager
+//! otherwise.
+//!
+//! If an operation cannot provide structured data under a given package
+//! manager, i.e. there is no parser for its output, only `plain` is available:
+//! no output will be printed for the other formats and [`pacaptr`](crate) will
+//! exit with code `4` instead.
+//!
+//! # Porcelain Format
+//!
+//! ```txt
+//! name<TAB>version<TAB>source
+//! ```
+//!
+//! This format is guaranteed to stay stable across releases: new information
+//! might only be appended as new columns at the end of each line.
+
+use std::{
+    collections::HashMap,
+    fmt::{self, Display},
+};
+
+use serde::{Deserialize, Serialize};
+
+/// A package record parsed from the output of a package manager.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Pkg {
+    /// The name of the package.
+    pub name: String,
+
+    /// The version of the package.
+    pub version: String,
+
+    /// The repository or channel the package comes from.
+    pub source: String,
+}
+
+impl Pkg {
+    /// Makes a new [`Pkg`] instance.
+    #[must_use]
+    pub fn new(
+        name: impl Into<String>,
+        version: impl Into<String>,
+        source: impl Into<String>,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            version: version.into(),
+            source: source.into(),
+        }
+    }
+}
+
+/// Displays a [`Pkg`] in the porcelain format.
+impl Display for Pkg {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}\t{}\t{}", self.name, self.version, self.source)
+    }
+}
+
+/// A function parsing the output of a package manager into [`Pkg`]s.
+pub type Parser = fn(&str) -> Vec<Pkg>;
+
+/// A package update parsed from the output of a package manager.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Update {
+    /// The name of the package.
+    pub name: String,
+
+    /// The currently installed version of the package.
+    pub old: String,
+
+    /// The version the package can be upgraded to.
+    pub new: String,
+}
+
+impl Update {
+    /// Makes a new [`Update`] instance.
+    #[must_use]
+    pub fn new(name: impl Into<String>, old: impl Into<String>, new: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            old: old.into(),
+            new: new.into(),
+        }
+    }
+}
+
+/// A function parsing the output of a package manager into [`Update`]s.
+///
+/// Each line that looks like an entry but cannot be parsed is kept as is in an
+/// `Err`, while headers and other noises are skipped.
+pub type UpdateParser = fn(&str) -> Vec<Result<Update, String>>;
+
+/// The result of verifying an installed package against the package
+/// database and the repositories.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Verification {
+    /// The name of the package.
+    pub name: String,
+
+    /// The installed version of the package.
+    pub version: String,
+
+    /// How the installed version compares to the ones in the repositories.
+    pub repo: RepoStatus,
+
+    /// The installed files that differ from the packaged ones.
+    pub changes: Vec<FileChange>,
+}
+
+impl Verification {
+    /// Checks if the package is intact, i.e. none of its files has changed and
+    /// the installed version is available in the repositories.
+    #[must_use]
+    pub fn is_intact(&self) -> bool {
+        self.changes.is_empty() && self.repo == RepoStatus::Match
+    }
+}
+
+/// How the installed version of a package compares to the ones available in
+/// the repositories.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RepoStatus {
+    /// The installed version is available in the repositories.
+    Match,
+
+    /// The package is available in the repositories, but not in the installed
+    /// version.
+    Mismatch,
+
+    /// The package is not available in any of the repositories.
+    NotFound,
+}
+
+impl RepoStatus {
+    /// Compares the installed `version` to the ones available in the
+    /// repositories.
+    #[must_use]
+    pub fn of<'a>(version: &str, available: impl IntoIterator<Item = &'a str>) -> Self {
+        let mut available = available.into_iter().peekable();
+        if available.peek().is_none() {
+            Self::NotFound
+        } else if available.any(|ver| ver == version) {
+            Self::Match
+        } else {
+            Self::Mismatch
+        }
+    }
+}
+
+/// An installed file that differs from the packaged one.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct FileChange {
+    /// The path of the file.
+    pub path: String,
+
+    /// The attributes that differ as reported by the package manager, e.g.
+    /// `S.5....T.` for `rpm -V`, or `missing` if the file is gone.
+    pub attrs: String,
+
+    /// Whether the file is a config file, which is usually expected to be
+    /// modified.
+    pub config: bool,
+}
+
+/// The result of checking the files of an installed package against the
+/// package database.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct FileCheck {
+    /// The name of the package.
+    pub name: String,
+
+    /// The number of files owned by the package.
+    pub total: usize,
+
+    /// The files that differ from the packaged ones.
+    pub altered: Vec<FileChange>,
+}
+
+impl FileCheck {
+    /// Groups the `(package, path)` pairs listing the files owned by each
+    /// package into [`FileCheck`]s, in the order where each package first
+    /// appears, and attributes the given `changes` to the packages owning the
+    /// changed files.
+    ///
+    /// This is useful when the verification tool only reports the changed
+    /// paths, e.g. `rpm -Va` or `dpkg --verify`.
+    #[must_use]
+    pub fn group<'a>(
+        files: impl IntoIterator<Item = (&'a str, &'a str)>,
+        changes: &[FileChange],
+    ) -> Vec<Self> {
+        let changes: HashMap<_, _> = (changes.iter())
+            .map(|change| (change.path.as_str(), change))
+            .collect();
+        let mut checks: Vec<Self> = vec![];
+        let mut indices = HashMap::new();
+        for (name, path) in files {
+            let idx = *indices.entry(name).or_insert_with(|| {
+                checks.push(Self {
+                    name: name.into(),
+                    total: 0,
+                    altered: vec![],
+                });
+                checks.len() - 1
+            });
+            let check = &mut checks[idx];
+            check.total += 1;
+            if let Some(&change) = changes.get(path) {
+                check.altered.push(change.clone());
+            }
+        }
+        checks
+    }
+}
+
+/// A function parsing the file list and the verification output of a package
+/// manager into [`FileCheck`]s.
+pub type FileCheckParser = fn(&str, &str) -> Vec<FileCheck>;
+
+/// A dependency conflict reported by the resolver of a package manager.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Conflict {
+    /// The package whose dependency cannot be satisfied.
+    pub pkg: String,
+
+    /// How `pkg` relates to `target`, e.g. `depends on` or `conflicts with`.
+    pub relation: String,
+
+    /// The dependency in question, possibly with a version constraint.
+    pub target: String,
+
+    /// What the resolver has found instead, if reported, e.g. `found 1.5-1`.
+    pub detail: Option<String>,
+}
+
+impl Conflict {
+    /// Makes a new [`Conflict`] instance without any detail.
+    #[must_use]
+    pub fn new(
+        pkg: impl Into<String>,
+        relation: impl Into<String>,
+        target: impl Into<String>,
+    ) -> Self {
+        Self {
+            pkg: pkg.into(),
+            relation: relation.into(),
+            target: target.into(),
+            detail: None,
+        }
+    }
+
+    /// Sets the detail of this [`Conflict`].
+    #[must_use]
+    pub fn detail(mut self, detail: impl Into<String>) -> Self {
+        self.detail = Some(detail.into());
+        self
+    }
+}
+
+impl Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} {}", self.pkg, self.relation, self.target)?;
+        self.detail
+            .as_ref()
+            .map_or(Ok(()), |detail| write!(f, " ({detail})"))
+    }
+}
+
+/// A function parsing the verbose output of a resolver into [`Conflict`]s.
+pub type ConflictParser = fn(&str) -> Vec<Conflict>;
+
+/// The dependencies of packages, mapping the name of each package to the
+/// names of its direct dependencies.
+pub type DepGraph = HashMap<String, Vec<String>>;
+
+/// A function parsing the output of a package manager into a [`DepGraph`].
+pub type DepGraphParser = fn(&str) -> DepGraph;
+
+/// A function parsing the output of a package manager into package names.
+pub type NameParser = fn(&str) -> Vec<String>;
+
+/// A function parsing the output of a package manager into the installed
+/// sizes of packages, as `(name, bytes)`.
+pub type PkgSizeParser = fn(&str) -> Vec<(String, u64)>;
+
+/// Parses the lines starting with whitespace-separated `name version` pairs,
+/// with `source` as the source of all packages.
+///
+/// Empty lines and lines starting with `#` are skipped.
+pub(crate) fn parse_name_version(text: &str, source: &str) -> Vec<Pkg> {
+    text.lines()
+        .filter(|ln| !ln.starts_with('#'))
+        .filter_map(|ln| {
+            let mut cols = ln.split_whitespace();
+            Some(Pkg::new(cols.next()?, cols.next()?, source))
+        })
+        .collect()
+}
+
+/// Splits a keyword pinning a package to a version, i.e. `name=version` (or
+/// `name==version` as with `pip`), into the name and the version.
+///
+/// A keyword without any version is returned as the name as is.
+pub(crate) fn split_pinned(kw: &str) -> (&str, Option<&str>) {
+    match kw.split_once('=') {
+        Some((name, version)) if !name.is_empty() => {
+            let version = version.trim_start_matches('=');
+            (name, (!version.is_empty()).then_some(version))
+        }
+        _ => (kw, None),
+    }
+}
+
+/// Parses each non-empty line of `text` into an [`Update`] with `parse`,
+/// skipping the lines that are considered noises by `is_noise`.
+///
+/// The lines that cannot be parsed are kept as is in an `Err`.
+pub(crate) fn parse_update_lines(
+    text: &str,
+    is_noise: impl Fn(&str) -> bool,
+    parse: impl Fn(&str) -> Option<Update>,
+) -> Vec<Result<Update, String>> {
+    text.lines()
+        .filter(|ln| !ln.trim().is_empty() && !is_noise(ln))
+        .map(|ln| parse(ln).ok_or_else(|| ln.to_owned()))
+        .collect()
+}
+
+/// Checks the porcelain output of `parse` against the golden file
+/// `tests/golden/porcelain/{name}.out`, given the input from
+/// `tests/golden/porcelain/{name}.in`.
+#[cfg(test)]
+pub(crate) fn check_golden(name: &str, parse: Parser) {
+    use std::{fs, path::Path};
+
+    use itertools::Itertools;
+
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden/porcelain");
+    let read = |ext| fs::read_to_string(dir.join(format!("{name}.{ext}"))).unwrap();
+    let got = parse(&read("in"))
+        .iter()
+        .map(|pkg| format!("{pkg}\n"))
+        .join("");
+    assert_eq!(got, read("out"), "porcelain output mismatch for `{name}`");
+}
+
+/// Checks the updates parsed by `parse` against the golden file
+/// `tests/golden/updates/{name}.out`, given the input from
+/// `tests/golden/updates/{name}.in`.
+///
+/// Each update is written as `name old -> new`, and each unparsed line is
+/// prefixed with `? `.
+#[cfg(test)]
+pub(crate) fn check_golden_updates(name: &str, parse: UpdateParser) {
+    use std::{fs, path::Path};
+
+    use itertools::Itertools;
+
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden/updates");
+    let read = |ext| fs::read_to_string(dir.join(format!("{name}.{ext}"))).unwrap();
+    let got = parse(&read("in"))
+        .iter()
+        .map(|update| match update {
+            Ok(Update { name, old, new }) => format!("{name} {old} -> {new}\n"),
+            Err(line) => format!("? {line}\n"),
+        })
+        .join("");
+    assert_eq!(got, read("out"), "updates mismatch for `{name}`");
+}
+
+/// Checks the conflicts parsed by `parse` against the golden file
+/// `tests/golden/conflicts/{name}.out`, given the input from
+/// `tests/golden/conflicts/{name}.in`.
+#[cfg(test)]
+pub(crate) fn check_golden_conflicts(name: &str, parse: ConflictParser) {
+    use std::{fs, path::Path};
+
+    use itertools::Itertools;
+
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden/conflicts");
+    let read = |ext| fs::read_to_string(dir.join(format!("{name}.{ext}"))).unwrap();
+    let got = parse(&read("in"))
+        .iter()
+        .map(|conflict| format!("{conflict}\n"))
+        .join("");
+    assert_eq!(got, read("out"), "conflicts mismatch for `{name}`");
+}
+
+/// Checks the file checks parsed by `parse` against the golden file
+/// `tests/golden/file_checks/{name}.out`, given the file list from
+/// `tests/golden/file_checks/{name}.files` and the verification output from
+/// `tests/golden/file_checks/{name}.verify`.
+///
+/// Each package is written as `name altered/total`, followed by one line per
+/// altered file in the style of `rpm -V`.
+#[cfg(test)]
+pub(crate) fn check_golden_file_checks(name: &str, parse: FileCheckParser) {
+    use std::{fmt::Write, fs, path::Path};
+
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden/file_checks");
+    let read = |ext| fs::read_to_string(dir.join(format!("{name}.{ext}"))).unwrap();
+    let mut got = String::new();
+    for check in parse(&read("files"), &read("verify")) {
+        _ = writeln!(
+            got,
+            "{} {}/{}",
+            check.name,
+            check.altered.len(),
+            check.total
+        );
+        for change in &check.altered {
+            let kind = if change.config { 'c' } else { ' ' };
+            _ = writeln!(got, "    {:<9} {kind} {}", change.attrs, change.path);
+        }
+    }
+    assert_eq!(got, read("out"), "file checks mismatch for `{name}`");
+}
+
+/// Checks the dependency graph parsed by `parse` against the golden file
+/// `tests/golden/deps/{name}.out`, given the input from
+/// `tests/golden/deps/{name}.in`.
+///
+/// Each package is written as `name: deps...`, in the order of the names.
+#[cfg(test)]
+pub(crate) fn check_golden_deps(name: &str, parse: DepGraphParser) {
+    use std::{fs, path::Path};
+
+    use itertools::Itertools;
+
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden/deps");
+    let read = |ext| fs::read_to_string(dir.join(format!("{name}.{ext}"))).unwrap();
+    let got = parse(&read("in"))
+        .iter()
+        .sorted()
+        .map(|(pkg, deps)| {
+            format!(
+                "{pkg}:{}\n",
+                deps.iter().map(|dep| format!(" {dep}")).join("")
+            )
+        })
+        .join("");
+    assert_eq!(got, read("out"), "dependencies mismatch for `{name}`");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pkg_display() {
+        let pkg = Pkg::new("curl", "7.81.0-1ubuntu1.15", "jammy-updates");
+        assert_eq!(pkg.to_string(), "curl\t7.81.0-1ubuntu1.15\tjammy-updates");
+    }
+
+    #[test]
+    fn name_version() {
+        let text = "# comment\n\nfoo 1.0 extra\nbar\nbaz 2.1\n";
+        assert_eq!(
+            parse_name_version(text, "src"),
+            [Pkg::new("foo", "1.0", "src"), Pkg::new("baz", "2.1", "src")]
+        );
+    }
+
+    #[test]
+    fn pinned() {
+        assert_eq!(split_pinned("curl=7.81.0"), ("curl", Some("7.81.0")));
+        assert_eq!(split_pinned("requests==2.31"), ("requests", Some("2.31")));
+        assert_eq!(split_pinned("vim=2:8.2=1"), ("vim", Some("2:8.2=1")));
+        assert_eq!(split_pinned("curl"), ("curl", None));
+        assert_eq!(split_pinned("curl="), ("curl", None));
+        assert_eq!(split_pinned("=1.0"), ("=1.0", None));
+    }
+
+    #[test]
+    fn update_lines() {
+        let text = "# header\n\nfoo 1.0 2.0\nbar\n";
+        let parse = |ln: &str| {
+            let mut cols = ln.split_whitespace();
+            Some(Update::new(cols.next()?, cols.next()?, cols.next()?))
+        };
+        assert_eq!(
+            parse_update_lines(text, |ln| ln.starts_with('#'), parse),
+            [Ok(Update::new("foo", "1.0", "2.0")), Err("bar".into())]
+        );
+    }
+
+    #[test]
+    fn group_file_checks() {
+        let files = [
+            ("curl", "/usr/bin/curl"),
+            ("libcurl", "/usr/lib/libcurl.so"),
+            ("curl", "/usr/share/man/man1/curl.1.gz"),
+            ("libcurl", "/usr/share/licenses/curl"),
+        ];
+        let change = FileChange {
+            path: "/usr/share/licenses/curl".into(),
+            attrs: "missing".into(),
+            config: false,
+        };
+        assert_eq!(
+            FileCheck::group(files, std::slice::from_ref(&change)),
+            [
+                FileCheck {
+                    name: "curl".into(),
+                    total: 2,
+                    altered: vec![],
+                },
+                FileCheck {
+                    name: "libcurl".into(),
+                    total: 2,
+                    altered: vec![change],
+                },
+            ]
+        );
+    }
+}
@@ -11,6 +11,10 @@ macro_rules! pm_mods {
             $vis mod $mod;
             paste! { pub use self::$mod::[<$mod:camel>]; }
         )+
+
+        /// The names of all the modules declared above.
+        #[cfg(test)]
+        const PM_MODS: &[&str] = &[$( stringify!($mod), )+];
     }
 }
 
@@ -25,7 +29,9 @@ pm_mods! {
     pip;
     pkcon;
     port;
+    rpm_ostree;
     scoop;
+    stack;
     tlmgr;
     unknown;
     winget;
@@ -33,19 +39,40 @@ pm_mods! {
     zypper;
 }
 
-use std::env;
+pub mod guard;
+pub mod query_cache;
+pub mod snapshot;
+
+use std::{
+    collections::{HashMap, HashSet},
+    env, fs,
+    future::Future,
+    io::{self, IsTerminal},
+    path::{Path, PathBuf},
+    slice,
+    time::Duration,
+};
 
 use async_trait::async_trait;
-use itertools::Itertools;
+use indoc::indoc;
+use itertools::{chain, Itertools};
 use macro_rules_attribute::macro_rules_attribute;
 use paste::paste;
 use tt_call::tt_call;
 
 use crate::{
-    config::Config,
-    error::Result,
-    exec::{self, is_exe, Cmd, Mode, Output},
-    print::{println_quoted, prompt},
+    config::{Config, MacosPm, SearchSort},
+    error::{Error, ExitKind, MultiError, Result},
+    exec::{
+        self, download, is_exe, network, not_found,
+        result_class::{self, ResultClass},
+        Cmd, Mode, Output, StatusCode,
+    },
+    pkg::{
+        ConflictParser, DepGraphParser, FileCheckParser, NameParser, Parser, Pkg, PkgSizeParser,
+        Update, UpdateParser,
+    },
+    print::{self, prompt},
 };
 
 /// The list of [`pacman`](https://wiki.archlinux.org/index.php/Pacman) methods supported by [`pacaptr`](crate).
@@ -74,6 +101,9 @@ macro_rules! methods {
                 /// Qk verifies one or more packages.
                 async fn qk;
 
+                /// Qkk verifies one or more packages, listing every altered file.
+                async fn qkk;
+
                 /// Ql displays files provided by local package.
                 async fn ql;
 
@@ -155,6 +185,52 @@ macro_rules! methods {
     };
 }
 
+/// The [`methods!`] needing the network, which are refused up-front with
+/// [`Config::offline`] unless they are among [`Pm::offline_methods`].
+///
+/// The other ones, e.g. the local queries and the removals, are allowed.
+pub const NETWORK_METHODS: &[&str] = &[
+    "downgrade",
+    "module_enable",
+    "module_install",
+    "s",
+    "sg",
+    "si",
+    "sii",
+    "sl",
+    "ss",
+    "su",
+    "suy",
+    "sw",
+    "sy",
+];
+
+/// The name of a package manager, as returned by [`Pm::name`].
+pub type PmName = &'static str;
+
+/// The name of one of the [`methods!`], e.g. `suy`.
+pub type Op = &'static str;
+
+/// Whether an [`Op`] is implemented by a package manager, rather than failing
+/// with an [`Error::OperationUnimplementedError`].
+pub type Support = bool;
+
+/// The compatibility table of the package managers, i.e. whether each of them
+/// supports each of the [`methods!`].
+///
+/// This is generated from the same data as the compatibility table in the
+/// docs, see [`supports`] for the lookup.
+pub static COMPAT: &[(PmName, Op, Support)] = pacaptr_macros::compat_data!();
+
+/// Checks if the package manager `pm` (e.g. `rpm-ostree`) supports the
+/// operation `op` (e.g. `suy`), according to [`COMPAT`].
+#[must_use]
+pub fn supports(pm: &str, op: &str) -> bool {
+    COMPAT
+        .iter()
+        .any(|&(name, method, support)| name == pm && method == op && support)
+}
+
 macro_rules! make_op_body {
     ($self:ident, $method:ident) => {{
         Err(crate::error::Error::OperationUnimplementedError {
@@ -221,6 +297,215 @@ pub trait Pm: Sync {
     /// Gets the config of the package manager.
     fn cfg(&self) -> &Config;
 
+    /// Checks if the package manager can install packages into
+    /// [`Config::install_into`].
+    fn supports_install_into(&self) -> bool {
+        false
+    }
+
+    /// Checks if the package manager can downgrade packages when upgrading
+    /// them with [`Config::allow_downgrade`].
+    fn supports_allow_downgrade(&self) -> bool {
+        false
+    }
+
+    /// Checks if the package manager can install, upgrade and show packages
+    /// from [`Config::target_release`].
+    fn supports_target_release(&self) -> bool {
+        false
+    }
+
+    /// Gets the native flags of `-S` for the [`BuildOption`]s supported by
+    /// the package manager.
+    fn build_option_flags(&self) -> &[(BuildOption, &'static str)] {
+        &[]
+    }
+
+    /// Checks if the package manager can filter the results of `-Ss` with
+    /// [`Config::exclude_installed`] and [`Config::only_installed`].
+    fn supports_installed_filter(&self) -> bool {
+        false
+    }
+
+    /// Checks if the package manager can truncate the results of `-Ss` to
+    /// [`Config::search_limit`].
+    fn supports_search_limit(&self) -> bool {
+        false
+    }
+
+    /// Checks if the package manager can sort the results of `-Ss` by name
+    /// with [`Config::search_sort`].
+    fn supports_search_sort(&self) -> bool {
+        false
+    }
+
+    /// Gets the operations (`qi` and/or `si`) for which the package manager
+    /// can show the dependency trees of the packages with [`Config::tree`].
+    fn dep_tree_methods(&self) -> &[&'static str] {
+        &[]
+    }
+
+    /// Gets the operations for which the package manager can show its own plan
+    /// before the confirmation prompt with [`Config::preview`], i.e. the ones
+    /// with a [`PromptStrategy::CustomPrompt`] and a
+    /// [`DryRunStrategy::WithFlags`].
+    fn preview_methods(&self) -> &[&'static str] {
+        &[]
+    }
+
+    /// Gets the [`NETWORK_METHODS`] which the package manager can still run
+    /// with [`Config::offline`], by mapping it to an offline mode of its own,
+    /// e.g. from its cache.
+    fn offline_methods(&self) -> &[&'static str] {
+        &[]
+    }
+
+    /// Gets the operations (among `q`, `qi`, `s` and `su`) for which the
+    /// package manager can report the sizes of the packages with
+    /// [`Config::sizes`].
+    fn pkg_size_methods(&self) -> &[&'static str] {
+        &[]
+    }
+
+    /// Checks if the package manager can enable the repositories in
+    /// [`Config::enable_repo`] for the sync operations.
+    fn supports_enable_repo(&self) -> bool {
+        false
+    }
+
+    /// Checks if the package manager can disable the repositories in
+    /// [`Config::disable_repo`] for the sync operations.
+    fn supports_disable_repo(&self) -> bool {
+        false
+    }
+
+    /// Checks if the package manager can verify the package files downloaded
+    /// by `-Sw` against the checksums of the repositories with
+    /// [`Config::verify_checksums`].
+    fn supports_verify_checksums(&self) -> bool {
+        false
+    }
+
+    /// Checks if the package manager can download packages in parallel with
+    /// [`Config::parallel_downloads`] when installing or upgrading them.
+    fn supports_parallel_downloads(&self) -> bool {
+        false
+    }
+
+    /// Checks if the package manager is able to undo its own transactions
+    /// with [`Pm::undo`], in which case no [`snapshot`] is recorded for it.
+    fn supports_undo(&self) -> bool {
+        false
+    }
+
+    /// Checks if the failure `err` has been caused by a lock held by another
+    /// process, returning a description of the lock if so.
+    ///
+    /// The failed command is retried with an exponential backoff up to
+    /// [`Config::max_retries_on_lock`] times while the lock stays busy.
+    fn busy_lock(&self, _err: &Error) -> Option<String> {
+        None
+    }
+
+    /// Classifies the result of a command run by the package manager with the
+    /// exit `code` and the captured `output` into a [`ResultClass`].
+    ///
+    /// Every failed command goes through it: a failure classified as
+    /// successful is not reported as such, and the missing packages and
+    /// network issues are detailed in the returned [`Error`].
+    ///
+    /// Package managers with meaningful exit codes should refine the default
+    /// classification, which is based on the output only, see
+    /// [`result_class::classify`].
+    fn classify_result(&self, code: StatusCode, output: &Output) -> ResultClass {
+        result_class::classify(self.name(), code, output)
+    }
+
+    /// Autoremove shows all the packages that are no longer required by any
+    /// other installed package, then removes them after confirmation.
+    async fn autoremove(&self, _kws: &[&str], _flags: &[&str]) -> Result<()> {
+        make_op_body!(self, autoremove)
+    }
+
+    /// Autoremove interactive lists all the packages that are no longer
+    /// required by any other installed package with their sizes, then removes
+    /// the ones chosen by the user.
+    async fn autoremove_interactive(&self, _kws: &[&str], _flags: &[&str]) -> Result<()> {
+        make_op_body!(self, autoremove_interactive)
+    }
+
+    /// Repair tries to bring a system in a broken or half-configured state
+    /// back to a consistent one.
+    async fn repair(&self, _kws: &[&str], _flags: &[&str]) -> Result<()> {
+        make_op_body!(self, repair)
+    }
+
+    /// Verify checks the installed files of the given packages against the
+    /// packaged ones, and the installed versions against the repositories.
+    async fn verify(&self, _kws: &[&str], _flags: &[&str]) -> Result<()> {
+        make_op_body!(self, verify)
+    }
+
+    /// Refresh keys refreshes the keys used to verify the signatures of the
+    /// repositories and their packages.
+    async fn refresh_keys(&self, _kws: &[&str], _flags: &[&str]) -> Result<()> {
+        make_op_body!(self, refresh_keys)
+    }
+
+    /// Downgrade installs an older version of the given packages, i.e. the one
+    /// pinned with `name=version`, or the previous one (if supported)
+    /// otherwise.
+    async fn downgrade(&self, _kws: &[&str], _flags: &[&str]) -> Result<()> {
+        make_op_body!(self, downgrade)
+    }
+
+    /// Undo reverts the transaction given by its ID in the history of the
+    /// package manager, or the last one if no ID is given.
+    async fn undo(&self, _kws: &[&str], _flags: &[&str]) -> Result<()> {
+        make_op_body!(self, undo)
+    }
+
+    /// Module list lists the module streams (e.g. of `dnf`) matching the
+    /// given patterns, or all of them if none is given.
+    async fn module_list(&self, _kws: &[&str], _flags: &[&str]) -> Result<()> {
+        make_op_body!(self, module_list)
+    }
+
+    /// Module enable enables the given module streams, e.g. `nodejs:18`, so
+    /// that their packages take precedence over the ones outside of them.
+    async fn module_enable(&self, _kws: &[&str], _flags: &[&str]) -> Result<()> {
+        make_op_body!(self, module_enable)
+    }
+
+    /// Module install enables the given module streams and installs their
+    /// packages, e.g. `nodejs:18` or `nodejs:18/development` with a profile.
+    async fn module_install(&self, _kws: &[&str], _flags: &[&str]) -> Result<()> {
+        make_op_body!(self, module_install)
+    }
+
+    /// Module reset resets the given modules to their initial state, so that
+    /// none of their streams is enabled or disabled.
+    async fn module_reset(&self, _kws: &[&str], _flags: &[&str]) -> Result<()> {
+        make_op_body!(self, module_reset)
+    }
+
+    /// Service list lists the services (e.g. of `zypper`), which manage sets
+    /// of repositories on their own.
+    async fn service_list(&self, _kws: &[&str], _flags: &[&str]) -> Result<()> {
+        make_op_body!(self, service_list)
+    }
+
+    /// Service add adds the service given by its URI and its alias.
+    async fn service_add(&self, _kws: &[&str], _flags: &[&str]) -> Result<()> {
+        make_op_body!(self, service_add)
+    }
+
+    /// Service remove removes the given services, along with their
+    /// repositories.
+    async fn service_remove(&self, _kws: &[&str], _flags: &[&str]) -> Result<()> {
+        make_op_body!(self, service_remove)
+    }
+
     /// Wraps the [`Pm`] instance in a [`Box`].
     fn boxed<'a>(self) -> BoxPm<'a>
     where
@@ -237,12 +522,8 @@ impl From<Config> for BoxPm<'_> {
     /// Generates the `Pm` instance according it's name, feeding it with the
     /// current `Config`.
     fn from(mut cfg: Config) -> Self {
-        // If the `Pm` to be used is not stated in any config,
-        // we should fall back to automatic detection and overwrite `cfg`.
-        let pm = cfg.default_pm.get_or_insert_with(|| detect_pm_str().into());
-
         #[allow(clippy::match_single_binding)]
-        match pm.as_ref() {
+        match resolve_default_pm(&mut cfg) {
             // Chocolatey
             "choco" => Choco::new(cfg).boxed(),
 
@@ -267,6 +548,9 @@ impl From<Config> for BoxPm<'_> {
             // Dnf for RedHat
             "dnf" => Dnf::new(cfg).boxed(),
 
+            // Rpm-ostree for immutable Fedora variants
+            "rpm-ostree" => RpmOstree::new(cfg).boxed(),
+
             // Portage for Gentoo
             "emerge" => Emerge::new(cfg).boxed(),
 
@@ -287,15 +571,15 @@ impl From<Config> for BoxPm<'_> {
             // PackageKit
             "pkcon" => Pkcon::new(cfg).boxed(),
 
+            // Stack
+            "stack" => Stack::new(cfg).boxed(),
+
             // Tlmgr
             "tlmgr" => Tlmgr::new(cfg).boxed(),
 
-            // Test-only mock package manager
-            #[cfg(feature = "test")]
-            "mockpm" => {
-                use self::tests::MockPm;
-                MockPm { cfg }.boxed()
-            }
+            // Mock package manager for testing
+            #[cfg(any(test, feature = "mock"))]
+            "mock" => mock::MockPm::new(cfg).boxed(),
 
             // Unknown package manager X
             x => Unknown::new(x).boxed(),
@@ -303,20 +587,158 @@ impl From<Config> for BoxPm<'_> {
     }
 }
 
-/// Detects the name of the package manager to be used in auto dispatch.
-#[must_use]
-fn detect_pm_str() -> &'static str {
-    /// Check if one of the following conditions are met:
-    /// - `$TERMUX_APP_PACKAGE_MANAGER` is `apt`;
-    /// - `$TERMUX_MAIN_PACKAGE_FORMAT` is `debian`.
-    ///
-    /// See: <https://github.com/rami3l/pacaptr/issues/576#issuecomment-1565122604>
-    fn is_termux_apt() -> bool {
-        env::var("TERMUX_APP_PACKAGE_MANAGER").as_deref() == Ok("apt")
-            || env::var("TERMUX_MAIN_PACKAGE_FORMAT").as_deref() == Ok("debian")
+/// The installation hints of each package manager, in the form of
+/// `(name, executable, hint)`, to be shown when it cannot be found.
+///
+/// Every package manager registered above should have an entry here.
+const INSTALL_HINTS: &[(&str, &str, &str)] = &[
+    ("apk", "apk", "apk ships with Alpine Linux"),
+    (
+        "apt",
+        "apt",
+        "apt ships with Debian, Ubuntu and their derivatives",
+    ),
+    ("brew", "brew", "install Homebrew from https://brew.sh"),
+    (
+        "choco",
+        "choco",
+        "install Chocolatey from https://chocolatey.org/install",
+    ),
+    (
+        "conda",
+        "conda",
+        indoc! {"
+            conda ships with Miniconda or Miniforge, see:
+              https://docs.anaconda.com/miniconda/
+              https://github.com/conda-forge/miniforge"},
+    ),
+    (
+        "dnf",
+        "dnf",
+        "dnf ships with Fedora, RHEL and their derivatives",
+    ),
+    (
+        "emerge",
+        "emerge",
+        "emerge (Portage) ships with Gentoo Linux",
+    ),
+    (
+        "pip",
+        "pip",
+        "pip ships with Python, or can be installed with `python -m ensurepip`",
+    ),
+    (
+        "pkcon",
+        "pkcon",
+        "pkcon is part of PackageKit, install the `packagekit` package of your distribution",
+    ),
+    (
+        "port",
+        "port",
+        "install MacPorts from https://www.macports.org/install.php",
+    ),
+    (
+        "rpm-ostree",
+        "rpm-ostree",
+        "rpm-ostree ships with Fedora Silverblue, Kinoite and the other immutable variants",
+    ),
+    (
+        "scoop",
+        "scoop",
+        "enable scoop in PowerShell with `irm get.scoop.sh | iex`",
+    ),
+    (
+        "stack",
+        "stack",
+        "install Stack from https://docs.haskellstack.org/en/stable/install_and_upgrade/",
+    ),
+    (
+        "tlmgr",
+        "tlmgr",
+        "tlmgr ships with TeX Live, see https://tug.org/texlive/",
+    ),
+    (
+        "winget",
+        "winget",
+        "winget ships with the App Installer from the Microsoft Store",
+    ),
+    ("xbps", "xbps-install", "xbps ships with Void Linux"),
+    ("zypper", "zypper", "zypper ships with openSUSE and SLES"),
+];
+
+/// The official install scripts of the package managers supporting
+/// [`Config::bootstrap`], in the form of `(name, exe, cmd)`.
+const BOOTSTRAP_CMDS: &[(&str, &str, &[&str])] = &[
+    (
+        "brew",
+        "brew",
+        &[
+            "/bin/bash",
+            "-c",
+            r#"/bin/bash -c "$(curl -fsSL https://raw.githubusercontent.com/Homebrew/install/HEAD/install.sh)""#,
+        ],
+    ),
+    (
+        "scoop",
+        "scoop",
+        &[
+            "powershell",
+            "-NoProfile",
+            "-Command",
+            "irm get.scoop.sh | iex",
+        ],
+    ),
+];
+
+/// Returns the hint to be shown when the package manager `name` (as given by
+/// the user) is unsupported or not detected at all.
+fn unknown_hint(name: &str) -> String {
+    let pms = INSTALL_HINTS.iter().map(|(name, ..)| name).join(", ");
+    let head = if name == "unknown" {
+        "no supported package manager has been detected"
+    } else {
+        "this package manager is not supported"
+    };
+    format!("{head}, use `--using <pm>` with one of:\n  {pms}")
+}
+
+/// A package manager found on the system by [`detect_all`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DetectedPm {
+    /// The name of the package manager, as given to `--using`.
+    pub name: &'static str,
+
+    /// The path of its executable.
+    pub path: PathBuf,
+}
+
+/// The package managers found on the system by [`detect_all`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Detection {
+    /// All the package managers found, in the order of preference.
+    pub found: Vec<DetectedPm>,
+}
+
+impl Detection {
+    /// Returns the package manager chosen for auto dispatch, i.e. the first
+    /// one found.
+    #[must_use]
+    pub fn chosen(&self) -> Option<&DetectedPm> {
+        self.found.first()
     }
 
-    let pairs: &[(&str, &str)] = match () {
+    /// Returns the name of the package manager chosen for auto dispatch, or
+    /// `unknown` if none has been found.
+    #[must_use]
+    pub fn chosen_name(&self) -> &'static str {
+        self.chosen().map_or("unknown", |pm| pm.name)
+    }
+}
+
+/// Returns the package managers to be detected on the current platform, in
+/// the form of `(name, path)` and in the order of preference.
+const fn candidates() -> &'static [(&'static str, &'static str)] {
+    match () {
         () if cfg!(windows) => &[("scoop", ""), ("choco", ""), ("winget", "")],
 
         () if cfg!(target_os = "macos") => &[
@@ -330,6 +752,7 @@ fn detect_pm_str() -> &'static str {
         () if cfg!(target_os = "linux") => &[
             ("apk", "/sbin/apk"),
             ("apt", "/usr/bin/apt"),
+            ("rpm-ostree", "/usr/bin/rpm-ostree"),
             ("dnf", "/usr/bin/dnf"),
             ("emerge", "/usr/bin/emerge"),
             ("xbps-install", "/usr/bin/xbps-install"),
@@ -337,17 +760,88 @@ fn detect_pm_str() -> &'static str {
         ],
 
         () => &[],
-    };
+    }
+}
+
+/// Detects all the package managers installed on the system.
+#[must_use]
+pub fn detect_all() -> Detection {
+    detect_all_with(candidates(), exec::find_exe)
+}
+
+/// Detects the package managers among `candidates` with `find_exe`, which
+/// returns the path of an executable given its name and its usual path.
+fn detect_all_with(
+    candidates: &[(&'static str, &str)],
+    find_exe: impl Fn(&str, &str) -> Option<PathBuf>,
+) -> Detection {
+    /// Check if one of the following conditions are met:
+    /// - `$TERMUX_APP_PACKAGE_MANAGER` is `apt`;
+    /// - `$TERMUX_MAIN_PACKAGE_FORMAT` is `debian`.
+    ///
+    /// See: <https://github.com/rami3l/pacaptr/issues/576#issuecomment-1565122604>
+    fn is_termux_apt() -> bool {
+        env::var("TERMUX_APP_PACKAGE_MANAGER").as_deref() == Ok("apt")
+            || env::var("TERMUX_MAIN_PACKAGE_FORMAT").as_deref() == Ok("debian")
+    }
 
-    pairs
+    /// Check if the system has been booted from an `ostree` deployment, where
+    /// `rpm-ostree` should be used instead of `dnf` (if any).
+    fn is_ostree_booted() -> bool {
+        Path::new("/run/ostree-booted").exists()
+    }
+
+    let found = candidates
         .iter()
-        .find_map(|&(name, path)| is_exe(name, path).then_some(name))
-        .map_or("unknown", |name| {
-            if name == "apt" && is_termux_apt() {
-                return "pkg";
+        .filter_map(|&(name, path)| {
+            if name == "rpm-ostree" && !is_ostree_booted() {
+                return None;
             }
-            name
+            let path = find_exe(name, path)?;
+            let name = if name == "apt" && is_termux_apt() {
+                "pkg"
+            } else {
+                name
+            };
+            Some(DetectedPm { name, path })
         })
+        .collect();
+    Detection { found }
+}
+
+/// Returns the name of the package manager to be used with `cfg`.
+///
+/// If the package manager is not stated in any config, we should fall back to
+/// automatic detection and overwrite [`Config::default_pm`] accordingly.
+pub fn resolve_default_pm(cfg: &mut Config) -> &str {
+    let macos_default = (cfg.macos_default)
+        .filter(|_| cfg!(target_os = "macos"))
+        .map(MacosPm::name);
+    (cfg.default_pm).get_or_insert_with(|| macos_default.unwrap_or_else(detect_pm_str).into())
+}
+
+/// Detects the name of the package manager to be used in auto dispatch.
+///
+/// This is a shorthand for the [`Detection::chosen_name`] of [`detect_all`].
+#[must_use]
+pub fn detect_pm_str() -> &'static str {
+    detect_all().chosen_name()
+}
+
+/// The delay before the first retry on a busy lock, which is doubled on each
+/// subsequent retry.
+const LOCK_BACKOFF_BASE: Duration = Duration::from_secs(1);
+
+/// The maximum delay between two retries on a busy lock.
+const LOCK_BACKOFF_MAX: Duration = Duration::from_secs(30);
+
+/// Returns the delay before the `attempt`-th retry (starting from 1) on a busy
+/// lock.
+fn lock_backoff(attempt: u32) -> Duration {
+    let factor = 2_u32.saturating_pow(attempt.saturating_sub(1));
+    LOCK_BACKOFF_BASE
+        .saturating_mul(factor)
+        .min(LOCK_BACKOFF_MAX)
 }
 
 /// Extra implementation helper functions for [`Pm`],
@@ -360,32 +854,72 @@ pub trait PmHelper: Pm {
     async fn check_output(&self, mut cmd: Cmd, mode: PmMode, strat: &Strategy) -> Result<Output> {
         async fn run(cfg: &Config, cmd: &Cmd, mode: PmMode, strat: &Strategy) -> Result<Output> {
             let mut curr_cmd = cmd.clone();
-            let no_confirm = cfg.no_confirm;
             if cfg.no_cache {
                 if let NoCacheStrategy::WithFlags(v) = &strat.no_cache {
                     curr_cmd.flags.extend(v.clone());
                 }
             }
+            let strat = if cfg.no_confirm {
+                &strat.confirmed(&mut curr_cmd)
+            } else {
+                strat
+            };
             match &strat.prompt {
-                PromptStrategy::None => curr_cmd.exec(mode.into()).await,
-                PromptStrategy::CustomPrompt if no_confirm => curr_cmd.exec(mode.into()).await,
-                PromptStrategy::CustomPrompt => curr_cmd.exec(Mode::Prompt).await,
-                PromptStrategy::NativeNoConfirm(v) => {
-                    if no_confirm {
-                        curr_cmd.flags.extend(v.clone());
-                    }
+                PromptStrategy::None | PromptStrategy::NativeNoConfirm(_) => {
                     curr_cmd.exec(mode.into()).await
                 }
-                PromptStrategy::NativeConfirm(v) => {
-                    if !no_confirm {
-                        curr_cmd.flags.extend(v.clone());
+                PromptStrategy::CustomPrompt => match (&strat.dry_run, cfg.preview) {
+                    (DryRunStrategy::WithFlags(v), true) => {
+                        let mut preview = curr_cmd.clone().sudo(false);
+                        preview.flags.extend(v.clone());
+                        curr_cmd.exec_prompt_with_preview(preview).await
                     }
+                    _ => curr_cmd.exec(Mode::Prompt).await,
+                },
+                PromptStrategy::NativeConfirm(v) => {
+                    curr_cmd.flags.extend(v.clone());
                     curr_cmd.exec(mode.into()).await
                 }
             }
         }
 
+        /// Runs `cmd` with [`run`], retrying while [`Pm::busy_lock`] reports a
+        /// busy lock, up to [`Config::max_retries_on_lock`] times.
+        async fn run_retrying<P: Pm + ?Sized>(
+            pm: &P,
+            cmd: &Cmd,
+            mode: PmMode,
+            strat: &Strategy,
+        ) -> Result<Output> {
+            let cfg = pm.cfg();
+            let max_retries = cfg.max_retries_on_lock.unwrap_or_default();
+            for attempt in 1.. {
+                let err = match run(cfg, cmd, mode, strat).await {
+                    Err(e) if attempt <= max_retries => e,
+                    res => return res,
+                };
+                let Some(lock) = pm.busy_lock(&err) else {
+                    return Err(err);
+                };
+                let delay = lock_backoff(attempt);
+                print::println(
+                    &*prompt::WARNING,
+                    format_args!(
+                        "waiting for {lock}, retrying in {}s ({attempt}/{max_retries})",
+                        delay.as_secs()
+                    ),
+                );
+                tokio::time::sleep(delay).await;
+            }
+            unreachable!("the retries should have ended with a result")
+        }
+
         let cfg = self.cfg();
+        // The download summary might be printed out to `stdout`.
+        let mode = match mode {
+            PmMode::CheckErr { quiet } if cfg.measure_network => PmMode::CheckAll { quiet },
+            mode => mode,
+        };
 
         // `--dry-run` should apply to both the main command and the cleanup.
         let res = match &strat.dry_run {
@@ -394,10 +928,20 @@ pub trait PmHelper: Pm {
                 cmd.flags.extend(v.clone());
                 // -- A dry run with extra flags does not need `sudo`. --
                 cmd = cmd.sudo(false);
-                run(cfg, &cmd, mode, strat).await?
+                classify_failure(self, run_retrying(self, &cmd, mode, strat).await)?
+            }
+            _ => {
+                let _guard = if strat.is_mutating(&cmd) {
+                    Some(guard::acquire(self.name()).await)
+                } else {
+                    None
+                };
+                classify_failure(self, run_retrying(self, &cmd, mode, strat).await)?
             }
-            _ => run(cfg, &cmd, mode, strat).await?,
         };
+        if cfg.measure_network && !cfg.dry_run && !exec::is_unmeasured() {
+            download::record(self.name(), &res);
+        }
 
         // Perform the cleanup.
         if cfg.no_cache {
@@ -425,6 +969,91 @@ pub trait PmHelper: Pm {
         self.check_output(cmd, mode, strat).await.map(|_| ())
     }
 
+    /// Executes a command in the context of the [`Pm`] implementation,
+    /// with custom [`PmMode`] and [`Strategy`], then reports the disk space
+    /// reclaimed by it.
+    async fn run_reclaiming(&self, cmd: Cmd, mode: PmMode, strat: &Strategy) -> Result<()> {
+        let free = || exec::free_space("/");
+        let before = free();
+        self.run_with(cmd, mode, strat).await?;
+        if self.cfg().dry_run {
+            return Ok(());
+        }
+        if let Some((before, after)) = before.zip(free()) {
+            let reclaimed = print::format_size(after.saturating_sub(before));
+            print::println(
+                &*prompt::INFO,
+                format_args!("{reclaimed} of disk space reclaimed"),
+            );
+        }
+        Ok(())
+    }
+
+    /// Executes a command listing the files of packages, e.g. `dpkg-query -L`.
+    ///
+    /// If [`Config::size`] is set, the output is collected instead, and the
+    /// files listed are printed out along with their sizes and the total.
+    /// Directories are skipped, and the files that are listed but not present
+    /// are marked as missing.
+    async fn list_files(&self, cmd: Cmd) -> Result<()> {
+        if !self.cfg().size {
+            return self.run(cmd).await;
+        }
+        if !self.cfg().dry_run {
+            cmd.echo(&*prompt::RUNNING);
+        }
+        let out = self
+            .check_output(cmd, PmMode::Mute, &Strategy::default())
+            .await?;
+        let files = (exec::decode(&out).lines())
+            .map(str::trim)
+            .filter(|line| line.starts_with('/'))
+            .filter_map(|path| match fs::symlink_metadata(path) {
+                Ok(meta) if meta.is_dir() => None,
+                Ok(meta) => Some((path.to_owned(), Some(meta.len()))),
+                Err(_) => Some((path.to_owned(), None)),
+            })
+            .collect_vec();
+        if !self.cfg().dry_run {
+            print::println_file_sizes(&files);
+        }
+        Ok(())
+    }
+
+    /// Checks the files of installed packages against the package database,
+    /// e.g. for `-Qk`.
+    ///
+    /// `list` lists the files owned by each package, and `verify` reports the
+    /// altered ones, which may exit with code 1 if there is any. Their outputs
+    /// are parsed by `parse`, then the summary of each package is printed out,
+    /// followed by the altered files if `details` is set.
+    async fn check_files(
+        &self,
+        list: Cmd,
+        verify: Cmd,
+        parse: FileCheckParser,
+        details: bool,
+    ) -> Result<()> {
+        if self.cfg().dry_run {
+            list.exec(Mode::PrintCmd).await?;
+            verify.exec(Mode::PrintCmd).await?;
+            return Ok(());
+        }
+        let files = list.exec(Mode::Mute).await?;
+        let changes = match verify.exec(Mode::Mute).await {
+            Ok(out)
+            | Err(Error::CmdStatusCodeError {
+                code: 1,
+                output: out,
+                ..
+            }) => out,
+            Err(e) => return Err(e),
+        };
+        let checks = parse(&exec::decode(&files), &exec::decode(&changes));
+        print::println_file_checks(&checks, details, self.cfg().query_format());
+        Ok(())
+    }
+
     /// Executes a command in the context of the [`Pm`] implementation with
     /// default settings.
     async fn run(&self, cmd: Cmd) -> Result<()> {
@@ -432,6 +1061,46 @@ pub trait PmHelper: Pm {
             .await
     }
 
+    /// Executes a [`CmdSeq`] in the context of the [`Pm`] implementation as a
+    /// single transaction.
+    ///
+    /// If any step of the transaction would prompt, all steps are printed out
+    /// first and confirmed with a single prompt, and the `sudo` credentials are
+    /// asked for (at most) once. The steps are then run in order with their
+    /// own [`Strategy`]s but without further prompts, and the transaction is
+    /// aborted as soon as one of them fails.
+    ///
+    /// With [`Config::step`], each step is confirmed right before it is run
+    /// instead, and declining one aborts the remaining steps.
+    async fn run_seq(&self, seq: CmdSeq) -> Result<()> {
+        let cfg = self.cfg();
+        let step = cfg.step && !cfg.dry_run && !cfg.no_confirm;
+        let confirm = !step
+            && !cfg.dry_run
+            && !cfg.no_confirm
+            && (seq.steps.iter()).any(|(_, strat)| strat.prompts());
+        if confirm {
+            let cmds = seq.steps.iter().map(|(cmd, _)| cmd.clone()).collect_vec();
+            if !exec::confirm_all(&cmds)? {
+                return Ok(());
+            }
+        }
+        if !cfg.dry_run && seq.steps.iter().any(|(cmd, _)| cmd.should_sudo()) {
+            exec::validate_sudo().await?;
+        }
+        for (mut cmd, mut strat) in seq.steps {
+            if step && !exec::confirm_all(slice::from_ref(&cmd))? {
+                return Ok(());
+            }
+            if confirm || step {
+                // The user has already confirmed this step.
+                strat = strat.confirmed(&mut cmd);
+            }
+            self.run_with(cmd, self.default_mode(), &strat).await?;
+        }
+        Ok(())
+    }
+
     /// Executes a command in [`PmMode::Mute`] and prints the output lines
     /// that match against the given regex `patterns`.
     async fn search_regex(&self, cmd: Cmd, patterns: &[&str]) -> Result<()> {
@@ -449,18 +1118,848 @@ pub trait PmHelper: Pm {
         patterns: &[&str],
         header_lines: usize,
     ) -> Result<()> {
+        ensure_unstructured(self)?;
         if !self.cfg().dry_run {
-            println_quoted(&*prompt::RUNNING, &cmd);
+            cmd.echo(&*prompt::RUNNING);
         }
         let out_bytes = self
             .check_output(cmd, PmMode::Mute, &Strategy::default())
             .await?;
-        exec::grep_print_with_header(&String::from_utf8(out_bytes)?, patterns, header_lines)
+        exec::grep_print_with_header(&exec::decode(&out_bytes), patterns, header_lines)
+    }
+
+    /// Like [`PmHelper::search_regex`], but reuses the output of `cmd` saved
+    /// in the [`query_cache`] if it is recent enough, saving it otherwise.
+    ///
+    /// The command should be an expensive read-only query, e.g. a full listing
+    /// of the installed packages.
+    async fn search_regex_cached(&self, cmd: Cmd, patterns: &[&str]) -> Result<()> {
+        let cfg = self.cfg();
+        let ttl = cfg.query_cache_ttl();
+        if cfg.dry_run || cfg.no_cache || ttl.is_zero() {
+            return self.search_regex(cmd, patterns).await;
+        }
+        ensure_unstructured(self)?;
+        let out_bytes = if let Some(out_bytes) = query_cache::get(self.name(), &cmd, ttl) {
+            cmd.echo(&*prompt::CACHED);
+            out_bytes
+        } else {
+            cmd.echo(&*prompt::RUNNING);
+            let out_bytes = self
+                .check_output(cmd.clone(), PmMode::Mute, &Strategy::default())
+                .await?;
+            query_cache::put(self.name(), &cmd, &out_bytes);
+            out_bytes
+        };
+        exec::grep_print(&exec::decode(&out_bytes), patterns)
+    }
+
+    /// Executes a command in [`PmMode::Mute`] and prints the packages parsed
+    /// from its output in the requested [`OutputFormat`](print::OutputFormat),
+    /// keeping only those whose names match against all the given regex
+    /// `patterns`.
+    ///
+    /// The command should be a read-only query, as it is run directly without
+    /// any [`Strategy`].
+    ///
+    /// With [`OutputFormat::JsonLines`](print::OutputFormat::JsonLines), the
+    /// output is parsed line by line as it comes in, and each package is
+    /// printed out right away, so `parse` should not rely on more than one
+    /// line at a time. Otherwise, use [`PmHelper::print_pkgs_buffered`].
+    async fn print_pkgs(&self, cmd: Cmd, patterns: &[&str], parse: Parser) -> Result<()> {
+        let format = self.cfg().query_format();
+        if self.cfg().dry_run || format != print::OutputFormat::JsonLines {
+            return self.print_pkgs_buffered(cmd, patterns, parse).await;
+        }
+        let patterns = exec::grep_patterns(patterns)?;
+        cmd.exec_lines(|ln| {
+            let pkgs = (parse(ln).into_iter())
+                .filter(|pkg| patterns.matches(&pkg.name).into_iter().count() == patterns.len())
+                .collect_vec();
+            // `stdout` is line-buffered, so each package is flushed right away.
+            print::println_pkgs(&pkgs, format);
+        })
+        .await
+    }
+
+    /// Like [`PmHelper::print_pkgs`], but always parses the whole output at
+    /// once, e.g. for a JSON document.
+    async fn print_pkgs_buffered(&self, cmd: Cmd, patterns: &[&str], parse: Parser) -> Result<()> {
+        if self.cfg().dry_run {
+            cmd.exec(Mode::PrintCmd).await?;
+            return Ok(());
+        }
+        let pkgs = parse(&exec::decode(&cmd.exec(Mode::Mute).await?));
+        let names = pkgs.iter().map(|pkg| &pkg.name).join("\n");
+        let matched: HashSet<_> = exec::grep(&names, patterns)?.into_iter().collect();
+        let pkgs = pkgs
+            .into_iter()
+            .filter(|pkg| matched.contains(pkg.name.as_str()))
+            .collect_vec();
+        print::println_pkgs(&pkgs, self.cfg().query_format());
+        Ok(())
+    }
+
+    /// Prints out the installed packages that are not available in any
+    /// repository (anymore), as parsed from the output of `cmd` with `parse`.
+    ///
+    /// As such packages are never upgraded, a hint to reinstall or remove
+    /// them follows, unless the output is meant for another program, i.e.
+    /// with [`Config::quiet`], a machine-readable format or a `stdout` that
+    /// is not a terminal.
+    async fn print_unavailable(&self, cmd: Cmd, parse: Parser) -> Result<()> {
+        if self.cfg().dry_run {
+            cmd.exec(Mode::PrintCmd).await?;
+            return Ok(());
+        }
+        let pkgs = parse(&exec::decode(&cmd.exec(Mode::Mute).await?));
+        let format = self.cfg().query_format();
+        print::println_pkgs(&pkgs, format);
+        let readable = matches!(
+            format,
+            print::OutputFormat::Plain | print::OutputFormat::Table
+        );
+        if !pkgs.is_empty() && readable && !self.cfg().quiet() && io::stdout().is_terminal() {
+            print::println_unavailable_hint(&pkgs.iter().map(|pkg| &pkg.name).collect_vec());
+        }
+        Ok(())
+    }
+
+    /// Prints out the packages parsed from the output of the search `search`
+    /// with `parse`, keeping only the installed ones with
+    /// [`Config::only_installed`], or the other ones with
+    /// [`Config::exclude_installed`].
+    ///
+    /// The installed packages are parsed from the output of `installed` with
+    /// `parse_installed`, then matched against the search results by name.
+    async fn print_search_filtered(
+        &self,
+        search: Cmd,
+        parse: Parser,
+        installed: Cmd,
+        parse_installed: Parser,
+    ) -> Result<()> {
+        if self.cfg().dry_run {
+            search.exec(Mode::PrintCmd).await?;
+            installed.exec(Mode::PrintCmd).await?;
+            return Ok(());
+        }
+        let found = parse(&exec::decode(&search.exec(Mode::Mute).await?));
+        let installed: HashSet<_> =
+            parse_installed(&exec::decode(&installed.exec(Mode::Mute).await?))
+                .into_iter()
+                .map(|pkg| pkg.name)
+                .collect();
+        let only_installed = self.cfg().only_installed;
+        let pkgs = found
+            .into_iter()
+            .filter(|pkg| installed.contains(&pkg.name) == only_installed)
+            .collect_vec();
+        self.println_search(pkgs);
+        Ok(())
+    }
+
+    /// Prints out the packages parsed from the output of the search `search`
+    /// with `parse`, see [`PmHelper::println_search`].
+    async fn print_search(&self, search: Cmd, parse: Parser) -> Result<()> {
+        if self.cfg().dry_run {
+            search.exec(Mode::PrintCmd).await?;
+            return Ok(());
+        }
+        self.println_search(parse(&exec::decode(&search.exec(Mode::Mute).await?)));
+        Ok(())
+    }
+
+    /// Prints out the search results `pkgs` without duplicates, sorted with
+    /// [`Config::search_sort`] and truncated to [`Config::search_limit`].
+    ///
+    /// Unless in a structured [`OutputFormat`](print::OutputFormat), a
+    /// truncation is noted after the results.
+    fn println_search(&self, pkgs: Vec<Pkg>) {
+        let cfg = self.cfg();
+        // A package can be listed more than once, e.g. under each field it matches.
+        let mut pkgs = pkgs
+            .into_iter()
+            .unique_by(|pkg| (pkg.name.clone(), pkg.source.clone()))
+            .collect_vec();
+        if cfg.search_sort == Some(SearchSort::Name) {
+            pkgs.sort_by(|a, b| a.name.cmp(&b.name));
+        }
+        let total = pkgs.len();
+        let limit = cfg.search_limit.filter(|&limit| limit < total);
+        if let Some(limit) = limit {
+            pkgs.truncate(limit);
+        }
+        let format = cfg.query_format();
+        print::println_pkgs(&pkgs, format);
+        if let (Some(limit), false) = (limit, format.is_structured()) {
+            print::println(
+                &*prompt::INFO,
+                format_args!("showing first {limit} of {total} results"),
+            );
+        }
+    }
+
+    /// Prints out the dependency trees of the packages `roots` in the
+    /// dependency graph parsed from the output of `cmd` with `parse`, see
+    /// [`print::println_dep_tree`].
+    async fn print_dep_tree(&self, cmd: Cmd, roots: &[&str], parse: DepGraphParser) -> Result<()> {
+        ensure_unstructured(self)?;
+        if self.cfg().dry_run {
+            cmd.exec(Mode::PrintCmd).await?;
+            return Ok(());
+        }
+        let deps = parse(&exec::decode(&cmd.exec(Mode::Mute).await?));
+        print::println_dep_tree(roots, &deps);
+        Ok(())
+    }
+
+    /// Prints out the installed sizes of the packages parsed from the output of
+    /// `cmd` with `parse`, largest first, see [`print::println_pkg_sizes`].
+    async fn print_pkg_sizes(&self, cmd: Cmd, parse: PkgSizeParser) -> Result<()> {
+        ensure_unstructured(self)?;
+        if self.cfg().dry_run {
+            cmd.exec(Mode::PrintCmd).await?;
+            return Ok(());
+        }
+        let sizes = parse(&exec::decode(&cmd.exec(Mode::Mute).await?))
+            .into_iter()
+            .sorted_by(|(name1, size1), (name2, size2)| size2.cmp(size1).then(name1.cmp(name2)))
+            .collect_vec();
+        print::println_pkg_sizes(&sizes);
+        Ok(())
+    }
+
+    /// Lists the orphaned packages parsed with `parse` from the output of
+    /// `orphans` along with their installed sizes, then removes the ones chosen
+    /// by the user with [`Pm::r`], see [`exec::ask_select`].
+    ///
+    /// `sizes` makes the read-only command listing the installed sizes of the
+    /// given packages, whose output is parsed with `parse_sizes`. All the
+    /// orphans are chosen with [`Config::no_confirm`].
+    async fn remove_orphans_interactively(
+        &self,
+        orphans: Cmd,
+        parse: NameParser,
+        sizes: for<'a, 'b> fn(&'a [&'b str]) -> Cmd,
+        parse_sizes: PkgSizeParser,
+        flags: &[&str],
+    ) -> Result<()> {
+        if self.cfg().dry_run {
+            orphans.exec(Mode::PrintCmd).await?;
+            sizes(&[]).exec(Mode::PrintCmd).await?;
+            return Ok(());
+        }
+        let orphans = parse(&exec::decode(&orphans.exec(Mode::Mute).await?));
+        if orphans.is_empty() {
+            print::println(&*prompt::INFO, "no orphaned package to remove");
+            return Ok(());
+        }
+        let names = orphans.iter().map(String::as_str).collect_vec();
+        let sizes: HashMap<_, _> =
+            parse_sizes(&exec::decode(&sizes(&names).exec(Mode::Mute).await?))
+                .into_iter()
+                .collect();
+        let items = (orphans.iter())
+            .map(|name| match sizes.get(name) {
+                Some(&size) => format!("{name} ({})", print::format_size(size)),
+                None => name.clone(),
+            })
+            .collect_vec();
+        let chosen = if self.cfg().no_confirm {
+            (0..names.len()).collect()
+        } else {
+            let total = print::format_size(sizes.values().sum());
+            let question = format!(
+                "remove which of the {} orphaned package(s) ({total} in total)?",
+                names.len()
+            );
+            exec::ask_select(&question, &items)?
+        };
+        if chosen.is_empty() {
+            print::println(&*prompt::INFO, "no orphaned package has been chosen");
+            return Ok(());
+        }
+        let chosen = chosen.into_iter().map(|i| names[i]).collect_vec();
+        self.r(&chosen, flags).await
+    }
+
+    /// Reports the sizes of a pending transaction if [`Config::sizes`] is set,
+    /// as parsed with `parse` from the output of `plan`.
+    ///
+    /// `plan` should show the transaction planned by the package manager
+    /// without committing it, e.g. by answering no to its own prompt.
+    async fn print_transaction_size(
+        &self,
+        plan: Cmd,
+        parse: for<'s> fn(&'s str) -> Vec<String>,
+    ) -> Result<()> {
+        if !self.cfg().sizes {
+            return Ok(());
+        }
+        if self.cfg().dry_run {
+            plan.exec(Mode::PrintCmd).await?;
+            return Ok(());
+        }
+        print::println(
+            &*prompt::INFO,
+            format_args!("checking the transaction size with `{plan}`"),
+        );
+        // Declining the transaction is expected to fail.
+        let out = match plan.exec(Mode::Mute).await {
+            Ok(out) | Err(Error::CmdStatusCodeError { output: out, .. }) => out,
+            Err(e) => return Err(e),
+        };
+        let summary = parse(&exec::decode(&out));
+        if summary.is_empty() {
+            print::println(
+                &*prompt::INFO,
+                format_args!("no transaction size has been reported by `{}`", self.name()),
+            );
+        }
+        for line in summary {
+            print::println(&*prompt::INFO, line);
+        }
+        Ok(())
+    }
+
+    /// Prints out the package updates parsed from the output of `cmd` with
+    /// `parse`, filtered by `patterns`.
+    ///
+    /// See [`PmHelper::println_updates`] for more info.
+    async fn print_updates(&self, cmd: Cmd, patterns: &[&str], parse: UpdateParser) -> Result<()> {
+        if self.cfg().dry_run {
+            cmd.exec(Mode::PrintCmd).await?;
+            return Ok(());
+        }
+        let updates = parse(&exec::decode(&cmd.exec(Mode::Mute).await?));
+        self.println_updates(updates, patterns)
+    }
+
+    /// Prints out the package updates whose names match all the `patterns`.
+    ///
+    /// The lines that could not be parsed are matched as a whole.
+    /// See [`print::println_updates`] for more info.
+    ///
+    /// # Errors
+    /// Returns an [`Error::OtherError`] when any of the regex patterns is
+    /// ill-formed.
+    fn println_updates(
+        &self,
+        updates: Vec<Result<Update, String>>,
+        patterns: &[&str],
+    ) -> Result<()> {
+        let key = |update: &Result<Update, String>| match update {
+            Ok(update) => update.name.clone(),
+            Err(line) => line.clone(),
+        };
+        let keys = updates.iter().map(key).join("\n");
+        let matched: HashSet<_> = exec::grep(&keys, patterns)?.into_iter().collect();
+        let updates = updates
+            .into_iter()
+            .filter(|update| matched.contains(key(update).as_str()))
+            .collect_vec();
+        print::println_updates(&updates);
+        Ok(())
+    }
+
+    /// Checks for dependency conflicts with the read-only command `check` if
+    /// [`Config::check_deps`] is set, before installing packages.
+    ///
+    /// `check` should be a dry run of the installation, whose output (either
+    /// successful or not) is parsed with `parse`. The conflicts found are
+    /// printed out, in which case an [`Error::DepConflictError`] is returned.
+    async fn check_deps(&self, check: Cmd, parse: ConflictParser) -> Result<()> {
+        if !self.cfg().check_deps {
+            return Ok(());
+        }
+        print::println(
+            &*prompt::INFO,
+            format_args!("checking the dependencies with `{check}`"),
+        );
+        // The resolver is expected to fail if there is any conflict.
+        let out = match check.exec(Mode::Mute).await {
+            Ok(out) | Err(Error::CmdStatusCodeError { output: out, .. }) => out,
+            Err(e) => return Err(e),
+        };
+        let conflicts = parse(&exec::decode(&out));
+        if conflicts.is_empty() {
+            print::println(&*prompt::INFO, "no dependency conflict found");
+            return Ok(());
+        }
+        print::println_conflicts(&conflicts);
+        Err(Error::DepConflictError {
+            count: conflicts.len(),
+        })
+    }
+
+    /// Warns that the modifier `m` of the operation being run has no effect or
+    /// is translated into something semantically weaker.
+    ///
+    /// This is meant to be called by the method dropping the modifier, e.g.
+    /// an `scc` falling back to `sc`, before doing so.
+    fn warn_weak_modifier(&self, m: WeakModifier) {
+        print::println(&*prompt::WARNING, m.warning(self.name()));
+    }
+
+    /// Verifies the package files downloaded by `-Sw` against the checksums
+    /// they have been published with, if [`Config::verify_checksums`] is set.
+    ///
+    /// The files are hashed with the read-only command of their checksums
+    /// (e.g. `sha256sum`), and the ones that are missing, that do not match
+    /// their checksums or that have none to be checked against are printed
+    /// out, in which case an [`Error::ChecksumMismatchError`] is returned.
+    ///
+    /// In a dry run, nothing is checked, as the files are not downloaded.
+    async fn verify_downloads(&self, downloads: Vec<Download>) -> Result<()> {
+        let cfg = self.cfg();
+        if !cfg.verify_checksums || cfg.dry_run {
+            return Ok(());
+        }
+        if downloads.is_empty() {
+            print::println(&*prompt::INFO, "no downloaded package file to verify");
+            return Ok(());
+        }
+        print::println(
+            &*prompt::INFO,
+            format_args!("verifying {} downloaded package file(s)", downloads.len()),
+        );
+        let mut got = HashSet::new();
+        let by_cmd = (downloads.iter())
+            .filter_map(|download| Some((download.checksum.as_ref()?.0, &download.path)))
+            .into_group_map();
+        for (sum, paths) in by_cmd.into_iter().sorted_unstable() {
+            // The command is expected to fail if any file is missing.
+            let out = match Cmd::new([sum]).kws(paths).exec(Mode::Mute).await {
+                Ok(out) | Err(Error::CmdStatusCodeError { output: out, .. }) => out,
+                Err(e) => return Err(e),
+            };
+            got.extend(exec::decode(&out).lines().filter_map(|ln| {
+                let (hex, path) = ln.split_once("  ")?;
+                Some((sum, hex.to_lowercase(), path.to_owned()))
+            }));
+        }
+        let mut failed = 0;
+        for Download { path, checksum } in &downloads {
+            match checksum {
+                None => print::println_err(format_args!(
+                    "`{path}` has no supported checksum to be verified against"
+                )),
+                Some((sum, hex)) if !got.contains(&(*sum, hex.to_lowercase(), path.clone())) => {
+                    print::println_err(format_args!(
+                        "`{path}` is missing or does not match its checksum"
+                    ));
+                }
+                Some(_) => continue,
+            }
+            failed += 1;
+        }
+        if failed > 0 {
+            return Err(Error::ChecksumMismatchError { count: failed });
+        }
+        print::println(
+            &*prompt::INFO,
+            "all the downloaded package files are intact",
+        );
+        Ok(())
+    }
+
+    /// Warns about the installed packages requiring any of the packages `kws`
+    /// if [`Config::check_required_by`] is set, before removing them.
+    ///
+    /// `rdeps` makes the read-only command listing the installed packages
+    /// requiring a given one, whose output is parsed with `parse`. The
+    /// packages among `kws` are not reported, as they are removed as well.
+    async fn check_required_by(
+        &self,
+        kws: &[&str],
+        rdeps: for<'s> fn(&'s str) -> Cmd,
+        parse: NameParser,
+    ) -> Result<()> {
+        if !self.cfg().check_required_by {
+            return Ok(());
+        }
+        for &kw in kws {
+            let check = rdeps(kw);
+            print::println(
+                &*prompt::INFO,
+                format_args!("checking the reverse dependencies with `{check}`"),
+            );
+            // A missing package is left to the removal itself to report.
+            let out = match check.exec(Mode::Mute).await {
+                Ok(out) | Err(Error::CmdStatusCodeError { output: out, .. }) => out,
+                Err(e) => return Err(e),
+            };
+            let required_by = parse(&exec::decode(&out))
+                .into_iter()
+                .filter(|name| !kws.contains(&name.as_str()))
+                .unique()
+                .collect_vec();
+            if !required_by.is_empty() {
+                print::println(
+                    &*prompt::WARNING,
+                    format_args!(
+                        "`{kw}` is required by the installed packages {}",
+                        required_by.join(", ")
+                    ),
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Explains the dependency conflicts behind the failure `err`, then returns
+    /// `err` as is.
+    ///
+    /// If the captured output of the failed command is recognized by
+    /// `is_conflict`, the read-only command `explain` is run with
+    /// `--explain-conflicts` to get the verbose output of the resolver, whose
+    /// conflicts are parsed with `parse` and printed out.
+    async fn explain_conflicts(
+        &self,
+        err: Error,
+        explain: Cmd,
+        is_conflict: for<'s> fn(&'s str) -> bool,
+        parse: ConflictParser,
+    ) -> Error {
+        let conflicting = match err.cmd_failure() {
+            Some(Error::CmdStatusCodeError { output, .. }) => is_conflict(&exec::decode(output)),
+            _ => false,
+        };
+        if !conflicting {
+            return err;
+        }
+        if !self.cfg().explain_conflicts {
+            print::println(
+                &*prompt::INFO,
+                "use `--explain-conflicts` for a summary of the dependency conflicts",
+            );
+            return err;
+        }
+        print::println(
+            &*prompt::INFO,
+            format_args!("explaining the dependency conflicts with `{explain}`"),
+        );
+        // The resolver is expected to fail again here.
+        let out = match explain.exec(Mode::Mute).await {
+            Ok(out) | Err(Error::CmdStatusCodeError { output: out, .. }) => out,
+            Err(e) => {
+                print::println(
+                    &*prompt::WARNING,
+                    format_args!("failed to explain the dependency conflicts: {e}"),
+                );
+                return err;
+            }
+        };
+        print::println_conflicts(&parse(&exec::decode(&out)));
+        err
+    }
+
+    /// Prints out a list of bare package names in the requested
+    /// [`OutputFormat`](print::OutputFormat).
+    ///
+    /// See [`print::println_names`] for more info.
+    fn print_names(&self, names: &[impl AsRef<str>]) {
+        print::println_names(names, self.cfg().query_format());
     }
 }
 
 impl<P: Pm> PmHelper for P {}
 
+/// An option of `-S` changing how the packages are built, which is
+/// translated into a native flag by each [`Pm`] supporting it, see
+/// [`Pm::build_option_flags`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BuildOption {
+    /// Build the development version of the packages, with `--head`.
+    Head,
+
+    /// Build the packages from source instead of installing prebuilt
+    /// binaries, with `--source-build`.
+    SourceBuild,
+}
+
+impl BuildOption {
+    /// All the [`BuildOption`]s.
+    pub const ALL: [Self; 2] = [Self::Head, Self::SourceBuild];
+
+    /// Returns the flag of `pacaptr` setting this option.
+    #[must_use]
+    pub const fn flag(self) -> &'static str {
+        match self {
+            Self::Head => "--head",
+            Self::SourceBuild => "--source-build",
+        }
+    }
+
+    /// Returns whether this option is set in `cfg`.
+    #[must_use]
+    pub const fn is_set(self, cfg: &Config) -> bool {
+        match self {
+            Self::Head => cfg.head,
+            Self::SourceBuild => cfg.source_build,
+        }
+    }
+}
+
+/// Returns the native flags of the [`BuildOption`]s set in the config of
+/// `pm`, skipping the ones it does not support.
+#[must_use]
+pub fn build_flags(pm: &(impl Pm + ?Sized)) -> Vec<&'static str> {
+    (pm.build_option_flags().iter())
+        .filter(|(opt, _)| opt.is_set(pm.cfg()))
+        .map(|&(_, flag)| flag)
+        .collect()
+}
+
+/// Returns the first [`BuildOption`] set in the config of `pm` that it does
+/// not support, if any.
+#[must_use]
+pub fn unsupported_build_option(pm: &(impl Pm + ?Sized)) -> Option<BuildOption> {
+    BuildOption::ALL.into_iter().find(|&opt| {
+        opt.is_set(pm.cfg())
+            && !pm
+                .build_option_flags()
+                .iter()
+                .any(|&(supported, _)| supported == opt)
+    })
+}
+
+/// A modifier of a `pacman` operation that has no effect or is translated into
+/// something semantically weaker by a specific [`Pm`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct WeakModifier {
+    /// The method name of the operation, e.g. `rs`.
+    pub op: &'static str,
+
+    /// The modifier in question, e.g. `-s`.
+    pub flag: &'static str,
+
+    /// What actually happens instead.
+    pub actual: &'static str,
+}
+
+impl WeakModifier {
+    /// Returns the warning about this modifier not being fully supported by
+    /// the package manager `pm`.
+    #[must_use]
+    pub fn warning(&self, pm: &str) -> String {
+        let mut chars = self.op.chars();
+        let op = chars.next().map_or_else(String::new, |c| {
+            chain!([c.to_ascii_uppercase()], chars).collect()
+        });
+        format!(
+            "`{}` in `-{op}` is not fully supported by `{pm}`: {}",
+            self.flag, self.actual,
+        )
+    }
+}
+
+/// A package file downloaded by [`Pm::sw`], to be verified by
+/// [`PmHelper::verify_downloads`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Download {
+    /// The path of the file.
+    pub path: String,
+
+    /// The command computing the checksum the file has been published with
+    /// (e.g. `sha256sum`) along with the expected digest in hex, if it is
+    /// known and supported, see [`checksum_cmd`].
+    pub checksum: Option<(&'static str, String)>,
+}
+
+/// Returns the command computing the checksums of the algorithm `algo` (e.g.
+/// `SHA256` or `sha`), in the output format of `sha256sum`, if it is
+/// supported.
+#[must_use]
+pub fn checksum_cmd(algo: &str) -> Option<&'static str> {
+    Some(match algo.to_ascii_lowercase().as_str() {
+        "md5" | "md5sum" => "md5sum",
+        "sha" | "sha1" => "sha1sum",
+        "sha224" => "sha224sum",
+        "sha256" => "sha256sum",
+        "sha384" => "sha384sum",
+        "sha512" => "sha512sum",
+        _ => return None,
+    })
+}
+
+/// Makes sure that `pm` is a supported package manager, and that it is
+/// installed unless in a dry run.
+///
+/// # Errors
+/// Returns an [`Error::PmNotFoundError`] with a hint on how to fix it if `pm`
+/// is [`Unknown`] or not installed.
+pub fn ensure_known(pm: &(impl Pm + ?Sized)) -> Result<()> {
+    if let Some(name) = pm.name().strip_prefix(unknown::NAME_PREFIX) {
+        return Err(Error::PmNotFoundError {
+            name: name.into(),
+            hint: Some(unknown_hint(name)),
+        });
+    }
+    let Some(&(name, exe, hint)) = INSTALL_HINTS.iter().find(|(name, ..)| *name == pm.name())
+    else {
+        return Ok(());
+    };
+    if pm.cfg().dry_run || is_installed(pm, exe) {
+        return Ok(());
+    }
+    Err(Error::PmNotFoundError {
+        name: name.into(),
+        hint: Some(hint.into()),
+    })
+}
+
+/// Checks if the executable `exe` of `pm` is installed.
+fn is_installed(pm: &(impl Pm + ?Sized), exe: &str) -> bool {
+    // The executable might also be given by the user, e.g. `pip3` for `pip`.
+    is_exe(exe, "") || (pm.cfg().default_pm.as_deref()).is_some_and(|pm| is_exe(pm, ""))
+}
+
+/// Installs `pm` with the official install script in [`BOOTSTRAP_CMDS`] if it
+/// is missing and [`Config::bootstrap`] is set.
+///
+/// The exact command is printed out first, and is only run after an explicit
+/// confirmation, even with `--yes`. Declining it leaves the missing package
+/// manager to [`ensure_known`].
+///
+/// # Errors
+/// Returns an error if the install script fails, and an
+/// [`Error::PmNotFoundError`] if `pm` is still not found in `PATH` after it.
+pub async fn bootstrap(pm: &(impl Pm + ?Sized)) -> Result<()> {
+    let cfg = pm.cfg();
+    let Some(&(name, exe, bootstrap_cmd)) =
+        BOOTSTRAP_CMDS.iter().find(|(name, ..)| *name == pm.name())
+    else {
+        return Ok(());
+    };
+    if !cfg.bootstrap || is_installed(pm, exe) {
+        return Ok(());
+    }
+    let cmd = Cmd::new(bootstrap_cmd);
+    if cfg.dry_run {
+        cmd.exec(Mode::PrintCmd).await?;
+        return Ok(());
+    }
+    // `--yes` is ignored on purpose, as the script is downloaded from the web.
+    if !exec::confirm_all(slice::from_ref(&cmd))? {
+        return Ok(());
+    }
+    cmd.exec(Mode::CheckErr { quiet: false }).await?;
+    if is_installed(pm, exe) {
+        return Ok(());
+    }
+    Err(Error::PmNotFoundError {
+        name: name.into(),
+        hint: Some(format!(
+            "{name} has been installed, but `{exe}` is not in `PATH` yet, open a new shell and try again"
+        )),
+    })
+}
+
+/// Returns the error of [`Pm::verify`] when some of the packages verified are
+/// not intact, see
+/// [`Verification::is_intact`](crate::pkg::Verification::is_intact).
+fn verification_failed() -> Error {
+    Error::OtherError("some of the packages have failed the verification".into())
+}
+
+/// Makes sure that structured output is not requested from `pm`, as the
+/// caller is only able to provide unstructured text.
+///
+/// # Errors
+/// Returns an [`Error::StructuredOutputUnsupportedError`] if a structured
+/// [`OutputFormat`](print::OutputFormat) is requested, except for the JSON
+/// report of [`Config::measure_network`].
+fn ensure_unstructured(pm: &(impl Pm + ?Sized)) -> Result<()> {
+    let cfg = pm.cfg();
+    let format = cfg.query_format();
+    // With `--measure-network`, the download report is printed out as JSON instead.
+    let json_report = cfg.measure_network
+        && matches!(
+            format,
+            print::OutputFormat::Json | print::OutputFormat::JsonLines
+        );
+    if format.is_structured() && !json_report {
+        return Err(Error::StructuredOutputUnsupportedError {
+            pm: pm.name().into(),
+        });
+    }
+    Ok(())
+}
+
+/// Returns the ID of the transaction to be undone by [`Pm::undo`], which is
+/// given as the only keyword (if any).
+///
+/// # Errors
+/// Returns an [`Error::ArgParseError`] if more than one keyword is given.
+fn undo_id<'a>(kws: &[&'a str]) -> Result<Option<&'a str>> {
+    match kws {
+        [] => Ok(None),
+        [id] => Ok(Some(id)),
+        _ => Err(Error::ArgParseError {
+            msg: "only one transaction can be undone at a time".into(),
+        }),
+    }
+}
+
+/// Classifies a failure of a command run by the package manager `pm` with
+/// [`Pm::classify_result`].
+///
+/// A failure classified as successful (e.g. with an exit code only reporting
+/// that a reboot is needed) is turned back into its output, and the other
+/// ones are detailed with [`network::classify`] or [`not_found::classify`]
+/// according to their class.
+fn classify_failure(pm: &(impl Pm + ?Sized), res: Result<Output>) -> Result<Output> {
+    let Err(Error::CmdStatusCodeError { code, output, .. }) = &res else {
+        return res;
+    };
+    match pm.classify_result(*code, output) {
+        class if class.is_success() => Ok(output.clone()),
+        ResultClass::PkgNotFound => res.map_err(|e| not_found::classify(pm.name(), e)),
+        ResultClass::Network => res.map_err(|e| network::classify(pm.name(), e)),
+        _ => res,
+    }
+}
+
+/// Runs `f` on each of the given keywords in order.
+///
+/// Unless [`Config::fail_fast`] is set, a keyword failing doesn't stop the
+/// loop, and the failures are reported together as an [`Error::MultiError`]
+/// in the end. An interruption always stops the loop, and a single keyword
+/// reports its own error as is.
+///
+/// # Errors
+/// Returns the error of the failing keyword if the loop has been stopped, or
+/// an [`Error::MultiError`] otherwise.
+pub async fn for_each_kw<'a, Fut>(
+    cfg: &Config,
+    kws: &[&'a str],
+    mut f: impl FnMut(&'a str) -> Fut + Send,
+) -> Result<()>
+where
+    Fut: Future<Output = Result<()>> + Send,
+{
+    let mut failures = vec![];
+    for &kw in kws {
+        match f(kw).await {
+            Ok(()) => (),
+            Err(e) if cfg.fail_fast || kws.len() == 1 || e.exit_kind() == ExitKind::Interrupted => {
+                return Err(e);
+            }
+            Err(e) => failures.push((kw.to_owned(), e)),
+        }
+    }
+    if failures.is_empty() {
+        return Ok(());
+    }
+    Err(MultiError {
+        failures,
+        total: kws.len(),
+    }
+    .into())
+}
+
 /// Different ways in which a command shall be dealt with.
 ///
 /// This is a [`Pm`] specified version intended to be used along with
@@ -475,7 +1974,6 @@ pub enum PmMode {
     ///
     /// This is potentially dangerous as it destroys the colored `stdout`. Use
     /// it only if really necessary.
-    #[allow(dead_code)]
     CheckAll {
         /// Whether the log output should be suppressed.
         quiet: bool,
@@ -514,6 +2012,67 @@ pub struct Strategy {
 
     /// How the cache is cleaned when `no_cache` is set to `true`.
     no_cache: NoCacheStrategy,
+
+    /// Whether the command changes the system even though it neither prompts
+    /// for confirmation nor needs `sudo`, e.g. `brew update`.
+    mutating: bool,
+}
+
+impl Strategy {
+    /// Checks if the command `cmd` run with this strategy might change the
+    /// system, which is the case if it is tagged as such, or assumed to be if
+    /// it would prompt for confirmation or needs `sudo`.
+    ///
+    /// Such a command holds the [`guard::Guard`] of its package manager while
+    /// running.
+    #[must_use]
+    pub const fn is_mutating(&self, cmd: &Cmd) -> bool {
+        self.mutating || cmd.sudo || self.prompts()
+    }
+
+    /// Checks if the command run with this strategy would prompt for
+    /// confirmation.
+    const fn prompts(&self) -> bool {
+        !matches!(self.prompt, PromptStrategy::None)
+    }
+
+    /// Returns this strategy for running `cmd` once it has been confirmed,
+    /// e.g. with `--yes` or in a [`CmdSeq`], so that it no longer prompts.
+    ///
+    /// The flags disabling the native prompt (if any) are added to `cmd`.
+    fn confirmed(&self, cmd: &mut Cmd) -> Self {
+        if let PromptStrategy::NativeNoConfirm(v) = &self.prompt {
+            cmd.flags.extend(v.clone());
+        }
+        Self {
+            prompt: PromptStrategy::None,
+            ..self.clone()
+        }
+    }
+}
+
+/// A sequence of [`Cmd`]s to be run in order as a single transaction, each
+/// with its own [`Strategy`].
+///
+/// See [`PmHelper::run_seq`] for more info.
+#[must_use]
+#[derive(Clone, Debug, Default)]
+pub struct CmdSeq {
+    /// The steps of the transaction.
+    steps: Vec<(Cmd, Strategy)>,
+}
+
+impl CmdSeq {
+    /// Makes a new, empty [`CmdSeq`] instance.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends a step to the transaction, which runs `cmd` with `strat`.
+    pub fn then(mut self, cmd: Cmd, strat: &Strategy) -> Self {
+        self.steps.push((cmd, strat.clone()));
+        self
+    }
 }
 
 /// How a dry run is dealt with.
@@ -598,53 +2157,407 @@ impl NoCacheStrategy {
     }
 }
 
-#[allow(missing_docs)]
-#[cfg(feature = "test")]
-pub mod tests {
-    use async_trait::async_trait;
-    use tt_call::tt_call;
+// Declared here so that the `methods!` macro above is in scope.
+#[cfg(any(test, feature = "mock"))]
+pub mod mock;
+
+/// Runs `-Ss` with `pm`, where the commands starting with `prefix` output the
+/// captured search `tests/golden/porcelain/{name}.in`, and returns the names
+/// of the packages printed out.
+#[cfg(test)]
+pub(crate) async fn search_golden(
+    pm: &(impl Pm + ?Sized),
+    prefix: &str,
+    name: &str,
+) -> Vec<String> {
+    use std::sync::Arc;
+
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden/porcelain");
+    let log =
+        mock::MockLog::new().output(prefix, fs::read(dir.join(format!("{name}.in"))).unwrap());
+    let sink = print::PkgSink::default();
+    let search = print::capture_pkgs(Arc::clone(&sink), pm.ss(&[], &[]));
+    log.scope(search).await.unwrap();
+    let names = sink
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|pkg| pkg.name.clone())
+        .collect();
+    names
+}
+
+#[cfg(all(test, unix))]
+mod seq_tests {
+    use std::{
+        env, fs,
+        path::{Path, PathBuf},
+        process,
+    };
+
+    use super::*;
+
+    fn pm() -> impl Pm {
+        Tlmgr::new(Config {
+            no_confirm: true,
+            quiet: Some(true),
+            ..Config::default()
+        })
+    }
+
+    fn temp_file(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("pacaptr-{name}-{}", process::id()))
+    }
+
+    fn append(line: &str, file: &Path) -> Cmd {
+        Cmd::new(["sh", "-c", &format!("echo {line} >> '{}'", file.display())])
+    }
+
+    #[test]
+    fn weak_modifier_warnings() {
+        let rns = WeakModifier {
+            op: "rns",
+            flag: "-n",
+            actual: "the configuration files are kept",
+        };
+        assert_eq!(
+            rns.warning("foo"),
+            "`-n` in `-Rns` is not fully supported by `foo`: the configuration files are kept"
+        );
+    }
+
+    #[test]
+    fn mutating_strategies() {
+        let prompt = Strategy {
+            prompt: PromptStrategy::CustomPrompt,
+            ..Strategy::default()
+        };
+        let refresh = Strategy {
+            mutating: true,
+            ..Strategy::default()
+        };
+        let query = Cmd::new(["brew", "list"]);
+        assert!(!Strategy::default().is_mutating(&query));
+        assert!(prompt.is_mutating(&query));
+        assert!(refresh.is_mutating(&Cmd::new(["brew", "update"])));
+        assert!(Strategy::default().is_mutating(&Cmd::with_sudo(["apt", "update"])));
+    }
+
+    #[tokio::test]
+    async fn cmd_seq_runs_in_order() {
+        let file = temp_file("seq-order");
+        _ = fs::remove_file(&file);
+        let seq = CmdSeq::new()
+            .then(append("foo", &file), &Strategy::default())
+            .then(append("bar", &file), &Strategy::default());
+        pm().run_seq(seq).await.unwrap();
+        assert_eq!(fs::read_to_string(&file).unwrap(), "foo\nbar\n");
+        fs::remove_file(&file).unwrap();
+    }
+
+    #[tokio::test]
+    async fn cmd_seq_aborts_on_first_failure() {
+        let file = temp_file("seq-abort");
+        _ = fs::remove_file(&file);
+        let seq = CmdSeq::new()
+            .then(append("foo", &file), &Strategy::default())
+            .then(Cmd::new(["sh", "-c", "exit 42"]), &Strategy::default())
+            .then(append("bar", &file), &Strategy::default());
+        let res = pm().run_seq(seq).await;
+        assert!(matches!(
+            res,
+            Err(Error::CmdStatusCodeError { code: 42, .. })
+        ));
+        assert_eq!(fs::read_to_string(&file).unwrap(), "foo\n");
+        fs::remove_file(&file).unwrap();
+    }
+}
 
+#[cfg(test)]
+mod hint_tests {
     use super::*;
-    use crate::config::Config;
 
-    #[derive(Debug)]
-    pub struct MockPm {
-        pub cfg: Config,
+    #[test]
+    fn install_hints() {
+        // The names of the modules are snake-cased, e.g. `rpm_ostree`.
+        for name in (PM_MODS.iter())
+            .filter(|&&name| name != "unknown")
+            .map(|name| name.replace('_', "-"))
+        {
+            assert!(
+                INSTALL_HINTS.iter().any(|(pm, ..)| *pm == name),
+                "`{name}` should have an installation hint"
+            );
+            let pm = BoxPm::from(Config {
+                default_pm: Some(name.clone()),
+                ..Config::default()
+            });
+            // `port` is only available on macOS.
+            if !pm.name().starts_with(unknown::NAME_PREFIX) {
+                assert_eq!(pm.name(), name);
+            }
+        }
+    }
+
+    #[test]
+    fn detection() {
+        let candidates = [
+            ("apk", "/sbin/apk"),
+            ("apt", "/usr/bin/apt"),
+            ("dnf", "/usr/bin/dnf"),
+            ("zypper", "/usr/bin/zypper"),
+        ];
+        // `dnf` is found by its path, and `zypper` in `$PATH` only.
+        let find_exe = |name: &str, path: &str| match name {
+            "dnf" => Some(PathBuf::from(path)),
+            "zypper" => Some(PathBuf::from("/opt/bin/zypper")),
+            _ => None,
+        };
+        let detection = detect_all_with(&candidates, find_exe);
+        assert_eq!(
+            detection.found,
+            [
+                DetectedPm {
+                    name: "dnf",
+                    path: "/usr/bin/dnf".into(),
+                },
+                DetectedPm {
+                    name: "zypper",
+                    path: "/opt/bin/zypper".into(),
+                },
+            ]
+        );
+        assert_eq!(detection.chosen_name(), "dnf");
+
+        let detection = detect_all_with(&candidates, |_, _| None);
+        assert_eq!(detection.chosen(), None);
+        assert_eq!(detection.chosen_name(), "unknown");
     }
 
-    macro_rules! make_mock_op_body {
-        ($self:ident, $kws:ident, $flags:ident, $method:ident) => {{
-            let kws: Vec<_> = itertools::chain!($kws, $flags).collect();
-            panic!("should run: {} {:?}", stringify!($method), &kws)
-        }};
+    #[test]
+    fn missing_pm() {
+        let cfg = Config {
+            default_pm: Some("conda".into()),
+            ..Config::default()
+        };
+        let res = ensure_known(&*BoxPm::from(cfg.clone()));
+        if !is_exe("conda", "") {
+            let hint = res.unwrap_err().hint().unwrap().to_owned();
+            assert!(hint.contains("Miniconda"));
+        }
+        let cfg = Config {
+            dry_run: true,
+            ..cfg
+        };
+        ensure_known(&*BoxPm::from(cfg)).unwrap();
+        let err = ensure_known(&Unknown::new("foo")).unwrap_err();
+        assert!(err.hint().unwrap().contains("apt, brew"));
     }
 
-    macro_rules! impl_pm_mock {(
+    #[test]
+    fn lock_backoffs() {
+        let delays = (1..=7).map(|n| lock_backoff(n).as_secs()).collect_vec();
+        assert_eq!(delays, [1, 2, 4, 8, 16, 30, 30]);
+        assert_eq!(lock_backoff(u32::MAX), LOCK_BACKOFF_MAX);
+    }
+}
+
+/// Snapshot tests of the commands generated by every backend in a dry run.
+///
+/// The command echoes of each backend, along with the commands still run in a
+/// dry run, are checked against the golden file
+/// `tests/golden/dry_run/{pm}.out`. Set `PACAPTR_BLESS=1` to rewrite the
+/// golden files instead after an intended change.
+///
+/// The operations are run with `--yes` as well, since some dry runs would
+/// still prompt otherwise.
+#[cfg(all(test, target_os = "linux"))]
+mod dry_run_tests {
+    use std::{
+        fmt::Write as _,
+        fs,
+        path::Path,
+        sync::{Arc, Mutex},
+    };
+
+    use super::{mock::MockLog, *};
+
+    macro_rules! impl_run_op {(
         methods = [{ $(
             $( #[$meta:meta] )*
             async fn $method:ident;
         )* }]
     ) => {
-        #[async_trait]
-        impl Pm for MockPm {
-            /// Gets the name of the package manager.
-            fn name(&self) -> &str {
-                "mockpm"
-            }
-
-            fn cfg(&self) -> &Config {
-                &self.cfg
+        /// The names of all the methods generated by the `methods` macro.
+        const METHODS: &[&str] = &[$( stringify!($method), )*];
+
+        /// Runs the method `method` of `pm`.
+        async fn run_op(pm: &dyn Pm, method: &str, kws: &[&str], flags: &[&str]) -> Result<()> {
+            match method {
+                $( stringify!($method) => pm.$method(kws, flags).await, )*
+                "autoremove" => pm.autoremove(kws, flags).await,
+                "autoremove_interactive" => pm.autoremove_interactive(kws, flags).await,
+                "repair" => pm.repair(kws, flags).await,
+                "verify" => pm.verify(kws, flags).await,
+                "refresh_keys" => pm.refresh_keys(kws, flags).await,
+                "downgrade" => pm.downgrade(kws, flags).await,
+                "undo" => pm.undo(kws, flags).await,
+                "module_list" => pm.module_list(kws, flags).await,
+                "module_enable" => pm.module_enable(kws, flags).await,
+                "module_install" => pm.module_install(kws, flags).await,
+                "module_reset" => pm.module_reset(kws, flags).await,
+                "service_list" => pm.service_list(kws, flags).await,
+                "service_add" => pm.service_add(kws, flags).await,
+                "service_remove" => pm.service_remove(kws, flags).await,
+                _ => unreachable!("unknown method `{method}`"),
             }
-
-            // * Automatically generated methods below... *
-            $( async fn $method(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
-                    make_mock_op_body!(self, kws, flags, $method)
-            } )*
         }
     };}
 
     tt_call! {
         macro = [{ methods }]
-        ~~> impl_pm_mock
+        ~~> impl_run_op
+    }
+
+    /// The operations out of [`METHODS`] to be run on every backend.
+    const EXTRA_METHODS: &[&str] = &[
+        "autoremove",
+        "autoremove_interactive",
+        "repair",
+        "verify",
+        "refresh_keys",
+        "downgrade",
+        "undo",
+        "module_list",
+        "module_enable",
+        "module_install",
+        "module_reset",
+        "service_list",
+        "service_add",
+        "service_remove",
+    ];
+
+    /// Runs `method` on `pm` in a dry run, returning the commands echoed,
+    /// followed by the ones actually run (prefixed with `$ `, e.g. the native
+    /// dry runs of [`DryRunStrategy::WithFlags`] or the read-only queries), or
+    /// the error (prefixed with `! `) if it has failed.
+    async fn dry_run(pm: &dyn Pm, method: &str, kws: &[&str], flags: &[&str]) -> String {
+        let sink = Arc::new(Mutex::new(Vec::new()));
+        let log = MockLog::new();
+        let res = log
+            .scope(print::capture_echoes(
+                sink.clone(),
+                run_op(pm, method, kws, flags),
+            ))
+            .await;
+        let mut out = String::from_utf8(sink.lock().unwrap().clone()).unwrap();
+        for line in log.lines() {
+            _ = writeln!(out, "$ {line}");
+        }
+        if let Err(e) = res {
+            _ = writeln!(out, "! {e}");
+        }
+        out
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn dry_run_golden() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden/dry_run");
+        let bless = env::var_os("PACAPTR_BLESS").is_some();
+        // `port` is only available on macOS.
+        for name in (PM_MODS.iter())
+            .filter(|&&name| !["port", "unknown"].contains(&name))
+            .map(|name| name.replace('_', "-"))
+        {
+            let pm = BoxPm::from(Config {
+                default_pm: Some(name.clone()),
+                dry_run: true,
+                no_confirm: true,
+                ..Config::default()
+            });
+            let mut got = String::new();
+            for &method in chain!(METHODS, EXTRA_METHODS) {
+                for kws in [&[][..], &["curl", "fish"]] {
+                    _ = writeln!(got, "# {}", chain!([method], kws.iter().copied()).join(" "));
+                    got += &dry_run(&*pm, method, kws, &["--flag"]).await;
+                }
+            }
+            let path = dir.join(format!("{name}.out"));
+            if bless {
+                fs::create_dir_all(&dir).unwrap();
+                fs::write(&path, &got).unwrap();
+                continue;
+            }
+            let expected = fs::read_to_string(&path).unwrap_or_default();
+            assert_eq!(got, expected, "dry run output mismatch for `{name}`");
+        }
+    }
+
+    /// Cross-checks [`COMPAT`] against the actual implementations of a few
+    /// backends, where an unsupported operation should fail as unimplemented.
+    #[tokio::test(flavor = "multi_thread")]
+    async fn compat_data() {
+        for name in ["apt", "brew", "dnf", "pip", "rpm-ostree"] {
+            let pm = BoxPm::from(Config {
+                default_pm: Some(name.into()),
+                dry_run: true,
+                no_confirm: true,
+                ..Config::default()
+            });
+            let ops = (COMPAT.iter())
+                .filter(|&&(pm, ..)| pm == name)
+                .map(|&(_, op, _)| op)
+                .sorted_unstable()
+                .collect_vec();
+            assert_eq!(ops, METHODS.iter().copied().sorted_unstable().collect_vec());
+            for &method in METHODS {
+                let out = dry_run(&*pm, method, &["curl"], &[]).await;
+                let unimplemented = out.contains(&format!("operation `{method}` is unimplemented"));
+                assert_eq!(
+                    supports(name, method),
+                    !unimplemented,
+                    "`{name}` with `{method}`"
+                );
+            }
+        }
+    }
+
+    /// Checks the options injected by [`Config::parallel_downloads`] into the
+    /// sync operations of the backends supporting it, against the golden file
+    /// `tests/golden/dry_run/parallel_downloads.out`.
+    #[tokio::test(flavor = "multi_thread")]
+    async fn parallel_downloads_golden() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/golden/dry_run/parallel_downloads.out");
+        let mut got = String::new();
+        for (name, n) in [("dnf", 1), ("dnf", 8), ("dnf", 64)] {
+            let pm = BoxPm::from(Config {
+                default_pm: Some(name.into()),
+                dry_run: true,
+                no_confirm: true,
+                parallel_downloads: Some(n),
+                ..Config::default()
+            });
+            assert!(pm.supports_parallel_downloads());
+            for (method, kws) in [
+                ("s", &["curl"][..]),
+                ("su", &[]),
+                ("suy", &[]),
+                ("sw", &["curl"]),
+            ] {
+                let op = chain!([name, method], kws.iter().copied()).join(" ");
+                _ = writeln!(got, "# {op} (parallel_downloads = {n})");
+                got += &dry_run(&*pm, method, kws, &[]).await;
+            }
+        }
+        if env::var_os("PACAPTR_BLESS").is_some() {
+            fs::write(&path, &got).unwrap();
+            return;
+        }
+        let expected = fs::read_to_string(&path).unwrap_or_default();
+        assert_eq!(
+            got, expected,
+            "dry run output mismatch for `parallel_downloads`"
+        );
     }
 }
@@ -4,10 +4,16 @@ use std::sync::LazyLock;
 
 use async_trait::async_trait;
 use indoc::indoc;
+use itertools::Itertools;
 use tap::prelude::*;
 
 use super::{NoCacheStrategy, Pm, PmHelper, PromptStrategy, Strategy};
-use crate::{config::Config, error::Result, exec::Cmd};
+use crate::{
+    config::Config,
+    error::Result,
+    exec::{self, download, Cmd, Mode},
+    pkg::{FileChange, FileCheck},
+};
 
 macro_rules! doc_self {
     () => {
@@ -41,6 +47,48 @@ impl Apk {
     pub const fn new(cfg: Config) -> Self {
         Self { cfg }
     }
+
+    /// Checks the files of the given packages, or of all installed ones if none
+    /// is given, against their checksums with `apk audit`, for `-Qk`.
+    async fn check_apk_files(&self, kws: &[&str], flags: &[&str], details: bool) -> Result<()> {
+        let installed = if kws.is_empty() {
+            let mode = if self.cfg.dry_run {
+                Mode::PrintCmd
+            } else {
+                Mode::Mute
+            };
+            let out = Cmd::new(["apk", "info"]).exec(mode).await?;
+            exec::decode(&out).into_owned()
+        } else {
+            String::new()
+        };
+        let installed = installed.lines().collect_vec();
+        let list =
+            Cmd::new(["apk", "info", "-L"]).kws(if kws.is_empty() { &installed } else { kws });
+        let verify = Cmd::new(["apk", "audit", "--system"]).flags(flags);
+        self.check_files(list, verify, parse_apk_file_checks, details)
+            .await
+    }
+
+    /// Prints out the installed sizes of the given packages, or of all
+    /// installed ones if none is given, as reported by `apk info -s`.
+    async fn print_apk_sizes(&self, kws: &[&str]) -> Result<()> {
+        let installed = if kws.is_empty() {
+            let mode = if self.cfg.dry_run {
+                Mode::PrintCmd
+            } else {
+                Mode::Mute
+            };
+            let out = Cmd::new(["apk", "info"]).exec(mode).await?;
+            exec::decode(&out).into_owned()
+        } else {
+            String::new()
+        };
+        let installed = installed.lines().collect_vec();
+        let sizes =
+            Cmd::new(["apk", "info", "-s"]).kws(if kws.is_empty() { &installed } else { kws });
+        self.print_pkg_sizes(sizes, parse_sizes).await
+    }
 }
 
 #[async_trait]
@@ -54,8 +102,15 @@ impl Pm for Apk {
         &self.cfg
     }
 
+    fn pkg_size_methods(&self) -> &[&'static str] {
+        &["q", "qi"]
+    }
+
     /// Q generates a list of installed packages.
     async fn q(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
+        if self.cfg.sizes {
+            return self.print_apk_sizes(kws).await;
+        }
         if kws.is_empty() {
             self.run(Cmd::new(["apk", "info"]).flags(flags)).await
         } else {
@@ -65,7 +120,11 @@ impl Pm for Apk {
 
     /// Qi displays local package information: name, version, description, etc.
     async fn qi(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
-        self.si(kws, flags).await
+        self.si(kws, flags).await?;
+        if self.cfg.sizes {
+            self.print_apk_sizes(kws).await?;
+        }
+        Ok(())
     }
 
     /// Qii displays local packages which require X to be installed, aka local
@@ -74,6 +133,16 @@ impl Pm for Apk {
         self.sii(kws, flags).await
     }
 
+    /// Qk verifies one or more packages.
+    async fn qk(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
+        self.check_apk_files(kws, flags, false).await
+    }
+
+    /// Qkk verifies one or more packages, listing every altered file.
+    async fn qkk(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
+        self.check_apk_files(kws, flags, true).await
+    }
+
     /// Ql displays files provided by local package.
     async fn ql(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
         self.run(Cmd::new(["apk", "info", "-L"]).kws(kws).flags(flags))
@@ -107,6 +176,8 @@ impl Pm for Apk {
 
     /// R removes a single package, leaving all of its dependencies installed.
     async fn r(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
+        self.check_required_by(kws, required_by_cmd, parse_required_by)
+            .await?;
         Cmd::with_sudo(["apk", "del"])
             .kws(kws)
             .flags(flags)
@@ -117,6 +188,8 @@ impl Pm for Apk {
     /// Rn removes a package and skips the generation of configuration backup
     /// files.
     async fn rn(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
+        self.check_required_by(kws, required_by_cmd, parse_required_by)
+            .await?;
         Cmd::with_sudo(["apk", "del", "--purge"])
             .kws(kws)
             .flags(flags)
@@ -128,6 +201,8 @@ impl Pm for Apk {
     /// other installed package, and skips the generation of configuration
     /// backup files.
     async fn rns(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
+        self.check_required_by(kws, required_by_cmd, parse_required_by)
+            .await?;
         Cmd::with_sudo(["apk", "del", "--purge", "-r"])
             .kws(kws)
             .flags(flags)
@@ -137,6 +212,9 @@ impl Pm for Apk {
 
     /// Rs removes a package and its dependencies which are not required by any
     /// other installed package, and not explicitly installed by the user.
+    ///
+    /// `apk` installs exactly what is needed by the packages in its world file,
+    /// so `apk del` already removes such dependencies.
     async fn rs(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
         self.r(kws, flags).await
     }
@@ -251,3 +329,134 @@ impl Pm for Apk {
             .await
     }
 }
+
+/// Parses the output of `apk info -s` into the sizes of the installed
+/// packages.
+///
+/// Each package is listed as `name-version installed size:`, followed by its
+/// size on the next line, either with a unit (e.g. `924 KiB`) or in bytes.
+fn parse_sizes(text: &str) -> Vec<(String, u64)> {
+    text.lines()
+        .map(str::trim)
+        .tuple_windows()
+        .filter_map(|(head, size)| {
+            let pkg = head.strip_suffix(" installed size:")?;
+            let name = pkg.rsplitn(3, '-').nth(2)?;
+            let bytes = match size.split_once(' ') {
+                Some((num, unit)) => download::parse_size(num, unit, 1024)?,
+                None => size.parse().ok()?,
+            };
+            Some((name.to_owned(), bytes))
+        })
+        .collect()
+}
+
+/// Returns the command listing the installed packages requiring the package
+/// `kw`, in the form readable by [`parse_required_by`].
+fn required_by_cmd(kw: &str) -> Cmd {
+    Cmd::new(["apk", "info", "-r"]).kws([kw])
+}
+
+/// Parses the output of `apk info -r` into the names of the installed packages
+/// requiring the given one.
+///
+/// These are listed as `name-version` under `name-version is required by:`,
+/// where the version is made of the last two `-`-separated parts, e.g.
+/// `1.36.1-r2`.
+fn parse_required_by(text: &str) -> Vec<String> {
+    text.lines()
+        .map(str::trim)
+        .filter(|ln| !ln.is_empty() && !ln.ends_with(':'))
+        .filter_map(|ln| ln.rsplitn(3, '-').nth(2))
+        .map(String::from)
+        .collect()
+}
+
+/// Parses the output of the commands of [`Apk::check_apk_files`] into
+/// [`FileCheck`]s.
+///
+/// Each package is listed by `apk info -L` as `name-version contains:`,
+/// followed by its files relative to the root, one per line. The altered files
+/// are reported by `apk audit` as `reason path`, where `reason` is a letter
+/// like `U` for updated files, and the files under `etc/` are taken as config
+/// files.
+fn parse_apk_file_checks(files: &str, verify: &str) -> Vec<FileCheck> {
+    let mut pkg = None;
+    let files = files.lines().filter_map(|ln| {
+        if let Some(name_version) = ln.strip_suffix(" contains:") {
+            // The version is always in the form of `ver-rN`.
+            pkg = name_version.rsplitn(3, '-').nth(2);
+            return None;
+        }
+        Some((pkg?, ln)).filter(|_| !ln.is_empty())
+    });
+    let changes = (verify.lines())
+        .filter_map(|ln| {
+            let (reason, path) = ln.split_once(' ')?;
+            Some(FileChange {
+                path: path.into(),
+                attrs: reason.into(),
+                config: path.starts_with("etc/"),
+            })
+        })
+        .collect_vec();
+    let mut checks = FileCheck::group(files, &changes);
+    for change in checks.iter_mut().flat_map(|check| &mut check.altered) {
+        change.path.insert(0, '/');
+    }
+    checks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{pkg::check_golden_file_checks, pm::mock::MockLog};
+
+    #[test]
+    fn file_checks_golden() {
+        check_golden_file_checks("apk", parse_apk_file_checks);
+    }
+
+    #[tokio::test]
+    async fn required_by() {
+        let text = indoc! {"
+            musl-1.2.4-r2 is required by:
+            busybox-1.36.1-r2
+            libcrypto3-3.1.4-r5
+            ssl_client-1.36.1-r2
+
+        "};
+        assert_eq!(
+            parse_required_by(text),
+            ["busybox", "libcrypto3", "ssl_client"]
+        );
+
+        let apk = Apk::new(Config {
+            check_required_by: true,
+            no_confirm: true,
+            ..Config::default()
+        });
+        let log = MockLog::new().output("apk info -r", text);
+        log.scope(apk.rns(&["musl"], &[])).await.unwrap();
+        assert_eq!(log.lines(), ["apk info -r musl", "apk del --purge -r musl"]);
+    }
+
+    #[test]
+    fn sizes() {
+        let text = indoc! {"
+            busybox-1.36.1-r2 installed size:
+            924 KiB
+
+            musl-1.2.4-r2 installed size:
+            626688
+
+        "};
+        assert_eq!(
+            parse_sizes(text),
+            [
+                ("busybox".to_owned(), 924 * 1024),
+                ("musl".to_owned(), 626_688),
+            ]
+        );
+    }
+}
@@ -1,13 +1,23 @@
 #![doc = doc_self!()]
 
-use std::sync::LazyLock;
+use std::{collections::HashSet, env, fs, process, sync::LazyLock};
 
 use async_trait::async_trait;
 use indoc::indoc;
+use itertools::{chain, Itertools};
 use tap::prelude::*;
 
-use super::{NoCacheStrategy, Pm, PmHelper, PromptStrategy, Strategy};
-use crate::{config::Config, error::Result, exec::Cmd};
+use super::{
+    checksum_cmd, dnf::parse_rpm_verify, CmdSeq, Download, DryRunStrategy, NoCacheStrategy, Pm,
+    PmHelper, PmMode, PromptStrategy, Strategy,
+};
+use crate::{
+    config::Config,
+    error::{Error, Result},
+    exec::{self, Cmd, Mode},
+    pkg::{self, Conflict, DepGraph, FileCheck, Pkg, Update},
+    print::{self, prompt, Progress},
+};
 
 macro_rules! doc_self {
     () => {
@@ -24,17 +34,49 @@ pub struct Apt {
     cfg: Config,
 }
 
+/// The `dpkg-query` format listing the files of the packages in a form readable
+/// by [`parse_dpkg_file_checks`].
+const DPKG_FILES_FORMAT: &str = "${db:Status-Abbrev}${binary:Package}\\n${db-fsys:Files}";
+
+/// The `dpkg-query` format listing the packages with their installed sizes (in
+/// KiB) in a form readable by [`parse_installed_sizes`].
+const DPKG_SIZES_FORMAT: &str = "${db:Status-Status}\\t${Installed-Size}\\t${binary:Package}\\n";
+
+/// The directory where `apt` keeps the downloaded `.deb`s.
+const ARCHIVES_DIR: &str = "/var/cache/apt/archives";
+
+/// The prefix of the metapackages installing the `tasksel` tasks.
+const TASK_PREFIX: &str = "task-";
+
+/// The `dpkg` options keeping the current version of the modified config files
+/// without asking, see [`Config::apt_noninteractive_dpkg_opts`].
+const DPKG_CONF_OPTS: &[&str] = &[
+    "Dpkg::Options::=--force-confdef",
+    "Dpkg::Options::=--force-confold",
+];
+
 static STRAT_PROMPT: LazyLock<Strategy> = LazyLock::new(|| Strategy {
     prompt: PromptStrategy::native_no_confirm(["--yes"]),
     ..Strategy::default()
 });
 
+static STRAT_PRUNE: LazyLock<Strategy> = LazyLock::new(|| Strategy {
+    prompt: PromptStrategy::CustomPrompt,
+    ..Strategy::default()
+});
+
 static STRAT_INSTALL: LazyLock<Strategy> = LazyLock::new(|| Strategy {
     prompt: PromptStrategy::native_no_confirm(["--yes"]),
     no_cache: NoCacheStrategy::Scc,
     ..Strategy::default()
 });
 
+/// Runs the command even in a dry run, as it only queries information.
+static STRAT_QUERY: LazyLock<Strategy> = LazyLock::new(|| Strategy {
+    dry_run: DryRunStrategy::WithFlags(vec![]),
+    ..Strategy::default()
+});
+
 impl Apt {
     #[must_use]
     #[allow(missing_docs)]
@@ -50,6 +92,262 @@ impl Apt {
             .as_deref()
             .expect("default package manager should have been assigned before initialization")
     }
+
+    /// Returns the given `flags` preceded by the options setting the proxies in
+    /// the config, to be used by the commands accessing the network.
+    ///
+    /// With [`Config::offline`], `--no-download` is added as well, so that
+    /// only the packages in the cache are installed.
+    fn net_flags(&self, flags: &[&str]) -> Vec<String> {
+        let proxies = [
+            ("http", self.cfg.http_proxy()),
+            ("https", self.cfg.https_proxy()),
+        ]
+        .into_iter()
+        .filter_map(|(scheme, url)| Some((scheme, url?)))
+        .collect_vec();
+        // `apt` only supports bypassing the proxy for exact host names.
+        let direct = self
+            .cfg
+            .no_proxy_hosts()
+            .filter(|host| !host.starts_with('.') && !host.contains(['*', '/']))
+            .collect_vec();
+        let opts = proxies.iter().flat_map(|&(scheme, url)| {
+            chain!(
+                [format!("Acquire::{scheme}::Proxy={url}")],
+                (direct.iter()).map(move |host| format!("Acquire::{scheme}::Proxy::{host}=DIRECT")),
+            )
+        });
+        chain!(
+            opts.flat_map(|opt| ["-o".to_owned(), opt]),
+            self.cfg.offline.then(|| "--no-download".to_owned()),
+            flags.iter().map(|&flag| flag.to_owned()),
+        )
+        .collect()
+    }
+
+    /// Returns the file names of the `.deb`s to be downloaded when installing
+    /// the given packages, along with the checksums they are published with in
+    /// the `Packages` indices, if [`Config::verify_checksums`] is set.
+    ///
+    /// `apt-get install --print-uris` omits the files already in the cache, so
+    /// it is pointed to an empty archives directory in the meantime to list
+    /// every file of the transaction. In a dry run, the planning command is
+    /// only printed out.
+    async fn planned_debs(&self, kws: &[&str], flags: &[&str]) -> Result<Vec<Download>> {
+        if !self.cfg.verify_checksums {
+            return Ok(vec![]);
+        }
+        let empty = env::temp_dir().join(format!("pacaptr-apt-archives-{}", process::id()));
+        let plan = Cmd::new(["apt-get", "install", "--print-uris", "-qq"])
+            .kws(kws)
+            .flags(chain!(
+                [
+                    "-o".to_owned(),
+                    format!("Dir::Cache::Archives={}/", empty.display()),
+                ],
+                self.net_flags(flags),
+            ));
+        if self.cfg.dry_run {
+            plan.exec(Mode::PrintCmd).await?;
+            return Ok(vec![]);
+        }
+        fs::create_dir_all(empty.join("partial"))?;
+        let out = plan.exec(Mode::Mute).await;
+        _ = fs::remove_dir_all(&empty);
+        Ok(parse_print_uris(&exec::decode(&out?))
+            .into_iter()
+            .map(|(file, checksum)| Download {
+                path: format!("{ARCHIVES_DIR}/{file}"),
+                checksum,
+            })
+            .collect())
+    }
+
+    /// Makes the given command running `dpkg` fully non-interactive with
+    /// `--yes`, so that it never waits for an answer to a `debconf` question,
+    /// nor to one about a modified config file if
+    /// [`Config::apt_noninteractive_dpkg_opts`] is set.
+    fn unattended(&self, mut cmd: Cmd) -> Cmd {
+        if !self.cfg.no_confirm {
+            return cmd;
+        }
+        if self.cfg.apt_noninteractive_dpkg_opts {
+            let opts = DPKG_CONF_OPTS.iter().flat_map(|&opt| ["-o", opt]);
+            cmd.flags.splice(0..0, opts.map(Into::into));
+        }
+        cmd.env("DEBIAN_FRONTEND", "noninteractive")
+    }
+
+    /// Returns the command installing the given packages.
+    fn install_cmd(&self, kws: &[&str], flags: &[&str]) -> Cmd {
+        if self.cfg.needed {
+            Cmd::with_sudo(&[self.cmd(), "install"][..])
+        } else {
+            Cmd::with_sudo(&[self.cmd(), "install", "--reinstall"][..])
+        }
+        .kws(kws)
+        .flags(self.net_flags(&self.release_flags(flags)))
+        .pipe(|cmd| self.unattended(cmd))
+    }
+
+    /// Returns the given `flags` preceded by `-t` and the
+    /// [`Config::target_release`] (if any), for the commands choosing the
+    /// versions of the packages.
+    fn release_flags<'a>(&'a self, flags: &[&'a str]) -> Vec<&'a str> {
+        let release = (self.cfg.target_release.as_deref())
+            .into_iter()
+            .flat_map(|rel| ["-t", rel]);
+        chain!(release, flags.iter().copied()).collect()
+    }
+
+    /// Checks the files of the given packages, or of all installed ones if none
+    /// is given, against their checksums with `dpkg --verify`, for `-Qk`.
+    ///
+    /// If `dpkg --verify` is not available, `debsums` is suggested instead.
+    async fn check_dpkg_files(&self, kws: &[&str], flags: &[&str], details: bool) -> Result<()> {
+        let list = Cmd::new(["dpkg-query", "-W", "-f", DPKG_FILES_FORMAT]).kws(kws);
+        let verify = Cmd::new(["dpkg", "--verify"]).kws(kws).flags(flags);
+        let res = (self.check_files(list, verify, parse_dpkg_file_checks, details)).await;
+        if let Err(Error::CmdStatusCodeError { cmd, .. }) = &res {
+            if cmd.contains("--verify") {
+                print::println(
+                    &*prompt::INFO,
+                    "`debsums -s` can also check the files against their checksums",
+                );
+            }
+        }
+        res
+    }
+
+    /// Returns the read-only command showing how the resolver handles the
+    /// installation of the given packages, for
+    /// [`PmHelper::explain_conflicts`].
+    fn explain_cmd(&self, kws: &[&str], flags: &[&str]) -> Cmd {
+        Cmd::new([
+            "apt-get",
+            "install",
+            "--simulate",
+            "-o",
+            "Debug::pkgProblemResolver=true",
+        ])
+        .kws(kws)
+        .flags(self.net_flags(&self.release_flags(flags)))
+    }
+
+    /// Runs `seq` followed by the upgrade of all the packages (except the
+    /// ignored ones) as a single transaction.
+    ///
+    /// The ignored packages are held as part of the transaction, i.e. only once
+    /// it has been confirmed, and unheld afterwards even if the upgrade fails.
+    async fn upgrade_all(&self, seq: CmdSeq, flags: &[&str]) -> Result<()> {
+        let ignored = self.ignored_to_hold().await?;
+        let hold = Cmd::with_sudo(["apt-mark", "hold"]).kws(&ignored);
+        let unhold = Cmd::with_sudo(["apt-mark", "unhold"]).kws(&ignored);
+        let seq = if ignored.is_empty() {
+            seq
+        } else {
+            if !self.cfg.dry_run {
+                print::println(
+                    &*prompt::INFO,
+                    format_args!(
+                        "the ignored packages will be held during the upgrade, run `{unhold}` if it is interrupted"
+                    ),
+                );
+            }
+            seq.then(hold, &Strategy::default())
+        };
+        let res = seq
+            .then(
+                Cmd::with_sudo(["apt", "upgrade"])
+                    .flags(self.net_flags(&self.release_flags(flags)))
+                    .pipe(|cmd| self.unattended(cmd)),
+                &STRAT_PROMPT,
+            )
+            .then(
+                Cmd::with_sudo(["apt", "dist-upgrade"])
+                    .flags(self.net_flags(&self.release_flags(flags)))
+                    .pipe(|cmd| self.unattended(cmd)),
+                &STRAT_INSTALL,
+            )
+            .pipe(|seq| self.run_seq(seq))
+            .await;
+        // Nothing has been held if the whole transaction has been declined,
+        // but with `--step`, the hold might have been confirmed on its own.
+        if ignored.is_empty() || (exec::prompt_declined() && !self.cfg.step) {
+            return res;
+        }
+        // The packages should be unheld even if the upgrade has failed, whose
+        // error is the one to be returned.
+        let strat = Strategy::default();
+        if let Err(e) = self
+            .run_with(unhold.clone(), self.default_mode(), &strat)
+            .await
+        {
+            print::println(
+                &*prompt::WARNING,
+                format_args!(
+                    "failed to unhold the ignored packages ({e}), run `{unhold}` to do so"
+                ),
+            );
+        }
+        res
+    }
+
+    /// Returns the packages in the `--ignore` list that are not held yet, which
+    /// should be held so that they are skipped by upgrades. The glob patterns
+    /// in the list are matched against the installed packages.
+    async fn ignored_to_hold(&self) -> Result<Vec<String>> {
+        if self.cfg.ignore.is_empty() {
+            return Ok(vec![]);
+        }
+        print::println(
+            &*prompt::INFO,
+            format_args!("ignoring upgrades of {}", self.cfg.ignore.join(", ")),
+        );
+        let out = Cmd::new(["apt-mark", "showhold"])
+            .pipe(|cmd| self.check_output(cmd, PmMode::Mute, &STRAT_QUERY))
+            .await?;
+        let out = exec::decode(&out);
+        let held = out.lines().map(str::trim).collect::<HashSet<_>>();
+        let (globs, names): (Vec<_>, Vec<_>) =
+            (self.cfg.ignore.iter()).partition(|pkg| exec::is_glob(pkg));
+        let mut ignored = names.into_iter().cloned().collect_vec();
+        if !globs.is_empty() {
+            let patterns = exec::glob_patterns(&globs)?;
+            let installed = Cmd::new(["dpkg-query", "-W", "-f", "${Package}\\n"])
+                .pipe(|cmd| self.check_output(cmd, PmMode::Mute, &STRAT_QUERY))
+                .await?;
+            ignored.extend(
+                (exec::decode(&installed).lines())
+                    .map(str::trim)
+                    .filter(|pkg| patterns.is_match(pkg))
+                    .map(String::from),
+            );
+        }
+        Ok((ignored.into_iter())
+            .filter(|pkg| !held.contains(pkg.as_str()))
+            .unique()
+            .collect())
+    }
+
+    /// Returns the names of the installed keyring packages, e.g.
+    /// `debian-archive-keyring`.
+    async fn keyrings(&self) -> Result<Vec<String>> {
+        let cmd = Cmd::new([
+            "dpkg-query",
+            "-W",
+            "-f",
+            "${db:Status-Abbrev}${Package}\\n",
+            "*keyring*",
+        ]);
+        match self.check_output(cmd, PmMode::Mute, &STRAT_QUERY).await {
+            Ok(out) => Ok(parse_keyrings(&exec::decode(&out))),
+            // ! `dpkg-query` exits with code 1 if no package matches the pattern.
+            Err(Error::CmdStatusCodeError { code: 1, .. }) => Ok(vec![]),
+            Err(e) => Err(e),
+        }
+    }
 }
 
 #[async_trait]
@@ -63,19 +361,168 @@ impl Pm for Apt {
         &self.cfg
     }
 
-    /// Q generates a list of installed packages.
-    async fn q(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
-        Cmd::new(["apt", "list", "--installed"])
-            .kws(kws)
+    fn supports_target_release(&self) -> bool {
+        true
+    }
+
+    fn supports_installed_filter(&self) -> bool {
+        true
+    }
+
+    fn supports_search_limit(&self) -> bool {
+        true
+    }
+
+    fn supports_search_sort(&self) -> bool {
+        true
+    }
+
+    fn dep_tree_methods(&self) -> &[&'static str] {
+        &["qi", "si"]
+    }
+
+    fn pkg_size_methods(&self) -> &[&'static str] {
+        &["q", "qi", "s", "su"]
+    }
+
+    fn offline_methods(&self) -> &[&'static str] {
+        // Apart from the installations and the upgrades, these only read the
+        // local package database.
+        &["s", "sg", "si", "sii", "ss", "su"]
+    }
+
+    fn supports_verify_checksums(&self) -> bool {
+        true
+    }
+
+    /// Autoremove shows all the packages that are no longer required by any
+    /// other installed package, then removes them after confirmation.
+    async fn autoremove(&self, _kws: &[&str], flags: &[&str]) -> Result<()> {
+        self.run(Cmd::new(["apt", "autoremove", "--simulate"]).flags(flags))
+            .await?;
+        // The user has already been asked by our custom prompt at this point.
+        Cmd::with_sudo(["apt", "autoremove", "--yes"])
             .flags(flags)
+            .pipe(|cmd| self.run_reclaiming(cmd, self.default_mode(), &STRAT_PRUNE))
+            .await
+    }
+
+    /// Autoremove interactive lists all the packages that are no longer
+    /// required by any other installed package with their sizes, then removes
+    /// the ones chosen by the user.
+    async fn autoremove_interactive(&self, _kws: &[&str], flags: &[&str]) -> Result<()> {
+        let orphans = Cmd::new(["apt-get", "autoremove", "--simulate"]).flags(flags);
+        self.remove_orphans_interactively(
+            orphans,
+            parse_simulated_removals,
+            sizes_cmd,
+            parse_installed_sizes,
+            flags,
+        )
+        .await
+    }
+
+    /// Repair tries to bring a system in a broken or half-configured state
+    /// back to a consistent one.
+    async fn repair(&self, _kws: &[&str], flags: &[&str]) -> Result<()> {
+        CmdSeq::new()
+            .then(
+                Cmd::with_sudo(["dpkg", "--configure", "-a"]),
+                &Strategy::default(),
+            )
+            .then(
+                Cmd::with_sudo(["apt", "--fix-broken", "install"])
+                    .flags(self.net_flags(flags))
+                    .pipe(|cmd| self.unattended(cmd)),
+                &STRAT_PROMPT,
+            )
+            .pipe(|seq| self.run_seq(seq))
+            .await
+    }
+
+    /// Refresh keys refreshes the package lists, then upgrades the installed
+    /// keyring packages which provide the keys of the repositories.
+    async fn refresh_keys(&self, _kws: &[&str], flags: &[&str]) -> Result<()> {
+        let keyrings = self.keyrings().await?;
+        let progress = Progress::new(if keyrings.is_empty() { 1 } else { 2 }, &self.cfg);
+        progress.step("refreshing the package lists");
+        match Cmd::with_sudo(["apt-get", "update"])
+            .flags(self.net_flags(flags))
             .pipe(|cmd| self.run(cmd))
             .await
+        {
+            Ok(()) => (),
+            // The lists of the other repositories might still have been refreshed,
+            // which is enough to upgrade the keyrings.
+            Err(Error::CmdStatusCodeError { code, .. }) => print::println(
+                &*prompt::WARNING,
+                format_args!(
+                    "`apt-get update` exited with code {code}, upgrading the keyrings anyway"
+                ),
+            ),
+            Err(e) => return Err(e),
+        }
+        if keyrings.is_empty() {
+            print::println(&*prompt::INFO, "no keyring package is installed");
+            return Ok(());
+        }
+        progress.step(format_args!("upgrading {}", keyrings.join(", ")));
+        Cmd::with_sudo(["apt-get", "install", "--only-upgrade"])
+            .kws(keyrings)
+            .flags(self.net_flags(flags))
+            .pipe(|cmd| self.unattended(cmd))
+            .pipe(|cmd| self.run_with(cmd, self.default_mode(), &STRAT_PROMPT))
+            .await
+    }
+
+    /// Downgrade installs an older version of the given packages.
+    ///
+    /// `apt` keeps no track of the previous versions, so each package must be
+    /// pinned to the version to downgrade to with `name=version`.
+    async fn downgrade(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
+        if kws.is_empty() {
+            return Err(Error::ArgParseError {
+                msg: "the packages to downgrade are required, e.g. `curl=7.81.0`".into(),
+            });
+        }
+        let kws = kws.iter().map(|&kw| match pkg::split_pinned(kw) {
+            (name, Some(version)) => Ok(format!("{name}={version}")),
+            (name, None) => Err(Error::ArgParseError {
+                msg: format!("the version to downgrade `{name}` to is required, e.g. `{name}=1.0`"),
+            }),
+        });
+        Cmd::with_sudo([self.cmd(), "install", "--allow-downgrades"])
+            .kws(kws.collect::<Result<Vec<_>>>()?)
+            .flags(self.net_flags(flags))
+            .pipe(|cmd| self.unattended(cmd))
+            .pipe(|cmd| self.run_with(cmd, self.default_mode(), &STRAT_INSTALL))
+            .await
+    }
+
+    /// Q generates a list of installed packages.
+    async fn q(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
+        if self.cfg.sizes {
+            return self
+                .print_pkg_sizes(sizes_cmd(kws), parse_installed_sizes)
+                .await;
+        }
+        let cmd = Cmd::new(["apt", "list", "--installed"])
+            .kws(kws)
+            .flags(flags);
+        if self.cfg.query_format().is_structured() {
+            return self.print_pkgs(cmd, &[], parse_list).await;
+        }
+        self.run(cmd).await
     }
 
     /// Qc shows the changelog of a package.
     async fn qc(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
-        self.run(Cmd::new(["apt", "changelog"]).kws(kws).flags(flags))
-            .await
+        self.run(
+            Cmd::new(["apt", "changelog"])
+                .kws(kws)
+                .flags(self.net_flags(flags)),
+        )
+        .await
     }
 
     /// Qe lists packages installed explicitly (not as dependencies).
@@ -86,14 +533,54 @@ impl Pm for Apt {
 
     /// Qi displays local package information: name, version, description, etc.
     async fn qi(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
+        if self.cfg.tree {
+            let cmd = depends_cmd(true).kws(kws).flags(flags);
+            return self.print_dep_tree(cmd, kws, parse_depends).await;
+        }
         self.run(Cmd::new(["dpkg-query", "-s"]).kws(kws).flags(flags))
-            .await
+            .await?;
+        if self.cfg.sizes {
+            self.print_pkg_sizes(sizes_cmd(kws), parse_installed_sizes)
+                .await?;
+        }
+        Ok(())
     }
 
     /// Qii displays local packages which require X to be installed, aka local
     /// reverse dependencies.
     async fn qii(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
-        self.sii(kws, flags).await
+        self.run(
+            Cmd::new(["apt-cache", "rdepends", "--installed"])
+                .kws(kws)
+                .flags(flags),
+        )
+        .await
+    }
+
+    /// Qk verifies one or more packages.
+    async fn qk(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
+        self.check_dpkg_files(kws, flags, false).await
+    }
+
+    /// Qkk verifies one or more packages, listing every altered file.
+    async fn qkk(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
+        self.check_dpkg_files(kws, flags, true).await
+    }
+
+    /// Ql displays files provided by local package.
+    async fn ql(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
+        self.list_files(Cmd::new(["dpkg-query", "-L"]).kws(kws).flags(flags))
+            .await
+    }
+
+    /// Qm lists packages that are installed but are not available in any
+    /// installation source (anymore).
+    async fn qm(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
+        Cmd::new(["apt", "list", "--installed"])
+            .kws(kws)
+            .flags(flags)
+            .pipe(|cmd| self.print_unavailable(cmd, parse_local))
+            .await
     }
 
     /// Qo queries the package which provides FILE.
@@ -114,6 +601,12 @@ impl Pm for Apt {
     // when including multiple search terms, only packages with descriptions
     // matching ALL of those terms are returned.
     async fn qs(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
+        if self.cfg.query_format().is_structured() {
+            return Cmd::new(["apt", "list", "--installed"])
+                .flags(flags)
+                .pipe(|cmd| self.print_pkgs(cmd, kws, parse_list))
+                .await;
+        }
         Cmd::new(["dpkg-query", "-l"])
             .flags(flags)
             .pipe(|cmd| self.search_regex_with_header(cmd, kws, 4))
@@ -122,18 +615,26 @@ impl Pm for Apt {
 
     /// Qu lists packages which have an update available.
     async fn qu(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
-        Cmd::with_sudo(["apt", "upgrade", "--trivial-only"])
-            .kws(kws)
+        if self.cfg.query_format().is_structured() {
+            return Cmd::new(["apt", "list", "--upgradable"])
+                .flags(flags)
+                .pipe(|cmd| self.print_pkgs(cmd, kws, parse_list))
+                .await;
+        }
+        Cmd::new(["apt", "-qq", "list", "--upgradable"])
             .flags(flags)
-            .pipe(|cmd| self.run(cmd))
+            .pipe(|cmd| self.print_updates(cmd, kws, parse_upgradable))
             .await
     }
 
     /// R removes a single package, leaving all of its dependencies installed.
     async fn r(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
+        self.check_required_by(kws, rdepends_cmd, parse_rdepends)
+            .await?;
         Cmd::with_sudo(["apt", "remove"])
             .kws(kws)
             .flags(flags)
+            .pipe(|cmd| self.unattended(cmd))
             .pipe(|cmd| self.run_with(cmd, self.default_mode(), &STRAT_PROMPT))
             .await
     }
@@ -141,9 +642,12 @@ impl Pm for Apt {
     /// Rn removes a package and skips the generation of configuration backup
     /// files.
     async fn rn(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
+        self.check_required_by(kws, rdepends_cmd, parse_rdepends)
+            .await?;
         Cmd::with_sudo(["apt", "purge"])
             .kws(kws)
             .flags(flags)
+            .pipe(|cmd| self.unattended(cmd))
             .pipe(|cmd| self.run_with(cmd, self.default_mode(), &STRAT_PROMPT))
             .await
     }
@@ -152,9 +656,12 @@ impl Pm for Apt {
     /// other installed package, and skips the generation of configuration
     /// backup files.
     async fn rns(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
+        self.check_required_by(kws, rdepends_cmd, parse_rdepends)
+            .await?;
         Cmd::with_sudo(["apt", "autoremove", "--purge"])
             .kws(kws)
             .flags(flags)
+            .pipe(|cmd| self.unattended(cmd))
             .pipe(|cmd| self.run_with(cmd, self.default_mode(), &STRAT_PROMPT))
             .await
     }
@@ -162,24 +669,35 @@ impl Pm for Apt {
     /// Rs removes a package and its dependencies which are not required by any
     /// other installed package, and not explicitly installed by the user.
     async fn rs(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
+        self.check_required_by(kws, rdepends_cmd, parse_rdepends)
+            .await?;
         Cmd::with_sudo(["apt", "autoremove"])
             .kws(kws)
             .flags(flags)
+            .pipe(|cmd| self.unattended(cmd))
             .pipe(|cmd| self.run_with(cmd, self.default_mode(), &STRAT_PROMPT))
             .await
     }
 
     /// S installs one or more packages by name.
     async fn s(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
-        if self.cfg.needed {
-            Cmd::with_sudo(&[self.cmd(), "install"][..])
-        } else {
-            Cmd::with_sudo(&[self.cmd(), "install", "--reinstall"][..])
+        let mut plan = self.install_cmd(kws, flags);
+        plan.flags.push("--assume-no".into());
+        self.print_transaction_size(plan, parse_transaction_size)
+            .await?;
+        let res = self
+            .install_cmd(kws, flags)
+            .pipe(|cmd| self.run_with(cmd, self.default_mode(), &STRAT_INSTALL))
+            .await;
+        match res {
+            Err(e) => {
+                let explain = self.explain_cmd(kws, flags);
+                Err(self
+                    .explain_conflicts(e, explain, is_conflict, parse_resolver)
+                    .await)
+            }
+            ok => ok,
         }
-        .kws(kws)
-        .flags(flags)
-        .pipe(|cmd| self.run_with(cmd, self.default_mode(), &STRAT_INSTALL))
-        .await
     }
 
     /// Sc removes all the cached packages that are not currently installed, and
@@ -202,22 +720,40 @@ impl Pm for Apt {
     }
 
     /// Sg lists all packages belonging to the GROUP.
+    ///
+    /// The groups are the `tasksel` tasks. Without `tasksel`, or if any of
+    /// them is given as its `task-*` metapackage, they are looked up through
+    /// these metapackages instead.
     async fn sg(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
-        Cmd::new(if kws.is_empty() {
-            ["tasksel", "--list-task"]
+        let tasksel = !kws.iter().any(|kw| kw.starts_with(TASK_PREFIX))
+            && (self.cfg.dry_run || exec::is_exe("tasksel", ""));
+        let cmd = if tasksel {
+            Cmd::new(if kws.is_empty() {
+                ["tasksel", "--list-task"]
+            } else {
+                ["tasksel", "--task-packages"]
+            })
+            .kws(kws)
+        } else if kws.is_empty() {
+            Cmd::new(["apt-cache", "search", "--names-only"]).kws([format!("^{TASK_PREFIX}")])
         } else {
-            ["tasksel", "--task-packages"]
-        })
-        .kws(kws)
-        .flags(flags)
-        .pipe(|cmd| self.run(cmd))
-        .await
+            Cmd::new(["apt-cache", "depends"]).kws(kws.iter().map(|&kw| task_pkg(kw)))
+        };
+        self.run(cmd.flags(flags)).await
     }
 
     /// Si displays remote package information: name, version, description, etc.
     async fn si(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
-        self.run(Cmd::new(["apt", "show"]).kws(kws).flags(flags))
-            .await
+        if self.cfg.tree {
+            let cmd = depends_cmd(false).kws(kws).flags(flags);
+            return self.print_dep_tree(cmd, kws, parse_depends).await;
+        }
+        self.run(
+            Cmd::new(["apt", "show"])
+                .kws(kws)
+                .flags(self.release_flags(flags)),
+        )
+        .await
     }
 
     /// Sii displays packages which require X to be installed, aka reverse
@@ -230,50 +766,1034 @@ impl Pm for Apt {
     /// Ss searches for package(s) by searching the expression in name,
     /// description, short description.
     async fn ss(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
-        self.run(Cmd::new([self.cmd(), "search"]).kws(kws).flags(flags))
-            .await
+        let cmd = Cmd::new([self.cmd(), "search"]).kws(kws).flags(flags);
+        if self.cfg.exclude_installed || self.cfg.only_installed {
+            let installed = Cmd::new(["apt", "list", "--installed"]);
+            return (self.print_search_filtered(cmd, parse_list, installed, parse_list)).await;
+        }
+        if self.cfg.sorts_or_limits_search() {
+            return self.print_search(cmd, parse_list).await;
+        }
+        if self.cfg.query_format().is_structured() {
+            return self.print_pkgs(cmd, &[], parse_list).await;
+        }
+        self.run(cmd).await
     }
 
     /// Su updates outdated packages.
     async fn su(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
-        if kws.is_empty() {
-            Cmd::with_sudo(["apt", "upgrade"])
-                .flags(flags)
-                .pipe(|cmd| self.run_with(cmd, self.default_mode(), &STRAT_PROMPT))
-                .await?;
-            Cmd::with_sudo(["apt", "dist-upgrade"])
-                .flags(flags)
-                .pipe(|cmd| self.run_with(cmd, self.default_mode(), &STRAT_INSTALL))
-                .await
-        } else {
-            self.s(kws, flags).await
+        if !kws.is_empty() {
+            return self.s(kws, flags).await;
         }
+        Cmd::with_sudo(["apt-get", "dist-upgrade", "--assume-no"])
+            .flags(self.net_flags(&self.release_flags(flags)))
+            .pipe(|plan| self.print_transaction_size(plan, parse_transaction_size))
+            .await?;
+        self.upgrade_all(CmdSeq::new(), flags).await
     }
 
     /// Suy refreshes the local package database, then updates outdated
     /// packages.
     async fn suy(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
-        self.sy(&[], flags).await?;
-        self.su(kws, flags).await
+        let seq = CmdSeq::new().then(
+            Cmd::with_sudo([self.cmd(), "update"]).flags(self.net_flags(flags)),
+            &Strategy::default(),
+        );
+        if kws.is_empty() {
+            self.upgrade_all(seq, flags).await
+        } else {
+            self.run_seq(seq.then(self.install_cmd(kws, flags), &STRAT_INSTALL))
+                .await
+        }
     }
 
     /// Sw retrieves all packages from the server, but does not install/upgrade
     /// anything.
+    ///
+    /// With [`Config::verify_checksums`], the checksums of the `.deb`s to be
+    /// downloaded are taken from the `Packages` indices beforehand, and
+    /// checked against the files in the cache afterwards.
     async fn sw(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
+        let downloads = self.planned_debs(kws, flags).await?;
         Cmd::with_sudo([self.cmd(), "install", "--download-only"])
             .kws(kws)
-            .flags(flags)
+            .flags(self.net_flags(flags))
             .pipe(|cmd| self.run_with(cmd, self.default_mode(), &STRAT_INSTALL))
-            .await
+            .await?;
+        self.verify_downloads(downloads).await
     }
 
     /// Sy refreshes the local package database.
     async fn sy(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
-        self.run(Cmd::with_sudo([self.cmd(), "update"]).flags(flags))
+        self.run(Cmd::with_sudo([self.cmd(), "update"]).flags(self.net_flags(flags)))
             .await?;
         if !kws.is_empty() {
             self.s(kws, flags).await?;
         }
         Ok(())
     }
+
+    /// Verify checks the installed files of the given packages against the
+    /// packaged ones, and the installed versions against the repositories.
+    ///
+    /// It fails if some of the installed files have changed.
+    async fn verify(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
+        if kws.is_empty() {
+            return Err(Error::ArgParseError {
+                msg: "no package to verify was given".into(),
+            });
+        }
+        super::ensure_unstructured(self)?;
+        CmdSeq::new()
+            .then(
+                Cmd::new(["apt-cache", "policy"]).kws(kws).flags(flags),
+                &Strategy::default(),
+            )
+            .then(Cmd::new(["debsums", "-s"]).kws(kws), &Strategy::default())
+            .pipe(|seq| self.run_seq(seq))
+            .await
+            .map_err(|e| match e {
+                // ! `debsums` exits with code 2 if any file has changed.
+                Error::CmdStatusCodeError { code: 2, .. } => super::verification_failed(),
+                e => e,
+            })
+    }
+}
+
+/// Parses the output of `dpkg-query -W -f '${db:Status-Abbrev}${Package}\n'`
+/// into the names of the installed packages.
+fn parse_keyrings(text: &str) -> Vec<String> {
+    text.lines()
+        .filter_map(|ln| Some(ln.strip_prefix("ii ")?.trim().to_owned()))
+        .collect()
+}
+
+/// Parses the output of the commands of [`Apt::check_dpkg_files`] into
+/// [`FileCheck`]s.
+///
+/// Each package is listed by [`DPKG_FILES_FORMAT`] as its status followed by
+/// its name, e.g. `ii curl`, and then its files, one per line indented by a
+/// space. Packages that are not installed are skipped. The altered files are
+/// reported by `dpkg --verify` in the same format as `rpm -V`.
+fn parse_dpkg_file_checks(files: &str, verify: &str) -> Vec<FileCheck> {
+    let mut pkg = None;
+    let files = files.lines().filter_map(|ln| {
+        let Some(path) = ln.strip_prefix(' ') else {
+            // The second letter of the status is `i` for installed packages.
+            pkg = (ln.as_bytes().get(1) == Some(&b'i'))
+                .then(|| ln.get(3..))
+                .flatten();
+            return None;
+        };
+        // ! `/.` is listed as the root of each package.
+        Some((pkg?, path)).filter(|_| path != "/.")
+    });
+    FileCheck::group(files, &parse_rpm_verify(verify))
+}
+
+/// Returns the name of the metapackage of the `tasksel` task `task`, e.g.
+/// `task-kde-desktop` for `kde-desktop`.
+fn task_pkg(task: &str) -> String {
+    if task.starts_with(TASK_PREFIX) {
+        task.to_owned()
+    } else {
+        format!("{TASK_PREFIX}{task}")
+    }
+}
+
+/// Checks if the output of a failed installation reports dependency
+/// conflicts.
+fn is_conflict(text: &str) -> bool {
+    text.contains("Unable to correct problems") || text.contains("unmet dependencies")
+}
+
+/// Parses the output of [`Apt::explain_cmd`] into [`Conflict`]s.
+///
+/// A conflict is listed as `Broken pkg:arch Relation on target:arch < versions
+/// flags > (constraint)`, where `versions` is either `current` or `current ->
+/// candidate`, and the constraint is optional.
+fn parse_resolver(text: &str) -> Vec<Conflict> {
+    let strip_arch = |name: &str| {
+        name.split_once(':')
+            .map_or(name, |(name, _arch)| name)
+            .to_owned()
+    };
+    text.lines()
+        .filter_map(|ln| {
+            let rest = ln.trim_start().strip_prefix("Broken ")?;
+            let (pkg, rest) = rest.split_once(' ')?;
+            let (relation, rest) = rest.split_once(" on ")?;
+            let (target, rest) = rest.split_once(" < ")?;
+            let (versions, constraint) = rest.split_once(" >")?;
+            let found = (versions.split_whitespace())
+                .take_while(|word| !word.starts_with('@'))
+                .last()?;
+            let relation = match relation {
+                "Depends" => "depends on",
+                "PreDepends" => "pre-depends on",
+                "Conflicts" => "conflicts with",
+                "Breaks" => "breaks",
+                _ => relation,
+            };
+            let target = match constraint.trim() {
+                "" => strip_arch(target),
+                constraint => format!("{} {constraint}", strip_arch(target)),
+            };
+            let detail = match found {
+                "none" => "none available".to_owned(),
+                found => format!("found {found}"),
+            };
+            Some(Conflict::new(strip_arch(pkg), relation, target).detail(detail))
+        })
+        .unique()
+        .collect()
+}
+
+/// Returns the command listing the dependencies of packages recursively, in a
+/// form readable by [`parse_depends`], among the `installed` packages only if
+/// set.
+///
+/// Only the hard dependencies are followed, i.e. `Depends` and `PreDepends`.
+fn depends_cmd(installed: bool) -> Cmd {
+    Cmd::new(chain!(
+        [
+            "apt-cache",
+            "depends",
+            "--recurse",
+            "--no-recommends",
+            "--no-suggests",
+            "--no-conflicts",
+            "--no-breaks",
+            "--no-replaces",
+            "--no-enhances",
+        ],
+        installed.then_some("--installed"),
+    ))
+}
+
+/// Returns the command listing the installed packages depending on the
+/// package `kw` in the form readable by [`parse_rdepends`].
+///
+/// Only the hard dependencies are taken into account, as in [`depends_cmd`].
+fn rdepends_cmd(kw: &str) -> Cmd {
+    Cmd::new([
+        "apt-cache",
+        "rdepends",
+        "--installed",
+        "--no-recommends",
+        "--no-suggests",
+        "--no-conflicts",
+        "--no-breaks",
+        "--no-replaces",
+        "--no-enhances",
+    ])
+    .kws([kw])
+}
+
+/// Parses the output of `apt-cache rdepends` for a single package into the
+/// names of its reverse dependencies.
+///
+/// These are listed under `Reverse Depends:`, one per line, where an
+/// alternative is prefixed with `|`.
+fn parse_rdepends(text: &str) -> Vec<String> {
+    text.lines()
+        .skip_while(|ln| ln.trim() != "Reverse Depends:")
+        .skip(1)
+        .map(|ln| ln.trim().trim_start_matches('|'))
+        .filter(|name| !name.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// Returns the command listing the installed sizes of the given packages (or
+/// of all of them if none is given) with [`DPKG_SIZES_FORMAT`].
+fn sizes_cmd(kws: &[&str]) -> Cmd {
+    Cmd::new(["dpkg-query", "-W", "-f", DPKG_SIZES_FORMAT]).kws(kws)
+}
+
+/// Parses the output of [`sizes_cmd`] into the sizes of the installed
+/// packages, skipping the ones that have been removed but not purged.
+fn parse_installed_sizes(text: &str) -> Vec<(String, u64)> {
+    text.lines()
+        .filter_map(|ln| {
+            let mut cols = ln.split('\t');
+            let (status, kib, name) = (cols.next()?, cols.next()?, cols.next()?);
+            let kib: u64 = kib.trim().parse().ok()?;
+            (status == "installed").then(|| (name.to_owned(), kib * 1024))
+        })
+        .collect()
+}
+
+/// Parses the output of `apt-get --simulate` into the names of the packages
+/// that would be removed, i.e. the ones on the `Remv <name> [<version>]` lines.
+fn parse_simulated_removals(text: &str) -> Vec<String> {
+    text.lines()
+        .filter_map(|ln| ln.strip_prefix("Remv "))
+        .filter_map(|rest| rest.split_whitespace().next())
+        .map(String::from)
+        .collect()
+}
+
+/// Parses the output of `apt-get` declining a transaction into its size
+/// summary, i.e. the `Need to get ...` and `After this operation, ...` lines.
+fn parse_transaction_size(text: &str) -> Vec<String> {
+    text.lines()
+        .map(str::trim)
+        .filter(|ln| ln.starts_with("Need to get ") || ln.starts_with("After this operation, "))
+        .map(String::from)
+        .collect()
+}
+
+/// Parses the output of `apt-get install --print-uris` into the file names of
+/// the `.deb`s to be downloaded along with their checksums, if these are
+/// supported (see [`checksum_cmd`]).
+///
+/// Each `.deb` is listed as `'url' file size algo:checksum`, where `algo` is
+/// e.g. `SHA256` or `MD5Sum`.
+fn parse_print_uris(text: &str) -> Vec<(String, Option<(&'static str, String)>)> {
+    text.lines()
+        .filter_map(|ln| {
+            let mut cols = ln.split_whitespace().skip(1);
+            let (file, _size, hash) = (cols.next()?, cols.next()?, cols.next()?);
+            let checksum = (hash.split_once(':'))
+                .and_then(|(algo, hex)| Some((checksum_cmd(algo)?, hex.to_lowercase())));
+            Some((file.to_owned(), checksum))
+        })
+        .collect()
+}
+
+/// Parses the output of `apt-cache depends --recurse` into a [`DepGraph`].
+///
+/// Each package is listed on a line of its own, followed by its dependencies
+/// as `  Depends: name` (or `PreDepends`), where an alternative is prefixed
+/// with `|` and a virtual package is enclosed in `<>`. The packages providing
+/// a virtual one are further indented, and are skipped.
+fn parse_depends(text: &str) -> DepGraph {
+    let mut deps = DepGraph::new();
+    let mut curr = None;
+    for ln in text.lines() {
+        if !ln.starts_with(' ') {
+            let name = ln.trim().trim_start_matches('<').trim_end_matches('>');
+            if !name.is_empty() {
+                deps.entry(name.to_owned()).or_default();
+                curr = Some(name);
+            }
+            continue;
+        }
+        let Some((kind, dep)) = ln.trim_start_matches([' ', '|']).split_once(": ") else {
+            continue;
+        };
+        let (Some(pkg), "Depends" | "PreDepends") = (curr, kind) else {
+            continue;
+        };
+        let dep = dep.trim().trim_start_matches('<').trim_end_matches('>');
+        let pkg_deps = deps.entry(pkg.to_owned()).or_default();
+        if !pkg_deps.iter().any(|d| d == dep) {
+            pkg_deps.push(dep.to_owned());
+        }
+    }
+    deps
+}
+
+/// Parses the output of `apt list` and `apt search` into [`Pkg`]s.
+///
+/// A package is listed as `name/source[,..] version [..]`, e.g.
+/// `curl/jammy-updates,now 7.81.0-1ubuntu1.15 amd64 [installed]`.
+fn parse_list(text: &str) -> Vec<Pkg> {
+    text.lines()
+        .filter_map(|ln| {
+            let (name, rest) = ln.split_once('/')?;
+            if name.is_empty() || name.contains(char::is_whitespace) {
+                return None;
+            }
+            let mut cols = rest.split_whitespace();
+            let source = cols.next()?.split(',').next()?;
+            Some(Pkg::new(name, cols.next()?, source))
+        })
+        .collect()
+}
+
+/// Parses the output of `apt list --installed` into the [`Pkg`]s that are not
+/// available in any repository.
+///
+/// Such a package is only available from `now`, i.e. the `dpkg` status file,
+/// as `apt-cache policy` would show, and is then marked as `local` by `apt`,
+/// e.g. `zoom/now 6.2.11.5069 amd64 [installed,local]`.
+fn parse_local(text: &str) -> Vec<Pkg> {
+    parse_list(text)
+        .into_iter()
+        .filter(|pkg| pkg.source == "now")
+        .map(|pkg| Pkg::new(pkg.name, pkg.version, "local"))
+        .collect()
+}
+
+/// Parses the output of `apt list --upgradable` into [`Update`]s.
+///
+/// An update is listed as `name/source[,..] new [..] [upgradable from: old]`,
+/// e.g. `bash/stable 5.2.15-2+b13 amd64 [upgradable from: 5.2.15-2+b8]`.
+fn parse_upgradable(text: &str) -> Vec<Result<Update, String>> {
+    pkg::parse_update_lines(
+        text,
+        |ln| ln.starts_with("Listing..."),
+        |ln| {
+            let (name, rest) = ln.split_once('/')?;
+            let new = rest.split_whitespace().nth(1)?;
+            let (_, old) = rest.split_once("[upgradable from: ")?;
+            Some(Update::new(name, old.strip_suffix(']')?, new))
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        path::Path,
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
+    use super::*;
+    use crate::{
+        exec::{
+            context::{self, BufferedSink, Context, PromptHandler},
+            download,
+        },
+        pkg::{
+            check_golden, check_golden_conflicts, check_golden_deps, check_golden_file_checks,
+            check_golden_updates,
+        },
+        pm::{
+            mock::{capture_output, MockLog},
+            search_golden, NETWORK_METHODS,
+        },
+        print::PkgSink,
+    };
+
+    #[test]
+    fn porcelain_golden() {
+        check_golden("apt-list", parse_list);
+        check_golden("apt-search", parse_list);
+        check_golden("apt-local", parse_local);
+    }
+
+    #[tokio::test]
+    async fn ss_limit() {
+        let apt = Apt::new(Config {
+            default_pm: Some("apt".into()),
+            search_limit: Some(1),
+            ..Config::default()
+        });
+        let names = search_golden(&apt, "apt search", "apt-search").await;
+        assert_eq!(names, ["fish"]);
+    }
+
+    #[test]
+    fn updates_golden() {
+        check_golden_updates("apt-list-upgradable", parse_upgradable);
+    }
+
+    #[test]
+    fn conflicts_golden() {
+        check_golden_conflicts("apt-resolver", parse_resolver);
+        assert!(is_conflict(
+            "E: Unable to correct problems, you have held broken packages."
+        ));
+    }
+
+    #[test]
+    fn file_checks_golden() {
+        check_golden_file_checks("dpkg", parse_dpkg_file_checks);
+    }
+
+    #[test]
+    fn deps_golden() {
+        check_golden_deps("apt-depends", parse_depends);
+    }
+
+    #[tokio::test]
+    async fn dep_tree() {
+        let apt = Apt::new(Config {
+            default_pm: Some("apt".into()),
+            tree: true,
+            ..Config::default()
+        });
+        let log = MockLog::new();
+        log.scope(apt.qi(&["curl"], &[])).await.unwrap();
+        log.scope(apt.si(&["curl"], &[])).await.unwrap();
+        let flags = "--recurse --no-recommends --no-suggests --no-conflicts --no-breaks \
+                     --no-replaces --no-enhances";
+        assert_eq!(
+            log.lines(),
+            [
+                format!("apt-cache depends {flags} --installed curl"),
+                format!("apt-cache depends {flags} curl"),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn check_required_by() {
+        let apt = Apt::new(Config {
+            default_pm: Some("apt".into()),
+            check_required_by: true,
+            no_confirm: true,
+            ..Config::default()
+        });
+        let rdepends = indoc! {"
+            libcurl4
+            Reverse Depends:
+              curl
+             |libcurl4-openssl-dev
+              curl
+        "};
+        assert_eq!(
+            parse_rdepends(rdepends),
+            ["curl", "libcurl4-openssl-dev", "curl"]
+        );
+        let log = MockLog::new().output("apt-cache rdepends", rdepends);
+        log.scope(apt.r(&["libcurl4", "curl"], &[])).await.unwrap();
+        let flags = "--installed --no-recommends --no-suggests --no-conflicts --no-breaks \
+                     --no-replaces --no-enhances";
+        assert_eq!(
+            log.lines(),
+            [
+                format!("apt-cache rdepends {flags} libcurl4"),
+                format!("apt-cache rdepends {flags} curl"),
+                "apt remove --yes libcurl4 curl".into(),
+            ]
+        );
+
+        let log = MockLog::new().output("apt-cache rdepends", rdepends);
+        log.scope(apt.rns(&["libcurl4"], &[])).await.unwrap();
+        assert_eq!(
+            log.lines(),
+            [
+                format!("apt-cache rdepends {flags} libcurl4"),
+                "apt autoremove --purge --yes libcurl4".into(),
+            ]
+        );
+    }
+
+    #[test]
+    fn installed_sizes() {
+        let text = "installed\t1220\tcurl\nconfig-files\t96\tnano\ninstalled\t\tlibfoo\n";
+        assert_eq!(
+            parse_installed_sizes(text),
+            [("curl".to_owned(), 1220 * 1024)]
+        );
+    }
+
+    #[tokio::test]
+    async fn autoremove_interactive() {
+        let apt = Apt::new(Config {
+            default_pm: Some("apt".into()),
+            no_confirm: true,
+            ..Config::default()
+        });
+        let simulated = indoc! {"
+            The following packages will be REMOVED:
+              libfoo1 linux-image-6.1.0-9
+            0 upgraded, 0 newly installed, 2 to remove and 0 not upgraded.
+            Remv libfoo1 [1.2-3]
+            Remv linux-image-6.1.0-9 [6.1.27-1]
+        "};
+        assert_eq!(
+            parse_simulated_removals(simulated),
+            ["libfoo1", "linux-image-6.1.0-9"]
+        );
+        let log = MockLog::new()
+            .output("apt-get autoremove", simulated)
+            .output("dpkg-query", "installed\t96\tlibfoo1\n");
+        log.scope(apt.autoremove_interactive(&[], &[]))
+            .await
+            .unwrap();
+        assert_eq!(
+            log.lines(),
+            [
+                "apt-get autoremove --simulate",
+                &format!("dpkg-query -W -f {DPKG_SIZES_FORMAT} libfoo1 linux-image-6.1.0-9"),
+                "apt remove --yes libfoo1 linux-image-6.1.0-9",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn verify_failed() {
+        let apt = Apt::new(Config {
+            default_pm: Some("apt".into()),
+            quiet: Some(true),
+            ..Config::default()
+        });
+        let log = MockLog::new().fail("debsums", 2);
+        let err = log.scope(apt.verify(&["curl"], &[])).await.unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("some of the packages have failed the verification"));
+        assert_eq!(log.lines(), ["apt-cache policy curl", "debsums -s curl"]);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn autoremove_interactive_subset() {
+        /// Chooses the orphans at the given indices, and says yes otherwise.
+        struct Pick(Vec<usize>);
+
+        impl PromptHandler for Pick {
+            fn prompt(&self, _: &str, _: &str, _: &[&str], default: usize) -> Result<usize> {
+                Ok(default)
+            }
+
+            fn select(&self, _: &str, _: &str, _: &[String]) -> Result<Vec<usize>> {
+                Ok(self.0.clone())
+            }
+        }
+
+        let apt = Apt::new(Config {
+            default_pm: Some("apt".into()),
+            quiet: Some(true),
+            ..Config::default()
+        });
+        let simulated = indoc! {"
+            Remv libfoo1 [1.2-3]
+            Remv linux-image-6.1.0-9 [6.1.27-1]
+            Remv libbar2 [2.0-1]
+        "};
+        let log = MockLog::new()
+            .output("apt-get autoremove", simulated)
+            .output("dpkg-query", "installed\t96\tlibfoo1\n");
+        let ctx = Context::new(
+            Arc::new(BufferedSink::default()),
+            Arc::new(Pick(vec![0, 2])),
+        );
+        let run = log.scope(apt.autoremove_interactive(&[], &["--purge"]));
+        context::scope(ctx, run).await.unwrap();
+        assert_eq!(
+            log.lines(),
+            [
+                "apt-get autoremove --simulate --purge",
+                &format!(
+                    "dpkg-query -W -f {DPKG_SIZES_FORMAT} libfoo1 linux-image-6.1.0-9 libbar2"
+                ),
+                "apt remove --purge libfoo1 libbar2",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn offline() {
+        let apt = Apt::new(Config {
+            default_pm: Some("apt".into()),
+            offline: true,
+            no_confirm: true,
+            ..Config::default()
+        });
+        let methods = apt.offline_methods();
+        assert!(methods
+            .iter()
+            .all(|method| NETWORK_METHODS.contains(method)));
+        let log = MockLog::new();
+        log.scope(apt.s(&["ripgrep"], &[])).await.unwrap();
+        log.scope(apt.ss(&["ripgrep"], &[])).await.unwrap();
+        assert_eq!(
+            log.lines(),
+            [
+                "apt install --reinstall --no-download --yes ripgrep",
+                "apt search ripgrep",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn verify_checksums() {
+        let apt = Apt::new(Config {
+            default_pm: Some("apt".into()),
+            verify_checksums: true,
+            no_confirm: true,
+            ..Config::default()
+        });
+        let (good, bad) = ("a".repeat(64), "b".repeat(64));
+        let uris = format!(
+            "'http://deb.debian.org/debian/pool/main/c/curl/curl_7.88.1-10_amd64.deb' \
+             curl_7.88.1-10_amd64.deb 315000 SHA256:{good}\n\
+             'http://deb.debian.org/debian/pool/main/c/curl/libcurl4_7.88.1-10_amd64.deb' \
+             libcurl4_7.88.1-10_amd64.deb 390000 SHA512:{good}\n\
+             'http://deb.debian.org/debian/pool/main/n/nghttp2/libnghttp2-14_1.52.0-1_amd64.deb' \
+             libnghttp2-14_1.52.0-1_amd64.deb 72000 MD5Sum:{good}\n\
+             'http://deb.debian.org/debian/pool/main/b/brotli/libbrotli1_1.0.9-2_amd64.deb' \
+             libbrotli1_1.0.9-2_amd64.deb 27000 BLAKE3:{good}\n"
+        );
+        let log = MockLog::new()
+            .output("apt-get install --print-uris", uris)
+            .output(
+                "sha256sum",
+                format!("{good}  /var/cache/apt/archives/curl_7.88.1-10_amd64.deb\n"),
+            )
+            .output(
+                "sha512sum",
+                format!("{bad}  /var/cache/apt/archives/libcurl4_7.88.1-10_amd64.deb\n"),
+            )
+            .fail("md5sum", 1);
+        let res = log.scope(apt.sw(&["curl"], &[])).await;
+        assert!(matches!(
+            res,
+            Err(Error::ChecksumMismatchError { count: 3 })
+        ));
+        let empty = env::temp_dir().join(format!("pacaptr-apt-archives-{}", process::id()));
+        assert!(!empty.exists());
+        assert_eq!(
+            log.lines(),
+            [
+                format!(
+                    "apt-get install --print-uris -qq -o Dir::Cache::Archives={}/ curl",
+                    empty.display()
+                ),
+                "apt install --download-only --yes curl".into(),
+                "md5sum /var/cache/apt/archives/libnghttp2-14_1.52.0-1_amd64.deb".into(),
+                "sha256sum /var/cache/apt/archives/curl_7.88.1-10_amd64.deb".into(),
+                "sha512sum /var/cache/apt/archives/libcurl4_7.88.1-10_amd64.deb".into(),
+            ]
+        );
+
+        let apt = Apt::new(Config {
+            dry_run: true,
+            ..apt.cfg
+        });
+        let log = MockLog::new();
+        (log.scope(apt.sw(&["curl"], &[])).await).unwrap();
+        assert!(log.lines().is_empty());
+    }
+
+    #[tokio::test]
+    async fn transaction_size() {
+        let apt = Apt::new(Config {
+            default_pm: Some("apt".into()),
+            sizes: true,
+            no_confirm: true,
+            ..Config::default()
+        });
+        let plan = indoc! {"
+            The following NEW packages will be installed:
+              ripgrep
+            0 upgraded, 1 newly installed, 0 to remove and 0 not upgraded.
+            Need to get 1,337 kB of archives.
+            After this operation, 4,613 kB of additional disk space will be used.
+            Abort.
+        "};
+        assert_eq!(
+            parse_transaction_size(plan),
+            [
+                "Need to get 1,337 kB of archives.",
+                "After this operation, 4,613 kB of additional disk space will be used.",
+            ]
+        );
+        let log = MockLog::new().fail_with("apt install --reinstall --assume-no", 1, plan);
+        log.scope(apt.s(&["ripgrep"], &[])).await.unwrap();
+        assert_eq!(
+            log.lines(),
+            [
+                "apt install --reinstall --assume-no ripgrep",
+                "apt install --reinstall --yes ripgrep",
+            ]
+        );
+
+        // Nothing is run in a dry run, not even the planning command.
+        let apt = Apt::new(Config {
+            dry_run: true,
+            ..apt.cfg
+        });
+        let log = MockLog::new();
+        log.scope(apt.s(&["ripgrep"], &[])).await.unwrap();
+        assert!(log.lines().is_empty(), "{:?}", log.lines());
+    }
+
+    #[test]
+    fn task_pkgs() {
+        assert_eq!(task_pkg("kde-desktop"), "task-kde-desktop");
+        assert_eq!(task_pkg("task-ssh-server"), "task-ssh-server");
+    }
+
+    #[tokio::test]
+    async fn sg_dry_run() {
+        let apt = Apt::new(Config {
+            dry_run: true,
+            ..Config::default()
+        });
+        let sg = |kws: &'static [&'static str]| async {
+            let sink = Arc::new(Mutex::new(Vec::new()));
+            (print::capture_echoes(sink.clone(), apt.sg(kws, &[])).await).unwrap();
+            let echoes = String::from_utf8(sink.lock().unwrap().clone()).unwrap();
+            echoes.trim().to_owned()
+        };
+        assert_eq!(
+            sg(&["kde-desktop"]).await,
+            "Canceled `tasksel --task-packages kde-desktop`"
+        );
+        // The `task-*` metapackages are unknown to `tasksel`.
+        assert_eq!(
+            sg(&["task-ssh-server", "kde-desktop"]).await,
+            "Canceled `apt-cache depends task-ssh-server task-kde-desktop`"
+        );
+    }
+
+    #[test]
+    fn keyrings() {
+        let out = "un debian-archive-keyring\nii ubuntu-keyring\nii ubuntu-pro-keyring\n";
+        assert_eq!(
+            parse_keyrings(out),
+            ["ubuntu-keyring", "ubuntu-pro-keyring"]
+        );
+    }
+
+    #[tokio::test]
+    async fn refresh_keys() {
+        let apt = Apt::new(Config {
+            no_confirm: true,
+            quiet: Some(true),
+            ..Config::default()
+        });
+        let log = MockLog::new().output("dpkg-query", "ii ubuntu-keyring\n");
+        log.scope(apt.refresh_keys(&[], &[])).await.unwrap();
+        assert_eq!(
+            log.lines()[1..],
+            [
+                "apt-get update",
+                "apt-get install --only-upgrade --yes ubuntu-keyring"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn downgrade() {
+        let apt = Apt::new(Config {
+            default_pm: Some("apt".into()),
+            no_confirm: true,
+            ..Config::default()
+        });
+        let log = MockLog::new();
+        (log.scope(apt.downgrade(&["curl=7.81.0", "vim==2:8.2"], &[]))
+            .await)
+            .unwrap();
+        assert_eq!(
+            log.lines(),
+            ["apt install --allow-downgrades --yes curl=7.81.0 vim=2:8.2"]
+        );
+
+        let log = MockLog::new();
+        let res = log.scope(apt.downgrade(&["curl=7.81.0", "vim"], &[])).await;
+        assert!(matches!(res, Err(Error::ArgParseError { msg }) if msg.contains("`vim=1.0`")));
+        assert!(log.lines().is_empty());
+
+        let log = MockLog::new();
+        let res = log.scope(apt.downgrade(&[], &[])).await;
+        assert!(matches!(res, Err(Error::ArgParseError { msg }) if msg.contains("required")));
+        assert!(log.lines().is_empty());
+    }
+
+    #[tokio::test]
+    async fn ss_installed_filter() {
+        for (only_installed, expected) in [(false, "ripgrep-all"), (true, "ripgrep")] {
+            let apt = Apt::new(Config {
+                default_pm: Some("apt".into()),
+                exclude_installed: !only_installed,
+                only_installed,
+                ..Config::default()
+            });
+            let log = MockLog::new()
+                .output(
+                    "apt search",
+                    indoc! {"
+                        ripgrep/jammy 13.0.0-2 amd64
+                          Recursively searches directories for a regex pattern
+
+                        ripgrep-all/jammy 0.9.6-1 amd64
+                          rga: ripgrep, but also search in PDFs, E-Books, etc.
+                    "},
+                )
+                .output(
+                    "apt list --installed",
+                    "ripgrep/jammy,now 13.0.0-2 amd64 [installed]\n",
+                );
+            let sink = PkgSink::default();
+            let search = apt.ss(&["ripgrep"], &[]);
+            (log.scope(print::capture_pkgs(Arc::clone(&sink), search))
+                .await)
+                .unwrap();
+            let names = sink
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|pkg| pkg.name.clone())
+                .collect_vec();
+            assert_eq!(names, [expected]);
+        }
+    }
+
+    #[tokio::test]
+    async fn qm_local() {
+        let apt = Apt::new(Config {
+            default_pm: Some("apt".into()),
+            ..Config::default()
+        });
+        let log = MockLog::new().output(
+            "apt list --installed",
+            indoc! {"
+                curl/jammy-updates,now 7.81.0-1ubuntu1.15 amd64 [installed]
+                zoom/now 6.2.11.5069 amd64 [installed,local]
+            "},
+        );
+        let sink = PkgSink::default();
+        let query = apt.qm(&["*o*"], &[]);
+        (log.scope(print::capture_pkgs(Arc::clone(&sink), query))
+            .await)
+            .unwrap();
+        assert_eq!(
+            *sink.lock().unwrap(),
+            [Pkg::new("zoom", "6.2.11.5069", "local")]
+        );
+        assert_eq!(log.lines(), ["apt list --installed *o*"]);
+    }
+
+    #[tokio::test]
+    async fn ignore_globs() {
+        let apt = Apt::new(Config {
+            default_pm: Some("apt".into()),
+            no_confirm: true,
+            ignore: vec!["curl".into(), "linux-image-*".into(), "zfs*".into()],
+            ..Config::default()
+        });
+        let log = MockLog::new()
+            .output("apt-mark showhold", "linux-image-6.1.0-17-amd64\n")
+            .output(
+                "dpkg-query -W",
+                "curl\nlinux-image-6.1.0-17-amd64\nlinux-image-6.1.0-18-amd64\nlinux-image-amd64\n",
+            );
+        log.scope(apt.su(&[], &[])).await.unwrap();
+        assert_eq!(
+            log.lines(),
+            [
+                "apt-mark showhold",
+                "dpkg-query -W -f ${Package}\\n",
+                "apt-mark hold curl linux-image-6.1.0-18-amd64 linux-image-amd64",
+                "apt upgrade --yes",
+                "apt dist-upgrade --yes",
+                "apt-mark unhold curl linux-image-6.1.0-18-amd64 linux-image-amd64",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn ignore_failed_upgrade() {
+        let apt = Apt::new(Config {
+            default_pm: Some("apt".into()),
+            no_confirm: true,
+            ignore: vec!["curl".into()],
+            ..Config::default()
+        });
+        let log = MockLog::new()
+            .fail("apt upgrade", 100)
+            .fail("apt-mark unhold", 1);
+        let (res, out) = capture_output(log.scope(apt.su(&[], &[]))).await;
+        // The upgrade error is returned, although the unhold has failed too.
+        assert!(
+            matches!(res, Err(Error::CmdStatusCodeError { code: 100, .. })),
+            "{res:?}"
+        );
+        assert_eq!(
+            log.lines(),
+            [
+                "apt-mark showhold",
+                "apt-mark hold curl",
+                "apt upgrade --yes",
+                "apt-mark unhold curl",
+            ]
+        );
+        assert!(out.contains("failed to unhold"), "{out}");
+        assert!(out.contains("apt-mark unhold curl` to do so"), "{out}");
+    }
+
+    #[tokio::test]
+    async fn measure_network() {
+        let apt = Apt::new(Config {
+            default_pm: Some("apt".into()),
+            no_confirm: true,
+            measure_network: true,
+            ..Config::default()
+        });
+        let golden = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden/download/apt.txt");
+        let log = MockLog::new().output("apt install", fs::read(golden).unwrap());
+        // The downloads are added up in the context of the operation only.
+        let ctx = Context::default();
+        let run = async {
+            log.scope(apt.s(&["curl"], &[])).await.unwrap();
+            log.scope(apt.s(&["curl"], &[])).await.unwrap();
+            download::downloaded()
+        };
+        let downloaded = context::scope(ctx.clone(), run).await.unwrap();
+        assert_eq!(downloaded.bytes, 2 * 568_000);
+        assert_eq!(downloaded.elapsed, Duration::from_secs(4));
+        assert_eq!(ctx.downloaded(), Some(downloaded));
+        let other = context::scope(Context::default(), async { download::downloaded() }).await;
+        assert_eq!(other, None);
+    }
+
+    #[test]
+    fn unattended() {
+        let apt = |no_confirm, apt_noninteractive_dpkg_opts| {
+            Apt::new(Config {
+                no_confirm,
+                apt_noninteractive_dpkg_opts,
+                ..Config::default()
+            })
+        };
+        let cmd = || {
+            Cmd::with_sudo(["apt", "install"])
+                .flags(["-q"])
+                .kws(["tzdata"])
+        };
+        let frontend = [("DEBIAN_FRONTEND".to_owned(), "noninteractive".to_owned())];
+
+        let unattended = apt(true, false).unattended(cmd());
+        assert_eq!(unattended.flags, ["-q"]);
+        assert_eq!(unattended.env, frontend);
+
+        let unattended = apt(true, true).unattended(cmd());
+        assert_eq!(
+            unattended.flags,
+            [
+                "-o",
+                "Dpkg::Options::=--force-confdef",
+                "-o",
+                "Dpkg::Options::=--force-confold",
+                "-q"
+            ]
+        );
+        assert_eq!(unattended.env, frontend);
+
+        // The questions are left to the user without `--yes`.
+        let interactive = apt(false, true).unattended(cmd());
+        assert_eq!(interactive.flags, ["-q"]);
+        assert!(interactive.env.is_empty());
+    }
+
+    #[test]
+    fn proxy_flags() {
+        let apt = Apt::new(Config {
+            http_proxy: Some("http://proxy:3128".into()),
+            no_proxy: Some("localhost, .example.com, mirror.local".into()),
+            ..Config::default()
+        });
+        assert_eq!(
+            apt.net_flags(&["-y"]),
+            [
+                "-o",
+                "Acquire::http::Proxy=http://proxy:3128",
+                "-o",
+                "Acquire::http::Proxy::localhost=DIRECT",
+                "-o",
+                "Acquire::http::Proxy::mirror.local=DIRECT",
+                "-y",
+            ]
+        );
+        assert_eq!(Apt::new(Config::default()).net_flags(&["-y"]), ["-y"]);
+    }
 }
@@ -1,13 +1,24 @@
 #![doc = doc_self!()]
 
-use std::sync::LazyLock;
+use std::{path::Path, sync::LazyLock};
 
 use async_trait::async_trait;
 use indoc::indoc;
+use itertools::{chain, Itertools};
+use serde::Deserialize;
 use tap::prelude::*;
 
-use super::{DryRunStrategy, NoCacheStrategy, Pm, PmHelper, PromptStrategy, Strategy};
-use crate::{config::Config, error::Result, exec::Cmd};
+use super::{
+    build_flags, BuildOption, CmdSeq, DryRunStrategy, NoCacheStrategy, Pm, PmHelper,
+    PromptStrategy, Strategy,
+};
+use crate::{
+    config::Config,
+    error::{Error, Result},
+    exec::{self, Cmd, Mode},
+    pkg::{self, FileChange, FileCheck, Pkg, Update},
+    print,
+};
 
 macro_rules! doc_self {
     () => {
@@ -35,12 +46,97 @@ static STRAT_INSTALL: LazyLock<Strategy> = LazyLock::new(|| Strategy {
     ..Strategy::default()
 });
 
+/// Changes the system without prompting for confirmation, e.g. by refreshing
+/// the local package database.
+static STRAT_REFRESH: LazyLock<Strategy> = LazyLock::new(|| Strategy {
+    mutating: true,
+    ..Strategy::default()
+});
+
 impl Brew {
     #[must_use]
     #[allow(missing_docs)]
     pub const fn new(cfg: Config) -> Self {
         Self { cfg }
     }
+
+    /// Returns the given `flags`, preceded by `--fetch-HEAD` with
+    /// [`Config::head`] so that the formulae installed with `--HEAD` are
+    /// checked for updates as well.
+    fn fetch_head_flags<'a>(&self, flags: &[&'a str]) -> Vec<&'a str> {
+        chain!(
+            self.cfg.head.then_some("--fetch-HEAD"),
+            flags.iter().copied()
+        )
+        .collect()
+    }
+
+    /// Checks that the files of the given formulae, or of all installed ones if
+    /// none is given, are present, for `-Qk`.
+    ///
+    /// Homebrew keeps no checksums of the installed files, so only the files
+    /// listed by `brew list --verbose` are checked for existence.
+    async fn check_brew_files(&self, kws: &[&str], flags: &[&str], details: bool) -> Result<()> {
+        let list = |name: &str| {
+            Cmd::new(["brew", "list", "--verbose", "--formula"])
+                .kws([name])
+                .flags(flags)
+        };
+        let list_all = Cmd::new(["brew", "list", "--formula"]);
+        if self.cfg.dry_run {
+            let cmds = if kws.is_empty() {
+                vec![list_all]
+            } else {
+                kws.iter().map(|kw| list(kw)).collect_vec()
+            };
+            for cmd in cmds {
+                cmd.exec(Mode::PrintCmd).await?;
+            }
+            return Ok(());
+        }
+        let formulae = if kws.is_empty() {
+            list_formulae(list_all).await?
+        } else {
+            kws.iter().map(|&kw| kw.to_owned()).collect()
+        };
+        let mut checks = vec![];
+        for name in &formulae {
+            let out = list(name).exec(Mode::Mute).await?;
+            checks.push(check_paths(name, &exec::decode(&out), |path| {
+                Path::new(path).exists()
+            }));
+        }
+        print::println_file_checks(&checks, details, self.cfg.query_format());
+        Ok(())
+    }
+
+    /// Prints out the installed sizes of the given formulae, or of all
+    /// installed ones if none is given, as measured by `du` over their kegs in
+    /// the Cellar.
+    async fn print_keg_sizes(&self, kws: &[&str]) -> Result<()> {
+        let cellar = Cmd::new(["brew", "--cellar"]);
+        if self.cfg.dry_run {
+            cellar.exec(Mode::PrintCmd).await?;
+            return Ok(());
+        }
+        let cellar = exec::decode(&cellar.exec(Mode::Mute).await?)
+            .trim()
+            .to_owned();
+        let formulae = if kws.is_empty() {
+            list_formulae(Cmd::new(["brew", "list", "--formula"])).await?
+        } else {
+            kws.iter().map(|&kw| kw.to_owned()).collect()
+        };
+        // `du` would measure the current directory instead.
+        if formulae.is_empty() {
+            let none: [(&str, u64); 0] = [];
+            print::println_pkg_sizes(&none);
+            return Ok(());
+        }
+        let kegs = formulae.iter().map(|name| format!("{cellar}/{name}"));
+        self.print_pkg_sizes(Cmd::new(["du", "-sk"]).kws(kegs), parse_du)
+            .await
+    }
 }
 
 #[async_trait]
@@ -54,9 +150,43 @@ impl Pm for Brew {
         &self.cfg
     }
 
+    fn supports_installed_filter(&self) -> bool {
+        true
+    }
+
+    fn supports_search_limit(&self) -> bool {
+        true
+    }
+
+    fn supports_search_sort(&self) -> bool {
+        true
+    }
+
+    fn dep_tree_methods(&self) -> &[&'static str] {
+        &["qi", "si"]
+    }
+
+    fn preview_methods(&self) -> &[&'static str] {
+        &["sc", "scc", "sccc"]
+    }
+
+    fn pkg_size_methods(&self) -> &[&'static str] {
+        &["q", "qi"]
+    }
+
+    fn build_option_flags(&self) -> &[(BuildOption, &'static str)] {
+        &[
+            (BuildOption::Head, "--HEAD"),
+            (BuildOption::SourceBuild, "--build-from-source"),
+        ]
+    }
+
     /// Q generates a list of installed packages.
     async fn q(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
-        if kws.is_empty() {
+        if self.cfg.sizes {
+            return self.print_keg_sizes(kws).await;
+        }
+        if kws.is_empty() && !self.cfg.query_format().is_structured() {
             self.run(Cmd::new(["brew", "list"]).flags(flags)).await
         } else {
             self.qs(kws, flags).await
@@ -71,7 +201,18 @@ impl Pm for Brew {
 
     /// Qi displays local package information: name, version, description, etc.
     async fn qi(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
-        self.si(kws, flags).await
+        if self.cfg.tree {
+            return Cmd::new(["brew", "deps", "--tree", "--installed"])
+                .kws(kws)
+                .flags(flags)
+                .pipe(|cmd| self.run(cmd))
+                .await;
+        }
+        self.si(kws, flags).await?;
+        if self.cfg.sizes {
+            self.print_keg_sizes(kws).await?;
+        }
+        Ok(())
     }
 
     /// Qii displays local packages which require X to be installed, aka local
@@ -84,6 +225,16 @@ impl Pm for Brew {
             .await
     }
 
+    /// Qk verifies one or more packages.
+    async fn qk(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
+        self.check_brew_files(kws, flags, false).await
+    }
+
+    /// Qkk verifies one or more packages, listing every altered file.
+    async fn qkk(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
+        self.check_brew_files(kws, flags, true).await
+    }
+
     /// Ql displays files provided by local package.
     async fn ql(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
         // TODO: it seems that the output of `brew list python` in fish has a mechanism
@@ -93,12 +244,50 @@ impl Pm for Brew {
             .await
     }
 
+    /// Qm lists packages that are installed but are not available in any
+    /// installation source (anymore).
+    ///
+    /// For `brew`, these are the formulae and casks from the third-party taps,
+    /// and the formulae installed with `--HEAD`.
+    async fn qm(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
+        let cmd = if kws.is_empty() {
+            Cmd::new(["brew", "info", "--json=v2", "--installed"])
+        } else {
+            Cmd::new(["brew", "info", "--json=v2"]).kws(kws)
+        };
+        let cmd = cmd.flags(flags);
+        if self.cfg.dry_run {
+            cmd.exec(Mode::PrintCmd).await?;
+            return Ok(());
+        }
+        let pkgs = parse_foreign(&exec::decode(&cmd.exec(Mode::Mute).await?))?;
+        print::println_pkgs(&pkgs, self.cfg.query_format());
+        Ok(())
+    }
+
     /// Qs searches locally installed package for names or descriptions.
     // According to https://www.archlinux.org/pacman/pacman.8.html#_query_options_apply_to_em_q_em_a_id_qo_a,
     // when including multiple search terms, only packages with descriptions
     // matching ALL of those terms are returned.
     async fn qs(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
         // ! `brew list` lists all formulae and casks only when using tty.
+        if self.cfg.query_format().is_structured() {
+            self.print_pkgs(
+                Cmd::new(["brew", "list", "--formula", "--versions"]).flags(flags),
+                kws,
+                parse_list,
+            )
+            .await?;
+            if cfg!(target_os = "macos") {
+                self.print_pkgs(
+                    Cmd::new(["brew", "list", "--cask", "--versions"]).flags(flags),
+                    kws,
+                    parse_list,
+                )
+                .await?;
+            }
+            return Ok(());
+        }
         self.search_regex(Cmd::new(["brew", "list", "--formula"]).flags(flags), kws)
             .await?;
         if cfg!(target_os = "macos") {
@@ -111,12 +300,16 @@ impl Pm for Brew {
 
     /// Qu lists packages which have an update available.
     async fn qu(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
-        self.run(Cmd::new(["brew", "outdated"]).kws(kws).flags(flags))
+        Cmd::new(["brew", "outdated", "--verbose"])
+            .flags(self.fetch_head_flags(flags))
+            .pipe(|cmd| self.print_updates(cmd, kws, parse_outdated))
             .await
     }
 
     /// R removes a single package, leaving all of its dependencies installed.
     async fn r(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
+        self.check_required_by(kws, uses_cmd, parse_formulae)
+            .await?;
         Cmd::new(["brew", "uninstall"])
             .kws(kws)
             .flags(flags)
@@ -127,6 +320,8 @@ impl Pm for Brew {
     /// Rn removes a package and skips the generation of configuration backup
     /// files.
     async fn rn(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
+        self.check_required_by(kws, uses_cmd, parse_formulae)
+            .await?;
         Cmd::new(["brew", "uninstall", "--zap", "-f"])
             .kws(kws)
             .flags(flags)
@@ -138,18 +333,59 @@ impl Pm for Brew {
     /// other installed package, and skips the generation of configuration
     /// backup files.
     async fn rns(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
-        self.rn(kws, flags).await?;
-        Cmd::new(["brew", "autoremove"])
-            .flags(flags)
-            .pipe(|cmd| self.run_with(cmd, self.default_mode(), &STRAT_PROMPT))
+        self.check_required_by(kws, uses_cmd, parse_formulae)
+            .await?;
+        CmdSeq::new()
+            .then(
+                Cmd::new(["brew", "uninstall", "--zap", "-f"])
+                    .kws(kws)
+                    .flags(flags),
+                &STRAT_PROMPT,
+            )
+            .then(Cmd::new(["brew", "autoremove"]).flags(flags), &STRAT_PROMPT)
+            .pipe(|seq| self.run_seq(seq))
             .await
     }
 
     /// Rs removes a package and its dependencies which are not required by any
     /// other installed package, and not explicitly installed by the user.
+    ///
+    /// `brew autoremove` would also remove the formulae left behind by earlier
+    /// removals, so only the dependencies of the given packages are picked from
+    /// its dry run. Without any package given, all of them are removed.
     async fn rs(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
-        self.r(kws, flags).await?;
-        Cmd::new(["brew", "autoremove"])
+        let autoremove = Cmd::new(["brew", "autoremove"]);
+        if kws.is_empty() {
+            return self
+                .run_with(autoremove.flags(flags), self.default_mode(), &STRAT_PROMPT)
+                .await;
+        }
+        self.check_required_by(kws, uses_cmd, parse_formulae)
+            .await?;
+        let uninstall = Cmd::new(["brew", "uninstall"]).kws(kws).flags(flags);
+        if self.cfg.dry_run {
+            return self
+                .run_with(uninstall, self.default_mode(), &STRAT_PROMPT)
+                .await;
+        }
+        let deps = Cmd::new(["brew", "deps", "--installed", "--formula", "--union"])
+            .kws(kws)
+            .pipe(list_formulae)
+            .await?;
+        self.run_with(uninstall, self.default_mode(), &STRAT_PROMPT)
+            .await?;
+        if deps.is_empty() || exec::prompt_declined() {
+            return Ok(());
+        }
+        let orphans = (list_formulae(autoremove.flags(["--dry-run"])).await?)
+            .into_iter()
+            .filter(|name| deps.contains(name))
+            .collect_vec();
+        if orphans.is_empty() {
+            return Ok(());
+        }
+        Cmd::new(["brew", "uninstall", "--formula"])
+            .kws(orphans)
             .flags(flags)
             .pipe(|cmd| self.run_with(cmd, self.default_mode(), &STRAT_PROMPT))
             .await
@@ -157,7 +393,8 @@ impl Pm for Brew {
 
     /// S installs one or more packages by name.
     async fn s(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
-        Cmd::new(if self.cfg.needed {
+        // `brew reinstall` has no `--HEAD`.
+        Cmd::new(if self.cfg.needed || self.cfg.head {
             ["brew", "install"]
         } else {
             // If the package is not installed, `brew reinstall` behaves just like `brew
@@ -166,7 +403,7 @@ impl Pm for Brew {
             ["brew", "reinstall"]
         })
         .kws(kws)
-        .flags(flags)
+        .flags(chain!(build_flags(self), flags.iter().copied()))
         .pipe(|cmd| self.run_with(cmd, self.default_mode(), &STRAT_INSTALL))
         .await
     }
@@ -216,8 +453,12 @@ impl Pm for Brew {
 
     /// Si displays remote package information: name, version, description, etc.
     async fn si(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
-        self.run(Cmd::new(["brew", "info"]).kws(kws).flags(flags))
-            .await
+        let cmd = if self.cfg.tree {
+            Cmd::new(["brew", "deps", "--tree"])
+        } else {
+            Cmd::new(["brew", "info"])
+        };
+        self.run(cmd.kws(kws).flags(flags)).await
     }
 
     /// Sii displays packages which require X to be installed, aka reverse
@@ -233,15 +474,25 @@ impl Pm for Brew {
     /// Ss searches for package(s) by searching the expression in name,
     /// description, short description.
     async fn ss(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
-        self.run(Cmd::new(["brew", "search"]).kws(kws).flags(flags))
-            .await
+        let cmd = Cmd::new(["brew", "search"]).kws(kws).flags(flags);
+        if self.cfg.exclude_installed || self.cfg.only_installed {
+            let installed = Cmd::new(["brew", "list", "--versions"]);
+            return (self.print_search_filtered(cmd, parse_search, installed, parse_list)).await;
+        }
+        if self.cfg.sorts_or_limits_search() {
+            return self.print_search(cmd, parse_search).await;
+        }
+        if self.cfg.query_format().is_structured() {
+            return self.print_pkgs(cmd, &[], parse_search).await;
+        }
+        self.run(cmd).await
     }
 
     /// Su updates outdated packages.
     async fn su(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
         Cmd::new(["brew", "upgrade"])
             .kws(kws)
-            .flags(flags)
+            .flags(self.fetch_head_flags(flags))
             .pipe(|cmd| self.run_with(cmd, self.default_mode(), &STRAT_INSTALL))
             .await
     }
@@ -249,8 +500,16 @@ impl Pm for Brew {
     /// Suy refreshes the local package database, then updates outdated
     /// packages.
     async fn suy(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
-        self.sy(&[], flags).await?;
-        self.su(kws, flags).await
+        CmdSeq::new()
+            .then(Cmd::new(["brew", "update"]).flags(flags), &STRAT_REFRESH)
+            .then(
+                Cmd::new(["brew", "upgrade"])
+                    .kws(kws)
+                    .flags(self.fetch_head_flags(flags)),
+                &STRAT_INSTALL,
+            )
+            .pipe(|seq| self.run_seq(seq))
+            .await
     }
 
     /// Sw retrieves all packages from the server, but does not install/upgrade
@@ -265,10 +524,338 @@ impl Pm for Brew {
 
     /// Sy refreshes the local package database.
     async fn sy(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
-        self.run(Cmd::new(["brew", "update"]).flags(flags)).await?;
+        Cmd::new(["brew", "update"])
+            .flags(flags)
+            .pipe(|cmd| self.run_with(cmd, self.default_mode(), &STRAT_REFRESH))
+            .await?;
         if !kws.is_empty() {
             self.s(kws, flags).await?;
         }
         Ok(())
     }
 }
+
+/// Runs a `brew` query listing formulae by name, e.g. `brew deps`, returning
+/// their names.
+async fn list_formulae(cmd: Cmd) -> Result<Vec<String>> {
+    let out = cmd.exec(Mode::Mute).await?;
+    Ok(parse_formulae(&exec::decode(&out)))
+}
+
+/// Parses the output of `du -sk` over the kegs in the Cellar into the sizes of
+/// the formulae, where each keg is listed as `KiB<TAB>path`.
+fn parse_du(text: &str) -> Vec<(String, u64)> {
+    text.lines()
+        .filter_map(|ln| {
+            let (kib, path) = ln.split_once('\t')?;
+            let name = Path::new(path.trim()).file_name()?.to_str()?;
+            Some((name.to_owned(), kib.trim().parse::<u64>().ok()? * 1024))
+        })
+        .collect()
+}
+
+/// Returns the command listing the installed formulae using the formula `kw`,
+/// in the form readable by [`parse_formulae`].
+fn uses_cmd(kw: &str) -> Cmd {
+    Cmd::new(["brew", "uses", "--installed"]).kws([kw])
+}
+
+/// Parses a list of formulae, one per line and possibly under a `==>` heading,
+/// e.g. the output of `brew autoremove --dry-run`.
+fn parse_formulae(text: &str) -> Vec<String> {
+    (text.lines())
+        .map(str::trim)
+        .filter(|ln| !ln.is_empty() && !ln.starts_with("==>"))
+        .map(String::from)
+        .collect()
+}
+
+/// Checks the files of the formula `name` listed by `brew list --verbose`,
+/// where the ones for which `exists` returns `false` are marked as missing.
+fn check_paths(name: &str, text: &str, exists: impl Fn(&str) -> bool) -> FileCheck {
+    let paths = text.lines().map(str::trim).filter(|ln| ln.starts_with('/'));
+    let (mut total, mut altered) = (0, vec![]);
+    for path in paths {
+        total += 1;
+        if !exists(path) {
+            altered.push(FileChange {
+                path: path.into(),
+                attrs: "missing".into(),
+                config: false,
+            });
+        }
+    }
+    FileCheck {
+        name: name.into(),
+        total,
+        altered,
+    }
+}
+
+/// The taps of the formulae and casks from the official repositories.
+const CORE_TAPS: [&str; 2] = ["homebrew/core", "homebrew/cask"];
+
+/// The output of `brew info --json=v2`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct Info {
+    formulae: Vec<FormulaInfo>,
+    casks: Vec<CaskInfo>,
+}
+
+/// A formula in [`Info`].
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct FormulaInfo {
+    name: String,
+    tap: Option<String>,
+    installed: Vec<InstalledFormula>,
+}
+
+/// An installed version of a [`FormulaInfo`].
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct InstalledFormula {
+    version: String,
+}
+
+/// A cask in [`Info`].
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct CaskInfo {
+    token: String,
+    tap: Option<String>,
+    installed: Option<String>,
+}
+
+/// Parses the output of `brew info --json=v2` into the installed [`Pkg`]s that
+/// are not from [`CORE_TAPS`], or are installed with `--HEAD`.
+///
+/// The source of each package is its tap, or `local` if it has been installed
+/// from a local file.
+///
+/// # Errors
+/// Returns an [`Error::OtherError`] if the output is not the expected JSON.
+fn parse_foreign(text: &str) -> Result<Vec<Pkg>> {
+    let info = serde_json::from_str::<Info>(text).map_err(|e| {
+        Error::OtherError(format!("failed to parse the output of `brew info`: {e}"))
+    })?;
+    let is_foreign =
+        |tap: &Option<String>| !tap.as_deref().is_some_and(|tap| CORE_TAPS.contains(&tap));
+    let source = |tap: Option<String>| tap.unwrap_or_else(|| "local".into());
+    let formulae = info.formulae.into_iter().filter_map(|formula| {
+        let version = formula.installed.last()?.version.clone();
+        (is_foreign(&formula.tap) || version.starts_with("HEAD"))
+            .then(|| Pkg::new(formula.name, version, source(formula.tap)))
+    });
+    let casks = info.casks.into_iter().filter_map(|cask| {
+        let version = cask.installed?;
+        is_foreign(&cask.tap).then(|| Pkg::new(cask.token, version, source(cask.tap)))
+    });
+    Ok(formulae.chain(casks).collect())
+}
+
+/// Parses the output of `brew list --versions` into [`Pkg`]s.
+///
+/// A package is listed as `name version..`, where the last version is
+/// taken if there are multiple of them.
+fn parse_list(text: &str) -> Vec<Pkg> {
+    text.lines()
+        .filter_map(|ln| {
+            let mut cols = ln.split_whitespace();
+            let name = cols.next()?;
+            Some(Pkg::new(name, cols.last()?, "brew"))
+        })
+        .collect()
+}
+
+/// Parses the output of `brew search` into [`Pkg`]s.
+///
+/// The packages are listed by name only, possibly in columns and under the
+/// `==> Formulae` and `==> Casks` headings, so their versions are left empty.
+fn parse_search(text: &str) -> Vec<Pkg> {
+    text.lines()
+        .filter(|ln| !ln.starts_with("==>"))
+        .flat_map(str::split_whitespace)
+        .map(|name| Pkg::new(name, "", "brew"))
+        .collect()
+}
+
+/// Parses the output of `brew outdated --verbose` into [`Update`]s.
+///
+/// An update is listed as `name (old[, ..]) < new` for formulae and
+/// `name (old) != new` for casks, where the last old version is taken if there
+/// are multiple of them.
+fn parse_outdated(text: &str) -> Vec<Result<Update, String>> {
+    pkg::parse_update_lines(
+        text,
+        |ln| ln.starts_with("==>"),
+        |ln| {
+            let (name, rest) = ln.split_once(" (")?;
+            let (olds, rest) = rest.split_once(") ")?;
+            let (_, new) = rest.split_once(['<', '='])?;
+            let new = new.trim_start_matches('=').split_whitespace().next()?;
+            Some(Update::new(name, olds.rsplit(", ").next()?, new))
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{
+        pkg::{check_golden, check_golden_updates},
+        pm::{mock::MockLog, search_golden},
+        print::{self, OutputFormat, PkgSink},
+    };
+
+    #[test]
+    fn porcelain_golden() {
+        check_golden("brew-list", parse_list);
+        check_golden("brew-search", parse_search);
+        check_golden("brew-foreign", |text| parse_foreign(text).unwrap());
+        let err = parse_foreign("Error: No available formula with the name \"foo\".").unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("failed to parse the output of `brew info`"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn keg_sizes() {
+        let text = "10472\t/opt/homebrew/Cellar/curl\n3308\t/opt/homebrew/Cellar/fish\n";
+        assert_eq!(
+            parse_du(text),
+            [
+                ("curl".to_owned(), 10472 * 1024),
+                ("fish".to_owned(), 3308 * 1024),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn ss_limit() {
+        let brew = Brew::new(Config {
+            search_limit: Some(2),
+            ..Config::default()
+        });
+        let names = search_golden(&brew, "brew search", "brew-search").await;
+        assert_eq!(names, ["ripgrep", "ripgrep-all"]);
+    }
+
+    #[test]
+    fn updates_golden() {
+        check_golden_updates("brew-outdated", parse_outdated);
+    }
+
+    #[tokio::test]
+    async fn colored_search() {
+        let brew = Brew::new(Config {
+            default_pm: Some("brew".into()),
+            output_format: Some(OutputFormat::Porcelain),
+            ..Config::default()
+        });
+        // The headings would be taken as packages if left colored.
+        let log = MockLog::new().output(
+            "brew search",
+            "\x1b[1;34m==>\x1b[0m \x1b[1mFormulae\x1b[0m\nfish  \x1b[32mfisher\x1b[0m\n",
+        );
+        let sink = PkgSink::default();
+        let search = print::capture_pkgs(Arc::clone(&sink), brew.ss(&["fish"], &[]));
+        log.scope(search).await.unwrap();
+        assert_eq!(
+            *sink.lock().unwrap(),
+            [Pkg::new("fish", "", "brew"), Pkg::new("fisher", "", "brew")]
+        );
+    }
+
+    #[tokio::test]
+    async fn rs_scoped() {
+        let brew = Brew::new(Config {
+            default_pm: Some("brew".into()),
+            no_confirm: true,
+            quiet: Some(true),
+            ..Config::default()
+        });
+        // `x265` has been left behind by an earlier removal.
+        let log = MockLog::new()
+            .output("brew deps", "libidn2\nlibunistring\nopenssl@3\n")
+            .output(
+                "brew autoremove --dry-run",
+                "==> Would autoremove 3 unneeded formulae:\nlibidn2\nlibunistring\nx265\n",
+            );
+        log.scope(brew.rs(&["wget"], &[])).await.unwrap();
+        assert_eq!(
+            log.lines(),
+            [
+                "brew deps --installed --formula --union wget",
+                "brew uninstall wget",
+                "brew autoremove --dry-run",
+                "brew uninstall --formula libidn2 libunistring",
+            ]
+        );
+
+        let log = MockLog::new().output("brew autoremove --dry-run", "");
+        log.scope(brew.rs(&["wget"], &[])).await.unwrap();
+        assert_eq!(
+            log.lines(),
+            [
+                "brew deps --installed --formula --union wget",
+                "brew uninstall wget"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn build_options() {
+        let brew = |head, source_build| {
+            Brew::new(Config {
+                default_pm: Some("brew".into()),
+                no_confirm: true,
+                head,
+                source_build,
+                ..Config::default()
+            })
+        };
+        let log = MockLog::new();
+        (log.scope(brew(true, false).s(&["neovim"], &["-v"])).await).unwrap();
+        (log.scope(brew(false, true).s(&["neovim"], &[])).await).unwrap();
+        (log.scope(brew(true, false).su(&[], &[])).await).unwrap();
+        (log.scope(brew(true, false).suy(&["neovim"], &[])).await).unwrap();
+        assert_eq!(
+            log.lines(),
+            [
+                "brew install --HEAD -v neovim",
+                "brew reinstall --build-from-source neovim",
+                "brew upgrade --fetch-HEAD",
+                "brew update",
+                "brew upgrade --fetch-HEAD neovim",
+            ]
+        );
+    }
+
+    #[test]
+    fn missing_paths() {
+        let text = indoc! {"
+            /opt/homebrew/Cellar/wget/1.24.5/bin/wget
+            /opt/homebrew/Cellar/wget/1.24.5/share/man/man1/wget.1
+            /opt/homebrew/Cellar/wget/1.24.5/.brew/wget.rb
+        "};
+        let check = check_paths("wget", text, |path| !path.ends_with("wget.1"));
+        assert_eq!(
+            check,
+            FileCheck {
+                name: "wget".into(),
+                total: 3,
+                altered: vec![FileChange {
+                    path: "/opt/homebrew/Cellar/wget/1.24.5/share/man/man1/wget.1".into(),
+                    attrs: "missing".into(),
+                    config: false,
+                }],
+            }
+        );
+    }
+}
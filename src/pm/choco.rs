@@ -4,10 +4,21 @@ use std::sync::LazyLock;
 
 use async_trait::async_trait;
 use indoc::indoc;
+use itertools::Itertools;
 use tap::prelude::*;
 
 use super::{DryRunStrategy, Pm, PmHelper, PromptStrategy, Strategy};
-use crate::{config::Config, error::Result, exec::Cmd};
+use crate::{
+    config::{Config, SearchSort},
+    error::Result,
+    exec::{
+        self,
+        result_class::{self, ResultClass},
+        Cmd, Output, StatusCode,
+    },
+    pkg::{self, Update},
+    print::{self, prompt},
+};
 
 macro_rules! doc_self {
     () => {
@@ -35,6 +46,9 @@ static STRAT_CHECK_DRY: LazyLock<Strategy> = LazyLock::new(|| Strategy {
     ..Strategy::default()
 });
 
+/// The exit codes of `choco` reporting a success that requires a reboot.
+const EXIT_REBOOT_REQUIRED: [StatusCode; 2] = [1641, 3010];
+
 impl Choco {
     #[must_use]
     #[allow(missing_docs)]
@@ -42,6 +56,37 @@ impl Choco {
         Self { cfg }
     }
 
+    /// Returns the command upgrading the given packages, or all the outdated
+    /// ones (except the ignored ones) if none is given.
+    ///
+    /// `--except` only takes package names, so the glob patterns among the
+    /// ignored ones are skipped with a warning.
+    fn upgrade_cmd(&self, kws: &[&str], flags: &[&str]) -> Cmd {
+        if !kws.is_empty() {
+            return Cmd::new(["choco", "upgrade"]).kws(kws).flags(flags);
+        }
+        let (globs, ignored): (Vec<_>, Vec<_>) =
+            (self.cfg.ignore.iter()).partition(|pkg| exec::is_glob(pkg));
+        if !globs.is_empty() {
+            print::println(
+                &*prompt::WARNING,
+                format_args!(
+                    "glob patterns are unsupported by `choco` and will be ignored: {}",
+                    globs.iter().join(", ")
+                ),
+            );
+        }
+        let except =
+            (!ignored.is_empty()).then(|| format!("--except={}", ignored.iter().join(",")));
+        Cmd::new(
+            ["choco", "upgrade", "all"]
+                .map(String::from)
+                .into_iter()
+                .chain(except),
+        )
+        .flags(flags)
+    }
+
     async fn check_dry(&self, cmd: Cmd) -> Result<()> {
         self.run_with(cmd, self.default_mode(), &STRAT_CHECK_DRY)
             .await
@@ -60,6 +105,23 @@ impl Pm for Choco {
         &self.cfg
     }
 
+    fn supports_search_limit(&self) -> bool {
+        true
+    }
+
+    fn supports_search_sort(&self) -> bool {
+        true
+    }
+
+    fn classify_result(&self, code: StatusCode, output: &Output) -> ResultClass {
+        let code = if EXIT_REBOOT_REQUIRED.contains(&code) {
+            0
+        } else {
+            code
+        };
+        result_class::classify(self.name(), code, output)
+    }
+
     /// Q generates a list of installed packages.
     async fn q(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
         Cmd::new(["choco", "list"])
@@ -76,7 +138,9 @@ impl Pm for Choco {
 
     /// Qu lists packages which have an update available.
     async fn qu(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
-        self.check_dry(Cmd::new(["choco", "outdated"]).kws(kws).flags(flags))
+        Cmd::new(["choco", "outdated", "--limit-output"])
+            .flags(flags)
+            .pipe(|cmd| self.print_updates(cmd, kws, parse_outdated))
             .await
     }
 
@@ -120,22 +184,27 @@ impl Pm for Choco {
 
     /// Ss searches for package(s) by searching the expression in name,
     /// description, short description.
+    ///
+    /// The results are truncated and sorted natively with `--page-size` and
+    /// `--order-by`.
     async fn ss(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
-        self.check_dry(Cmd::new(["choco", "search"]).kws(kws).flags(flags))
+        let mut search_flags = vec![];
+        if let Some(limit) = self.cfg.search_limit {
+            search_flags.extend(["--page=0".to_owned(), format!("--page-size={limit}")]);
+        }
+        if self.cfg.search_sort == Some(SearchSort::Name) {
+            search_flags.push("--order-by=Id".to_owned());
+        }
+        search_flags.extend(flags.iter().map(|&flag| flag.to_owned()));
+        self.check_dry(Cmd::new(["choco", "search"]).kws(kws).flags(search_flags))
             .await
     }
 
     /// Su updates outdated packages.
     async fn su(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
-        Cmd::new(if kws.is_empty() {
-            &["choco", "upgrade", "all"][..]
-        } else {
-            &["choco", "upgrade"][..]
-        })
-        .kws(kws)
-        .flags(flags)
-        .pipe(|cmd| self.run_with(cmd, self.default_mode(), &STRAT_PROMPT))
-        .await
+        self.upgrade_cmd(kws, flags)
+            .pipe(|cmd| self.run_with(cmd, self.default_mode(), &STRAT_PROMPT))
+            .await
     }
 
     /// Suy refreshes the local package database, then updates outdated
@@ -144,3 +213,73 @@ impl Pm for Choco {
         self.su(kws, flags).await
     }
 }
+
+/// Parses the output of `choco outdated --limit-output` into [`Update`]s.
+///
+/// An update is listed as `name|old|new|pinned`.
+fn parse_outdated(text: &str) -> Vec<Result<Update, String>> {
+    pkg::parse_update_lines(
+        text,
+        |_| false,
+        |ln| {
+            let mut cols = ln.trim().split('|');
+            Some(Update::new(cols.next()?, cols.next()?, cols.next()?))
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{pkg::check_golden_updates, pm::mock::MockLog};
+
+    #[test]
+    fn updates_golden() {
+        check_golden_updates("choco-outdated", parse_outdated);
+    }
+
+    #[tokio::test]
+    async fn ss_native_sort_limit() {
+        let choco = Choco::new(Config {
+            search_limit: Some(10),
+            search_sort: Some(SearchSort::Name),
+            ..Config::default()
+        });
+        let log = MockLog::new();
+        log.scope(choco.ss(&["git"], &["--exact"])).await.unwrap();
+        assert_eq!(
+            log.lines(),
+            ["choco search --page=0 --page-size=10 --order-by=Id --exact git"]
+        );
+    }
+
+    #[test]
+    fn classify_result() {
+        let choco = Choco::new(Config::default());
+        let installed = b"git v2.45.1 already installed.\n Use --force to reinstall.\n".to_vec();
+        for (code, class) in [
+            (0, ResultClass::AlreadyInstalled),
+            (3010, ResultClass::AlreadyInstalled),
+            (1, ResultClass::Failed),
+        ] {
+            assert_eq!(
+                choco.classify_result(code, &installed),
+                class,
+                "code {code}"
+            );
+        }
+    }
+
+    #[test]
+    #[cfg(windows)]
+    fn upgrade_all_except_ignored() {
+        let choco = Choco::new(Config {
+            ignore: vec!["git".into(), "nodejs".into(), "python3*".into()],
+            ..Config::default()
+        });
+        let cmd = choco.upgrade_cmd(&[], &[]).to_string();
+        assert_eq!(cmd, "choco upgrade all --except=git,nodejs");
+        let cmd = choco.upgrade_cmd(&["git"], &[]).to_string();
+        assert_eq!(cmd, "choco upgrade git");
+    }
+}
@@ -3,12 +3,19 @@
 use std::sync::LazyLock;
 
 use async_trait::async_trait;
-use futures::prelude::*;
 use indoc::indoc;
+use itertools::Itertools;
+use regex::Regex;
 use tap::prelude::*;
 
-use super::{Pm, PmHelper, PromptStrategy, Strategy};
-use crate::{config::Config, error::Result, exec::Cmd};
+use super::{for_each_kw, Pm, PmHelper, PromptStrategy, Strategy};
+use crate::{
+    config::Config,
+    error::{Error, Result},
+    exec::{self, Cmd},
+    pkg::{Conflict, Pkg},
+    print::Progress,
+};
 
 macro_rules! doc_self {
     () => {
@@ -49,15 +56,47 @@ impl Pm for Conda {
         &self.cfg
     }
 
+    fn dep_tree_methods(&self) -> &[&'static str] {
+        &["qi"]
+    }
+
     /// Q generates a list of installed packages.
     async fn q(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
-        if kws.is_empty() {
+        if kws.is_empty() && !self.cfg.query_format().is_structured() {
             self.run(Cmd::new(["conda", "list"]).flags(flags)).await
         } else {
             self.qs(kws, flags).await
         }
     }
 
+    /// Qi displays local package information: name, version, description, etc.
+    ///
+    /// Only the dependency trees of the packages are available, with `--tree`,
+    /// which relies on `conda-tree`.
+    async fn qi(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
+        if !self.cfg.tree {
+            return Err(Error::OperationUnimplementedError {
+                op: "qi".into(),
+                pm: self.name().into(),
+            });
+        }
+        if !self.cfg.dry_run && !exec::is_exe("conda-tree", "") {
+            return Err(Error::OtherError(
+                "showing the dependency tree of a `conda` package requires `conda-tree`, \
+                 which can be installed with `conda install -c conda-forge conda-tree`"
+                    .into(),
+            ));
+        }
+        for_each_kw(&self.cfg, kws, |kw| {
+            self.run(
+                Cmd::new(["conda-tree", "depends", "-t"])
+                    .kws([kw])
+                    .flags(flags),
+            )
+        })
+        .await
+    }
+
     /// Qo queries the package which provides FILE.
     async fn qo(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
         Cmd::new(["conda", "package", "--which"])
@@ -72,8 +111,11 @@ impl Pm for Conda {
     // when including multiple search terms, only packages with descriptions
     // matching ALL of those terms are returned.
     async fn qs(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
-        self.search_regex(Cmd::new(["conda", "list"]).flags(flags), kws)
-            .await
+        let cmd = Cmd::new(["conda", "list"]).flags(flags);
+        if self.cfg.query_format().is_structured() {
+            return self.print_pkgs(cmd, kws, parse_list).await;
+        }
+        self.search_regex_cached(cmd, kws).await
     }
 
     /// R removes a single package, leaving all of its dependencies installed.
@@ -87,6 +129,11 @@ impl Pm for Conda {
 
     /// S installs one or more packages by name.
     async fn s(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
+        Cmd::new(["conda", "install", "--dry-run"])
+            .kws(kws)
+            .flags(flags)
+            .pipe(|cmd| self.check_deps(cmd, parse_conflicts))
+            .await?;
         Cmd::new(["conda", "install"])
             .kws(kws)
             .flags(flags)
@@ -115,10 +162,16 @@ impl Pm for Conda {
     /// Ss searches for package(s) by searching the expression in name,
     /// description, short description.
     async fn ss(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
-        stream::iter(kws)
-            .map(|s| Ok(format!("*{s}*")))
-            .try_for_each(|kw| self.run(Cmd::new(["conda", "search"]).kws([kw]).flags(flags)))
-            .await
+        let progress = Progress::new(kws.len(), &self.cfg);
+        for_each_kw(&self.cfg, kws, |kw| {
+            progress.step(kw);
+            self.run(
+                Cmd::new(["conda", "search"])
+                    .kws([format!("*{kw}*")])
+                    .flags(flags),
+            )
+        })
+        .await
     }
 
     /// Su updates outdated packages.
@@ -136,3 +189,83 @@ impl Pm for Conda {
         self.su(kws, flags).await
     }
 }
+
+/// Parses the output of `conda list` into [`Pkg`]s.
+///
+/// A package is listed as `name version build [channel]`, where the channel
+/// is omitted for the default one.
+fn parse_list(text: &str) -> Vec<Pkg> {
+    text.lines()
+        .filter(|ln| !ln.starts_with('#'))
+        .filter_map(|ln| {
+            let cols: Vec<_> = ln.split_whitespace().collect();
+            let (name, version) = (cols.first()?, cols.get(1)?);
+            Some(Pkg::new(*name, *version, *cols.get(3).unwrap_or(&"conda")))
+        })
+        .collect()
+}
+
+/// Parses the dependency conflicts reported by the solver of `conda install`
+/// into [`Conflict`]s.
+///
+/// The `libmamba` solver lists the problems as `- package name requires
+/// target, but none of the providers can be installed` or `- nothing provides
+/// target needed by name`, while the classic solver lists the dependency
+/// chains leading to each conflicting package as `name -> ... -> target` below
+/// `Package X conflicts for:`.
+fn parse_conflicts(text: &str) -> Vec<Conflict> {
+    /// The problems reported by `libmamba`, in the form of `(pattern,
+    /// detail)`.
+    static PROBLEMS: LazyLock<[(Regex, &str); 2]> = LazyLock::new(|| {
+        let re = |pattern| Regex::new(pattern).expect("pattern should be valid");
+        [
+            (
+                re(
+                    r"^package (?<pkg>\S+) requires (?<target>.+), but none of the providers can be installed$",
+                ),
+                "none of the providers can be installed",
+            ),
+            (
+                re(r"^nothing provides (?<target>.+) needed by (?<pkg>\S+)$"),
+                "nothing provides it",
+            ),
+        ]
+    });
+
+    let mut in_chains = false;
+    text.lines()
+        .filter_map(|ln| {
+            let ln = ln.trim();
+            if ln.starts_with("Package ") && ln.ends_with(" conflicts for:") {
+                in_chains = true;
+                return None;
+            }
+            let desc = ln.strip_prefix("- ").unwrap_or(ln);
+            let problem = PROBLEMS.iter().find_map(|(re, detail)| {
+                let caps = re.captures(desc)?;
+                Some(Conflict::new(&caps["pkg"], "requires", &caps["target"]).detail(*detail))
+            });
+            problem.or_else(|| {
+                let (pkg, target) = desc.rsplit_once(" -> ").filter(|_| in_chains)?;
+                Some(Conflict::new(pkg, "depends on", target))
+            })
+        })
+        .unique()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pkg::{check_golden, check_golden_conflicts};
+
+    #[test]
+    fn porcelain_golden() {
+        check_golden("conda-list", parse_list);
+    }
+
+    #[test]
+    fn conflicts_golden() {
+        check_golden_conflicts("conda-install", parse_conflicts);
+    }
+}
@@ -1,13 +1,28 @@
 #![doc = doc_self!()]
 
-use std::sync::LazyLock;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{LazyLock, Mutex},
+};
 
 use async_trait::async_trait;
 use indoc::indoc;
+use itertools::{chain, Itertools};
+use regex::Regex;
 use tap::prelude::*;
 
-use super::{NoCacheStrategy, Pm, PmHelper, PromptStrategy, Strategy};
-use crate::{config::Config, error::Result, exec::Cmd};
+use super::{
+    checksum_cmd, for_each_kw, undo_id, CmdSeq, Download, NoCacheStrategy, Pm, PmHelper,
+    PromptStrategy, Strategy,
+};
+use crate::{
+    config::Config,
+    error::{Error, Result},
+    exec::{self, Cmd, Mode},
+    pkg::{self, Conflict, FileChange, FileCheck, Pkg, RepoStatus, Update, Verification},
+    print::{self, prompt, Progress},
+};
 
 macro_rules! doc_self {
     () => {
@@ -40,12 +55,267 @@ static STRAT_INSTALL: LazyLock<Strategy> = LazyLock::new(|| Strategy {
     ..Strategy::default()
 });
 
+/// The `rpm` query listing all installed packages in a form readable by
+/// [`parse_rpm`].
+pub(super) const RPM_QUERY: [&str; 4] = ["rpm", "-qa", "--qf", "%{NAME} %{VERSION}-%{RELEASE}\\n"];
+
+/// The `rpm` query printing the name and the version of an installed package,
+/// where the version is in the same form as `%{evr}` of `dnf repoquery`.
+const RPM_EVR_QUERY: [&str; 4] = [
+    "rpm",
+    "-q",
+    "--qf",
+    "%{NAME}\\t%|EPOCH?{%{EPOCH}:}:{}|%{VERSION}-%{RELEASE}\\n",
+];
+
+/// The `dnf` query listing the installed packages that are not available in
+/// any of the repositories, in a form readable by [`parse_extras`].
+const EXTRAS_QUERY: [&str; 6] = [
+    "dnf",
+    "repoquery",
+    "-q",
+    "--extras",
+    "--qf",
+    "%{name} %{evr}\\n",
+];
+
+/// The `rpm` query format listing the files of the installed packages in a
+/// form readable by [`parse_rpm_file_checks`].
+const RPM_FILES_FORMAT: &str = "[%{NAME}\\t%{FILENAMES}\\n]";
+
+/// The `dnf` query listing the versions of a package available in the
+/// repositories.
+const REPO_EVR_QUERY: [&str; 6] = ["dnf", "repoquery", "-q", "--available", "--qf", "%{evr}\\n"];
+
+/// The `dnf` query listing the names of the installed packages requiring a
+/// package, one per line.
+const WHATREQUIRES_QUERY: [&str; 7] = [
+    "dnf",
+    "repoquery",
+    "-q",
+    "--installed",
+    "--qf",
+    "%{name}\\n",
+    "--whatrequires",
+];
+
+/// The `dnf` query listing the names of the installed packages no longer
+/// required by any other one, one per line.
+const UNNEEDED_QUERY: [&str; 6] = ["dnf", "repoquery", "-q", "--unneeded", "--qf", "%{name}\\n"];
+
+/// The `dnf` query listing the installed packages with their installed sizes
+/// (in bytes), in a form readable by [`parse_installed_sizes`].
+///
+/// `%{size}` is the size of the package file itself, hence `%{installsize}`.
+const SIZES_QUERY: [&str; 6] = [
+    "dnf",
+    "repoquery",
+    "-q",
+    "--installed",
+    "--qf",
+    "%{installsize}\\t%{name}\\n",
+];
+
+/// The exit code of `dnf` when it has failed to acquire a lock.
+const LOCK_EXIT_CODE: i32 = 200;
+
+/// The directory where the GPG keys of the repositories are installed.
+const RPM_GPG_DIR: &str = "/etc/pki/rpm-gpg";
+
+/// The cache directories of `dnf5` and `dnf4`, where the packages downloaded
+/// from each repository are kept in `{repo}-{hash}/packages`.
+const CACHE_DIRS: [&str; 2] = ["/var/cache/libdnf5", "/var/cache/dnf"];
+
 impl Dnf {
     #[must_use]
     #[allow(missing_docs)]
     pub const fn new(cfg: Config) -> Self {
         Self { cfg }
     }
+
+    /// Returns the given `flags` preceded by the option setting the proxy in
+    /// the config, to be used by the commands accessing the network.
+    ///
+    /// `dnf` uses the same proxy for all the repositories, so the HTTPS one is
+    /// preferred. If retries on busy locks are enabled, `dnf` is also told to
+    /// fail instead of waiting for a lock, so that [`Pm::busy_lock`] can
+    /// recognize it.
+    fn net_flags(&self, flags: &[&str]) -> Vec<String> {
+        let proxy = self.cfg.https_proxy().or_else(|| self.cfg.http_proxy());
+        let exit_on_lock = self.cfg.max_retries_on_lock.is_some_and(|n| n > 0);
+        chain!(
+            proxy.map(|url| format!("--setopt=proxy={url}")),
+            exit_on_lock.then(|| "--setopt=exit_on_lock=True".to_owned()),
+            flags.iter().map(|&flag| flag.to_owned()),
+        )
+        .collect()
+    }
+
+    /// Returns the given `flags` preceded by the repositories enabled with
+    /// [`Config::target_release`] and [`Config::enable_repo`], the ones
+    /// disabled with [`Config::disable_repo`], and [`Dnf::net_flags`], for the
+    /// sync operations.
+    ///
+    /// With [`Config::offline`], `--cacheonly` is added as well, so that only
+    /// the cached metadata and packages are used.
+    fn repo_flags(&self, flags: &[&str]) -> Vec<String> {
+        let cfg = &self.cfg;
+        chain!(
+            cfg.offline.then(|| "--cacheonly".to_owned()),
+            chain!(&cfg.target_release, &cfg.enable_repo)
+                .map(|repo| format!("--enablerepo={repo}")),
+            (cfg.disable_repo.iter()).map(|repo| format!("--disablerepo={repo}")),
+            self.net_flags(flags),
+        )
+        .collect()
+    }
+
+    /// Returns the given `flags` preceded by the option translating
+    /// [`Config::parallel_downloads`] (if any) and [`Dnf::repo_flags`], for the
+    /// commands downloading packages.
+    ///
+    /// `dnf` accepts up to 20 parallel downloads.
+    fn download_flags(&self, flags: &[&str]) -> Vec<String> {
+        let parallel = self.cfg.parallel_downloads.map(|n| n.clamp(1, 20));
+        chain!(
+            parallel.map(|n| format!("--setopt=max_parallel_downloads={n}")),
+            self.repo_flags(flags),
+        )
+        .collect()
+    }
+
+    /// Returns the repositories and the file names of the packages to be
+    /// downloaded when installing the given ones, as planned by `dnf` without
+    /// committing the transaction, if [`Config::verify_checksums`] is set.
+    ///
+    /// In a dry run, the planning command is only printed out.
+    async fn planned_rpms(&self, kws: &[&str], flags: &[&str]) -> Result<Vec<(String, String)>> {
+        if !self.cfg.verify_checksums {
+            return Ok(vec![]);
+        }
+        let plan = Cmd::with_sudo(["dnf", "install", "--assumeno"])
+            .kws(kws)
+            .flags(self.repo_flags(flags));
+        if self.cfg.dry_run {
+            plan.exec(Mode::PrintCmd).await?;
+            return Ok(vec![]);
+        }
+        // Declining the transaction is expected to fail.
+        let out = match plan.exec(Mode::Mute).await {
+            Ok(out) | Err(Error::CmdStatusCodeError { output: out, .. }) => out,
+            Err(e) => return Err(e),
+        };
+        Ok(parse_planned_rpms(&exec::decode(&out)))
+    }
+
+    /// Returns the read-only command showing how the resolver handles the
+    /// installation of the given packages, for
+    /// [`PmHelper::explain_conflicts`].
+    fn explain_cmd(&self, kws: &[&str], flags: &[&str]) -> Cmd {
+        Cmd::with_sudo(["dnf", "install", "--assumeno", "--verbose"])
+            .kws(kws)
+            .flags(self.repo_flags(flags))
+    }
+
+    /// Returns the commands used to verify the installed package `pkg`.
+    fn verify_cmds(&self, pkg: &str, flags: &[&str]) -> [Cmd; 3] {
+        [
+            Cmd::new(RPM_EVR_QUERY).kws([pkg]),
+            Cmd::new(["rpm", "-V"]).kws([pkg]),
+            Cmd::new(REPO_EVR_QUERY)
+                .kws([pkg])
+                .flags(self.net_flags(flags)),
+        ]
+    }
+
+    /// Verifies the installed package `pkg` with [`Dnf::verify_cmds`].
+    async fn verify_pkg(&self, pkg: &str, flags: &[&str]) -> Result<Verification> {
+        let [query, verify, repo_query] = self.verify_cmds(pkg, flags);
+        let installed = query.exec(Mode::Mute).await?;
+        let installed = exec::decode(&installed);
+        let (name, version) = (installed.lines().next())
+            .and_then(|ln| ln.split_once('\t'))
+            .ok_or_else(|| {
+                Error::OtherError(format!("failed to get the installed version of `{pkg}`"))
+            })?;
+        let changes = match verify.exec(Mode::Mute).await {
+            // ! `rpm -V` exits with code 1 if any file has changed.
+            Ok(out)
+            | Err(Error::CmdStatusCodeError {
+                code: 1,
+                output: out,
+                ..
+            }) => parse_rpm_verify(&exec::decode(&out)),
+            Err(e) => return Err(e),
+        };
+        let available = repo_query.exec(Mode::Mute).await?;
+        let repo = RepoStatus::of(version, exec::decode(&available).lines().map(str::trim));
+        Ok(Verification {
+            name: name.into(),
+            version: version.into(),
+            repo,
+            changes,
+        })
+    }
+
+    /// Returns the modules among the requested streams `kws` (e.g.
+    /// `nodejs:18`) with another stream enabled, which have to be reset before
+    /// switching to the requested one.
+    async fn streams_to_switch(&self, kws: &[&str]) -> Result<Vec<String>> {
+        let streams = kws.iter().filter_map(|kw| split_stream(kw)).collect_vec();
+        if streams.is_empty() {
+            return Ok(vec![]);
+        }
+        let list = Cmd::new(["dnf", "module", "list", "--enabled", "-q"])
+            .kws(streams.iter().map(|&(name, _)| name).unique())
+            .flags(self.repo_flags(&[]));
+        // `dnf` fails if none of the modules is enabled.
+        let out = match list.exec(Mode::Mute).await {
+            Ok(out) | Err(Error::CmdStatusCodeError { output: out, .. }) => out,
+            Err(e) => return Err(e),
+        };
+        let enabled = parse_enabled_streams(&exec::decode(&out));
+        Ok(streams
+            .into_iter()
+            .filter(|&(name, stream)| enabled.iter().any(|(n, s)| n == name && s != stream))
+            .map(|(name, _)| name.to_owned())
+            .unique()
+            .collect())
+    }
+
+    /// Runs `dnf module {subcmd}` (`enable` or `install`) with the module
+    /// streams `kws`, resetting the modules switching to another stream first.
+    ///
+    /// Both steps are confirmed with a single prompt, but `dnf` still runs
+    /// them as two transactions: if the second one fails, the modules are left
+    /// reset, with none of their streams enabled.
+    async fn switch_streams(&self, subcmd: &str, kws: &[&str], flags: &[&str]) -> Result<()> {
+        let reset = self.streams_to_switch(kws).await?;
+        let mut seq = CmdSeq::new();
+        if !reset.is_empty() {
+            print::println(
+                &*prompt::INFO,
+                format_args!(
+                    "resetting {} to switch to the requested stream",
+                    reset.iter().map(|name| format!("`{name}`")).join(", ")
+                ),
+            );
+            seq = seq.then(
+                Cmd::with_sudo(["dnf", "module", "reset"])
+                    .kws(&reset)
+                    .flags(flags),
+                &STRAT_PROMPT,
+            );
+        }
+        seq.then(
+            Cmd::with_sudo(["dnf", "module", subcmd])
+                .kws(kws)
+                .flags(self.repo_flags(flags)),
+            &STRAT_INSTALL,
+        )
+        .pipe(|seq| self.run_seq(seq))
+        .await
+    }
 }
 
 #[async_trait]
@@ -59,9 +329,205 @@ impl Pm for Dnf {
         &self.cfg
     }
 
+    fn busy_lock(&self, err: &Error) -> Option<String> {
+        match err.cmd_failure()? {
+            Error::CmdStatusCodeError {
+                code: LOCK_EXIT_CODE,
+                output,
+                ..
+            } => parse_busy_lock(&exec::decode(output)),
+            _ => None,
+        }
+    }
+
+    /// Autoremove shows all the packages that are no longer required by any
+    /// other installed package, then removes them after confirmation.
+    async fn autoremove(&self, _kws: &[&str], flags: &[&str]) -> Result<()> {
+        match self
+            .run(Cmd::with_sudo(["dnf", "autoremove", "--assumeno"]).flags(flags))
+            .await
+        {
+            // ! `dnf` exits with code 1 after answering `no` to its prompt.
+            Ok(()) | Err(Error::CmdStatusCodeError { code: 1, .. }) => (),
+            Err(e) => return Err(e),
+        }
+        // The user has already been asked by our custom prompt at this point.
+        Cmd::with_sudo(["dnf", "autoremove", "-y"])
+            .flags(flags)
+            .pipe(|cmd| self.run_reclaiming(cmd, self.default_mode(), &STRAT_PROMPT_CUSTOM))
+            .await
+    }
+
+    /// Autoremove interactive lists all the packages that are no longer
+    /// required by any other installed package with their sizes, then removes
+    /// the ones chosen by the user.
+    async fn autoremove_interactive(&self, _kws: &[&str], flags: &[&str]) -> Result<()> {
+        self.remove_orphans_interactively(
+            Cmd::new(UNNEEDED_QUERY),
+            parse_names,
+            |kws| Cmd::new(SIZES_QUERY).kws(kws),
+            parse_installed_sizes,
+            flags,
+        )
+        .await
+    }
+
+    /// Repair tries to bring a system in a broken or half-configured state
+    /// back to a consistent one.
+    async fn repair(&self, _kws: &[&str], flags: &[&str]) -> Result<()> {
+        match self.run(Cmd::new(["dnf", "check"]).flags(flags)).await {
+            Ok(()) => (),
+            // ! `dnf` exits with code 1 if any problem is found.
+            Err(Error::CmdStatusCodeError { code: 1, .. }) => print::println(
+                &*prompt::INFO,
+                "if the RPM database itself is corrupted, run `sudo rpm --rebuilddb` first",
+            ),
+            Err(e) => return Err(e),
+        }
+        Cmd::with_sudo(["dnf", "distro-sync"])
+            .flags(self.net_flags(flags))
+            .pipe(|cmd| self.run_with(cmd, self.default_mode(), &STRAT_PROMPT))
+            .await
+    }
+
+    /// Refresh keys re-imports the GPG keys of the repositories into the RPM
+    /// database, then refreshes the metadata of the repositories.
+    async fn refresh_keys(&self, _kws: &[&str], flags: &[&str]) -> Result<()> {
+        let keys = key_files(Path::new(RPM_GPG_DIR));
+        let progress = Progress::new(if keys.is_empty() { 1 } else { 2 }, &self.cfg);
+        if keys.is_empty() {
+            print::println(
+                &*prompt::INFO,
+                format_args!("no key was found in `{RPM_GPG_DIR}`"),
+            );
+        } else {
+            progress.step(format_args!("importing {} keys", keys.len()));
+            Cmd::with_sudo(["rpm", "--import"])
+                .kws(keys.iter().map(|key| key.to_string_lossy()))
+                .pipe(|cmd| self.run(cmd))
+                .await?;
+        }
+        progress.step("refreshing the metadata");
+        Cmd::with_sudo(["dnf", "makecache", "--refresh"])
+            .flags(self.net_flags(flags))
+            .pipe(|cmd| self.run(cmd))
+            .await
+    }
+
+    fn supports_allow_downgrade(&self) -> bool {
+        true
+    }
+
+    fn supports_target_release(&self) -> bool {
+        true
+    }
+
+    fn supports_installed_filter(&self) -> bool {
+        true
+    }
+
+    fn supports_search_limit(&self) -> bool {
+        true
+    }
+
+    fn supports_search_sort(&self) -> bool {
+        true
+    }
+
+    fn dep_tree_methods(&self) -> &[&'static str] {
+        &["qi", "si"]
+    }
+
+    fn pkg_size_methods(&self) -> &[&'static str] {
+        &["q", "qi", "s", "su"]
+    }
+
+    fn offline_methods(&self) -> &[&'static str] {
+        &["s", "si", "sii", "sl", "ss", "su", "suy"]
+    }
+
+    fn supports_parallel_downloads(&self) -> bool {
+        true
+    }
+
+    fn supports_verify_checksums(&self) -> bool {
+        true
+    }
+
+    fn supports_enable_repo(&self) -> bool {
+        true
+    }
+
+    fn supports_disable_repo(&self) -> bool {
+        true
+    }
+
+    fn supports_undo(&self) -> bool {
+        true
+    }
+
+    /// Downgrade installs an older version of the given packages, i.e. the one
+    /// pinned with `name=version`, or the previous one otherwise.
+    async fn downgrade(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
+        Cmd::with_sudo(["dnf", "downgrade"])
+            .kws(kws.iter().map(|&kw| match pkg::split_pinned(kw) {
+                (name, Some(version)) => format!("{name}-{version}"),
+                (name, None) => name.to_owned(),
+            }))
+            .flags(self.net_flags(flags))
+            .pipe(|cmd| self.run_with(cmd, self.default_mode(), &STRAT_INSTALL))
+            .await
+    }
+
+    /// Undo reverts the transaction given by its ID in `dnf history`, or the
+    /// last one if no ID is given.
+    async fn undo(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
+        let id = undo_id(kws)?.unwrap_or("last");
+        Cmd::with_sudo(["dnf", "history", "undo", id])
+            .flags(flags)
+            .pipe(|cmd| self.run_with(cmd, self.default_mode(), &STRAT_PROMPT))
+            .await
+    }
+
+    /// Module list lists the module streams matching the given patterns, or
+    /// all of them if none is given.
+    async fn module_list(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
+        Cmd::new(["dnf", "module", "list"])
+            .kws(kws)
+            .flags(self.repo_flags(flags))
+            .pipe(|cmd| self.run(cmd))
+            .await
+    }
+
+    /// Module enable enables the given module streams, resetting the modules
+    /// with another stream enabled first.
+    async fn module_enable(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
+        self.switch_streams("enable", kws, flags).await
+    }
+
+    /// Module install enables the given module streams and installs their
+    /// default (or given) profiles, resetting the modules with another stream
+    /// enabled first.
+    async fn module_install(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
+        self.switch_streams("install", kws, flags).await
+    }
+
+    /// Module reset resets the given modules to their initial state.
+    async fn module_reset(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
+        Cmd::with_sudo(["dnf", "module", "reset"])
+            .kws(kws)
+            .flags(flags)
+            .pipe(|cmd| self.run_with(cmd, self.default_mode(), &STRAT_PROMPT))
+            .await
+    }
+
     /// Q generates a list of installed packages.
     async fn q(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
-        if kws.is_empty() {
+        if self.cfg.sizes {
+            return (self.print_pkg_sizes(Cmd::new(SIZES_QUERY).kws(kws), parse_installed_sizes))
+                .await;
+        }
+        if kws.is_empty() && !self.cfg.query_format().is_structured() {
             self.run(Cmd::new(["rpm", "-qa", "--qf", "%{NAME} %{VERSION}\\n"]).flags(flags))
                 .await
         } else {
@@ -89,11 +555,22 @@ impl Pm for Dnf {
 
     /// Qi displays local package information: name, version, description, etc.
     async fn qi(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
+        if self.cfg.tree {
+            return Cmd::new(["dnf", "repoquery", "--installed", "--tree", "--requires"])
+                .kws(kws)
+                .flags(flags)
+                .pipe(|cmd| self.run(cmd))
+                .await;
+        }
         Cmd::new(["dnf", "info", "--installed"])
             .kws(kws)
             .flags(flags)
             .pipe(|cmd| self.run(cmd))
-            .await
+            .await?;
+        if self.cfg.sizes {
+            (self.print_pkg_sizes(Cmd::new(SIZES_QUERY).kws(kws), parse_installed_sizes)).await?;
+        }
+        Ok(())
     }
 
     /// Qii displays local packages which require X to be installed, aka local
@@ -106,16 +583,33 @@ impl Pm for Dnf {
             .await
     }
 
+    /// Qk verifies one or more packages.
+    async fn qk(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
+        let [list, verify] = rpm_check_cmds(kws, flags);
+        self.check_files(list, verify, parse_rpm_file_checks, false)
+            .await
+    }
+
+    /// Qkk verifies one or more packages, listing every altered file.
+    async fn qkk(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
+        let [list, verify] = rpm_check_cmds(kws, flags);
+        self.check_files(list, verify, parse_rpm_file_checks, true)
+            .await
+    }
+
     /// Ql displays files provided by local package.
     async fn ql(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
-        self.run(Cmd::new(["rpm", "-ql"]).kws(kws).flags(flags))
+        self.list_files(Cmd::new(["rpm", "-ql"]).kws(kws).flags(flags))
             .await
     }
 
     /// Qm lists packages that are installed but are not available in any
     /// installation source (anymore).
-    async fn qm(&self, _kws: &[&str], flags: &[&str]) -> Result<()> {
-        self.run(Cmd::new(["dnf", "list", "--extras"]).flags(flags))
+    async fn qm(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
+        Cmd::new(EXTRAS_QUERY)
+            .kws(kws)
+            .flags(flags)
+            .pipe(|cmd| self.print_unavailable(cmd, parse_extras))
             .await
     }
 
@@ -138,18 +632,35 @@ impl Pm for Dnf {
     // matching ALL of those terms are returned.
     // TODO: Is this right?
     async fn qs(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
+        if self.cfg.query_format().is_structured() {
+            return Cmd::new(RPM_QUERY)
+                .flags(flags)
+                .pipe(|cmd| self.print_pkgs(cmd, kws, parse_rpm))
+                .await;
+        }
         self.search_regex(Cmd::new(["rpm", "-qa"]).flags(flags), kws)
             .await
     }
 
     /// Qu lists packages which have an update available.
     async fn qu(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
-        self.run(Cmd::new(["dnf", "list", "updates"]).kws(kws).flags(flags))
-            .await
+        let list = Cmd::new(["dnf", "-q", "list", "--upgrades"]).flags(self.net_flags(flags));
+        if self.cfg.dry_run {
+            list.exec(Mode::PrintCmd).await?;
+            return Ok(());
+        }
+        // `dnf` doesn't show the installed versions, so they are queried
+        // separately.
+        let installed = parse_rpm(&exec::decode(&Cmd::new(RPM_QUERY).exec(Mode::Mute).await?));
+        let out = list.exec(Mode::Mute).await?;
+        let text = exec::decode(&out);
+        self.println_updates(parse_upgrades(&text, &installed), kws)
     }
 
     /// R removes a single package, leaving all of its dependencies installed.
     async fn r(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
+        self.check_required_by(kws, whatrequires_cmd, parse_names)
+            .await?;
         Cmd::with_sudo(["dnf", "remove"])
             .kws(kws)
             .flags(flags)
@@ -157,13 +668,50 @@ impl Pm for Dnf {
             .await
     }
 
+    /// Rs removes a package and its dependencies which are not required by any
+    /// other installed package, and not explicitly installed by the user.
+    ///
+    /// Without any package given, all such dependencies are removed as with
+    /// [`Dnf::autoremove`].
+    async fn rs(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
+        if kws.is_empty() {
+            return self.autoremove(kws, flags).await;
+        }
+        self.check_required_by(kws, whatrequires_cmd, parse_names)
+            .await?;
+        // `dnf remove` already does so by default, unless disabled in `dnf.conf`.
+        Cmd::with_sudo([
+            "dnf",
+            "remove",
+            "--setopt=clean_requirements_on_remove=True",
+        ])
+        .kws(kws)
+        .flags(flags)
+        .pipe(|cmd| self.run_with(cmd, self.default_mode(), &STRAT_PROMPT))
+        .await
+    }
+
     /// S installs one or more packages by name.
     async fn s(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
-        Cmd::with_sudo(["dnf", "install"])
+        Cmd::with_sudo(["dnf", "install", "--assumeno"])
             .kws(kws)
-            .flags(flags)
+            .flags(self.repo_flags(flags))
+            .pipe(|plan| self.print_transaction_size(plan, parse_transaction_size))
+            .await?;
+        let res = Cmd::with_sudo(["dnf", "install"])
+            .kws(kws)
+            .flags(self.download_flags(flags))
             .pipe(|cmd| self.run_with(cmd, self.default_mode(), &STRAT_INSTALL))
-            .await
+            .await;
+        match res {
+            Err(e) => {
+                let explain = self.explain_cmd(kws, flags);
+                Err(self
+                    .explain_conflicts(e, explain, is_conflict, parse_problems)
+                    .await)
+            }
+            ok => ok,
+        }
     }
 
     /// Sc removes all the cached packages that are not currently installed, and
@@ -194,8 +742,19 @@ impl Pm for Dnf {
     /// Si displays remote package information: name, version, description, etc.
 
     async fn si(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
-        self.run(Cmd::new(["dnf", "info"]).kws(kws).flags(flags))
-            .await
+        if self.cfg.tree {
+            return Cmd::new(["dnf", "repoquery", "--tree", "--requires"])
+                .kws(kws)
+                .flags(self.repo_flags(flags))
+                .pipe(|cmd| self.run(cmd))
+                .await;
+        }
+        self.run(
+            Cmd::new(["dnf", "info"])
+                .kws(kws)
+                .flags(self.repo_flags(flags)),
+        )
+        .await
     }
 
     /// Sii displays packages which require X to be installed, aka reverse
@@ -203,7 +762,7 @@ impl Pm for Dnf {
     async fn sii(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
         Cmd::new(["dnf", "repoquery", "--whatdepends"])
             .kws(kws)
-            .flags(flags)
+            .flags(self.repo_flags(flags))
             .pipe(|cmd| self.run(cmd))
             .await
     }
@@ -216,7 +775,7 @@ impl Pm for Dnf {
             ["dnf", "group", "info"]
         })
         .kws(kws)
-        .flags(flags)
+        .flags(self.net_flags(flags))
         .pipe(|cmd| self.run(cmd))
         .await
     }
@@ -226,7 +785,7 @@ impl Pm for Dnf {
     async fn sl(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
         Cmd::new(["dnf", "list", "--available"])
             .kws(kws)
-            .flags(flags)
+            .flags(self.repo_flags(flags))
             .pipe(|cmd| self.run(cmd))
             .await
     }
@@ -234,15 +793,37 @@ impl Pm for Dnf {
     /// Ss searches for package(s) by searching the expression in name,
     /// description, short description.
     async fn ss(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
-        self.run(Cmd::new(["dnf", "search"]).kws(kws).flags(flags))
-            .await
+        let cmd = Cmd::new(["dnf", "search"])
+            .kws(kws)
+            .flags(self.repo_flags(flags));
+        if self.cfg.exclude_installed || self.cfg.only_installed {
+            let installed = Cmd::new(RPM_QUERY);
+            return (self.print_search_filtered(cmd, parse_search, installed, parse_rpm)).await;
+        }
+        if self.cfg.sorts_or_limits_search() {
+            return self.print_search(cmd, parse_search).await;
+        }
+        self.run(cmd).await
     }
 
     /// Su updates outdated packages.
+    ///
+    /// With [`Config::allow_downgrade`], the packages are synchronized with the
+    /// versions in the repositories instead, downgrading them if needed.
     async fn su(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
-        Cmd::with_sudo(["dnf", "upgrade"])
+        let subcmd = if self.cfg.allow_downgrade {
+            "distro-sync"
+        } else {
+            "upgrade"
+        };
+        Cmd::with_sudo(["dnf", subcmd, "--assumeno"])
             .kws(kws)
-            .flags(flags)
+            .flags(self.repo_flags(flags))
+            .pipe(|plan| self.print_transaction_size(plan, parse_transaction_size))
+            .await?;
+        Cmd::with_sudo(["dnf", subcmd])
+            .kws(kws)
+            .flags(self.download_flags(flags))
             .pipe(|cmd| self.run_with(cmd, self.default_mode(), &STRAT_INSTALL))
             .await
     }
@@ -255,18 +836,26 @@ impl Pm for Dnf {
 
     /// Sw retrieves all packages from the server, but does not install/upgrade
     /// anything.
+    ///
+    /// With [`Config::verify_checksums`], the packages to be downloaded are
+    /// taken from the planned transaction beforehand, and their files in the
+    /// cache are hashed afterwards and checked against the checksums published
+    /// in the metadata of their repositories (`primary.xml`).
     async fn sw(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
+        let planned = self.planned_rpms(kws, flags).await?;
         Cmd::with_sudo(["dnf", "install", "--downloadonly"])
             .kws(kws)
-            .flags(flags)
+            .flags(self.download_flags(flags))
             .pipe(|cmd| self.run_with(cmd, self.default_mode(), &STRAT_INSTALL))
-            .await
+            .await?;
+        let downloads = cached_downloads(&CACHE_DIRS, planned).await?;
+        self.verify_downloads(downloads).await
     }
 
     /// Sy refreshes the local package database.
     async fn sy(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
         self.sc(&[], flags).await?;
-        self.run(Cmd::new(["dnf", "check-update"]).flags(flags))
+        self.run(Cmd::new(["dnf", "check-update"]).flags(self.repo_flags(flags)))
             .await?;
         if !kws.is_empty() {
             self.s(kws, flags).await?;
@@ -279,4 +868,878 @@ impl Pm for Dnf {
     async fn u(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
         self.s(kws, flags).await
     }
+
+    /// Verify checks the installed files of the given packages against the
+    /// packaged ones, and the installed versions against the repositories.
+    ///
+    /// It fails if some of the packages are not intact, once all of them have
+    /// been reported.
+    async fn verify(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
+        if kws.is_empty() {
+            return Err(Error::ArgParseError {
+                msg: "no package to verify was given".into(),
+            });
+        }
+        if self.cfg.dry_run {
+            for cmd in kws.iter().flat_map(|kw| self.verify_cmds(kw, flags)) {
+                cmd.exec(Mode::PrintCmd).await?;
+            }
+            return Ok(());
+        }
+        let verifications = Mutex::new(vec![]);
+        let res = for_each_kw(&self.cfg, kws, |kw| {
+            let verifications = &verifications;
+            async move {
+                let verification = self.verify_pkg(kw, flags).await?;
+                verifications.lock().unwrap().push(verification);
+                Ok(())
+            }
+        })
+        .await;
+        let verifications = verifications.into_inner().unwrap();
+        print::println_verifications(&verifications, self.cfg.query_format());
+        res?;
+        if !verifications.iter().all(Verification::is_intact) {
+            return Err(super::verification_failed());
+        }
+        Ok(())
+    }
+}
+
+/// Parses the output of [`RPM_QUERY`] into [`Pkg`]s.
+pub(super) fn parse_rpm(text: &str) -> Vec<Pkg> {
+    pkg::parse_name_version(text, "rpm")
+}
+
+/// Parses the output of [`SIZES_QUERY`] into the sizes of the installed
+/// packages.
+fn parse_installed_sizes(text: &str) -> Vec<(String, u64)> {
+    text.lines()
+        .filter_map(|ln| {
+            let (bytes, name) = ln.split_once('\t')?;
+            Some((name.trim().to_owned(), bytes.trim().parse().ok()?))
+        })
+        .collect()
+}
+
+/// Parses the output of `dnf` declining a transaction into its size summary,
+/// e.g. `Total download size: 1.2 M` and `Installed size: 4.0 M` (`dnf4`) or
+/// `After this operation, 4 MiB extra will be used (...)` (`dnf5`).
+fn parse_transaction_size(text: &str) -> Vec<String> {
+    const PREFIXES: [&str; 6] = [
+        "Total size:",
+        "Total download size:",
+        "Installed size:",
+        "Freed space:",
+        "Total size of inbound packages",
+        "After this operation,",
+    ];
+    text.lines()
+        .map(str::trim)
+        .filter(|ln| PREFIXES.iter().any(|prefix| ln.starts_with(prefix)))
+        .map(String::from)
+        .collect()
+}
+
+/// Returns the command listing the installed packages requiring the package
+/// `kw`, for [`PmHelper::check_required_by`].
+fn whatrequires_cmd(kw: &str) -> Cmd {
+    Cmd::new(WHATREQUIRES_QUERY).kws([kw])
+}
+
+/// Parses the output of [`WHATREQUIRES_QUERY`] or [`UNNEEDED_QUERY`] into
+/// package names.
+fn parse_names(text: &str) -> Vec<String> {
+    (text.lines())
+        .map(str::trim)
+        .filter(|ln| !ln.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// Splits a module spec such as `nodejs:18/development` into the name and the
+/// stream of the module, if the latter is given.
+fn split_stream(spec: &str) -> Option<(&str, &str)> {
+    let (name, rest) = spec.split_once(':')?;
+    let stream = rest.split([':', '/']).next()?;
+    Some((name, stream))
+}
+
+/// Parses the output of `dnf module list --enabled` into the names and the
+/// enabled streams of the modules.
+///
+/// A module is listed as `name stream [d][e] profiles summary`, where the
+/// markers after the stream are only kept if it is enabled.
+fn parse_enabled_streams(text: &str) -> Vec<(String, String)> {
+    text.lines()
+        .filter_map(|ln| {
+            let mut fields = ln.split_whitespace();
+            let (name, stream, markers) = (fields.next()?, fields.next()?, fields.next()?);
+            let is_markers = markers.starts_with('[') && markers.ends_with(']');
+            (is_markers && markers.contains("[e]")).then(|| (name.into(), stream.into()))
+        })
+        .collect()
+}
+
+/// Parses the output of `dnf search` into [`Pkg`]s.
+///
+/// A package is listed as `name.arch : summary` (`dnf4`) or
+/// `name.arch\tsummary` (`dnf5`) under the headings of the matched fields, so
+/// its version is left empty.
+fn parse_search(text: &str) -> Vec<Pkg> {
+    text.lines()
+        .filter_map(|ln| {
+            let (name, arch) = ln.split_whitespace().next()?.rsplit_once('.')?;
+            let is_arch = |s: &str| {
+                !s.is_empty()
+                    && (s.chars()).all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+            };
+            (!name.is_empty() && is_arch(arch)).then(|| Pkg::new(name, "", "dnf"))
+        })
+        .collect()
+}
+
+/// Parses the output of [`EXTRAS_QUERY`] into [`Pkg`]s, which are all from
+/// `@System`, i.e. the installed packages only.
+fn parse_extras(text: &str) -> Vec<Pkg> {
+    pkg::parse_name_version(text, "@System")
+}
+
+/// Parses the output of `dnf` declining a transaction into the repositories
+/// and the file names of the packages to be downloaded.
+///
+/// A package is listed as `name arch [epoch:]version-release repo size`, where
+/// the packages from `@System` or `@commandline` are not downloaded at all.
+fn parse_planned_rpms(text: &str) -> Vec<(String, String)> {
+    text.lines()
+        .filter(|ln| ln.starts_with(' '))
+        .filter_map(|ln| {
+            let mut fields = ln.split_whitespace();
+            let (name, arch, evr, repo, size) = (
+                fields.next()?,
+                fields.next()?,
+                fields.next()?,
+                fields.next()?,
+                fields.next()?,
+            );
+            let vr = evr.split_once(':').map_or(evr, |(_, vr)| vr);
+            let is_row = vr.contains('-') && size.parse::<f64>().is_ok() && !repo.starts_with('@');
+            is_row.then(|| (repo.to_owned(), format!("{name}-{vr}.{arch}.rpm")))
+        })
+        .collect()
+}
+
+/// Returns the cache directories of `repo`, named `{repo}-{hash}` in the
+/// `roots` (e.g. [`CACHE_DIRS`]).
+fn repo_cache_dirs(roots: &[&str], repo: &str) -> Vec<PathBuf> {
+    roots
+        .iter()
+        .filter_map(|dir| Path::new(dir).read_dir().ok())
+        .flatten()
+        .filter_map(Result::ok)
+        .filter(|entry| {
+            let name = entry.file_name();
+            let hash = (name.to_str()).and_then(|name| name.strip_prefix(repo)?.strip_prefix('-'));
+            hash.is_some_and(|hash| hash.chars().all(|c| c.is_ascii_hexdigit()))
+        })
+        .map(|entry| entry.path())
+        .collect()
+}
+
+/// Returns the read-only command printing out the (possibly compressed)
+/// repository metadata file `path`, if its format is supported.
+fn decompress_cmd(path: &Path) -> Option<Cmd> {
+    let cmd: &[&str] = match path.extension()?.to_str()? {
+        "xml" => &["cat"],
+        "gz" => &["gzip", "-dc"],
+        "xz" => &["xz", "-dc"],
+        "zst" => &["zstd", "-dc"],
+        "bz2" => &["bzip2", "-dc"],
+        "zck" => &["unzck", "--stdout"],
+        _ => return None,
+    };
+    Some(Cmd::new(cmd).kws([path.display().to_string()]))
+}
+
+/// Returns the `planned` package files (see [`Dnf::planned_rpms`]) downloaded
+/// in the cache directories in `roots`, along with the checksums published in
+/// the `primary.xml` metadata of their repositories.
+///
+/// The files not found in the cache are kept by name, so that they are
+/// reported as missing.
+async fn cached_downloads(roots: &[&str], planned: Vec<(String, String)>) -> Result<Vec<Download>> {
+    let mut checksums = HashMap::new();
+    let mut cached = HashMap::new();
+    let repos = planned
+        .iter()
+        .map(|(repo, _)| repo.clone())
+        .unique()
+        .collect_vec();
+    for repo in repos {
+        let dirs = repo_cache_dirs(roots, &repo);
+        let metadata = (dirs.iter())
+            .filter_map(|dir| dir.join("repodata").read_dir().ok())
+            .flatten()
+            .filter_map(|entry| Some(entry.ok()?.path()))
+            .filter(|path| {
+                (path.file_name().and_then(|name| name.to_str()))
+                    .is_some_and(|name| name.contains("primary.xml"))
+            })
+            .sorted_unstable()
+            .collect_vec();
+        for path in metadata {
+            let Some(cmd) = decompress_cmd(&path) else {
+                continue;
+            };
+            let out = cmd.exec(Mode::Mute).await?;
+            checksums.extend(
+                parse_primary(&exec::decode(&out))
+                    .into_iter()
+                    .map(|(file, checksum)| ((repo.clone(), file), checksum)),
+            );
+        }
+        cached.insert(repo, dirs);
+    }
+    Ok(planned
+        .into_iter()
+        .map(|(repo, file)| {
+            let path = (cached[&repo].iter())
+                .map(|dir| dir.join("packages").join(&file))
+                .find(|path| path.is_file())
+                .map_or_else(|| file.clone(), |path| path.display().to_string());
+            let checksum = checksums.remove(&(repo, file)).flatten();
+            Download { path, checksum }
+        })
+        .collect())
+}
+
+/// Parses the `primary.xml` metadata of a repository into the file names of
+/// its packages, along with their checksums if these are supported (see
+/// [`checksum_cmd`]).
+fn parse_primary(text: &str) -> Vec<(String, Option<(&'static str, String)>)> {
+    static RE: LazyLock<Regex> = LazyLock::new(|| {
+        Regex::new(
+            r#"(?s)<checksum\s+type="([^"]+)"[^>]*>\s*([0-9A-Fa-f]+)\s*</checksum>.*?<location\b[^>]*?\bhref="([^"]+)""#,
+        )
+        .unwrap()
+    });
+    RE.captures_iter(text)
+        .map(|caps| {
+            let file = caps[3].rsplit('/').next().unwrap_or_default().to_owned();
+            let checksum = checksum_cmd(&caps[1]).map(|sum| (sum, caps[2].to_lowercase()));
+            (file, checksum)
+        })
+        .collect()
+}
+
+/// Returns the key files in `dir` in order, or nothing if `dir` cannot be read.
+fn key_files(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = dir.read_dir() else {
+        return vec![];
+    };
+    let mut keys = entries
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|path| path.is_file())
+        .collect::<Vec<_>>();
+    keys.sort_unstable();
+    keys
+}
+
+/// Parses the error of `dnf` failing to acquire a lock with
+/// `--setopt=exit_on_lock=True`, e.g. `Error: metadata already locked by 1234`,
+/// into a description of the lock.
+///
+/// The metadata lock is taken while refreshing the repositories, and is kept
+/// apart from the `RPMDB` lock taken for the whole transaction.
+fn parse_busy_lock(text: &str) -> Option<String> {
+    static LOCKED: LazyLock<Regex> = LazyLock::new(|| {
+        Regex::new(r"\b(metadata|cachedir|RPMDB) already locked by (\d+)")
+            .expect("pattern should be valid")
+    });
+    let caps = LOCKED.captures(text)?;
+    let lock = match &caps[1] {
+        "metadata" => "the metadata lock",
+        "cachedir" => "the download cache lock",
+        _ => "the transaction (RPMDB) lock",
+    };
+    Some(format!("{lock} held by PID {}", &caps[2]))
+}
+
+/// Checks if the output of a failed installation reports dependency
+/// conflicts, i.e. any problem found by the resolver.
+fn is_conflict(text: &str) -> bool {
+    text.lines()
+        .any(|ln| ln.trim_start().starts_with("Problem"))
+}
+
+/// Parses the problems reported by the resolver in the output of
+/// [`Dnf::explain_cmd`] into [`Conflict`]s.
+///
+/// Each problem is listed as `Problem N: description`, followed by the causes
+/// as `- description`. Only the descriptions naming both a package and its
+/// dependency are taken into account.
+fn parse_problems(text: &str) -> Vec<Conflict> {
+    /// The descriptions of problems, in the form of `(pattern, relation,
+    /// detail)`, where `pattern` captures the package, the dependency, and the
+    /// provider of the dependency where applicable.
+    static PROBLEMS: LazyLock<[(Regex, &str, &str); 3]> = LazyLock::new(|| {
+        let re = |pattern| Regex::new(pattern).expect("pattern should be valid");
+        [
+            (
+                re(
+                    r"^package (?<pkg>\S+)(?: from \S+)? requires (?<target>.+), but none of the providers can be installed",
+                ),
+                "requires",
+                "none of the providers can be installed",
+            ),
+            (
+                re(r"^nothing provides (?<target>.+) needed by (?<pkg>\S+)"),
+                "requires",
+                "nothing provides it",
+            ),
+            (
+                re(
+                    r"^package (?<pkg>\S+)(?: from \S+)? conflicts with (?<target>.+) provided by (?<provider>\S+)",
+                ),
+                "conflicts with",
+                "provided by",
+            ),
+        ]
+    });
+
+    text.lines()
+        .filter_map(|ln| {
+            let ln = ln.trim_start();
+            let desc = ln.strip_prefix("- ").or_else(|| {
+                let (head, desc) = ln.split_once(": ")?;
+                head.starts_with("Problem").then_some(desc)
+            })?;
+            PROBLEMS.iter().find_map(|(re, relation, detail)| {
+                let caps = re.captures(desc)?;
+                let detail = caps.name("provider").map_or_else(
+                    || (*detail).to_owned(),
+                    |provider| format!("{detail} {}", provider.as_str()),
+                );
+                Some(Conflict::new(&caps["pkg"], *relation, &caps["target"]).detail(detail))
+            })
+        })
+        .unique()
+        .collect()
+}
+
+/// Returns the commands listing and verifying the files of the given packages,
+/// or of all installed ones if none is given, for [`PmHelper::check_files`].
+pub(super) fn rpm_check_cmds(kws: &[&str], flags: &[&str]) -> [Cmd; 2] {
+    let (query, verify) = if kws.is_empty() {
+        ("-qa", "-Va")
+    } else {
+        ("-q", "-V")
+    };
+    [
+        Cmd::new(["rpm", query, "--qf", RPM_FILES_FORMAT]).kws(kws),
+        Cmd::new(["rpm", verify]).kws(kws).flags(flags),
+    ]
+}
+
+/// Parses the output of the commands of [`rpm_check_cmds`] into
+/// [`FileCheck`]s.
+///
+/// The files are listed as `name\tpath` by [`RPM_FILES_FORMAT`], and the
+/// altered ones are parsed by [`parse_rpm_verify`].
+pub(super) fn parse_rpm_file_checks(files: &str, verify: &str) -> Vec<FileCheck> {
+    let files = files.lines().filter_map(|ln| ln.split_once('\t'));
+    FileCheck::group(files, &parse_rpm_verify(verify))
+}
+
+/// Parses the output of `rpm -V` into [`FileChange`]s.
+///
+/// Each changed file is listed as `attrs [kind] path`, where `attrs` is either
+/// a string like `S.5....T.` or `missing`, and the optional `kind` is `c` for
+/// config files. Lines not ending with an absolute path are skipped.
+///
+/// This is also the format of `dpkg --verify`.
+pub(super) fn parse_rpm_verify(text: &str) -> Vec<FileChange> {
+    text.lines()
+        .filter_map(|ln| {
+            let (attrs, rest) = ln.trim_start().split_once(' ')?;
+            let (kind, path) = match rest.trim_start() {
+                path if path.starts_with('/') => ("", path),
+                rest => rest.split_once(' ')?,
+            };
+            let path = path.trim_start();
+            path.starts_with('/').then(|| FileChange {
+                path: path.into(),
+                attrs: attrs.into(),
+                config: kind == "c",
+            })
+        })
+        .collect()
+}
+
+/// Parses the output of `dnf list --upgrades` into [`Update`]s, with the old
+/// versions looked up in the `installed` packages.
+///
+/// An update is listed as `name.arch new repo`, where the columns after
+/// `name.arch` are wrapped onto the next line if it is too long.
+fn parse_upgrades(text: &str, installed: &[Pkg]) -> Vec<Result<Update, String>> {
+    let installed: HashMap<_, _> = (installed.iter())
+        .map(|pkg| (pkg.name.as_str(), pkg.version.as_str()))
+        .collect();
+    // An entry never starts with whitespace, so such a line must be wrapped.
+    pkg::parse_update_lines(
+        &text.replace("\n ", " "),
+        |ln| ln.starts_with("Available ") || ln.starts_with("Last metadata"),
+        |ln| {
+            let &[name_arch, new, _repo] = &ln.split_whitespace().collect::<Vec<_>>()[..] else {
+                return None;
+            };
+            let (name, _arch) = name_arch.rsplit_once('.')?;
+            Some(Update::new(name, *installed.get(name)?, new))
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, process};
+
+    use super::*;
+    use crate::{
+        config::SearchSort,
+        pkg::{
+            check_golden, check_golden_conflicts, check_golden_file_checks, check_golden_updates,
+        },
+        pm::{
+            mock::{capture_output, MockLog},
+            search_golden, NETWORK_METHODS,
+        },
+    };
+
+    #[test]
+    fn porcelain_golden() {
+        check_golden("rpm-qa", parse_rpm);
+        check_golden("dnf-extras", parse_extras);
+        check_golden("dnf-search", parse_search);
+    }
+
+    #[test]
+    fn sizes() {
+        assert_eq!(
+            parse_installed_sizes("3351765\tcurl\n(none)\tgpg-pubkey\n"),
+            [("curl".to_owned(), 3_351_765)]
+        );
+        let dnf4 = indoc! {"
+            Transaction Summary
+            ================================================================================
+            Install  1 Package
+
+            Total download size: 1.3 M
+            Installed size: 4.4 M
+            Operation aborted.
+        "};
+        assert_eq!(
+            parse_transaction_size(dnf4),
+            ["Total download size: 1.3 M", "Installed size: 4.4 M"]
+        );
+        let dnf5 = indoc! {"
+            Transaction Summary:
+             Installing:         1 package
+
+            Total size of inbound packages is 1 MiB. Need to download 1 MiB.
+            After this operation, 4 MiB extra will be used (install 4 MiB, remove 0 B).
+            Operation aborted by the user.
+        "};
+        assert_eq!(
+            parse_transaction_size(dnf5),
+            [
+                "Total size of inbound packages is 1 MiB. Need to download 1 MiB.",
+                "After this operation, 4 MiB extra will be used (install 4 MiB, remove 0 B).",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn ss_sort_limit() {
+        let dnf = Dnf::new(Config {
+            search_limit: Some(3),
+            search_sort: Some(SearchSort::Name),
+            ..Config::default()
+        });
+        let names = search_golden(&dnf, "dnf search", "dnf-search").await;
+        assert_eq!(names, ["python3-wget", "wget", "wget2"]);
+
+        let golden =
+            Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden/porcelain/dnf-search.in");
+        let log = MockLog::new().output("dnf search", fs::read(golden).unwrap());
+        let (res, out) = capture_output(log.scope(dnf.ss(&["wget"], &[]))).await;
+        res.unwrap();
+        assert!(out.contains("showing first 3 of 5 results"), "{out}");
+    }
+
+    #[test]
+    fn updates_golden() {
+        check_golden_updates("dnf-list-upgrades", |text| {
+            let installed = [
+                Pkg::new("bash", "5.2.26-3.fc40", "rpm"),
+                Pkg::new("curl", "8.6.0-8.fc40", "rpm"),
+                Pkg::new("python3-setuptools-wheel", "69.0.3-3.fc40", "rpm"),
+            ];
+            parse_upgrades(text, &installed)
+        });
+    }
+
+    #[test]
+    fn conflicts_golden() {
+        check_golden_conflicts("dnf-install", parse_problems);
+        assert!(is_conflict("Error: \n Problem: conflicting requests\n"));
+    }
+
+    #[tokio::test]
+    async fn verify_failed() {
+        let dnf = Dnf::new(Config {
+            quiet: Some(true),
+            ..Config::default()
+        });
+        let log = MockLog::new()
+            .output("rpm -q", "curl\t8.6.0-8.fc40\n")
+            .output("dnf repoquery", "8.6.0-8.fc40\n");
+        let (res, _) = capture_output(log.scope(dnf.verify(&["curl"], &[]))).await;
+        res.unwrap();
+
+        let log = MockLog::new()
+            .output("rpm -q", "curl\t8.6.0-8.fc40\n")
+            .fail_with("rpm -V", 1, "S.5....T.  c /etc/curlrc\n")
+            .output("dnf repoquery", "8.6.0-8.fc40\n");
+        let (res, out) = capture_output(log.scope(dnf.verify(&["curl"], &[]))).await;
+        assert!(out.contains("/etc/curlrc"), "{out}");
+        let err = res.unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("some of the packages have failed the verification"));
+    }
+
+    #[test]
+    fn rpm_verify() {
+        let text = indoc! {"
+            S.5....T.  c /etc/ssh/sshd_config
+            missing     /usr/share/doc/openssh/README
+            ..?......    /usr/libexec/openssh/ssh-keysign
+            Unsatisfied dependencies for openssh-server-9.6p1-1.fc40.x86_64:
+        "};
+        let change = |path: &str, attrs: &str, config| FileChange {
+            path: path.into(),
+            attrs: attrs.into(),
+            config,
+        };
+        assert_eq!(
+            parse_rpm_verify(text),
+            [
+                change("/etc/ssh/sshd_config", "S.5....T.", true),
+                change("/usr/share/doc/openssh/README", "missing", false),
+                change("/usr/libexec/openssh/ssh-keysign", "..?......", false),
+            ]
+        );
+    }
+
+    #[test]
+    fn file_checks_golden() {
+        check_golden_file_checks("rpm", parse_rpm_file_checks);
+    }
+
+    #[test]
+    fn proxy_flags() {
+        let dnf = Dnf::new(Config {
+            http_proxy: Some("http://proxy:3128".into()),
+            https_proxy: Some("http://proxy:3129".into()),
+            ..Config::default()
+        });
+        assert_eq!(
+            dnf.net_flags(&["-y"]),
+            ["--setopt=proxy=http://proxy:3129", "-y"]
+        );
+        assert_eq!(Dnf::new(Config::default()).net_flags(&["-y"]), ["-y"]);
+    }
+
+    #[test]
+    fn busy_locks() {
+        let dnf = Dnf::new(Config {
+            max_retries_on_lock: Some(3),
+            ..Config::default()
+        });
+        assert_eq!(dnf.net_flags(&[]), ["--setopt=exit_on_lock=True"]);
+
+        let locked = |code, text: &str| Error::CmdStatusCodeError {
+            code,
+            output: text.as_bytes().to_vec(),
+            cmd: "dnf makecache".into(),
+            mode: Mode::CheckErr { quiet: false },
+        };
+        assert_eq!(
+            dnf.busy_lock(&locked(200, "Error: metadata already locked by 1234\n"))
+                .as_deref(),
+            Some("the metadata lock held by PID 1234")
+        );
+        assert_eq!(
+            dnf.busy_lock(&locked(200, "Error: RPMDB already locked by 42\n"))
+                .as_deref(),
+            Some("the transaction (RPMDB) lock held by PID 42")
+        );
+        assert_eq!(
+            dnf.busy_lock(&locked(1, "Error: metadata already locked by 1234\n")),
+            None
+        );
+        assert_eq!(dnf.busy_lock(&locked(200, "Error: no space left\n")), None);
+    }
+
+    #[tokio::test]
+    async fn downgrade() {
+        let dnf = Dnf::new(Config {
+            no_confirm: true,
+            ..Config::default()
+        });
+        let log = MockLog::new();
+        (log.scope(dnf.downgrade(&["curl=7.81.0", "vim"], &[])).await).unwrap();
+        assert_eq!(log.lines(), ["dnf downgrade -y curl-7.81.0 vim"]);
+    }
+
+    #[tokio::test]
+    async fn rs_required_by() {
+        let dnf = Dnf::new(Config {
+            check_required_by: true,
+            no_confirm: true,
+            ..Config::default()
+        });
+        let log = MockLog::new().output("dnf repoquery -q --installed", "git\ncurl\n");
+        (log.scope(dnf.rs(&["curl"], &[])).await).unwrap();
+        assert_eq!(
+            log.lines(),
+            [
+                r"dnf repoquery -q --installed --qf %{name}\n --whatrequires curl",
+                "dnf remove --setopt=clean_requirements_on_remove=True -y curl",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn autoremove_interactive() {
+        let dnf = Dnf::new(Config {
+            no_confirm: true,
+            ..Config::default()
+        });
+        let log = MockLog::new()
+            .output("dnf repoquery -q --unneeded", "libfoo\nlibbar\n")
+            .output("dnf repoquery -q --installed", "3351765\tlibfoo\n");
+        (log.scope(dnf.autoremove_interactive(&[], &[])).await).unwrap();
+        assert_eq!(
+            log.lines(),
+            [
+                r"dnf repoquery -q --unneeded --qf %{name}\n",
+                r"dnf repoquery -q --installed --qf %{installsize}\t%{name}\n libfoo libbar",
+                "dnf remove -y libfoo libbar",
+            ]
+        );
+
+        let log = MockLog::new().output("dnf repoquery -q --unneeded", "");
+        (log.scope(dnf.autoremove_interactive(&[], &[])).await).unwrap();
+        assert_eq!(log.lines(), [r"dnf repoquery -q --unneeded --qf %{name}\n"]);
+    }
+
+    #[tokio::test]
+    async fn allow_downgrade() {
+        for (allow_downgrade, expected) in [
+            (false, "dnf upgrade -y curl"),
+            (true, "dnf distro-sync -y curl"),
+        ] {
+            let dnf = Dnf::new(Config {
+                no_confirm: true,
+                allow_downgrade,
+                ..Config::default()
+            });
+            let log = MockLog::new();
+            (log.scope(dnf.suy(&["curl"], &[])).await).unwrap();
+            assert_eq!(log.lines(), [expected]);
+        }
+    }
+
+    #[tokio::test]
+    async fn repo_flags() {
+        let dnf = Dnf::new(Config {
+            no_confirm: true,
+            target_release: Some("updates-testing".into()),
+            enable_repo: vec!["copr:copr.fedorainfracloud.org:atim:lazygit".into()],
+            disable_repo: vec!["fedora-cisco-openh264".into()],
+            ..Config::default()
+        });
+        let log = MockLog::new();
+        (log.scope(dnf.s(&["lazygit"], &[])).await).unwrap();
+        (log.scope(dnf.ss(&["lazygit"], &[])).await).unwrap();
+        let repos = "--enablerepo=updates-testing \
+                     --enablerepo=copr:copr.fedorainfracloud.org:atim:lazygit \
+                     --disablerepo=fedora-cisco-openh264";
+        assert_eq!(
+            log.lines(),
+            [
+                format!("dnf install {repos} -y lazygit"),
+                format!("dnf search {repos} lazygit"),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn offline() {
+        let dnf = Dnf::new(Config {
+            no_confirm: true,
+            offline: true,
+            ..Config::default()
+        });
+        let methods = dnf.offline_methods();
+        assert!(methods
+            .iter()
+            .all(|method| NETWORK_METHODS.contains(method)));
+        let log = MockLog::new();
+        (log.scope(dnf.s(&["lazygit"], &[])).await).unwrap();
+        (log.scope(dnf.ss(&["lazygit"], &[])).await).unwrap();
+        assert_eq!(
+            log.lines(),
+            [
+                "dnf install --cacheonly -y lazygit",
+                "dnf search --cacheonly lazygit",
+            ]
+        );
+    }
+
+    #[test]
+    fn checksums() {
+        let plan = indoc! {"
+            Dependencies resolved.
+            ================================================================================
+             Package             Architecture   Version                 Repository     Size
+            ================================================================================
+            Installing:
+             lazygit             x86_64         0.40.2-1.fc39           updates       4.2 M
+            Installing dependencies:
+             git-core            x86_64         2:2.43.0-1.fc39         fedora-cisco  4.5 M
+             local-tool          noarch         1.0-1                   @commandline   12 k
+            Transaction Summary
+            ================================================================================
+            Install  3 Packages
+        "};
+        assert_eq!(
+            parse_planned_rpms(plan),
+            [
+                ("updates".into(), "lazygit-0.40.2-1.fc39.x86_64.rpm".into()),
+                (
+                    "fedora-cisco".into(),
+                    "git-core-2.43.0-1.fc39.x86_64.rpm".into()
+                ),
+            ]
+        );
+        let primary = indoc! {r#"
+            <package type="rpm">
+              <name>lazygit</name>
+              <checksum type="sha256" pkgid="YES">0123ABCD</checksum>
+              <location href="Packages/l/lazygit-0.40.2-1.fc39.x86_64.rpm"/>
+            </package>
+            <package type="rpm">
+              <name>git-core</name>
+              <checksum type="whirlpool" pkgid="YES">4567</checksum>
+              <location href="Packages/g/git-core-2.43.0-1.fc39.x86_64.rpm"/>
+            </package>
+        "#};
+        assert_eq!(
+            parse_primary(primary),
+            [
+                (
+                    "lazygit-0.40.2-1.fc39.x86_64.rpm".into(),
+                    Some(("sha256sum", "0123abcd".into()))
+                ),
+                ("git-core-2.43.0-1.fc39.x86_64.rpm".into(), None),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn cached_downloads_checksums() {
+        let root = env::temp_dir().join(format!("pacaptr-dnf-cache-{}", process::id()));
+        let repo = root.join("updates-0123456789abcdef");
+        fs::create_dir_all(repo.join("repodata")).unwrap();
+        fs::create_dir_all(repo.join("packages")).unwrap();
+        let metadata = repo.join("repodata").join("f00-primary.xml.gz");
+        fs::write(&metadata, "").unwrap();
+        let rpm = repo
+            .join("packages")
+            .join("lazygit-0.40.2-1.fc39.x86_64.rpm");
+        fs::write(&rpm, "").unwrap();
+
+        let log = MockLog::new().output(
+            "gzip -dc",
+            indoc! {r#"
+                <package type="rpm">
+                  <checksum type="sha256" pkgid="YES">0123abcd</checksum>
+                  <location href="Packages/l/lazygit-0.40.2-1.fc39.x86_64.rpm"/>
+                </package>
+            "#},
+        );
+        let planned = vec![
+            ("updates".into(), "lazygit-0.40.2-1.fc39.x86_64.rpm".into()),
+            ("updates".into(), "vim-9.0-1.fc39.x86_64.rpm".into()),
+        ];
+        let roots = [root.to_str().unwrap()];
+        let downloads = (log.scope(cached_downloads(&roots, planned)).await).unwrap();
+        fs::remove_dir_all(&root).unwrap();
+        assert_eq!(log.lines(), [format!("gzip -dc {}", metadata.display())]);
+        assert_eq!(
+            downloads,
+            [
+                Download {
+                    path: rpm.display().to_string(),
+                    checksum: Some(("sha256sum", "0123abcd".into())),
+                },
+                Download {
+                    path: "vim-9.0-1.fc39.x86_64.rpm".into(),
+                    checksum: None,
+                },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn module_streams() {
+        let list = indoc! {"
+            Fedora Modular 38 - x86_64
+            Name        Stream      Profiles                    Summary
+            nodejs      18 [d][e]   common [d], development     Javascript runtime
+            postgresql  16 [e]      client, server [d]          PostgreSQL server and client module
+
+            Hint: [d]efault, [e]nabled, [x]disabled, [i]nstalled
+        "};
+        assert_eq!(
+            parse_enabled_streams(list),
+            [
+                ("nodejs".into(), "18".into()),
+                ("postgresql".into(), "16".into()),
+            ]
+        );
+        assert_eq!(
+            split_stream("nodejs:20/development"),
+            Some(("nodejs", "20"))
+        );
+        assert_eq!(split_stream("nodejs"), None);
+
+        let dnf = Dnf::new(Config {
+            no_confirm: true,
+            ..Config::default()
+        });
+        let log = MockLog::new().output("dnf module list", list);
+        let kws = ["nodejs:20/development", "postgresql:16", "ruby"];
+        log.scope(dnf.module_install(&kws, &[])).await.unwrap();
+        assert_eq!(
+            log.lines(),
+            [
+                "dnf module list --enabled -q nodejs postgresql",
+                "dnf module reset -y nodejs",
+                "dnf module install -y nodejs:20/development postgresql:16 ruby",
+            ]
+        );
+    }
 }
@@ -7,8 +7,13 @@ use indoc::indoc;
 use itertools::Itertools;
 use tap::prelude::*;
 
-use super::{NoCacheStrategy, Pm, PmHelper, PromptStrategy, Strategy};
-use crate::{config::Config, error::Result, exec::Cmd};
+use super::{CmdSeq, NoCacheStrategy, Pm, PmHelper, PromptStrategy, Strategy, WeakModifier};
+use crate::{
+    config::Config,
+    error::Result,
+    exec::{self, Cmd, Mode},
+    print::{self, prompt},
+};
 
 macro_rules! doc_self {
     () => {
@@ -47,6 +52,73 @@ impl Emerge {
     pub const fn new(cfg: Config) -> Self {
         Self { cfg }
     }
+
+    /// Returns the given packages except the ones that `emerge` would only
+    /// rebuild at their installed versions, reporting those that are skipped.
+    ///
+    /// Rebuilding a package from source can take a long time, so this is only
+    /// done with [`Config::force`]. Nothing is checked in a dry run.
+    async fn outdated<'a>(&self, kws: &[&'a str]) -> Vec<&'a str> {
+        if self.cfg.force || self.cfg.dry_run {
+            return kws.to_vec();
+        }
+        let mut outdated = vec![];
+        for &kw in kws {
+            let pretend = Cmd::new([
+                "emerge",
+                "--pretend",
+                "--quiet",
+                "--nodeps",
+                "--color=n",
+                kw,
+            ]);
+            // A failed query is left for the installation itself to report.
+            let out = pretend.exec(Mode::Mute).await.unwrap_or_default();
+            match parse_rebuilds(&exec::decode(&out)) {
+                Some(atoms) => print::println(
+                    &*prompt::INFO,
+                    format_args!(
+                        "skipping `{kw}`, which is up to date ({}); use `--force` to rebuild it",
+                        atoms.join(", ")
+                    ),
+                ),
+                None => outdated.push(kw),
+            }
+        }
+        outdated
+    }
+}
+
+/// Returns the packages listed in the output of `emerge --pretend`, if all of
+/// them would only be rebuilt at their installed versions (marked with `R`)
+/// and with the same USE flags.
+///
+/// A rebuild is not a mere reinstallation if it also comes with a new, a
+/// downgraded or an upgraded version (`N`, `S`, `D` or `U`), or with USE
+/// flags that have changed (marked with `*`) or been added or removed (marked
+/// with `%`) since the last build.
+fn parse_rebuilds(text: &str) -> Option<Vec<&str>> {
+    let mut atoms = vec![];
+    for line in text.lines() {
+        let Some((status, rest)) = line.strip_prefix('[').and_then(|ln| ln.split_once(']')) else {
+            continue;
+        };
+        let mut flags = status.split_whitespace();
+        if !matches!(flags.next(), Some("ebuild" | "binary")) {
+            continue;
+        }
+        let flags = flags.collect::<String>();
+        if !flags.contains('R') || flags.contains(['N', 'S', 'D', 'U']) {
+            return None;
+        }
+        let mut words = rest.split_whitespace();
+        let atom = words.next()?;
+        if words.any(|word| word.contains(['*', '%'])) {
+            return None;
+        }
+        atoms.push(atom.split_once("::").map_or(atom, |(atom, _repo)| atom));
+    }
+    (!atoms.is_empty()).then_some(atoms)
 }
 
 #[async_trait]
@@ -116,8 +188,12 @@ impl Pm for Emerge {
 
     /// S installs one or more packages by name.
     async fn s(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
+        let outdated = self.outdated(kws).await;
+        if outdated.is_empty() && !kws.is_empty() {
+            return Ok(());
+        }
         Cmd::with_sudo(["emerge"])
-            .kws(kws)
+            .kws(outdated)
             .flags(flags)
             .pipe(|cmd| self.run_with(cmd, self.default_mode(), &STRAT_INSTALL))
             .await
@@ -135,6 +211,11 @@ impl Pm for Emerge {
 
     /// Scc removes all files from the cache.
     async fn scc(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
+        self.warn_weak_modifier(WeakModifier {
+            op: "scc",
+            flag: "-cc",
+            actual: "only the source files are cleaned, just like with `-Sc`",
+        });
         self.sc(kws, flags).await
     }
 
@@ -163,8 +244,19 @@ impl Pm for Emerge {
     /// Suy refreshes the local package database, then updates outdated
     /// packages.
     async fn suy(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
-        self.sy(&[], flags).await?;
-        self.su(kws, flags).await
+        CmdSeq::new()
+            .then(
+                Cmd::with_sudo(["emerge", "--sync"]).flags(flags),
+                &Strategy::default(),
+            )
+            .then(
+                Cmd::with_sudo(["emerge", "-uDN"])
+                    .kws(if kws.is_empty() { &["@world"][..] } else { kws })
+                    .flags(flags),
+                &STRAT_INSTALL,
+            )
+            .pipe(|seq| self.run_seq(seq))
+            .await
     }
 
     /// Sy refreshes the local package database.
@@ -177,3 +269,88 @@ impl Pm for Emerge {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pm::mock::{capture_output, MockLog};
+
+    #[tokio::test]
+    async fn weak_modifiers() {
+        let pm = Emerge::new(Config {
+            dry_run: true,
+            ..Config::default()
+        });
+        let (res, out) = capture_output(MockLog::new().scope(pm.scc(&[], &[]))).await;
+        res.unwrap();
+        assert!(out.contains(
+            "`-cc` in `-Scc` is not fully supported by `emerge`: only the source files are cleaned, just like with `-Sc`"
+        ));
+        let (res, out) = capture_output(MockLog::new().scope(pm.sc(&[], &[]))).await;
+        res.unwrap();
+        assert!(!out.contains("not fully supported"));
+    }
+
+    #[test]
+    fn rebuilds() {
+        let rebuild = "[ebuild   R    ] app-shells/fish-3.7.1::gentoo  USE=\"doc\" 0 KiB";
+        assert_eq!(parse_rebuilds(rebuild), Some(vec!["app-shells/fish-3.7.1"]));
+        let upgrade = "[ebuild     U  ] sys-apps/ripgrep-14.1.1 [14.1.0]";
+        assert_eq!(parse_rebuilds(upgrade), None);
+        assert_eq!(parse_rebuilds(&format!("{rebuild}\n{upgrade}")), None);
+        let use_changes = [
+            "[ebuild   R    ] app-shells/fish-3.7.1::gentoo  USE=\"doc* -test\" 0 KiB",
+            "[ebuild   R    ] app-shells/fish-3.7.1::gentoo  USE=\"doc -nls%\" 0 KiB",
+            "[ebuild   R    ] dev-lang/python-3.12.3 PYTHON_TARGETS=\"python3_13%*\" 0 KiB",
+            "[ebuild     U  ] app-shells/fish-3.7.1 [3.7.0] USE=\"doc%*\" 0 KiB",
+        ];
+        for line in use_changes {
+            assert_eq!(parse_rebuilds(line), None, "{line}");
+        }
+        assert_eq!(
+            parse_rebuilds("emerge: there are no ebuilds to satisfy \"foo\"."),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn s_skips_up_to_date() {
+        let emerge = |force| {
+            Emerge::new(Config {
+                no_confirm: true,
+                quiet: Some(true),
+                force,
+                ..Config::default()
+            })
+        };
+        let pretend = "emerge --pretend --quiet --nodeps --color=n";
+        let log = MockLog::new()
+            .output(
+                format!("{pretend} fish"),
+                "[ebuild   R    ] app-shells/fish-3.7.1::gentoo\n",
+            )
+            .output(
+                format!("{pretend} ripgrep"),
+                "[ebuild  N     ] sys-apps/ripgrep-14.1.1::gentoo\n",
+            );
+        log.scope(emerge(false).s(&["fish", "ripgrep"], &[]))
+            .await
+            .unwrap();
+        assert_eq!(
+            log.lines(),
+            [
+                format!("{pretend} fish"),
+                format!("{pretend} ripgrep"),
+                "emerge ripgrep".into(),
+            ]
+        );
+
+        let log = MockLog::new().output(pretend, "[ebuild   R    ] app-shells/fish-3.7.1\n");
+        log.scope(emerge(false).s(&["fish"], &[])).await.unwrap();
+        assert_eq!(log.lines(), [format!("{pretend} fish")]);
+
+        let log = MockLog::new().output(pretend, "[ebuild   R    ] app-shells/fish-3.7.1\n");
+        log.scope(emerge(true).s(&["fish"], &[])).await.unwrap();
+        assert_eq!(log.lines(), ["emerge fish"]);
+    }
+}
@@ -0,0 +1,207 @@
+//! The serialization of the commands changing the system, per package manager.
+//!
+//! Some package managers, e.g. `brew` and `conda`, corrupt their state or fail
+//! confusingly when two instances change it at once, which is all the easier
+//! to trigger with several operations of [`pacaptr`](crate) running at the same
+//! time. The commands tagged as changing the system (see
+//! [`Strategy::is_mutating`](super::Strategy::is_mutating)) thus hold a
+//! [`Guard`] of their package manager while running: an async mutex keyed by
+//! its name within the current process, along with a lock on a file keyed the
+//! same way across processes (on Unix only). The read-only commands run
+//! concurrently as usual.
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{Arc, LazyLock, Mutex, PoisonError},
+};
+
+use tokio::sync::{Mutex as AsyncMutex, OwnedMutexGuard};
+
+use crate::print::{self, prompt};
+
+/// The async mutexes of the package managers, keyed by their names.
+static LOCKS: LazyLock<Mutex<HashMap<String, Arc<AsyncMutex<()>>>>> = LazyLock::new(Mutex::default);
+
+/// The lock on a file shared with the other processes, released on drop.
+#[cfg(unix)]
+type FileLock = nix::fcntl::Flock<std::fs::File>;
+
+/// The lock on a file shared with the other processes, which is unsupported.
+#[cfg(not(unix))]
+type FileLock = ();
+
+/// The exclusive right of running a command changing the system with a
+/// package manager, released on drop.
+#[must_use]
+#[derive(Debug)]
+pub struct Guard {
+    /// The guard of the mutex within the current process.
+    _local: OwnedMutexGuard<()>,
+
+    /// The lock on the file shared with the other processes, if it could be
+    /// taken.
+    _file: Option<FileLock>,
+}
+
+/// Returns the directory of the lock files of all the package managers.
+fn lock_root() -> Option<PathBuf> {
+    Some(dirs_next::cache_dir()?.join("pacaptr").join("locks"))
+}
+
+/// Returns the mutex of the package manager `pm` within the current process.
+fn local_lock(pm: &str) -> Arc<AsyncMutex<()>> {
+    let mut locks = LOCKS.lock().unwrap_or_else(PoisonError::into_inner);
+    Arc::clone(locks.entry(pm.to_owned()).or_default())
+}
+
+/// Prints out that the command of `pm` is waiting for the one run by
+/// `holder`.
+fn println_waiting(pm: &str, holder: &str) {
+    print::println(
+        &*prompt::INFO,
+        format_args!("waiting for {holder} changing the system with `{pm}` to finish"),
+    );
+}
+
+/// Locks the file of the package manager `pm` under `root`, waiting for any
+/// other process holding it.
+///
+/// Failing to open the file is ignored, as the package manager might still
+/// deal with its own concurrency.
+#[cfg(unix)]
+async fn lock_file(root: &Path, pm: &str) -> Option<FileLock> {
+    use std::fs::{self, File};
+
+    use nix::{errno::Errno, fcntl::FlockArg};
+
+    fs::create_dir_all(root).ok()?;
+    let file = File::create(root.join(format!("{pm}.lock"))).ok()?;
+    let file = match nix::fcntl::Flock::lock(file, FlockArg::LockExclusiveNonblock) {
+        Ok(lock) => return Some(lock),
+        Err((file, Errno::EWOULDBLOCK)) => file,
+        Err(_) => return None,
+    };
+    println_waiting(pm, "another `pacaptr`");
+    tokio::task::spawn_blocking(|| nix::fcntl::Flock::lock(file, FlockArg::LockExclusive).ok())
+        .await
+        .ok()
+        .flatten()
+}
+
+/// Locks the file of the package manager `pm` under `root`, which is
+/// unsupported.
+#[cfg(not(unix))]
+#[allow(clippy::unused_async)]
+async fn lock_file(_root: &Path, _pm: &str) -> Option<FileLock> {
+    None
+}
+
+/// Acquires the [`Guard`] of the package manager `pm`, with its lock file
+/// under `root` (if any).
+async fn acquire_in(root: Option<&Path>, pm: &str) -> Guard {
+    let local = local_lock(pm);
+    let local = if let Ok(guard) = Arc::clone(&local).try_lock_owned() {
+        guard
+    } else {
+        println_waiting(pm, "another operation");
+        local.lock_owned().await
+    };
+    let file = match root {
+        Some(root) => lock_file(root, pm).await,
+        None => None,
+    };
+    Guard {
+        _local: local,
+        _file: file,
+    }
+}
+
+/// Acquires the [`Guard`] of the package manager `pm`, waiting for any other
+/// command of `pm` changing the system to finish first, be it run in the
+/// current process or in another one.
+///
+/// Within [`MockLog::scope`](super::mock::MockLog::scope), no lock file is
+/// taken, as the commands are not actually run.
+pub async fn acquire(pm: &str) -> Guard {
+    #[cfg(any(test, feature = "mock"))]
+    if super::mock::is_active() {
+        return acquire_in(None, pm).await;
+    }
+    acquire_in(lock_root().as_deref(), pm).await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, process, time::Duration};
+
+    use super::*;
+    use crate::{
+        config::Config,
+        exec::Cmd,
+        pm::{
+            mock::{MockLog, MockPm},
+            Pm, PmHelper, PmMode, Strategy,
+        },
+    };
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn serialized() {
+        let root = env::temp_dir().join(format!("pacaptr-guard-{}", process::id()));
+        let events = Arc::new(Mutex::new(vec![]));
+        let op = |name: &'static str| {
+            let (root, events) = (root.clone(), Arc::clone(&events));
+            tokio::spawn(async move {
+                let _guard = acquire_in(Some(&root), "guard-test").await;
+                events.lock().unwrap().push(format!("{name} started"));
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                events.lock().unwrap().push(format!("{name} done"));
+            })
+        };
+        let (first, second) = tokio::join!(op("first"), op("second"));
+        first.unwrap();
+        second.unwrap();
+        // Each operation should be done before the other one has started.
+        let events = events.lock().unwrap().clone();
+        assert_eq!(events.len(), 4);
+        assert!(
+            (events.chunks(2)).all(|pair| pair[0].replace("started", "done") == pair[1]),
+            "the operations have overlapped: {events:?}"
+        );
+        assert!(root.join("guard-test.lock").is_file() || cfg!(not(unix)));
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn mutating_ops_wait() {
+        let log = MockLog::new();
+        let op = |kw: &'static str| {
+            let log = log.clone();
+            tokio::spawn(async move {
+                let pm = MockPm::new(Config {
+                    no_confirm: true,
+                    ..Config::default()
+                });
+                log.scope(pm.s(&[kw], &[])).await
+            })
+        };
+        // Another operation of `mock` is changing the system in the meantime.
+        let guard = log.scope(acquire("mock")).await;
+        let (first, second) = (op("foo"), op("bar"));
+        let pm = MockPm::new(Config::default());
+        let query = Cmd::new(["mock", "q"]);
+        (log.scope(pm.check_output(query, PmMode::Mute, &Strategy::default()))
+            .await)
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(log.lines(), ["mock q"]);
+
+        drop(guard);
+        first.await.unwrap().unwrap();
+        second.await.unwrap().unwrap();
+        let lines = log.lines();
+        assert_eq!(lines.len(), 3);
+        assert!(lines.contains(&"mock s --yes foo".to_owned()));
+        assert!(lines.contains(&"mock s --yes bar".to_owned()));
+    }
+}
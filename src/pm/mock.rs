@@ -0,0 +1,284 @@
+//! A mock package manager for testing the dispatch logic of
+//! [`pacaptr`](crate) without any real package manager installed.
+//!
+//! Instead of being executed, every [`Cmd`] run within [`MockLog::scope`] is
+//! recorded in the [`MockLog`], and its output is the one scripted with
+//! [`MockLog::output`] or [`MockLog::fail`] (if any). This applies to the
+//! commands of any [`Pm`], not only the ones of [`MockPm`].
+
+use std::{
+    future::Future,
+    sync::{Arc, Mutex, PoisonError},
+};
+
+use async_trait::async_trait;
+use itertools::chain;
+use tt_call::tt_call;
+
+use super::{Pm, PmHelper, PromptStrategy, Strategy};
+use crate::{
+    config::Config,
+    error::Result,
+    exec::{
+        context::{self, Console, Context, OutputSink, Stream},
+        Cmd, Output, StatusCode,
+    },
+};
+
+tokio::task_local! {
+    /// The [`MockLog`] of the current task, if any.
+    static LOG: MockLog;
+}
+
+/// The log of the [`Cmd`]s that would have been run within
+/// [`MockLog::scope`].
+#[must_use]
+#[derive(Debug, Clone, Default)]
+pub struct MockLog(Arc<Mutex<MockLogInner>>);
+
+#[derive(Debug, Default)]
+struct MockLogInner {
+    /// The commands recorded so far, in order.
+    cmds: Vec<Cmd>,
+
+    /// The scripted outputs, in the form of `(prefix, output, code)`.
+    outputs: Vec<(String, Output, StatusCode)>,
+}
+
+impl MockLog {
+    /// Makes a new, empty [`MockLog`] instance.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Scripts the output of the commands starting with `prefix`, e.g.
+    /// `dpkg-query -W`.
+    ///
+    /// When more than one prefix matches, the first one scripted wins.
+    /// Commands matching no prefix have an empty output.
+    pub fn output(self, prefix: impl Into<String>, output: impl Into<Output>) -> Self {
+        self.lock().outputs.push((prefix.into(), output.into(), 0));
+        self
+    }
+
+    /// Scripts the commands starting with `prefix` to fail with the exit
+    /// `code` and an empty output, just like [`MockLog::output`] otherwise.
+    pub fn fail(self, prefix: impl Into<String>, code: StatusCode) -> Self {
+        self.lock()
+            .outputs
+            .push((prefix.into(), Output::new(), code));
+        self
+    }
+
+    /// Scripts the commands starting with `prefix` to fail with the exit
+    /// `code` and the given `output`, just like [`MockLog::output`] otherwise.
+    pub fn fail_with(
+        self,
+        prefix: impl Into<String>,
+        code: StatusCode,
+        output: impl Into<Output>,
+    ) -> Self {
+        self.lock()
+            .outputs
+            .push((prefix.into(), output.into(), code));
+        self
+    }
+
+    /// Runs `fut` with every [`Cmd`] recorded in this log instead of being
+    /// executed.
+    pub async fn scope<F: Future>(&self, fut: F) -> F::Output {
+        LOG.scope(self.clone(), fut).await
+    }
+
+    /// Returns the [`Cmd`]s recorded so far, in order.
+    #[must_use]
+    pub fn cmds(&self) -> Vec<Cmd> {
+        self.lock().cmds.clone()
+    }
+
+    /// Returns the [`Cmd`]s recorded so far as command lines (without
+    /// `sudo`), in order.
+    #[must_use]
+    pub fn lines(&self) -> Vec<String> {
+        self.lock().cmds.iter().map(line).collect()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, MockLogInner> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Records `cmd` and returns its scripted output and exit code.
+    fn record(&self, cmd: &Cmd) -> (Output, StatusCode) {
+        let mut inner = self.lock();
+        inner.cmds.push(cmd.clone());
+        let line = line(cmd);
+        (inner.outputs.iter())
+            .find(|(prefix, ..)| line.starts_with(prefix.as_str()))
+            .map(|(_, output, code)| (output.clone(), *code))
+            .unwrap_or_default()
+    }
+}
+
+/// Returns the command line of `cmd` (without `sudo`), with the words
+/// separated by spaces.
+fn line(cmd: &Cmd) -> String {
+    itertools::join(chain!(&cmd.cmd, &cmd.flags, &cmd.kws), " ")
+}
+
+/// Records `cmd` in the [`MockLog`] of the current task if there is one,
+/// returning its scripted output and exit code, in which case `cmd` should not
+/// be executed.
+pub(crate) fn intercept(cmd: &Cmd) -> Option<(Output, StatusCode)> {
+    LOG.try_with(|log| log.record(cmd)).ok()
+}
+
+/// An [`OutputSink`] keeping the text printed out by [`pacaptr`](crate) itself
+/// without styles, for [`capture_output`].
+#[derive(Debug, Default)]
+struct TextSink(Mutex<String>);
+
+impl OutputSink for TextSink {
+    fn print(&self, text: &str) {
+        let mut buf = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        buf.push_str(&console::strip_ansi_codes(text));
+    }
+
+    fn cmd_output(&self, _stream: Stream, _chunk: &[u8]) {}
+}
+
+/// Runs `fut`, returning its output along with the text printed out by
+/// [`pacaptr`](crate) itself in the meantime, e.g. the warnings.
+///
+/// The text is stripped of styles, and its whitespace is collapsed into
+/// single spaces, as the messages might be wrapped.
+pub async fn capture_output<F: Future>(fut: F) -> (F::Output, String) {
+    let sink = Arc::new(TextSink::default());
+    let ctx = Context::new(Arc::clone(&sink) as _, Arc::new(Console));
+    let res = context::scope(ctx, fut).await;
+    let text = sink.0.lock().unwrap_or_else(PoisonError::into_inner);
+    (res, itertools::join(text.split_whitespace(), " "))
+}
+
+/// Checks if the current task is within [`MockLog::scope`], in which case the
+/// [`Cmd`]s are recorded instead of being executed.
+pub(crate) fn is_active() -> bool {
+    LOG.try_with(|_| ()).is_ok()
+}
+
+/// A mock package manager, whose operations simply run `mock <method>` with
+/// the keywords and the flags given.
+///
+/// The commands are meant to be recorded in a [`MockLog`] rather than
+/// executed. The `--yes` flag is added when the questions should be answered
+/// with yes.
+#[derive(Debug)]
+pub struct MockPm {
+    cfg: Config,
+}
+
+impl MockPm {
+    #[must_use]
+    #[allow(missing_docs)]
+    pub const fn new(cfg: Config) -> Self {
+        Self { cfg }
+    }
+
+    /// Runs `mock <method>` with the keywords and the flags given.
+    async fn run_op(&self, method: &str, kws: &[&str], flags: &[&str]) -> Result<()> {
+        let strat = Strategy {
+            prompt: PromptStrategy::native_no_confirm(["--yes"]),
+            ..Strategy::default()
+        };
+        let cmd = Cmd::new(["mock", method]).kws(kws).flags(flags);
+        self.run_with(cmd, self.default_mode(), &strat).await
+    }
+}
+
+macro_rules! impl_pm_mock {(
+    methods = [{ $(
+        $( #[$meta:meta] )*
+        async fn $method:ident;
+    )* }]
+) => {
+    #[async_trait]
+    impl Pm for MockPm {
+        /// Gets the name of the package manager.
+        fn name(&self) -> &'static str {
+            "mock"
+        }
+
+        fn cfg(&self) -> &Config {
+            &self.cfg
+        }
+
+        fn supports_undo(&self) -> bool {
+            true
+        }
+
+        async fn autoremove(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
+            self.run_op("autoremove", kws, flags).await
+        }
+
+        async fn autoremove_interactive(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
+            self.run_op("autoremove_interactive", kws, flags).await
+        }
+
+        async fn repair(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
+            self.run_op("repair", kws, flags).await
+        }
+
+        async fn verify(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
+            self.run_op("verify", kws, flags).await
+        }
+
+        async fn refresh_keys(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
+            self.run_op("refresh_keys", kws, flags).await
+        }
+
+        async fn downgrade(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
+            self.run_op("downgrade", kws, flags).await
+        }
+
+        async fn undo(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
+            self.run_op("undo", kws, flags).await
+        }
+
+        async fn module_list(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
+            self.run_op("module_list", kws, flags).await
+        }
+
+        async fn module_enable(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
+            self.run_op("module_enable", kws, flags).await
+        }
+
+        async fn module_install(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
+            self.run_op("module_install", kws, flags).await
+        }
+
+        async fn module_reset(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
+            self.run_op("module_reset", kws, flags).await
+        }
+
+        async fn service_list(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
+            self.run_op("service_list", kws, flags).await
+        }
+
+        async fn service_add(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
+            self.run_op("service_add", kws, flags).await
+        }
+
+        async fn service_remove(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
+            self.run_op("service_remove", kws, flags).await
+        }
+
+        // * Automatically generated methods below... *
+        $( async fn $method(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
+            self.run_op(stringify!($method), kws, flags).await
+        } )*
+    }
+};}
+
+tt_call! {
+    macro = [{ methods }]
+    ~~> impl_pm_mock
+}
@@ -1,16 +1,20 @@
 #![doc = doc_self!()]
 
-use std::sync::LazyLock;
+use std::{env, future::Future, sync::LazyLock};
 
 use async_trait::async_trait;
 use indoc::indoc;
+use itertools::{chain, Itertools};
+use regex::Regex;
 use tap::prelude::*;
 
-use super::{Pm, PmHelper, PromptStrategy, Strategy};
+use super::{ensure_unstructured, Pm, PmHelper, PromptStrategy, Strategy};
 use crate::{
-    config::Config,
+    config::{Config, PipStrategy},
     error::{Error, Result},
-    exec::Cmd,
+    exec::{self, Cmd, Mode},
+    pkg::{self, Conflict, DepGraph, Pkg, Update},
+    print::{self, prompt},
 };
 
 macro_rules! doc_self {
@@ -53,6 +57,102 @@ impl Pip {
             .as_deref()
             .expect("default package manager should have been assigned before initialization")
     }
+
+    /// Returns the given `flags` preceded by the `--target` flag to be used on
+    /// installation, if a directory has been specified with `--install-into`.
+    fn target_flags(&self, flags: &[&str]) -> Vec<String> {
+        let target = (self.cfg.install_into.iter())
+            .flat_map(|dir| ["--target".into(), dir.to_string_lossy().into_owned()]);
+        chain!(target, flags.iter().map(|&flag| flag.to_owned())).collect()
+    }
+
+    /// Checks if the installation might run into an externally managed
+    /// environment (PEP 668), i.e. outside a virtualenv and without
+    /// `--install-into`.
+    fn outside_virtualenv(&self) -> bool {
+        self.cfg.install_into.is_none() && env::var_os("VIRTUAL_ENV").is_none()
+    }
+
+    /// Returns the given `flags` to be used on installation, preceded by the
+    /// flag of [`Config::pip_strategy`] outside a virtualenv.
+    fn install_flags(&self, flags: &[&str]) -> Vec<String> {
+        let strategy = match self.cfg.pip_strategy {
+            Some(PipStrategy::User) => Some("--user"),
+            Some(PipStrategy::BreakSystemPackages) => Some("--break-system-packages"),
+            Some(PipStrategy::Fail) | None => None,
+        };
+        let strategy = strategy.filter(|_| self.outside_virtualenv());
+        chain!(strategy.map(String::from), self.target_flags(flags)).collect()
+    }
+
+    /// Runs the installation `cmd` with `strat`.
+    ///
+    /// If it fails as the environment is externally managed and no
+    /// [`Config::pip_strategy`] has been set, it is retried with `--user`
+    /// after a prompt.
+    async fn install(&self, mut cmd: Cmd, strat: &Strategy) -> Result<()> {
+        match self.run_with(cmd.clone(), self.default_mode(), strat).await {
+            Err(e)
+                if self.cfg.pip_strategy.is_none()
+                    && self.outside_virtualenv()
+                    && is_externally_managed(&e) => {}
+            res => return res,
+        }
+        print::println(
+            &*prompt::WARNING,
+            "the Python environment is externally managed (PEP 668), retrying with `--user`",
+        );
+        cmd.flags.insert(0, "--user".into());
+        self.run_with(cmd, self.default_mode(), &STRAT_PROMPT).await
+    }
+
+    /// Prints out the dependency trees of the given installed packages, as
+    /// walked by [`walk_deps`] over the output of `pip show`.
+    ///
+    /// Unlike [`PmHelper::print_dep_tree`], which parses the output of a
+    /// single command, `pip show` is run again for each level of the trees.
+    async fn print_walked_dep_tree(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
+        ensure_unstructured(self)?;
+        if self.cfg.dry_run {
+            Cmd::new([self.cmd(), "show"])
+                .kws(kws)
+                .flags(flags)
+                .exec(Mode::PrintCmd)
+                .await?;
+            return Ok(());
+        }
+        let show = |names: Vec<String>| {
+            let cmd = Cmd::new([self.cmd(), "show"]).kws(names).flags(flags);
+            async move {
+                // `pip show` fails if any of the packages is missing, but
+                // still shows the other ones.
+                match cmd.exec(Mode::Mute).await {
+                    Ok(out) | Err(Error::CmdStatusCodeError { output: out, .. }) => {
+                        Ok(exec::decode(&out).into_owned())
+                    }
+                    Err(e) => Err(e),
+                }
+            }
+        };
+        let deps = walk_deps(kws, show).await?;
+        let roots = kws.iter().map(|kw| normalize_name(kw)).collect_vec();
+        print::println_dep_tree(&roots, &deps);
+        Ok(())
+    }
+}
+
+/// The error reported by `pip` when installing packages into an externally
+/// managed environment (PEP 668).
+const EXTERNALLY_MANAGED: &str = "externally-managed-environment";
+
+/// Checks if `err` is a failure of `pip install` in an externally managed
+/// environment.
+fn is_externally_managed(err: &Error) -> bool {
+    matches!(
+        err.cmd_failure(),
+        Some(Error::CmdStatusCodeError { output, .. })
+            if exec::decode(output).contains(EXTERNALLY_MANAGED)
+    )
 }
 
 #[async_trait]
@@ -66,9 +166,17 @@ impl Pm for Pip {
         &self.cfg
     }
 
+    fn supports_install_into(&self) -> bool {
+        true
+    }
+
+    fn dep_tree_methods(&self) -> &[&'static str] {
+        &["qi"]
+    }
+
     /// Q generates a list of installed packages.
     async fn q(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
-        if kws.is_empty() {
+        if kws.is_empty() && !self.cfg.query_format().is_structured() {
             self.run(Cmd::new([self.cmd(), "list"]).flags(flags)).await
         } else {
             self.qs(kws, flags).await
@@ -77,6 +185,9 @@ impl Pm for Pip {
 
     /// Qi displays local package information: name, version, description, etc.
     async fn qi(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
+        if self.cfg.tree {
+            return self.print_walked_dep_tree(kws, flags).await;
+        }
         self.run(Cmd::new([self.cmd(), "show"]).kws(kws).flags(flags))
             .await
     }
@@ -86,16 +197,21 @@ impl Pm for Pip {
     // when including multiple search terms, only packages with descriptions
     // matching ALL of those terms are returned.
     async fn qs(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
-        self.search_regex(Cmd::new([self.cmd(), "list"]).flags(flags), kws)
+        if self.cfg.query_format().is_structured() {
+            return Cmd::new([self.cmd(), "list", "--format=freeze"])
+                .flags(flags)
+                .pipe(|cmd| self.print_pkgs(cmd, kws, parse_freeze))
+                .await;
+        }
+        self.search_regex_cached(Cmd::new([self.cmd(), "list"]).flags(flags), kws)
             .await
     }
 
     /// Qu lists packages which have an update available.
     async fn qu(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
         Cmd::new([self.cmd(), "list", "--outdated"])
-            .kws(kws)
             .flags(flags)
-            .pipe(|cmd| self.run(cmd))
+            .pipe(|cmd| self.print_updates(cmd, kws, parse_outdated))
             .await
     }
 
@@ -110,10 +226,15 @@ impl Pm for Pip {
 
     /// S installs one or more packages by name.
     async fn s(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
+        Cmd::new([self.cmd(), "install", "--dry-run"])
+            .kws(kws)
+            .flags(self.install_flags(flags))
+            .pipe(|cmd| self.check_deps(cmd, parse_conflicts))
+            .await?;
         Cmd::new([self.cmd(), "install"])
             .kws(kws)
-            .flags(flags)
-            .pipe(|cmd| self.run_with(cmd, self.default_mode(), &STRAT_PROMPT))
+            .flags(self.install_flags(flags))
+            .pipe(|cmd| self.install(cmd, &STRAT_PROMPT))
             .await
     }
 
@@ -132,11 +253,10 @@ impl Pm for Pip {
                 pm: self.name().into(),
             });
         }
-        Cmd::new([self.cmd(), "install", "--upgrade"])
+        let cmd = Cmd::new([self.cmd(), "install", "--upgrade"])
             .kws(kws)
-            .flags(flags)
-            .pipe(|cmd| self.run(cmd))
-            .await
+            .flags(self.install_flags(flags));
+        self.install(cmd, &Strategy::default()).await
     }
 
     /// Sw retrieves all packages from the server, but does not install/upgrade
@@ -149,3 +269,376 @@ impl Pm for Pip {
             .await
     }
 }
+
+/// Normalizes the name of a Python package as per PEP 503, e.g. `Jinja2` into
+/// `jinja2`, so that the names given in requirements can be matched.
+fn normalize_name(name: &str) -> String {
+    name.to_lowercase().replace(['_', '.'], "-")
+}
+
+/// Parses the output of `pip show` into a [`DepGraph`] with the normalized
+/// names of the packages.
+///
+/// Each package is shown as a list of fields separated by `---`, among which
+/// `Name: name` and `Requires: dep, ...`.
+fn parse_show(text: &str) -> DepGraph {
+    let mut deps = DepGraph::new();
+    let mut name = None;
+    for ln in text.lines() {
+        if let Some(val) = ln.strip_prefix("Name:") {
+            name = Some(normalize_name(val.trim()));
+        } else if let Some(val) = ln.strip_prefix("Requires:") {
+            let Some(name) = name.take() else {
+                continue;
+            };
+            let reqs = (val.split(','))
+                .map(str::trim)
+                .filter(|req| !req.is_empty())
+                .map(normalize_name)
+                .collect();
+            deps.insert(name, reqs);
+        }
+    }
+    deps
+}
+
+/// Walks the dependency graph of the packages `roots` level by level, where
+/// `show` returns the output of `pip show` for a batch of package names.
+///
+/// Each package is only shown once, so that the walk ends even if the graph
+/// has cycles. A package missing from the output of `show`, e.g. one that is
+/// not installed, is taken as having no dependencies.
+async fn walk_deps<F, Fut>(roots: &[&str], mut show: F) -> Result<DepGraph>
+where
+    F: FnMut(Vec<String>) -> Fut + Send,
+    Fut: Future<Output = Result<String>> + Send,
+{
+    let mut deps = DepGraph::new();
+    let mut batch = roots
+        .iter()
+        .map(|root| normalize_name(root))
+        .unique()
+        .collect_vec();
+    while !batch.is_empty() {
+        let mut shown = parse_show(&show(batch.clone()).await?);
+        for name in batch {
+            let reqs = shown.remove(&name).unwrap_or_default();
+            deps.insert(name, reqs);
+        }
+        batch = (deps.values().flatten())
+            .filter(|dep| !deps.contains_key(*dep))
+            .unique()
+            .cloned()
+            .collect();
+    }
+    Ok(deps)
+}
+
+/// Parses the output of `pip list --format=freeze` into [`Pkg`]s.
+///
+/// A package is listed as `name==version`, and any other line (e.g. an
+/// editable install) is skipped.
+fn parse_freeze(text: &str) -> Vec<Pkg> {
+    text.lines()
+        .filter_map(|ln| {
+            let (name, version) = ln.trim().split_once("==")?;
+            Some(Pkg::new(name, version, "pip"))
+        })
+        .collect()
+}
+
+/// Parses the output of `pip list --outdated` into [`Update`]s.
+///
+/// An update is listed as `name old new [type]` after a two-line header.
+fn parse_outdated(text: &str) -> Vec<Result<Update, String>> {
+    pkg::parse_update_lines(
+        text,
+        |ln| ln.starts_with("Package ") || ln.chars().all(|c| c == '-' || c == ' '),
+        |ln| {
+            let mut cols = ln.split_whitespace();
+            Some(Update::new(cols.next()?, cols.next()?, cols.next()?))
+        },
+    )
+}
+
+/// Parses the dependency conflicts reported by `pip install` into
+/// [`Conflict`]s.
+///
+/// The conflicts preventing the resolution are listed below `The conflict is
+/// caused by:` as `name version depends on target` or `The user requested
+/// target`, while the ones with the packages already installed are listed as
+/// `name version requires target, but you have found which is incompatible.`
+/// (or `..., which is not installed.`).
+fn parse_conflicts(text: &str) -> Vec<Conflict> {
+    static CAUSE: LazyLock<Regex> = LazyLock::new(|| {
+        Regex::new(r"^\s+(?:(?<pkg>\S+ \S+) depends on|The user requested) (?<target>.+)$")
+            .expect("pattern should be valid")
+    });
+    static INSTALLED: LazyLock<Regex> = LazyLock::new(|| {
+        Regex::new(
+            r"^(?<pkg>\S+ \S+) requires (?<target>.+?), (?:but you have (?<found>.+) which is incompatible|which is not installed)\.$",
+        )
+        .expect("pattern should be valid")
+    });
+
+    text.lines()
+        .filter_map(|ln| {
+            if let Some(caps) = CAUSE.captures(ln) {
+                let target = &caps["target"];
+                return Some(caps.name("pkg").map_or_else(
+                    || Conflict::new("the user", "requested", target),
+                    |pkg| Conflict::new(pkg.as_str(), "depends on", target),
+                ));
+            }
+            let caps = INSTALLED.captures(ln)?;
+            let detail = caps.name("found").map_or_else(
+                || "not installed".to_owned(),
+                |found| format!("found {}", found.as_str()),
+            );
+            Some(Conflict::new(&caps["pkg"], "requires", &caps["target"]).detail(detail))
+        })
+        .unique()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs, future,
+        path::Path,
+        sync::{Arc, Mutex},
+    };
+
+    use super::*;
+    use crate::{
+        error::Error,
+        exec::Mode,
+        pkg::{check_golden, check_golden_conflicts, check_golden_updates},
+        pm::mock::MockLog,
+        print::{self, OutputFormat, PkgSink},
+    };
+
+    #[test]
+    fn porcelain_golden() {
+        check_golden("pip-freeze", parse_freeze);
+    }
+
+    #[test]
+    fn updates_golden() {
+        check_golden_updates("pip-list-outdated", parse_outdated);
+    }
+
+    #[test]
+    fn conflicts_golden() {
+        check_golden_conflicts("pip-install", parse_conflicts);
+    }
+
+    #[tokio::test]
+    async fn check_deps() {
+        let pip = Pip::new(Config {
+            default_pm: Some("pip".into()),
+            check_deps: true,
+            no_confirm: true,
+            ..Config::default()
+        });
+        let conflict = "    requests 2.31.0 depends on urllib3<3 and >=1.21.1\n";
+        let log = MockLog::new().output("pip install --dry-run", conflict);
+        let res = log.scope(pip.s(&["requests"], &[])).await;
+        assert!(matches!(res, Err(Error::DepConflictError { count: 1 })));
+        assert_eq!(log.lines(), ["pip install --dry-run requests"]);
+
+        let log = MockLog::new();
+        log.scope(pip.s(&["requests"], &[])).await.unwrap();
+        assert_eq!(
+            log.lines(),
+            ["pip install --dry-run requests", "pip install requests"]
+        );
+    }
+
+    #[test]
+    fn target_flags() {
+        let pip = Pip::new(Config {
+            install_into: Some("/opt/py".into()),
+            ..Config::default()
+        });
+        assert_eq!(pip.target_flags(&["-q"]), ["--target", "/opt/py", "-q"]);
+        assert_eq!(Pip::new(Config::default()).target_flags(&["-q"]), ["-q"]);
+    }
+
+    /// Returns the captured output of `pip install` in an externally managed
+    /// environment.
+    fn externally_managed_output() -> String {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/golden/result/pip-externally-managed.txt");
+        fs::read_to_string(path).unwrap()
+    }
+
+    #[test]
+    fn externally_managed() {
+        let failure = |output: String| Error::CmdStatusCodeError {
+            code: 1,
+            output: output.into_bytes(),
+            cmd: "pip install requests".into(),
+            mode: Mode::Prompt,
+        };
+        assert!(is_externally_managed(&failure(externally_managed_output())));
+        assert!(!is_externally_managed(&failure(
+            "ERROR: No matching distribution found for no-such-package\n".into()
+        )));
+        assert!(!is_externally_managed(&Error::CmdInterruptedError));
+    }
+
+    #[tokio::test]
+    async fn user_fallback() {
+        let pip = |pip_strategy| {
+            Pip::new(Config {
+                default_pm: Some("pip".into()),
+                no_confirm: true,
+                pip_strategy,
+                ..Config::default()
+            })
+        };
+        let log =
+            || MockLog::new().fail_with("pip install requests", 1, externally_managed_output());
+
+        let log1 = log();
+        log1.scope(pip(None).s(&["requests"], &[])).await.unwrap();
+        assert_eq!(
+            log1.lines(),
+            ["pip install requests", "pip install --user requests"]
+        );
+
+        let log1 = log();
+        let res = log1
+            .scope(pip(Some(PipStrategy::Fail)).s(&["requests"], &[]))
+            .await;
+        assert!(matches!(
+            res,
+            Err(Error::CmdStatusCodeError { code: 1, .. })
+        ));
+        assert_eq!(log1.lines(), ["pip install requests"]);
+
+        for (strategy, flag) in [
+            (PipStrategy::User, "--user"),
+            (PipStrategy::BreakSystemPackages, "--break-system-packages"),
+        ] {
+            let log1 = log();
+            log1.scope(pip(Some(strategy)).su(&["requests"], &[]))
+                .await
+                .unwrap();
+            assert_eq!(
+                log1.lines(),
+                [format!("pip install --upgrade {flag} requests")]
+            );
+        }
+
+        // Neither applies to an installation into a custom directory.
+        let pip = Pip::new(Config {
+            install_into: Some("/opt/py".into()),
+            ..pip(Some(PipStrategy::User)).cfg
+        });
+        assert_eq!(pip.install_flags(&[]), ["--target", "/opt/py"]);
+    }
+
+    /// Mimics `pip show` over the synthetic dependency graph `graph`,
+    /// recording each batch of names in `batches`.
+    fn mock_show<'a>(
+        graph: &'a [(&'a str, &'a [&'a str])],
+        batches: &'a Mutex<Vec<Vec<String>>>,
+    ) -> impl FnMut(Vec<String>) -> future::Ready<Result<String>> + Send + 'a {
+        move |names| {
+            let out = (names.iter())
+                .filter_map(|name| graph.iter().find(|(pkg, _)| normalize_name(pkg) == *name))
+                .map(|(pkg, reqs)| {
+                    format!("Name: {pkg}\nVersion: 1.0\nRequires: {}\n", reqs.join(", "))
+                })
+                .join("---\n");
+            batches.lock().unwrap().push(names);
+            future::ready(Ok(out))
+        }
+    }
+
+    #[tokio::test]
+    async fn walk_dep_graph() {
+        let graph: &[(&str, &[&str])] = &[
+            (
+                "Flask",
+                &["Werkzeug", "Jinja2", "itsdangerous", "click", "blinker"],
+            ),
+            ("Werkzeug", &["MarkupSafe"]),
+            ("Jinja2", &["MarkupSafe"]),
+            ("MarkupSafe", &[]),
+            ("itsdangerous", &[]),
+            ("click", &[]),
+            ("blinker", &[]),
+        ];
+        let batches = Mutex::default();
+        let deps = walk_deps(&["flask"], mock_show(graph, &batches))
+            .await
+            .unwrap();
+        assert_eq!(
+            deps["flask"],
+            ["werkzeug", "jinja2", "itsdangerous", "click", "blinker"]
+        );
+        assert_eq!(deps["jinja2"], ["markupsafe"]);
+        assert_eq!(deps.len(), 7);
+        // Each level is shown at once, and each package only once.
+        let batches = batches.into_inner().unwrap();
+        assert_eq!(batches.len(), 3);
+        assert_eq!(batches[2], ["markupsafe"]);
+    }
+
+    #[tokio::test]
+    async fn walk_dep_cycles() {
+        let graph: &[(&str, &[&str])] = &[
+            ("a", &["b"]),
+            ("b", &["c", "a"]),
+            ("c", &["b", "not_installed"]),
+            ("self-dep", &["Self_Dep"]),
+        ];
+        let batches = Mutex::default();
+        let deps = walk_deps(&["a", "self-dep"], mock_show(graph, &batches))
+            .await
+            .unwrap();
+        assert_eq!(deps["b"], ["c", "a"]);
+        assert_eq!(deps["c"], ["b", "not-installed"]);
+        assert!(deps["not-installed"].is_empty());
+        assert_eq!(deps["self-dep"], ["self-dep"]);
+        assert_eq!(
+            batches.into_inner().unwrap(),
+            [
+                vec!["a", "self-dep"],
+                vec!["b"],
+                vec!["c"],
+                vec!["not-installed"],
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn qs_json_lines() {
+        let pip = Pip::new(Config {
+            default_pm: Some("pip".into()),
+            output_format: Some(OutputFormat::JsonLines),
+            ..Config::default()
+        });
+        let log = MockLog::new().output(
+            "pip list",
+            "requests==2.31.0\r\nrequests-toolbelt==1.0.0\nurllib3==2.2.1",
+        );
+        let sink = PkgSink::default();
+        let query = print::capture_pkgs(Arc::clone(&sink), pip.qs(&["^requests"], &[]));
+        log.scope(query).await.unwrap();
+        let pkgs = sink
+            .lock()
+            .unwrap()
+            .iter()
+            .map(ToString::to_string)
+            .collect_vec();
+        assert_eq!(
+            pkgs,
+            ["requests\t2.31.0\tpip", "requests-toolbelt\t1.0.0\tpip"]
+        );
+        assert_eq!(log.lines(), ["pip list --format=freeze"]);
+    }
+}
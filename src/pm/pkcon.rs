@@ -3,12 +3,11 @@
 use std::sync::LazyLock;
 
 use async_trait::async_trait;
-use futures::prelude::*;
 use indoc::indoc;
 use tap::prelude::*;
 
-use super::{Pm, PmHelper, PromptStrategy, Strategy};
-use crate::{config::Config, error::Result, exec::Cmd};
+use super::{for_each_kw, CmdSeq, Pm, PmHelper, PromptStrategy, Strategy};
+use crate::{config::Config, error::Result, exec::Cmd, print::Progress};
 
 macro_rules! doc_self {
     () => {
@@ -116,29 +115,29 @@ impl Pm for Pkcon {
 
     /// R removes a single package, leaving all of its dependencies installed.
     async fn r(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
-        stream::iter(kws)
-            .map(Ok)
-            .try_for_each(|kw| {
-                Cmd::with_sudo(["pkcon", "remove"])
-                    .kws([kw])
-                    .flags(flags)
-                    .pipe(|cmd| self.run_with(cmd, self.default_mode(), &STRAT_PROMPT))
-            })
-            .await
+        let progress = Progress::new(kws.len(), &self.cfg);
+        for_each_kw(&self.cfg, kws, |kw| {
+            progress.step(kw);
+            Cmd::with_sudo(["pkcon", "remove"])
+                .kws([kw])
+                .flags(flags)
+                .pipe(|cmd| self.run_with(cmd, self.default_mode(), &STRAT_PROMPT))
+        })
+        .await
     }
 
     /// Rs removes a package and its dependencies which are not required by any
     /// other installed package, and not explicitly installed by the user.
     async fn rs(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
-        stream::iter(kws)
-            .map(Ok)
-            .try_for_each(|kw| {
-                Cmd::with_sudo(["pkcon", "remove", "--autoremove"])
-                    .kws([kw])
-                    .flags(flags)
-                    .pipe(|cmd| self.run_with(cmd, self.default_mode(), &STRAT_PROMPT))
-            })
-            .await
+        let progress = Progress::new(kws.len(), &self.cfg);
+        for_each_kw(&self.cfg, kws, |kw| {
+            progress.step(kw);
+            Cmd::with_sudo(["pkcon", "remove", "--autoremove"])
+                .kws([kw])
+                .flags(flags)
+                .pipe(|cmd| self.run_with(cmd, self.default_mode(), &STRAT_PROMPT))
+        })
+        .await
     }
 
     /// S installs one or more packages by name.
@@ -186,8 +185,17 @@ impl Pm for Pkcon {
     /// Suy refreshes the local package database, then updates outdated
     /// packages.
     async fn suy(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
-        self.sy(&[], flags).await?;
-        self.su(kws, flags).await
+        CmdSeq::new()
+            .then(
+                Cmd::with_sudo(["pkcon", "refresh"]).flags(flags),
+                &Strategy::default(),
+            )
+            .then(
+                Cmd::with_sudo(["pkcon", "update"]).kws(kws).flags(flags),
+                &STRAT_PROMPT,
+            )
+            .pipe(|seq| self.run_seq(seq))
+            .await
     }
 
     /// Sw retrieves all packages from the server, but does not install/upgrade
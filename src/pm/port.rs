@@ -4,10 +4,13 @@ use std::sync::LazyLock;
 
 use async_trait::async_trait;
 use indoc::indoc;
+use itertools::chain;
 use tap::prelude::*;
 
-use super::{NoCacheStrategy, Pm, PmHelper, PromptStrategy, Strategy};
-use crate::{config::Config, error::Result, exec::Cmd};
+use super::{
+    build_flags, BuildOption, CmdSeq, NoCacheStrategy, Pm, PmHelper, PromptStrategy, Strategy,
+};
+use crate::{config::Config, error::Result, exec::Cmd, pkg::Pkg};
 
 macro_rules! doc_self {
     () => {
@@ -35,6 +38,13 @@ static STRAT_INSTALL: LazyLock<Strategy> = LazyLock::new(|| Strategy {
     ..Strategy::default()
 });
 
+/// Changes the system without prompting for confirmation, e.g. by refreshing
+/// the local package database.
+static STRAT_REFRESH: LazyLock<Strategy> = LazyLock::new(|| Strategy {
+    mutating: true,
+    ..Strategy::default()
+});
+
 impl Port {
     #[must_use]
     #[allow(missing_docs)]
@@ -54,10 +64,25 @@ impl Pm for Port {
         &self.cfg
     }
 
+    fn build_option_flags(&self) -> &[(BuildOption, &'static str)] {
+        &[(BuildOption::SourceBuild, "-s")]
+    }
+
+    fn supports_search_limit(&self) -> bool {
+        true
+    }
+
+    fn supports_search_sort(&self) -> bool {
+        true
+    }
+
     /// Q generates a list of installed packages.
     async fn q(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
-        self.run(Cmd::new(["port", "installed"]).kws(kws).flags(flags))
-            .await
+        let cmd = Cmd::new(["port", "installed"]).kws(kws).flags(flags);
+        if self.cfg.query_format().is_structured() {
+            return self.print_pkgs(cmd, &[], parse_installed).await;
+        }
+        self.run(cmd).await
     }
 
     /// Qc shows the changelog of a package.
@@ -119,7 +144,8 @@ impl Pm for Port {
 
     /// S installs one or more packages by name.
     async fn s(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
-        Cmd::with_sudo(["port", "install"])
+        // `-s` is a global option of `port`, which must precede the action.
+        Cmd::with_sudo(chain!(["port"], build_flags(self), ["install"]))
             .kws(kws)
             .flags(flags)
             .pipe(|cmd| self.run_with(cmd, self.default_mode(), &STRAT_INSTALL))
@@ -162,8 +188,14 @@ impl Pm for Port {
     /// Ss searches for package(s) by searching the expression in name,
     /// description, short description.
     async fn ss(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
-        self.run(Cmd::new(["port", "search"]).kws(kws).flags(flags))
-            .await
+        let cmd = Cmd::new(["port", "search"]).kws(kws).flags(flags);
+        if self.cfg.sorts_or_limits_search() {
+            return self.print_search(cmd, parse_search).await;
+        }
+        if self.cfg.query_format().is_structured() {
+            return self.print_pkgs(cmd, &[], parse_search).await;
+        }
+        self.run(cmd).await
     }
 
     /// Su updates outdated packages.
@@ -182,13 +214,30 @@ impl Pm for Port {
     /// Suy refreshes the local package database, then updates outdated
     /// packages.
     async fn suy(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
-        self.sy(&[], flags).await?;
-        self.su(kws, flags).await
+        CmdSeq::new()
+            .then(
+                Cmd::new(["port", "selfupdate"]).flags(flags),
+                &STRAT_REFRESH,
+            )
+            .then(
+                Cmd::with_sudo(if flags.is_empty() {
+                    &["port", "upgrade", "outdated"][..]
+                } else {
+                    &["port", "upgrade"][..]
+                })
+                .kws(kws)
+                .flags(flags),
+                &STRAT_INSTALL,
+            )
+            .pipe(|seq| self.run_seq(seq))
+            .await
     }
 
     /// Sy refreshes the local package database.
     async fn sy(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
-        self.run(Cmd::new(["port", "selfupdate"]).flags(flags))
+        Cmd::new(["port", "selfupdate"])
+            .flags(flags)
+            .pipe(|cmd| self.run_with(cmd, self.default_mode(), &STRAT_REFRESH))
             .await?;
         if !kws.is_empty() {
             self.s(kws, flags).await?;
@@ -196,3 +245,72 @@ impl Pm for Port {
         Ok(())
     }
 }
+
+/// Parses the output of `port installed` into [`Pkg`]s.
+///
+/// A package is listed as `  name @version (active)` under a heading, where
+/// the inactive versions are skipped.
+fn parse_installed(text: &str) -> Vec<Pkg> {
+    text.lines()
+        .filter(|ln| ln.starts_with(char::is_whitespace) && ln.ends_with("(active)"))
+        .filter_map(|ln| {
+            let (name, rest) = ln.trim().split_once(" @")?;
+            let version = rest.split_whitespace().next()?;
+            Some(Pkg::new(name, version, "port"))
+        })
+        .collect()
+}
+
+/// Parses the output of `port search` into [`Pkg`]s.
+///
+/// A package is listed as `name @version (categories)`, followed by its
+/// indented description.
+fn parse_search(text: &str) -> Vec<Pkg> {
+    text.lines()
+        .filter(|ln| !ln.starts_with(char::is_whitespace))
+        .filter_map(|ln| {
+            let (name, rest) = ln.split_once(" @")?;
+            let version = rest.split_whitespace().next()?;
+            Some(Pkg::new(name, version, "port"))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::SearchSort,
+        pkg::check_golden,
+        pm::{mock::MockLog, search_golden},
+    };
+
+    #[test]
+    fn porcelain_golden() {
+        check_golden("port-installed", parse_installed);
+        check_golden("port-search", parse_search);
+    }
+
+    #[tokio::test]
+    async fn ss_sort() {
+        let port = Port::new(Config {
+            search_sort: Some(SearchSort::Name),
+            ..Config::default()
+        });
+        let names = search_golden(&port, "port search", "port-search").await;
+        assert_eq!(names, ["ripgrep", "ripgrep-all", "ugrep"]);
+    }
+
+    #[tokio::test]
+    async fn source_build() {
+        let port = Port::new(Config {
+            no_confirm: true,
+            source_build: true,
+            ..Config::default()
+        });
+        let log = MockLog::new();
+        (log.scope(port.s(&["fish"], &[])).await).unwrap();
+        // `-s` is a global option, which must precede the action.
+        assert_eq!(log.lines(), ["port -s install fish"]);
+    }
+}
@@ -0,0 +1,120 @@
+//! A short-lived cache of the output of expensive read-only queries.
+//!
+//! Queries such as `conda list` are listed in full and then filtered by
+//! [`PmHelper::search_regex_cached`](super::PmHelper::search_regex_cached).
+//! Each output is saved in the cache directory under the name of the package
+//! manager and a hash of the command line, and reused until it is older than
+//! [`Config::query_cache_ttl`](crate::config::Config::query_cache_ttl). All the
+//! entries of a package manager are removed with [`invalidate`] after each
+//! operation that might change its installed packages.
+
+use std::{
+    collections::hash_map::DefaultHasher,
+    env, fs,
+    hash::{Hash, Hasher},
+    io,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use crate::exec::{Cmd, Output};
+
+/// The environment variables selecting the environment queried by the
+/// package managers, which are thus part of the key of each entry.
+const ENV_VARS: &[&str] = &["CONDA_PREFIX", "VIRTUAL_ENV"];
+
+/// Returns the directory of the cached queries of all the package managers.
+fn cache_root() -> Option<PathBuf> {
+    Some(dirs_next::cache_dir()?.join("pacaptr").join("queries"))
+}
+
+/// Returns the path of the entry of `cmd` run by the package manager `pm`
+/// under `root`.
+fn entry_path(root: &Path, pm: &str, cmd: &Cmd) -> PathBuf {
+    let mut hasher = DefaultHasher::new();
+    cmd.to_string().hash(&mut hasher);
+    for var in ENV_VARS {
+        env::var_os(var).hash(&mut hasher);
+    }
+    root.join(pm).join(format!("{:016x}", hasher.finish()))
+}
+
+/// Loads the output saved at `path`, unless it is older than `ttl` at `now`.
+fn load(path: &Path, ttl: Duration, now: SystemTime) -> Option<Output> {
+    let modified = fs::metadata(path).and_then(|meta| meta.modified()).ok()?;
+    // A timestamp in the future is treated as fresh.
+    if now.duration_since(modified).unwrap_or_default() >= ttl {
+        return None;
+    }
+    fs::read(path).ok()
+}
+
+/// Saves `out` at `path`.
+fn store(path: &Path, out: &[u8]) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, out)
+}
+
+/// Removes all the entries of the package manager `pm` under `root`.
+fn invalidate_in(root: &Path, pm: &str) {
+    _ = fs::remove_dir_all(root.join(pm));
+}
+
+/// Returns the cached output of `cmd` run by the package manager `pm`, if it
+/// has been saved within the last `ttl`.
+#[must_use]
+pub fn get(pm: &str, cmd: &Cmd, ttl: Duration) -> Option<Output> {
+    load(&entry_path(&cache_root()?, pm, cmd), ttl, SystemTime::now())
+}
+
+/// Saves the output `out` of `cmd` run by the package manager `pm`.
+///
+/// Failing to save it is ignored, as it would only be run again next time.
+pub fn put(pm: &str, cmd: &Cmd, out: &[u8]) {
+    if let Some(root) = cache_root() {
+        _ = store(&entry_path(&root, pm, cmd), out);
+    }
+}
+
+/// Removes all the cached outputs of the package manager `pm`.
+pub fn invalidate(pm: &str) {
+    if let Some(root) = cache_root() {
+        invalidate_in(&root, pm);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process;
+
+    use super::*;
+
+    #[test]
+    fn hit_expire_invalidate() {
+        let root = env::temp_dir().join(format!("pacaptr-query-cache-{}", process::id()));
+        let ttl = Duration::from_secs(30);
+        let list = Cmd::new(["conda", "list"]);
+        let path = entry_path(&root, "conda", &list);
+        assert_eq!(path, entry_path(&root, "conda", &list));
+        assert_ne!(path, entry_path(&root, "pip", &list));
+        assert_ne!(
+            path,
+            entry_path(&root, "conda", &Cmd::new(["conda", "list", "-n", "base"]))
+        );
+
+        assert_eq!(load(&path, ttl, SystemTime::now()), None);
+        store(&path, b"numpy 1.26.4").unwrap();
+        let now = SystemTime::now();
+        assert_eq!(load(&path, ttl, now), Some(b"numpy 1.26.4".to_vec()));
+        assert_eq!(load(&path, ttl, now + ttl), None);
+        assert_eq!(load(&path, Duration::ZERO, now), None);
+
+        invalidate_in(&root, "pip");
+        assert!(load(&path, ttl, now).is_some());
+        invalidate_in(&root, "conda");
+        assert_eq!(load(&path, ttl, now), None);
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
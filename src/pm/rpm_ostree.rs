@@ -0,0 +1,224 @@
+#![doc = doc_self!()]
+
+use std::sync::LazyLock;
+
+use async_trait::async_trait;
+use indoc::indoc;
+use itertools::chain;
+use tap::prelude::*;
+
+use super::{
+    dnf::{parse_rpm, RPM_QUERY},
+    DryRunStrategy, Pm, PmHelper, PromptStrategy, Strategy,
+};
+use crate::{
+    config::Config,
+    error::Result,
+    exec::{self, Cmd},
+    print::{self, prompt},
+};
+
+macro_rules! doc_self {
+    () => {
+        indoc! {"
+            The [rpm-ostree](https://coreos.github.io/rpm-ostree/) hybrid image/package system,
+            as found on Fedora Silverblue, Kinoite and the other immutable variants.
+        "}
+    };
+}
+use doc_self;
+
+#[doc = doc_self!()]
+#[derive(Debug)]
+pub struct RpmOstree {
+    cfg: Config,
+}
+
+static STRAT_LAYER: LazyLock<Strategy> = LazyLock::new(|| Strategy {
+    prompt: PromptStrategy::CustomPrompt,
+    dry_run: DryRunStrategy::with_flags(["--dry-run"]),
+    ..Strategy::default()
+});
+
+static STRAT_UPGRADE: LazyLock<Strategy> = LazyLock::new(|| Strategy {
+    prompt: PromptStrategy::CustomPrompt,
+    dry_run: DryRunStrategy::with_flags(["--preview"]),
+    ..Strategy::default()
+});
+
+impl RpmOstree {
+    #[must_use]
+    #[allow(missing_docs)]
+    pub const fn new(cfg: Config) -> Self {
+        Self { cfg }
+    }
+
+    /// Runs a command changing the deployment, then warns that the changes
+    /// will only be applied on the next boot.
+    async fn run_deploy(&self, cmd: Cmd, strat: &Strategy) -> Result<()> {
+        self.run_with(cmd, self.default_mode(), strat).await?;
+        if !self.cfg.dry_run && !exec::prompt_declined() {
+            print::println(
+                &*prompt::WARNING,
+                "the changes have been staged in a new deployment, and will only be applied after a reboot",
+            );
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Pm for RpmOstree {
+    /// Gets the name of the package manager.
+    fn name(&self) -> &'static str {
+        "rpm-ostree"
+    }
+
+    fn cfg(&self) -> &Config {
+        &self.cfg
+    }
+
+    fn preview_methods(&self) -> &[&'static str] {
+        &["r", "s", "su", "suy"]
+    }
+
+    /// Q generates a list of installed packages.
+    ///
+    /// The packages layered on top of the base image are listed by
+    /// [`RpmOstree::qe`] instead.
+    async fn q(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
+        if kws.is_empty() && !self.cfg.query_format().is_structured() {
+            Cmd::new(["rpm", "-qa", "--qf", "%{NAME} %{VERSION}\\n"])
+                .flags(flags)
+                .pipe(|cmd| self.run(cmd))
+                .await
+        } else {
+            self.qs(kws, flags).await
+        }
+    }
+
+    /// Qc shows the changelog of a package.
+    async fn qc(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
+        Cmd::new(["rpm", "-q", "--changelog"])
+            .kws(kws)
+            .flags(flags)
+            .pipe(|cmd| self.run(cmd))
+            .await
+    }
+
+    /// Qe lists packages installed explicitly (not as dependencies).
+    ///
+    /// These are the packages layered on top of the base image, as shown in
+    /// the status of the deployments.
+    async fn qe(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
+        let cmd = Cmd::new(["rpm-ostree", "status"]).flags(flags);
+        if kws.is_empty() {
+            return self.run(cmd).await;
+        }
+        self.search_regex(cmd, kws).await
+    }
+
+    /// Qi displays local package information: name, version, description, etc.
+    async fn qi(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
+        self.run(Cmd::new(["rpm", "-qi"]).kws(kws).flags(flags))
+            .await
+    }
+
+    /// Ql displays files provided by local package.
+    async fn ql(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
+        self.list_files(Cmd::new(["rpm", "-ql"]).kws(kws).flags(flags))
+            .await
+    }
+
+    /// Qo queries the package which provides FILE.
+    async fn qo(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
+        self.run(Cmd::new(["rpm", "-qf"]).kws(kws).flags(flags))
+            .await
+    }
+
+    /// Qp queries a package supplied through a file supplied on the command
+    /// line rather than an entry in the package management database.
+    async fn qp(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
+        self.run(Cmd::new(["rpm", "-qip"]).kws(kws).flags(flags))
+            .await
+    }
+
+    /// Qs searches locally installed package for names or descriptions.
+    async fn qs(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
+        if self.cfg.query_format().is_structured() {
+            return Cmd::new(RPM_QUERY)
+                .flags(flags)
+                .pipe(|cmd| self.print_pkgs(cmd, kws, parse_rpm))
+                .await;
+        }
+        self.search_regex(Cmd::new(["rpm", "-qa"]).flags(flags), kws)
+            .await
+    }
+
+    /// Qu lists packages which have an update available.
+    async fn qu(&self, _kws: &[&str], flags: &[&str]) -> Result<()> {
+        self.run(Cmd::new(["rpm-ostree", "upgrade", "--check"]).flags(flags))
+            .await
+    }
+
+    /// R removes a single package, leaving all of its dependencies installed.
+    async fn r(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
+        Cmd::with_sudo(["rpm-ostree", "uninstall"])
+            .kws(kws)
+            .flags(flags)
+            .pipe(|cmd| self.run_deploy(cmd, &STRAT_LAYER))
+            .await
+    }
+
+    /// S installs one or more packages by name.
+    async fn s(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
+        Cmd::with_sudo(["rpm-ostree", "install"])
+            .kws(kws)
+            .flags(flags)
+            .pipe(|cmd| self.run_deploy(cmd, &STRAT_LAYER))
+            .await
+    }
+
+    /// Si displays remote package information: name, version, description, etc.
+    async fn si(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
+        self.run(Cmd::new(["dnf", "info"]).kws(kws).flags(flags))
+            .await
+    }
+
+    /// Ss searches for package(s) by searching the expression in name,
+    /// description, short description.
+    async fn ss(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
+        self.run(Cmd::new(["dnf", "search"]).kws(kws).flags(flags))
+            .await
+    }
+
+    /// Su updates outdated packages.
+    ///
+    /// The whole deployment is upgraded at once, so any package given is
+    /// layered on top of the new one instead.
+    async fn su(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
+        Cmd::with_sudo(["rpm-ostree", "upgrade"])
+            .flags(chain!(
+                kws.iter().map(|kw| format!("--install={kw}")),
+                flags.iter().map(|&flag| flag.to_owned()),
+            ))
+            .pipe(|cmd| self.run_deploy(cmd, &STRAT_UPGRADE))
+            .await
+    }
+
+    /// Suy refreshes the local package database, then updates outdated
+    /// packages.
+    async fn suy(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
+        self.su(kws, flags).await
+    }
+
+    /// Sy refreshes the local package database.
+    async fn sy(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
+        self.run(Cmd::with_sudo(["rpm-ostree", "refresh-md"]).flags(flags))
+            .await?;
+        if !kws.is_empty() {
+            self.s(kws, flags).await?;
+        }
+        Ok(())
+    }
+}
@@ -7,7 +7,7 @@ use indoc::indoc;
 use tap::prelude::*;
 use which::which;
 
-use super::{NoCacheStrategy, Pm, PmHelper, PromptStrategy, Strategy};
+use super::{CmdSeq, NoCacheStrategy, Pm, PmHelper, PromptStrategy, Strategy, WeakModifier};
 use crate::{config::Config, error::Result, exec::Cmd};
 
 macro_rules! doc_self {
@@ -37,6 +37,13 @@ static STRAT_INSTALL: LazyLock<Strategy> = LazyLock::new(|| Strategy {
     ..Strategy::default()
 });
 
+/// Changes the system without prompting for confirmation, e.g. by refreshing
+/// the local package database.
+static STRAT_REFRESH: LazyLock<Strategy> = LazyLock::new(|| Strategy {
+    mutating: true,
+    ..Strategy::default()
+});
+
 impl Scoop {
     #[must_use]
     #[allow(missing_docs)]
@@ -142,6 +149,11 @@ impl Pm for Scoop {
 
     /// Scc removes all files from the cache.
     async fn scc(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
+        self.warn_weak_modifier(WeakModifier {
+            op: "scc",
+            flag: "-cc",
+            actual: "the cache is cleaned just like with `-Sc`",
+        });
         self.sc(kws, flags).await
     }
 
@@ -176,13 +188,26 @@ impl Pm for Scoop {
     /// Suy refreshes the local package database, then updates outdated
     /// packages.
     async fn suy(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
-        self.sy(&[], flags).await?;
-        self.su(kws, flags).await
+        CmdSeq::new()
+            .then(
+                Cmd::new([&self.shell, "-Command", "scoop", "update"]).flags(flags),
+                &STRAT_REFRESH,
+            )
+            .then(
+                Cmd::new([&self.shell, "-Command", "scoop", "update"])
+                    .kws(if kws.is_empty() { &["*"][..] } else { kws })
+                    .flags(flags),
+                &STRAT_INSTALL,
+            )
+            .pipe(|seq| self.run_seq(seq))
+            .await
     }
 
     /// Sy refreshes the local package database.
     async fn sy(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
-        self.run(Cmd::new([&self.shell, "-Command", "scoop", "update"]).flags(flags))
+        Cmd::new([&self.shell, "-Command", "scoop", "update"])
+            .flags(flags)
+            .pipe(|cmd| self.run_with(cmd, self.default_mode(), &STRAT_REFRESH))
             .await?;
         if !kws.is_empty() {
             self.s(kws, flags).await?;
@@ -190,3 +215,28 @@ impl Pm for Scoop {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pm::mock::{capture_output, MockLog};
+
+    #[tokio::test]
+    async fn weak_modifiers() {
+        let pm = Scoop::with_shell(
+            Config {
+                dry_run: true,
+                ..Config::default()
+            },
+            "pwsh",
+        );
+        let (res, out) = capture_output(MockLog::new().scope(pm.scc(&[], &[]))).await;
+        res.unwrap();
+        assert!(out.contains(
+            "`-cc` in `-Scc` is not fully supported by `scoop`: the cache is cleaned just like with `-Sc`"
+        ));
+        let (res, out) = capture_output(MockLog::new().scope(pm.sc(&[], &[]))).await;
+        res.unwrap();
+        assert!(!out.contains("not fully supported"));
+    }
+}
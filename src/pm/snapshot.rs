@@ -0,0 +1,411 @@
+//! Snapshots of the installed packages, allowing [`pacaptr`](crate) to undo
+//! the last transaction of the package managers keeping no history of their
+//! own.
+//!
+//! Before each operation changing the installed packages, the packages listed
+//! by `-Q` are saved in the local data directory. To undo the operation, they
+//! are compared with the ones installed now, and each [`Change`] is reverted
+//! with the usual operations of the package manager, which ask for
+//! confirmation as they normally would.
+
+use std::{
+    fmt::{self, Display},
+    fs, mem,
+    path::{Path, PathBuf},
+    sync::{Arc, PoisonError},
+};
+
+use itertools::{EitherOrBoth, Itertools};
+use serde::{Deserialize, Serialize};
+
+use super::{BoxPm, Pm};
+use crate::{
+    config::Config,
+    error::{Error, Result},
+    exec,
+    pkg::{Pkg, Update},
+    print::{self, prompt, OutputFormat, PkgSink},
+};
+
+/// The methods changing the installed packages, before which a [`Snapshot`]
+/// is recorded.
+const RECORDED_METHODS: &[&str] = &[
+    "autoremove",
+    "autoremove_interactive",
+    "downgrade",
+    "r",
+    "rn",
+    "rns",
+    "rs",
+    "rss",
+    "s",
+    "su",
+    "suy",
+    "u",
+];
+
+/// The packages installed before a transaction.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Snapshot {
+    /// The operation of the transaction, e.g. `-S curl`.
+    pub op: String,
+
+    /// The packages installed before the transaction.
+    pub pkgs: Vec<Pkg>,
+}
+
+/// A difference between two sets of installed packages.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Change {
+    /// A package has been installed.
+    Added(Pkg),
+
+    /// A package has been removed.
+    Removed(Pkg),
+
+    /// A package has changed from one version to another.
+    Changed(Update),
+}
+
+impl Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Added(pkg) => write!(f, "+ {} {}", pkg.name, pkg.version),
+            Self::Removed(pkg) => write!(f, "- {} {}", pkg.name, pkg.version),
+            Self::Changed(Update { name, old, new }) => write!(f, "~ {name} {old} -> {new}"),
+        }
+    }
+}
+
+/// Returns the path of the [`Snapshot`] of the package manager `pm`.
+fn snapshot_path(pm: &str) -> Option<PathBuf> {
+    Some(
+        dirs_next::data_local_dir()?
+            .join("pacaptr")
+            .join("snapshots")
+            .join(format!("{pm}.json")),
+    )
+}
+
+/// Saves `snapshot` at `path`.
+fn save(path: &Path, snapshot: &Snapshot) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let json = serde_json::to_string(snapshot)
+        .map_err(|e| Error::OtherError(format!("failed to serialize the snapshot: {e}")))?;
+    fs::write(path, json).map_err(Into::into)
+}
+
+/// Loads the [`Snapshot`] at `path`, if any.
+fn load(path: &Path) -> Option<Snapshot> {
+    serde_json::from_str(&fs::read_to_string(path).ok()?).ok()
+}
+
+/// Lists the packages installed by `pm` with `-Q`.
+///
+/// # Errors
+/// Returns an [`Error::StructuredOutputUnsupportedError`] if `pm` is unable to
+/// list them in a structured way, and any error of `-Q` otherwise.
+async fn installed(pm: &(impl Pm + ?Sized)) -> Result<Vec<Pkg>> {
+    let pm = BoxPm::from(Config {
+        output_format: Some(OutputFormat::Porcelain),
+        dry_run: false,
+        measure_network: false,
+        ..pm.cfg().clone()
+    });
+    let sink = PkgSink::default();
+    print::capture_pkgs(Arc::clone(&sink), pm.q(&[], &[])).await?;
+    let pkgs = mem::take(&mut *sink.lock().unwrap_or_else(PoisonError::into_inner));
+    Ok(pkgs)
+}
+
+/// Lists the packages installed by `pm` before running the operation `method`
+/// (given by the user as `op`) if [`Config::record_snapshots`] is set, it
+/// changes them and `pm` cannot undo it by itself.
+///
+/// The resulting [`Snapshot`] is only saved by [`commit`] once the operation
+/// has been run. The listing itself is left out of the
+/// [`ExecStats`](crate::exec::ExecStats) of the operation, and failing to list
+/// the packages never stops the operation, as only [`undo`] relies on them.
+pub async fn record(pm: &(impl Pm + ?Sized), method: &str, op: &str) -> Option<Snapshot> {
+    let cfg = pm.cfg();
+    if pm.supports_undo()
+        || cfg.dry_run
+        || !cfg.record_snapshots()
+        || !RECORDED_METHODS.contains(&method)
+    {
+        return None;
+    }
+    let pkgs = exec::unmeasured(installed(pm)).await.ok()?;
+    Some(Snapshot {
+        op: op.into(),
+        pkgs,
+    })
+}
+
+/// Saves the `snapshot` of `pm` taken by [`record`] after running its
+/// operation, unless the user has declined it, in which case the previous
+/// [`Snapshot`] (if any) is kept.
+pub fn commit(pm: &(impl Pm + ?Sized), snapshot: &Snapshot) {
+    if exec::prompt_declined() {
+        return;
+    }
+    if let Some(path) = snapshot_path(pm.name()) {
+        _ = save(&path, snapshot);
+    }
+}
+
+/// Compares the packages installed `before` and `after` a transaction,
+/// returning the [`Change`]s sorted by name.
+#[must_use]
+pub fn diff(before: &[Pkg], after: &[Pkg]) -> Vec<Change> {
+    let sorted = |pkgs: &[Pkg]| {
+        let mut pkgs = pkgs.to_vec();
+        pkgs.sort_by(|a, b| a.name.cmp(&b.name));
+        pkgs.dedup_by(|a, b| a.name == b.name);
+        pkgs
+    };
+    sorted(before)
+        .into_iter()
+        .merge_join_by(sorted(after), |a, b| a.name.cmp(&b.name))
+        .filter_map(|pair| match pair {
+            EitherOrBoth::Left(pkg) => Some(Change::Removed(pkg)),
+            EitherOrBoth::Right(pkg) => Some(Change::Added(pkg)),
+            EitherOrBoth::Both(old, new) => {
+                (old.version != new.version).then_some(Change::Changed(Update {
+                    name: new.name,
+                    old: old.version,
+                    new: new.version,
+                }))
+            }
+        })
+        .collect()
+}
+
+/// Undoes the last transaction of `pm` recorded by [`record`].
+///
+/// # Errors
+/// Returns an [`Error::ArgParseError`] if a transaction ID is given, an
+/// [`Error::OtherError`] if no transaction has been recorded, and any error of
+/// reverting the changes otherwise.
+pub async fn undo(pm: &(impl Pm + ?Sized), kws: &[&str], flags: &[&str]) -> Result<()> {
+    if !kws.is_empty() {
+        return Err(Error::ArgParseError {
+            msg: format!(
+                "`{}` keeps no history, so only the last transaction can be undone",
+                pm.name()
+            ),
+        });
+    }
+    let path = snapshot_path(pm.name());
+    let snapshot = (path.as_deref().and_then(load)).ok_or_else(|| {
+        Error::OtherError(format!(
+            "no transaction of `{}` has been recorded yet",
+            pm.name()
+        ))
+    })?;
+    revert(pm, &snapshot, flags).await?;
+    if let (false, Some(path)) = (pm.cfg().dry_run, path) {
+        _ = fs::remove_file(path);
+    }
+    Ok(())
+}
+
+/// Reverts the changes made to the packages installed by `pm` since
+/// `snapshot`, showing them beforehand.
+///
+/// The packages added are removed, the packages removed are installed again,
+/// and the packages changed are brought back to their previous versions with
+/// [`Pm::downgrade`] if possible.
+///
+/// # Errors
+/// Returns any error of listing the installed packages or of running the
+/// reverse operations.
+async fn revert(pm: &(impl Pm + ?Sized), snapshot: &Snapshot, flags: &[&str]) -> Result<()> {
+    let changes = diff(&snapshot.pkgs, &installed(pm).await?);
+    if changes.is_empty() {
+        print::println(
+            &*prompt::INFO,
+            format_args!("nothing has changed since `{}`", snapshot.op),
+        );
+        return Ok(());
+    }
+    print::println(
+        &*prompt::INFO,
+        format_args!("undoing the following changes since `{}`", snapshot.op),
+    );
+    for change in &changes {
+        print::print_raw(format_args!("{change}\n"));
+    }
+
+    let (mut to_remove, mut to_install, mut to_restore) = (vec![], vec![], vec![]);
+    for change in &changes {
+        match change {
+            Change::Added(pkg) => to_remove.push(pkg.name.as_str()),
+            Change::Removed(pkg) => to_install.push(pkg.name.as_str()),
+            Change::Changed(Update { name, old, .. }) => to_restore.push(format!("{name}={old}")),
+        }
+    }
+    if !to_remove.is_empty() {
+        pm.r(&to_remove, flags).await?;
+    }
+    if !to_install.is_empty() {
+        pm.s(&to_install, flags).await?;
+    }
+    if !to_restore.is_empty() {
+        let kws = to_restore.iter().map(String::as_str).collect_vec();
+        match pm.downgrade(&kws, flags).await {
+            Err(Error::OperationUnimplementedError { .. }) => print::println(
+                &*prompt::WARNING,
+                format_args!(
+                    "`{}` cannot install the previous versions of the changed packages: {}",
+                    pm.name(),
+                    to_restore.join(", ")
+                ),
+            ),
+            res => res?,
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, process};
+
+    use super::*;
+    use crate::pm::{mock::MockLog, Apt, Brew};
+
+    #[test]
+    fn diffs() {
+        let before = [
+            Pkg::new("curl", "8.5.0", "brew"),
+            Pkg::new("fish", "3.7.1", "brew"),
+            Pkg::new("wget", "1.24.5", "brew"),
+        ];
+        let after = [
+            Pkg::new("wget", "1.25.0", "brew"),
+            Pkg::new("curl", "8.5.0", "brew"),
+            Pkg::new("ripgrep", "14.1.1", "brew"),
+        ];
+        let changes = diff(&before, &after);
+        assert_eq!(
+            changes,
+            [
+                Change::Removed(Pkg::new("fish", "3.7.1", "brew")),
+                Change::Added(Pkg::new("ripgrep", "14.1.1", "brew")),
+                Change::Changed(Update {
+                    name: "wget".into(),
+                    old: "1.24.5".into(),
+                    new: "1.25.0".into(),
+                }),
+            ]
+        );
+        assert_eq!(
+            changes.iter().join("\n"),
+            indoc::indoc! {"
+            - fish 3.7.1
+            + ripgrep 14.1.1
+            ~ wget 1.24.5 -> 1.25.0"
+            }
+        );
+        assert!(diff(&before, &before).is_empty());
+    }
+
+    #[test]
+    fn save_load() {
+        let dir = env::temp_dir().join(format!("pacaptr-snapshot-{}", process::id()));
+        let path = dir.join("snapshots").join("brew.json");
+        assert_eq!(load(&path), None);
+        let snapshot = Snapshot {
+            op: "-S curl".into(),
+            pkgs: vec![Pkg::new("fish", "3.7.1", "brew")],
+        };
+        save(&path, &snapshot).unwrap();
+        assert_eq!(load(&path), Some(snapshot));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn record_opt_in() {
+        let cfg = Config {
+            default_pm: Some("brew".into()),
+            quiet: Some(true),
+            ..Config::default()
+        };
+        let log = MockLog::new().output("brew list --formula --versions", "wget 1.24.5\n");
+        let brew = Brew::new(cfg.clone());
+        assert_eq!(log.scope(record(&brew, "s", "-S curl")).await, None);
+        assert!(log.lines().is_empty());
+
+        let brew = Brew::new(Config {
+            record_snapshots: Some(true),
+            ..cfg
+        });
+        assert_eq!(log.scope(record(&brew, "q", "-Q")).await, None);
+        let snapshot = log.scope(record(&brew, "s", "-S curl")).await;
+        assert_eq!(
+            snapshot,
+            Some(Snapshot {
+                op: "-S curl".into(),
+                pkgs: vec![Pkg::new("wget", "1.24.5", "brew")],
+            })
+        );
+        assert_eq!(log.lines(), ["brew list --formula --versions"]);
+    }
+
+    #[tokio::test]
+    async fn revert_apt() {
+        let apt = Apt::new(Config {
+            default_pm: Some("apt".into()),
+            no_confirm: true,
+            quiet: Some(true),
+            ..Config::default()
+        });
+        let snapshot = Snapshot {
+            op: "-Syu".into(),
+            pkgs: vec![
+                Pkg::new("curl", "7.81.0-1ubuntu1.15", "jammy-updates"),
+                Pkg::new("fish", "3.3.1+ds-3", "jammy"),
+            ],
+        };
+        let log = MockLog::new().output(
+            "apt list --installed",
+            indoc::indoc! {"
+                Listing...
+                curl/jammy-updates,now 7.81.0-1ubuntu1.16 amd64 [installed]
+                ripgrep/jammy,now 13.0.0-2 amd64 [installed]
+            "},
+        );
+        log.scope(revert(&apt, &snapshot, &[])).await.unwrap();
+        assert_eq!(
+            log.lines(),
+            [
+                "apt list --installed",
+                "apt remove --yes ripgrep",
+                "apt install --reinstall --yes fish",
+                "apt install --allow-downgrades --yes curl=7.81.0-1ubuntu1.15",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn revert_brew() {
+        let brew = Brew::new(Config {
+            default_pm: Some("brew".into()),
+            no_confirm: true,
+            quiet: Some(true),
+            ..Config::default()
+        });
+        let snapshot = Snapshot {
+            op: "-Su".into(),
+            pkgs: vec![Pkg::new("wget", "1.24.5", "brew")],
+        };
+        let log = MockLog::new().output("brew list --formula --versions", "wget 1.25.0\n");
+        log.scope(revert(&brew, &snapshot, &[])).await.unwrap();
+        // `brew` cannot downgrade, so the changed package is only reported.
+        assert_eq!(log.lines()[..1], ["brew list --formula --versions"]);
+        assert!(!log.lines().iter().any(|ln| ln.contains("wget")));
+    }
+}
@@ -0,0 +1,313 @@
+#![doc = doc_self!()]
+
+use std::{
+    env,
+    path::{Path, PathBuf},
+    sync::LazyLock,
+};
+
+use async_trait::async_trait;
+use indoc::indoc;
+use itertools::{chain, Itertools};
+use tap::prelude::*;
+
+use super::{for_each_kw, CmdSeq, DryRunStrategy, Pm, PmHelper, PmMode, PromptStrategy, Strategy};
+use crate::{
+    config::Config,
+    error::{Error, Result},
+    exec::{self, Cmd},
+    pkg::Pkg,
+    print::{self, prompt, Progress},
+};
+
+macro_rules! doc_self {
+    () => {
+        indoc! {"
+            The [Haskell Tool Stack](https://docs.haskellstack.org/), managing
+            the global tools installed in `$(stack path --local-bin)`.
+        "}
+    };
+}
+use doc_self;
+
+#[doc = doc_self!()]
+#[derive(Debug)]
+pub struct Stack {
+    cfg: Config,
+}
+
+/// The command used to remove an installed binary.
+#[cfg(windows)]
+const RM: &[&str] = &["cmd", "/C", "del"];
+#[cfg(not(windows))]
+const RM: &[&str] = &["rm"];
+
+/// The resolver used when upgrading tools, i.e. the latest LTS snapshot.
+const LATEST_RESOLVER: &str = "lts";
+
+static STRAT_PROMPT: LazyLock<Strategy> = LazyLock::new(|| Strategy {
+    prompt: PromptStrategy::CustomPrompt,
+    ..Strategy::default()
+});
+
+/// Changes the system without prompting for confirmation, e.g. by refreshing
+/// the local package database.
+static STRAT_REFRESH: LazyLock<Strategy> = LazyLock::new(|| Strategy {
+    mutating: true,
+    ..Strategy::default()
+});
+
+/// Runs the command even in a dry run, as it only queries information.
+static STRAT_QUERY: LazyLock<Strategy> = LazyLock::new(|| Strategy {
+    dry_run: DryRunStrategy::WithFlags(vec![]),
+    ..Strategy::default()
+});
+
+impl Stack {
+    #[must_use]
+    #[allow(missing_docs)]
+    pub const fn new(cfg: Config) -> Self {
+        Self { cfg }
+    }
+
+    /// Returns the `--resolver` flag to be used on installation, if a resolver
+    /// has been specified in the config.
+    fn resolver_flags(&self) -> Vec<String> {
+        self.cfg
+            .stack_resolver
+            .iter()
+            .flat_map(|res| ["--resolver".into(), res.clone()])
+            .collect()
+    }
+
+    /// Returns the given `flags` preceded by the `--local-bin-path` flag to be
+    /// used on installation, if a directory has been specified with
+    /// `--install-into`.
+    fn local_bin_flags(&self, flags: &[&str]) -> Vec<String> {
+        let local_bin = (self.cfg.install_into.iter()).flat_map(|dir| {
+            [
+                "--local-bin-path".into(),
+                dir.to_string_lossy().into_owned(),
+            ]
+        });
+        chain!(local_bin, flags.iter().map(|&flag| flag.to_owned())).collect()
+    }
+
+    /// Returns the directory where `stack` installs the binaries.
+    async fn local_bin(&self) -> Result<PathBuf> {
+        let cmd = Cmd::new(["stack", "path", "--local-bin"]);
+        let out = self.check_output(cmd, PmMode::Mute, &STRAT_QUERY).await?;
+        Ok(PathBuf::from(exec::decode(&out).trim()))
+    }
+
+    /// Returns the command reinstalling the given packages (or all the
+    /// installed ones if none is given) with [`LATEST_RESOLVER`], or `None` if
+    /// there is nothing to upgrade.
+    async fn upgrade_cmd(&self, kws: &[&str], flags: &[&str]) -> Result<Option<Cmd>> {
+        let installed = if kws.is_empty() {
+            self.installed().await?
+        } else {
+            kws.iter().map(|&kw| kw.into()).collect()
+        };
+        Ok((!installed.is_empty()).then(|| {
+            Cmd::new(["stack", "install", "--resolver", LATEST_RESOLVER])
+                .kws(installed)
+                .flags(self.local_bin_flags(flags))
+        }))
+    }
+
+    /// Returns the names of the binaries installed by `stack`, in order.
+    async fn installed(&self) -> Result<Vec<String>> {
+        let bin = self.local_bin().await?;
+        // The directory might not exist if nothing has been installed yet.
+        let Ok(entries) = bin.read_dir() else {
+            return Ok(vec![]);
+        };
+        let mut names = entries
+            .filter_map(|entry| {
+                let path = entry.ok()?.path();
+                let name = path.file_name()?.to_str()?;
+                let name = name.strip_suffix(env::consts::EXE_SUFFIX).unwrap_or(name);
+                path.is_file().then(|| name.into())
+            })
+            .collect::<Vec<_>>();
+        names.sort_unstable();
+        Ok(names)
+    }
+}
+
+/// Warns the user if `stack` is not going to manage the tools of the
+/// current user globally.
+fn warn_scope() {
+    if exec::is_root() {
+        print::println(
+            &*prompt::WARNING,
+            "running as root, so the tools will be managed for the root user only",
+        );
+    }
+    let cwd = env::current_dir().unwrap_or_default();
+    if let Some(dir) = cwd.ancestors().find(|dir| dir.join("stack.yaml").is_file()) {
+        print::println(
+            &*prompt::WARNING,
+            format_args!(
+                "running inside the stack project `{}`, whose resolver will be used instead",
+                dir.display()
+            ),
+        );
+    }
+}
+
+/// Returns the path of the binary `name` installed in `bin`.
+fn bin_path(bin: &Path, name: &str) -> PathBuf {
+    bin.join(format!("{name}{}", env::consts::EXE_SUFFIX))
+}
+
+#[async_trait]
+impl Pm for Stack {
+    /// Gets the name of the package manager.
+    fn name(&self) -> &'static str {
+        "stack"
+    }
+
+    fn cfg(&self) -> &Config {
+        &self.cfg
+    }
+
+    fn supports_install_into(&self) -> bool {
+        true
+    }
+
+    /// Q generates a list of installed packages.
+    ///
+    /// `stack` keeps no record of the installed versions, so these are left
+    /// empty in the structured output formats.
+    async fn q(&self, kws: &[&str], _flags: &[&str]) -> Result<()> {
+        let names = self.installed().await?.join("\n");
+        let names = exec::grep(&names, kws)?;
+        let format = self.cfg.query_format();
+        if !format.is_structured() {
+            self.print_names(&names);
+            return Ok(());
+        }
+        let pkgs = (names.iter())
+            .map(|&name| Pkg::new(name, "", "stack"))
+            .collect_vec();
+        print::println_pkgs(&pkgs, format);
+        Ok(())
+    }
+
+    /// R removes a single package, leaving all of its dependencies installed.
+    async fn r(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
+        warn_scope();
+        let bin = self.local_bin().await?;
+        let progress = Progress::new(kws.len(), &self.cfg);
+        for_each_kw(&self.cfg, kws, |kw| {
+            progress.step(kw);
+            let path = bin_path(&bin, kw);
+            let bin = &bin;
+            async move {
+                if !path.is_file() {
+                    return Err(Error::OtherError(format!(
+                        "binary `{kw}` was not found in `{}`",
+                        bin.display()
+                    )));
+                }
+                Cmd::new(RM)
+                    .kws([path.to_string_lossy()])
+                    .flags(flags)
+                    .pipe(|cmd| self.run_with(cmd, self.default_mode(), &STRAT_PROMPT))
+                    .await?;
+                if !path.exists() {
+                    print::println(&*prompt::INFO, format_args!("removed `{}`", path.display()));
+                }
+                Ok(())
+            }
+        })
+        .await
+    }
+
+    /// S installs one or more packages by name.
+    async fn s(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
+        warn_scope();
+        Cmd::new(["stack", "install"])
+            .kws(kws)
+            .flags(chain!(self.resolver_flags(), self.local_bin_flags(flags)))
+            .pipe(|cmd| self.run_with(cmd, self.default_mode(), &STRAT_PROMPT))
+            .await
+    }
+
+    /// Su updates outdated packages.
+    async fn su(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
+        warn_scope();
+        let Some(cmd) = self.upgrade_cmd(kws, flags).await? else {
+            return Ok(());
+        };
+        self.run_with(cmd, self.default_mode(), &STRAT_PROMPT).await
+    }
+
+    /// Suy refreshes the local package database, then updates outdated
+    /// packages.
+    async fn suy(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
+        warn_scope();
+        let mut seq =
+            CmdSeq::new().then(Cmd::new(["stack", "update"]).flags(flags), &STRAT_REFRESH);
+        if let Some(cmd) = self.upgrade_cmd(kws, flags).await? {
+            seq = seq.then(cmd, &STRAT_PROMPT);
+        }
+        self.run_seq(seq).await
+    }
+
+    /// Sy refreshes the local package database.
+    async fn sy(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
+        Cmd::new(["stack", "update"])
+            .flags(flags)
+            .pipe(|cmd| self.run_with(cmd, self.default_mode(), &STRAT_REFRESH))
+            .await?;
+        if !kws.is_empty() {
+            self.s(kws, flags).await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, process, sync::Arc};
+
+    use super::*;
+    use crate::{
+        pm::mock::MockLog,
+        print::{OutputFormat, PkgSink},
+    };
+
+    #[test]
+    fn local_bin_flags() {
+        let stack = Stack::new(Config {
+            install_into: Some("/opt/bin".into()),
+            ..Config::default()
+        });
+        assert_eq!(
+            stack.local_bin_flags(&["--fast"]),
+            ["--local-bin-path", "/opt/bin", "--fast"]
+        );
+    }
+
+    #[tokio::test]
+    async fn q_structured() {
+        let dir = env::temp_dir().join(format!("pacaptr-stack-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for name in ["hlint", "pandoc"] {
+            fs::write(bin_path(&dir, name), "").unwrap();
+        }
+        let stack = Stack::new(Config {
+            output_format: Some(OutputFormat::Json),
+            ..Config::default()
+        });
+        let log = MockLog::new().output("stack path --local-bin", dir.to_string_lossy().as_bytes());
+        let sink = PkgSink::default();
+        let q = print::capture_pkgs(Arc::clone(&sink), stack.q(&["lint"], &[]));
+        log.scope(q).await.unwrap();
+        assert_eq!(*sink.lock().unwrap(), [Pkg::new("hlint", "", "stack")]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -68,6 +68,11 @@ impl Pm for Tlmgr {
             .await
     }
 
+    /// Qkk verifies one or more packages, listing every altered file.
+    async fn qkk(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
+        self.qk(kws, flags).await
+    }
+
     /// Ql displays files provided by local package.
     async fn ql(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
         Cmd::new(["tlmgr", "info", "--only-installed", "--list"])
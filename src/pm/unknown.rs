@@ -15,6 +15,9 @@ macro_rules! doc_self {
 }
 use doc_self;
 
+/// The prefix of the name of an [`Unknown`] package manager.
+pub(super) const NAME_PREFIX: &str = "unknown package manager: ";
+
 #[doc = doc_self!()]
 #[derive(Debug)]
 pub struct Unknown {
@@ -27,7 +30,7 @@ impl Unknown {
     /// Creates a new [`Unknown`] package manager with the given name.
     pub(crate) fn new(name: &str) -> Self {
         Self {
-            name: format!("unknown package manager: {name}"),
+            name: format!("{NAME_PREFIX}{name}"),
             cfg: Config::default(),
         }
     }
@@ -4,10 +4,16 @@ use std::sync::LazyLock;
 
 use async_trait::async_trait;
 use indoc::indoc;
+use regex::RegexSet;
 use tap::prelude::*;
 
-use super::{Pm, PmHelper, PromptStrategy, Strategy};
-use crate::{config::Config, error::Result, exec::Cmd};
+use super::{CmdSeq, DryRunStrategy, Pm, PmHelper, PmMode, PromptStrategy, Strategy};
+use crate::{
+    config::Config,
+    error::Result,
+    exec::{self, Cmd},
+    print::{self, prompt},
+};
 
 macro_rules! doc_self {
     () => {
@@ -34,12 +40,148 @@ static STRAT_INSTALL: LazyLock<Strategy> = LazyLock::new(|| Strategy {
     ..Strategy::default()
 });
 
+/// Changes the system without prompting for confirmation, e.g. by refreshing
+/// the local package database.
+static STRAT_REFRESH: LazyLock<Strategy> = LazyLock::new(|| Strategy {
+    mutating: true,
+    ..Strategy::default()
+});
+
+/// Runs the command even in a dry run, as it only queries information.
+static STRAT_QUERY: LazyLock<Strategy> = LazyLock::new(|| Strategy {
+    dry_run: DryRunStrategy::WithFlags(vec![]),
+    ..Strategy::default()
+});
+
+/// The command prefix for upgrading packages.
+const UPGRADE: &[&str] = &[
+    "winget",
+    "upgrade",
+    "--accept-package-agreements",
+    "--accept-source-agreements",
+];
+
+/// An entry of the `winget upgrade` listing.
+#[derive(Debug, PartialEq, Eq)]
+struct Upgrade {
+    id: String,
+    source: String,
+}
+
 impl Winget {
     #[must_use]
     #[allow(missing_docs)]
     pub const fn new(cfg: Config) -> Self {
         Self { cfg }
     }
+
+    /// Appends to `seq` the upgrade of the given packages, or all the
+    /// outdated ones (except the excluded ones) if none is given.
+    async fn then_upgrade(&self, seq: CmdSeq, kws: &[&str], flags: &[&str]) -> Result<CmdSeq> {
+        if !kws.is_empty() {
+            return Ok(seq.then(Cmd::new(UPGRADE).kws(kws).flags(flags), &STRAT_INSTALL));
+        }
+        if self.cfg.ignore.is_empty() && self.cfg.exclude_source.is_empty() {
+            return Ok(seq.then(
+                Cmd::new(UPGRADE).kws(["--all"]).flags(flags),
+                &STRAT_INSTALL,
+            ));
+        }
+        // `winget upgrade --all` has no way of skipping anything, so the
+        // packages are upgraded one by one instead.
+        let out = Cmd::new(["winget", "upgrade", "--accept-source-agreements"])
+            .pipe(|cmd| self.check_output(cmd, PmMode::Mute, &STRAT_QUERY))
+            .await?;
+        let out = exec::decode(&out);
+        let ignored = exec::glob_patterns(&self.cfg.ignore)?;
+        let (skipped, upgrades): (Vec<_>, Vec<_>) = parse_upgrades(&out)
+            .into_iter()
+            .partition(|upgrade| is_excluded(&self.cfg, &ignored, upgrade));
+        if !skipped.is_empty() {
+            let ids = skipped.iter().map(|upgrade| upgrade.id.as_str());
+            print::println(
+                &*prompt::INFO,
+                format_args!(
+                    "skipping upgrades of {}",
+                    ids.collect::<Vec<_>>().join(", ")
+                ),
+            );
+        }
+        Ok(upgrades.into_iter().fold(seq, |seq, upgrade| {
+            seq.then(
+                Cmd::new(
+                    UPGRADE
+                        .iter()
+                        .copied()
+                        .chain(["--exact", "--id", &upgrade.id]),
+                )
+                .flags(flags),
+                &STRAT_INSTALL,
+            )
+        }))
+    }
+}
+
+/// Returns whether `upgrade` should be skipped according to the `--ignore`
+/// list of `cfg` compiled into the glob patterns `ignored`, and its
+/// `--exclude-source` list.
+fn is_excluded(cfg: &Config, ignored: &RegexSet, upgrade: &Upgrade) -> bool {
+    let excluded = |src: &String| src.eq_ignore_ascii_case(&upgrade.source);
+    ignored.is_match(&upgrade.id) || cfg.exclude_source.iter().any(excluded)
+}
+
+/// Parses the table printed out by `winget upgrade`, whose columns are the
+/// name, ID, version, available version and source of each package.
+///
+/// The columns are located by the header of the table, so that the names and
+/// versions containing spaces are handled as well. Only the first table is
+/// parsed, as the packages listed afterwards (if any) are not upgraded by
+/// `winget upgrade --all` either.
+fn parse_upgrades(out: &str) -> Vec<Upgrade> {
+    // Progress spinners are overwritten with `\r`, so only the last segment of
+    // each line is actually visible.
+    let lines = out
+        .lines()
+        .map(|line| line.rsplit('\r').next().unwrap_or_default())
+        .collect::<Vec<_>>();
+    let Some(sep) = lines
+        .iter()
+        .position(|line| !line.is_empty() && line.chars().all(|c| c == '-'))
+    else {
+        return vec![];
+    };
+    let Some(header) = sep.checked_sub(1).map(|i| lines[i]) else {
+        return vec![];
+    };
+    // The starting columns of each header word, i.e. of each column.
+    let starts = header
+        .char_indices()
+        .filter(|&(i, c)| !c.is_whitespace() && (i == 0 || header[..i].ends_with(' ')))
+        .map(|(i, _)| console::measure_text_width(&header[..i]))
+        .collect::<Vec<_>>();
+    let &[_, id_col, version_col, _, source_col] = &starts[..] else {
+        return vec![];
+    };
+    lines[sep + 1..]
+        .iter()
+        .take_while(|line| console::measure_text_width(line) > source_col)
+        .map(|line| Upgrade {
+            id: slice_cols(line, id_col, version_col).trim().into(),
+            source: slice_cols(line, source_col, usize::MAX).trim().into(),
+        })
+        .collect()
+}
+
+/// Returns the part of `line` between the display columns `start` and `end`.
+fn slice_cols(line: &str, start: usize, end: usize) -> String {
+    let mut col = 0;
+    line.chars()
+        .filter(|c| {
+            let curr = col;
+            col += console::measure_text_width(c.encode_utf8(&mut [0; 4]));
+            (start..end).contains(&curr)
+        })
+        .collect()
 }
 
 // Windows is so special! It's better not to "sudo" automatically.
@@ -54,6 +196,10 @@ impl Pm for Winget {
         &self.cfg
     }
 
+    fn supports_search_limit(&self) -> bool {
+        true
+    }
+
     /// Q generates a list of installed packages.
     async fn q(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
         if kws.is_empty() {
@@ -129,10 +275,21 @@ impl Pm for Winget {
 
     /// Ss searches for package(s) by searching the expression in name,
     /// description, short description.
+    ///
+    /// The results are truncated natively with `--count`.
     async fn ss(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
+        let count = self
+            .cfg
+            .search_limit
+            .map(|limit| format!("--count={limit}"));
         Cmd::new(["winget", "search", "--accept-source-agreements"])
             .kws(kws)
-            .flags(flags)
+            .flags(
+                count
+                    .iter()
+                    .map(String::as_str)
+                    .chain(flags.iter().copied()),
+            )
             .pipe(|cmd| self.run(cmd))
             .await
     }
@@ -141,7 +298,7 @@ impl Pm for Winget {
     async fn sy(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
         Cmd::new(["winget", "source", "update", "--accept-source-agreements"])
             .flags(flags)
-            .pipe(|cmd| self.run(cmd))
+            .pipe(|cmd| self.run_with(cmd, self.default_mode(), &STRAT_REFRESH))
             .await?;
         if !kws.is_empty() {
             self.s(kws, flags).await?;
@@ -151,22 +308,97 @@ impl Pm for Winget {
 
     /// Su updates outdated packages.
     async fn su(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
-        Cmd::new([
-            "winget",
-            "upgrade",
-            "--accept-package-agreements",
-            "--accept-source-agreements",
-        ])
-        .kws(if kws.is_empty() { &["--all"][..] } else { kws })
-        .flags(flags)
-        .pipe(|cmd| self.run_with(cmd, self.default_mode(), &STRAT_INSTALL))
-        .await
+        let seq = self.then_upgrade(CmdSeq::new(), kws, flags).await?;
+        self.run_seq(seq).await
     }
 
     /// Suy refreshes the local package database, then updates outdated
     /// packages.
     async fn suy(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
-        self.sy(&[], flags).await?;
-        self.su(kws, flags).await
+        let seq = CmdSeq::new().then(
+            Cmd::new(["winget", "source", "update", "--accept-source-agreements"]).flags(flags),
+            &STRAT_REFRESH,
+        );
+        let seq = self.then_upgrade(seq, kws, flags).await?;
+        self.run_seq(seq).await
+    }
+}
+
+#[cfg(all(test, windows))]
+mod tests {
+    use indoc::indoc;
+
+    use super::*;
+    use crate::pm::mock::MockLog;
+
+    const UPGRADES: &str = indoc! {"
+        Name                      Id                      Version   Available  Source
+        -------------------------------------------------------------------------------
+        Microsoft Edge            Microsoft.Edge          129.0.1   130.0.2    winget
+        Spotify Music             9NCBCSZSJRSB            < 1.2.4   1.2.48     msstore
+        微信                      Tencent.WeChat          3.9.11    3.9.12     winget
+        3 upgrades available.
+
+        The following packages have an upgrade available, but require explicit targeting for upgrade:
+        Name    Id           Version  Available  Source
+        ------------------------------------------------
+        Discord Discord.App  1.0.90   1.0.91     winget
+    "};
+
+    fn upgrade(id: &str, source: &str) -> Upgrade {
+        Upgrade {
+            id: id.into(),
+            source: source.into(),
+        }
+    }
+
+    #[test]
+    fn parse_upgrade_listing() {
+        let out = format!("   - \r   \\ \r{UPGRADES}");
+        assert_eq!(
+            parse_upgrades(&out),
+            [
+                upgrade("Microsoft.Edge", "winget"),
+                upgrade("9NCBCSZSJRSB", "msstore"),
+                upgrade("Tencent.WeChat", "winget"),
+            ]
+        );
+        assert_eq!(parse_upgrades("No installed package found."), []);
+    }
+
+    #[test]
+    fn exclude_upgrades() {
+        let kept = |ignore: &str| {
+            let cfg = Config {
+                ignore: vec![ignore.into()],
+                exclude_source: vec!["msstore".into()],
+                ..Config::default()
+            };
+            let ignored = exec::glob_patterns(&cfg.ignore).unwrap();
+            parse_upgrades(UPGRADES)
+                .into_iter()
+                .filter(|upgrade| !is_excluded(&cfg, &ignored, upgrade))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            kept("microsoft.edge"),
+            [upgrade("Tencent.WeChat", "winget")]
+        );
+        // The ignored packages can also be given as globs.
+        assert_eq!(kept("tencent.*"), [upgrade("Microsoft.Edge", "winget")]);
+    }
+
+    #[tokio::test]
+    async fn ss_native_limit() {
+        let winget = Winget::new(Config {
+            search_limit: Some(5),
+            ..Config::default()
+        });
+        let log = MockLog::new();
+        log.scope(winget.ss(&["git"], &[])).await.unwrap();
+        assert_eq!(
+            log.lines(),
+            ["winget search --accept-source-agreements --count=5 git"]
+        );
     }
 }
@@ -1,18 +1,17 @@
 #![doc = doc_self!()]
 
-use std::{io::Write, sync::LazyLock};
+use std::sync::LazyLock;
 
 use async_trait::async_trait;
-use futures::prelude::*;
 use indoc::indoc;
 use tap::Pipe;
 
-use super::{Pm, PmHelper, PmMode, PromptStrategy, Strategy};
+use super::{for_each_kw, Pm, PmHelper, PmMode, PromptStrategy, Strategy};
 use crate::{
     config::Config,
     error::{Error, Result},
-    exec::{Cmd, StatusCode},
-    print::println_err,
+    exec::{self, Cmd, StatusCode},
+    print::{self, println_err},
 };
 
 macro_rules! doc_self {
@@ -37,12 +36,43 @@ static STRAT_PROMPT: LazyLock<Strategy> = LazyLock::new(|| Strategy {
     ..Strategy::default()
 });
 
+/// Changes the system without prompting for confirmation, e.g. by refreshing
+/// the local package database.
+static STRAT_REFRESH: LazyLock<Strategy> = LazyLock::new(|| Strategy {
+    mutating: true,
+    ..Strategy::default()
+});
+
 impl Xbps {
     #[must_use]
     #[allow(missing_docs)]
     pub const fn new(cfg: Config) -> Self {
         Self { cfg }
     }
+
+    /// Prints out the `pkgver` of the installed package `pkg`.
+    async fn print_pkgver(&self, pkg: &str, flags: &[&str]) -> Result<()> {
+        let cmd = Cmd::new(["xbps-query", "--property", "pkgver", pkg]).flags(flags);
+        match self
+            .check_output(cmd, PmMode::Mute, &Strategy::default())
+            .await
+        {
+            Ok(line) => {
+                print::print_raw(exec::decode(&line));
+                Ok(())
+            }
+            Err(
+                e @ Error::CmdStatusCodeError {
+                    code: PKG_NOT_FOUND_CODE,
+                    ..
+                },
+            ) => {
+                println_err(format_args!("package `{pkg}` was not found"));
+                Err(e)
+            }
+            Err(e) => Err(e),
+        }
+    }
 }
 
 #[async_trait]
@@ -63,37 +93,7 @@ impl Pm for Xbps {
                 .run(Cmd::new(["xbps-query", "-l"]).kws(kws).flags(flags))
                 .await;
         }
-
-        let lines: Vec<_> = stream::iter(kws)
-            .map(Ok)
-            .and_then(|&pkg| async {
-                let cmd = Cmd::new(["xbps-query", "--property", "pkgver", pkg]).flags(flags);
-                match self
-                    .check_output(cmd, PmMode::Mute, &Strategy::default())
-                    .await
-                {
-                    Ok(line) => Ok(Ok(line)),
-                    Err(Error::CmdStatusCodeError {
-                        code: PKG_NOT_FOUND_CODE,
-                        output,
-                    }) => Ok(Err((pkg.to_owned(), output))),
-                    Err(e) => Err(e),
-                }
-            })
-            .try_collect()
-            .await?;
-
-        let mut stdout = std::io::stdout();
-        lines.into_iter().try_for_each(|line| match line {
-            Ok(line) => stdout.write_all(&line).map_err(Into::into),
-            Err((missing, output)) => {
-                println_err(format_args!("package `{missing}` was not found"));
-                Err(Error::CmdStatusCodeError {
-                    code: PKG_NOT_FOUND_CODE,
-                    output,
-                })
-            }
-        })
+        for_each_kw(&self.cfg, kws, |pkg| self.print_pkgver(pkg, flags)).await
     }
 
     /// Qe lists packages installed explicitly (not as dependencies).
@@ -103,51 +103,21 @@ impl Pm for Xbps {
                 .run(Cmd::new(["xbps-query", "-m"]).kws(kws).flags(flags))
                 .await;
         }
-
-        let lines: Vec<Result<Vec<u8>, String>> = stream::iter(kws)
-            .filter(|pkg| async {
-                let check_cmd =
-                    Cmd::new(["xbps-query", "--property", "automatic-install", pkg]).flags(flags);
-                self.check_output(check_cmd, PmMode::Mute, &Strategy::default())
-                    .await
-                    // If a package is manually installed,
-                    // then the automatic-install field is empty.
-                    .map_or(true, |auto_stat| auto_stat.is_empty())
-            })
-            .map(Ok)
-            .and_then(|&pkg| async {
-                let cmd = Cmd::new(["xbps-query", "--property", "pkgver", pkg]).flags(flags);
-                match self
-                    .check_output(cmd, PmMode::Mute, &Strategy::default())
-                    .await
-                {
-                    Ok(line) => Ok(Ok(line)),
-                    Err(Error::CmdStatusCodeError {
-                        code: PKG_NOT_FOUND_CODE,
-                        ..
-                    }) => Ok(Err(pkg.to_owned())),
-                    Err(e) => Err(e),
-                }
-            })
-            .try_collect()
-            .await?;
-
-        let mut stdout = std::io::stdout();
-        lines.into_iter().try_fold(Ok(()), |acc, line| {
-            std::io::Result::Ok(match line {
-                Ok(line) => {
-                    stdout.write_all(&line)?;
-                    acc
-                }
-                Err(missing) => {
-                    println_err(format_args!("package `{missing}` was not found"));
-                    Err(Error::CmdStatusCodeError {
-                        code: PKG_NOT_FOUND_CODE,
-                        output: vec![],
-                    })
-                }
-            })
-        })?
+        for_each_kw(&self.cfg, kws, |pkg| async move {
+            let check_cmd =
+                Cmd::new(["xbps-query", "--property", "automatic-install", pkg]).flags(flags);
+            let manual = self
+                .check_output(check_cmd, PmMode::Mute, &Strategy::default())
+                .await
+                // If a package is manually installed,
+                // then the automatic-install field is empty.
+                .map_or(true, |auto_stat| auto_stat.is_empty());
+            if !manual {
+                return Ok(());
+            }
+            self.print_pkgver(pkg, flags).await
+        })
+        .await
     }
 
     /// Qi displays local package information: name, version, description, etc.
@@ -245,7 +215,10 @@ impl Pm for Xbps {
 
     /// Sy refreshes the local package database.
     async fn sy(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
-        self.run(Cmd::new(["xbps-install", "-S"]).kws(kws).flags(flags))
+        Cmd::new(["xbps-install", "-S"])
+            .kws(kws)
+            .flags(flags)
+            .pipe(|cmd| self.run_with(cmd, self.default_mode(), &STRAT_REFRESH))
             .await
     }
 
@@ -4,13 +4,23 @@ use std::sync::LazyLock;
 
 use async_trait::async_trait;
 use indoc::indoc;
+use itertools::{chain, Itertools};
 use tap::prelude::*;
 
-use super::{DryRunStrategy, NoCacheStrategy, Pm, PmHelper, PmMode, PromptStrategy, Strategy};
+use super::{
+    dnf::{parse_rpm, parse_rpm_file_checks, rpm_check_cmds, RPM_QUERY},
+    undo_id, DryRunStrategy, NoCacheStrategy, Pm, PmHelper, PmMode, PromptStrategy, Strategy,
+    WeakModifier,
+};
 use crate::{
     config::Config,
-    error::Result,
-    exec::{self, Cmd},
+    error::{Error, Result},
+    exec::{
+        self,
+        result_class::{self, ResultClass},
+        Cmd, Mode, Output, StatusCode,
+    },
+    pkg::{self, Pkg},
 };
 
 macro_rules! doc_self {
@@ -43,8 +53,37 @@ static STRAT_INSTALL: LazyLock<Strategy> = LazyLock::new(|| Strategy {
     prompt: PromptStrategy::native_no_confirm(["-y"]),
     no_cache: NoCacheStrategy::Scc,
     dry_run: DryRunStrategy::with_flags(["--dry-run"]),
+    ..Strategy::default()
+});
+
+static STRAT_PROMPT_CUSTOM: LazyLock<Strategy> = LazyLock::new(|| Strategy {
+    prompt: PromptStrategy::CustomPrompt,
+    ..Strategy::default()
 });
 
+/// The exit code of `zypper` when the transaction could not be committed.
+const EXIT_ERR_COMMIT: StatusCode = 8;
+
+/// The exit codes of `zypper` reporting a success along with some information,
+/// e.g. that a reboot is needed.
+const EXIT_INF: std::ops::RangeInclusive<StatusCode> = 100..=103;
+
+/// The exit code of `zypper` when some of the requested packages were not
+/// found.
+const EXIT_INF_CAP_NOT_FOUND: StatusCode = 104;
+
+/// The `zypper` query listing the installed packages requiring a package, in
+/// a form readable by [`parse_installed_names`].
+const REQUIRES_PKG_QUERY: [&str; 7] = [
+    "zypper",
+    "--quiet",
+    "search",
+    "--installed-only",
+    "--type",
+    "package",
+    "--requires-pkg",
+];
+
 impl Zypper {
     #[must_use]
     #[allow(missing_docs)]
@@ -56,6 +95,24 @@ impl Zypper {
         self.run_with(cmd, self.default_mode(), &STRAT_CHECK_DRY)
             .await
     }
+
+    /// Returns the `zypper` command line running `args`, preceded by the
+    /// global options adding the repositories in [`Config::enable_repo`] for
+    /// the sync operations.
+    fn zypper<'a>(&'a self, args: &[&'a str]) -> Vec<&'a str> {
+        let repos = (self.cfg.enable_repo.iter()).flat_map(|repo| ["--plus-repo", repo]);
+        chain!(["zypper"], repos, args.iter().copied()).collect()
+    }
+
+    /// Returns the flag of `zypper dist-upgrade` allowing or refusing
+    /// downgrades, according to [`Config::allow_downgrade`].
+    const fn downgrade_flag(&self) -> &'static str {
+        if self.cfg.allow_downgrade {
+            "--allow-downgrade"
+        } else {
+            "--no-allow-downgrade"
+        }
+    }
 }
 
 #[async_trait]
@@ -69,9 +126,129 @@ impl Pm for Zypper {
         &self.cfg
     }
 
+    fn supports_allow_downgrade(&self) -> bool {
+        true
+    }
+
+    fn supports_enable_repo(&self) -> bool {
+        true
+    }
+
+    fn supports_undo(&self) -> bool {
+        true
+    }
+
+    fn classify_result(&self, code: StatusCode, output: &Output) -> ResultClass {
+        match code {
+            EXIT_ERR_COMMIT => ResultClass::TransactionFailed,
+            EXIT_INF_CAP_NOT_FOUND => ResultClass::PkgNotFound,
+            _ if EXIT_INF.contains(&code) => result_class::classify(self.name(), 0, output),
+            _ => result_class::classify(self.name(), code, output),
+        }
+    }
+
+    /// Repair tries to bring a system in a broken or half-configured state
+    /// back to a consistent one.
+    async fn repair(&self, _kws: &[&str], flags: &[&str]) -> Result<()> {
+        Cmd::with_sudo(["zypper", "verify"])
+            .flags(flags)
+            .pipe(|cmd| self.run_with(cmd, self.default_mode(), &STRAT_CHECK_DRY))
+            .await
+    }
+
+    /// Downgrade installs an older version of the given packages.
+    ///
+    /// Each package should be pinned to the version to downgrade to with
+    /// `name=version`, which `zypper` understands as is.
+    async fn downgrade(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
+        Cmd::with_sudo(["zypper", "install", "--oldpackage"])
+            .kws(kws.iter().map(|&kw| match pkg::split_pinned(kw) {
+                (name, Some(version)) => format!("{name}={version}"),
+                (name, None) => name.to_owned(),
+            }))
+            .flags(flags)
+            .pipe(|cmd| self.run_with(cmd, self.default_mode(), &STRAT_INSTALL))
+            .await
+    }
+
+    /// Undo reverts the changes of the transaction given by the number of its
+    /// `pre` snapshot (or a `pre..post` range) in `snapper`, or of the last one
+    /// if no ID is given.
+    ///
+    /// This relies on the `snapper` integration of `zypper`, which takes a pair
+    /// of snapshots around each transaction.
+    async fn undo(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
+        let id = undo_id(kws)?;
+        if !self.cfg.dry_run && !exec::is_exe("snapper", "") {
+            return Err(Error::OtherError(
+                "undoing a `zypper` transaction requires `snapper`".into(),
+            ));
+        }
+        let range = if let Some(range) = id.filter(|id| id.contains("..")) {
+            range.to_owned()
+        } else {
+            let out = Cmd::new(["snapper", "list", "--type", "pre-post"])
+                .exec(Mode::Mute)
+                .await?;
+            let pairs = parse_snapper_pairs(&exec::decode(&out));
+            let (pre, post) = match id {
+                Some(id) => pairs.into_iter().find(|(pre, _)| pre == id),
+                None => pairs.into_iter().last(),
+            }
+            .ok_or_else(|| {
+                Error::OtherError(format!(
+                    "no `zypper` transaction {}is found in the `snapper` snapshots",
+                    id.map(|id| format!("`{id}` ")).unwrap_or_default(),
+                ))
+            })?;
+            format!("{pre}..{post}")
+        };
+        Cmd::with_sudo(["snapper", "undochange", &range])
+            .flags(flags)
+            .pipe(|cmd| self.run_with(cmd, self.default_mode(), &STRAT_PROMPT_CUSTOM))
+            .await
+    }
+
+    /// Service list lists the services, or the ones matching the given
+    /// patterns.
+    async fn service_list(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
+        let cmd = Cmd::new(["zypper", "services"]).flags(flags);
+        if kws.is_empty() {
+            return self.run(cmd).await;
+        }
+        let out = self
+            .check_output(cmd, PmMode::Mute, &Strategy::default())
+            .await?;
+        exec::grep_print_with_header(&exec::decode(&out), kws, 2)
+    }
+
+    /// Service add adds the service given by its URI and its alias.
+    async fn service_add(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
+        let [uri, alias] = kws else {
+            return Err(Error::ArgParseError {
+                msg: "`service --add` expects the URI and the alias of the service".into(),
+            });
+        };
+        Cmd::with_sudo(["zypper", "addservice"])
+            .kws([uri, alias])
+            .flags(flags)
+            .pipe(|cmd| self.run_with(cmd, self.default_mode(), &STRAT_PROMPT_CUSTOM))
+            .await
+    }
+
+    /// Service remove removes the given services, along with their
+    /// repositories.
+    async fn service_remove(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
+        Cmd::with_sudo(["zypper", "removeservice"])
+            .kws(kws)
+            .flags(flags)
+            .pipe(|cmd| self.run_with(cmd, self.default_mode(), &STRAT_PROMPT_CUSTOM))
+            .await
+    }
+
     /// Q generates a list of installed packages.
     async fn q(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
-        if kws.is_empty() {
+        if kws.is_empty() && !self.cfg.query_format().is_structured() {
             Cmd::new(["rpm", "-qa", "--qf", "%{NAME} %{VERSION}\\n"])
                 .flags(flags)
                 .pipe(|cmd| self.run(cmd))
@@ -95,23 +272,44 @@ impl Pm for Zypper {
         self.si(kws, flags).await
     }
 
+    /// Qii displays local packages which require X to be installed, aka local
+    /// reverse dependencies.
+    async fn qii(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
+        Cmd::new(["zypper", "search", "--installed-only", "--requires-pkg"])
+            .kws(kws)
+            .flags(flags)
+            .pipe(|cmd| self.run(cmd))
+            .await
+    }
+
+    /// Qk verifies one or more packages.
+    async fn qk(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
+        let [list, verify] = rpm_check_cmds(kws, flags);
+        self.check_files(list, verify, parse_rpm_file_checks, false)
+            .await
+    }
+
+    /// Qkk verifies one or more packages, listing every altered file.
+    async fn qkk(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
+        let [list, verify] = rpm_check_cmds(kws, flags);
+        self.check_files(list, verify, parse_rpm_file_checks, true)
+            .await
+    }
+
     /// Ql displays files provided by local package.
     async fn ql(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
-        self.run(Cmd::new(["rpm", "-ql"]).kws(kws).flags(flags))
+        self.list_files(Cmd::new(["rpm", "-ql"]).kws(kws).flags(flags))
             .await
     }
 
     /// Qm lists packages that are installed but are not available in any
     /// installation source (anymore).
     async fn qm(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
-        let cmd = Cmd::new(["zypper", "search", "-si"]).kws(kws).flags(flags);
-        let out_bytes = self
-            .check_output(cmd, PmMode::Mute, &Strategy::default())
-            .await?;
-        let out = String::from_utf8(out_bytes)?;
-
-        exec::grep_print(&out, &["System Packages"])?;
-        Ok(())
+        Cmd::new(["zypper", "search", "-si", "-t", "package"])
+            .kws(kws)
+            .flags(flags)
+            .pipe(|cmd| self.print_unavailable(cmd, parse_system_pkgs))
+            .await
     }
 
     /// Qo queries the package which provides FILE.
@@ -132,6 +330,12 @@ impl Pm for Zypper {
     // when including multiple search terms, only packages with descriptions
     // matching ALL of those terms are returned.
     async fn qs(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
+        if self.cfg.query_format().is_structured() {
+            return Cmd::new(RPM_QUERY)
+                .flags(flags)
+                .pipe(|cmd| self.print_pkgs(cmd, kws, parse_rpm))
+                .await;
+        }
         Cmd::new(["zypper", "search", "--installed-only"])
             .kws(kws)
             .flags(flags)
@@ -147,6 +351,8 @@ impl Pm for Zypper {
 
     /// R removes a single package, leaving all of its dependencies installed.
     async fn r(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
+        self.check_required_by(kws, required_by_cmd, parse_installed_names)
+            .await?;
         Cmd::with_sudo(["zypper", "remove"])
             .kws(kws)
             .flags(flags)
@@ -154,9 +360,11 @@ impl Pm for Zypper {
             .await
     }
 
-    /// Rss removes a package and its dependencies which are not required by any
-    /// other installed package.
-    async fn rss(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
+    /// Rs removes a package and its dependencies which are not required by any
+    /// other installed package, and not explicitly installed by the user.
+    async fn rs(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
+        self.check_required_by(kws, required_by_cmd, parse_installed_names)
+            .await?;
         Cmd::with_sudo(["zypper", "remove", "--clean-deps"])
             .kws(kws)
             .flags(flags)
@@ -164,9 +372,23 @@ impl Pm for Zypper {
             .await
     }
 
+    /// Rss removes a package and its dependencies which are not required by any
+    /// other installed package.
+    ///
+    /// `zypper` never removes the packages installed explicitly by the user
+    /// as dependencies, so this is the same as [`Zypper::rs`].
+    async fn rss(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
+        self.warn_weak_modifier(WeakModifier {
+            op: "rss",
+            flag: "-ss",
+            actual: "the dependencies installed explicitly are kept just like with `-Rs`",
+        });
+        self.rs(kws, flags).await
+    }
+
     /// S installs one or more packages by name.
     async fn s(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
-        Cmd::with_sudo(["zypper", "install"])
+        Cmd::with_sudo(self.zypper(&["install"]))
             .kws(kws)
             .flags(flags)
             .pipe(|cmd| self.run_with(cmd, self.default_mode(), &STRAT_INSTALL))
@@ -188,15 +410,22 @@ impl Pm for Zypper {
 
     /// Scc removes all files from the cache.
     async fn scc(&self, _kws: &[&str], flags: &[&str]) -> Result<()> {
+        self.warn_weak_modifier(WeakModifier {
+            op: "scc",
+            flag: "-cc",
+            actual: "the cache is cleaned just like with `-Sc`",
+        });
         self.sc(_kws, flags).await
     }
 
     /// Sg lists all packages belonging to the GROUP.
+    ///
+    /// The groups are the patterns of `zypper`.
     async fn sg(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
         Cmd::new(if kws.is_empty() {
-            ["zypper", "patterns"]
+            ["zypper", "search", "-t", "pattern"]
         } else {
-            ["zypper", "info"]
+            ["zypper", "info", "-t", "pattern"]
         })
         .kws(kws)
         .flags(flags)
@@ -206,7 +435,7 @@ impl Pm for Zypper {
 
     /// Si displays remote package information: name, version, description, etc.
     async fn si(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
-        Cmd::new(["zypper", "info", "--requires"])
+        Cmd::new(self.zypper(&["info", "--requires"]))
             .kws(kws)
             .flags(flags)
             .pipe(|cmd| self.check_dry(cmd))
@@ -216,7 +445,7 @@ impl Pm for Zypper {
     /// Sl displays a list of all packages in all installation sources that are
     /// handled by the package management.
     async fn sl(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
-        let cmd = &["zypper", "packages", "-R"];
+        let cmd = self.zypper(&["packages", "-R"]);
         if kws.is_empty() {
             let cmd = Cmd::new(cmd).kws(kws).flags(flags);
             return self.check_dry(cmd).await;
@@ -224,21 +453,22 @@ impl Pm for Zypper {
         let cmd = Cmd::new(cmd).flags(flags);
         let out = self
             .check_output(cmd, PmMode::Mute, &STRAT_CHECK_DRY)
-            .await?
-            .pipe(String::from_utf8)?;
-        exec::grep_print_with_header(&out, kws, 4)
+            .await?;
+        exec::grep_print_with_header(&exec::decode(&out), kws, 4)
     }
 
     /// Ss searches for package(s) by searching the expression in name,
     /// description, short description.
     async fn ss(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
-        self.check_dry(Cmd::new(["zypper", "search"]).kws(kws).flags(flags))
+        self.check_dry(Cmd::new(self.zypper(&["search"])).kws(kws).flags(flags))
             .await
     }
 
     /// Su updates outdated packages.
+    ///
+    /// Downgrades are only allowed with [`Config::allow_downgrade`].
     async fn su(&self, _kws: &[&str], flags: &[&str]) -> Result<()> {
-        Cmd::with_sudo(["zypper", "--no-refresh", "dist-upgrade"])
+        Cmd::with_sudo(self.zypper(&["--no-refresh", "dist-upgrade", self.downgrade_flag()]))
             .flags(flags)
             .pipe(|cmd| self.run_with(cmd, self.default_mode(), &STRAT_INSTALL))
             .await
@@ -247,7 +477,7 @@ impl Pm for Zypper {
     /// Suy refreshes the local package database, then updates outdated
     /// packages.
     async fn suy(&self, _kws: &[&str], flags: &[&str]) -> Result<()> {
-        Cmd::with_sudo(["zypper", "dist-upgrade"])
+        Cmd::with_sudo(self.zypper(&["dist-upgrade", self.downgrade_flag()]))
             .flags(flags)
             .pipe(|cmd| self.run_with(cmd, self.default_mode(), &STRAT_INSTALL))
             .await
@@ -256,7 +486,7 @@ impl Pm for Zypper {
     /// Sw retrieves all packages from the server, but does not install/upgrade
     /// anything.
     async fn sw(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
-        Cmd::with_sudo(["zypper", "install", "--download-only"])
+        Cmd::with_sudo(self.zypper(&["install", "--download-only"]))
             .kws(kws)
             .flags(flags)
             .pipe(|cmd| self.run_with(cmd, self.default_mode(), &STRAT_INSTALL))
@@ -265,7 +495,7 @@ impl Pm for Zypper {
 
     /// Sy refreshes the local package database.
     async fn sy(&self, kws: &[&str], flags: &[&str]) -> Result<()> {
-        self.check_dry(Cmd::with_sudo(["zypper", "refresh"]).flags(flags))
+        self.check_dry(Cmd::with_sudo(self.zypper(&["refresh"])).flags(flags))
             .await?;
         if !kws.is_empty() {
             self.s(kws, flags).await?;
@@ -279,3 +509,234 @@ impl Pm for Zypper {
         self.s(kws, flags).await
     }
 }
+
+/// Parses the output of `snapper list --type pre-post` into the numbers of
+/// the `pre` and `post` snapshots taken around each `zypper` transaction, in
+/// order.
+fn parse_snapper_pairs(text: &str) -> Vec<(String, String)> {
+    text.lines()
+        .filter(|ln| ln.contains("zypp("))
+        .filter_map(|ln| {
+            let mut cols = ln.split('|').map(str::trim);
+            let (pre, post) = (cols.next()?, cols.next()?);
+            let is_num = |s: &str| !s.is_empty() && s.chars().all(|c| c.is_ascii_digit());
+            (is_num(pre) && is_num(post)).then(|| (pre.to_owned(), post.to_owned()))
+        })
+        .collect()
+}
+
+/// Parses the output of `zypper search -si` into the [`Pkg`]s that are only
+/// available from `(System Packages)`, i.e. the installed packages only.
+///
+/// A package is listed as `S | name | type | version | arch | repository`.
+fn parse_system_pkgs(text: &str) -> Vec<Pkg> {
+    text.lines()
+        .filter_map(|ln| {
+            let cols = ln.split('|').map(str::trim).collect_vec();
+            let &[_, name, _, version, _, "(System Packages)"] = &cols[..] else {
+                return None;
+            };
+            Some(Pkg::new(name, version, "System Packages"))
+        })
+        .collect()
+}
+
+/// Returns the command listing the installed packages requiring the package
+/// `kw`, in the form readable by [`parse_installed_names`].
+fn required_by_cmd(kw: &str) -> Cmd {
+    Cmd::new(REQUIRES_PKG_QUERY).kws([kw])
+}
+
+/// Parses the output of [`REQUIRES_PKG_QUERY`] into the names of the
+/// installed packages.
+///
+/// A package is listed as `S | name | summary | type`, where the status `S`
+/// of an installed package is `i` (or `i+` if installed by the user).
+fn parse_installed_names(text: &str) -> Vec<String> {
+    text.lines()
+        .filter_map(|ln| {
+            let mut cols = ln.split('|').map(str::trim);
+            let (status, name) = (cols.next()?, cols.next()?);
+            status.starts_with('i').then(|| name.to_owned())
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use indoc::indoc;
+
+    use super::*;
+    use crate::{
+        pkg::check_golden,
+        pm::mock::{capture_output, MockLog},
+    };
+
+    #[test]
+    fn porcelain_golden() {
+        check_golden("zypper-system", parse_system_pkgs);
+    }
+
+    #[test]
+    fn snapper_pairs() {
+        let text = indoc! {"
+            Pre # | Post # | Pre Date                 | Post Date                | Description  | Userdata
+            ------+--------+--------------------------+--------------------------+--------------+-------------
+               12 |     13 | Mon Jan 22 10:00:00 2024 | Mon Jan 22 10:01:00 2024 | zypp(zypper) | important=no
+               14 |     15 | Tue Jan 23 09:00:00 2024 | Tue Jan 23 09:00:30 2024 | yast sw_single |
+               16 |     17 | Wed Jan 24 08:00:00 2024 | Wed Jan 24 08:02:00 2024 | zypp(zypper) | important=yes
+        "};
+        assert_eq!(
+            parse_snapper_pairs(text),
+            [
+                ("12".to_owned(), "13".to_owned()),
+                ("16".to_owned(), "17".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn installed_names() {
+        let text = indoc! {"
+            S  | Name          | Summary                                  | Type
+            ---+---------------+------------------------------------------+--------
+            i  | git-core      | Core git tools                           | package
+            i+ | libcurl-devel | Development files for the curl library   | package
+        "};
+        assert_eq!(parse_installed_names(text), ["git-core", "libcurl-devel"]);
+    }
+
+    #[tokio::test]
+    async fn weak_modifiers() {
+        let pm = Zypper::new(Config {
+            dry_run: true,
+            ..Config::default()
+        });
+        let (res, out) = capture_output(MockLog::new().scope(pm.scc(&[], &[]))).await;
+        res.unwrap();
+        assert!(out.contains(
+            "`-cc` in `-Scc` is not fully supported by `zypper`: the cache is cleaned just like with `-Sc`"
+        ));
+        let (res, out) = capture_output(MockLog::new().scope(pm.sc(&[], &[]))).await;
+        res.unwrap();
+        assert!(!out.contains("not fully supported"));
+
+        let (res, out) = capture_output(MockLog::new().scope(pm.rss(&["curl"], &[]))).await;
+        res.unwrap();
+        assert!(out.contains(
+            "`-ss` in `-Rss` is not fully supported by `zypper`: the dependencies installed \
+             explicitly are kept just like with `-Rs`"
+        ));
+    }
+
+    #[test]
+    fn classify_result() {
+        let zypper = Zypper::new(Config::default());
+        let nothing = b"Loading repository data...\nNothing to do.\n".to_vec();
+        for (code, class) in [
+            (0, ResultClass::NothingToDo),
+            (102, ResultClass::NothingToDo),
+            (104, ResultClass::PkgNotFound),
+            (8, ResultClass::TransactionFailed),
+            (1, ResultClass::Failed),
+        ] {
+            assert_eq!(zypper.classify_result(code, &nothing), class, "code {code}");
+        }
+    }
+
+    #[tokio::test]
+    async fn classified_failures() {
+        let zypper = Zypper::new(Config {
+            no_confirm: true,
+            quiet: Some(true),
+            ..Config::default()
+        });
+        // A reboot being needed is not a failure.
+        let log = MockLog::new().fail("zypper install", 102);
+        log.scope(zypper.s(&["curl"], &[])).await.unwrap();
+
+        let not_found = include_str!("../../tests/golden/not_found/zypper.txt");
+        let log = MockLog::new().fail_with("zypper install", 104, not_found);
+        let err = log
+            .scope(zypper.s(&["no-such-package"], &[]))
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(&err, Error::PkgNotFoundError { pkgs, .. } if pkgs == &["no-such-package"]),
+            "{err:?}"
+        );
+        let log = MockLog::new().fail("zypper install", 8);
+        let err = log.scope(zypper.s(&["curl"], &[])).await.unwrap_err();
+        assert_eq!(err.status_code(), Some(8));
+    }
+
+    #[tokio::test]
+    async fn allow_downgrade() {
+        for (allow_downgrade, flag) in
+            [(false, "--no-allow-downgrade"), (true, "--allow-downgrade")]
+        {
+            let zypper = Zypper::new(Config {
+                no_confirm: true,
+                allow_downgrade,
+                ..Config::default()
+            });
+            let log = MockLog::new();
+            (log.scope(zypper.su(&[], &[])).await).unwrap();
+            (log.scope(zypper.suy(&[], &[])).await).unwrap();
+            assert_eq!(
+                log.lines(),
+                [
+                    format!("zypper --no-refresh dist-upgrade {flag} -y"),
+                    format!("zypper dist-upgrade {flag} -y"),
+                ]
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn enable_repo() {
+        let zypper = Zypper::new(Config {
+            no_confirm: true,
+            enable_repo: vec![
+                "https://download.opensuse.org/repositories/shells:/fish/openSUSE_Tumbleweed/"
+                    .into(),
+            ],
+            ..Config::default()
+        });
+        let log = MockLog::new();
+        (log.scope(zypper.s(&["fish"], &[])).await).unwrap();
+        (log.scope(zypper.su(&[], &[])).await).unwrap();
+        // `--plus-repo` is a global option, which must precede the command.
+        let repo = "--plus-repo \
+                    https://download.opensuse.org/repositories/shells:/fish/openSUSE_Tumbleweed/";
+        assert_eq!(
+            log.lines(),
+            [
+                format!("zypper {repo} install -y fish"),
+                format!("zypper {repo} --no-refresh dist-upgrade --no-allow-downgrade -y"),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn services() {
+        let zypper = Zypper::new(Config {
+            no_confirm: true,
+            ..Config::default()
+        });
+        let uri = "https://download.opensuse.org/repositories/home:/user/openSUSE_Tumbleweed/";
+        let log = MockLog::new();
+        (log.scope(zypper.service_add(&[uri, "user"], &[])).await).unwrap();
+        (log.scope(zypper.service_remove(&["user"], &[])).await).unwrap();
+        assert_eq!(
+            log.lines(),
+            [
+                format!("zypper addservice {uri} user"),
+                "zypper removeservice user".into(),
+            ]
+        );
+        // Both the URI and the alias are required.
+        let res = log.scope(zypper.service_add(&[uri], &[])).await;
+        assert!(matches!(res, Err(Error::ArgParseError { .. })), "{res:?}");
+    }
+}
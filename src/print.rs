@@ -4,15 +4,78 @@
 
 pub mod prompt;
 pub(crate) mod style;
+pub mod theme;
 
-use std::fmt::{self, Debug, Display};
+use std::{
+    borrow::Cow,
+    collections::HashSet,
+    fmt::{self, Debug, Display, Write as _},
+    future::Future,
+    io,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, LazyLock, Mutex, OnceLock, PoisonError,
+    },
+    time::Duration,
+};
 
-use console::{style, Style};
+use console::{measure_text_width, style, Style};
 use dialoguer::theme::ColorfulTheme;
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use terminal_size::{terminal_size_of, Width};
+
+use self::theme::Theme;
+use crate::{
+    config::Config,
+    exec::context,
+    pkg::{Conflict, DepGraph, FileCheck, Pkg, RepoStatus, Update, Verification},
+};
 
 /// The right indentation to be applied on prompt prefixes.
 static PROMPT_INDENT: usize = 9;
 
+/// The extra indentation to be applied on the continuation lines of a wrapped
+/// command echo.
+static CMD_HANGING_INDENT: usize = 4;
+
+/// The maximum number of keywords to be shown in a command echo, where `0`
+/// stands for no limit.
+static CMD_ECHO_LIMIT: AtomicUsize = AtomicUsize::new(0);
+
+/// The width to reflow the output to, where `0` stands for the width of the
+/// current terminal.
+static WRAP_WIDTH: AtomicUsize = AtomicUsize::new(0);
+
+/// The width to reflow the output to when `stdout` is not a terminal.
+static DEFAULT_WRAP_WIDTH: usize = 80;
+
+/// The width of the terminal behind `stdout`, where the output is written, or
+/// [`DEFAULT_WRAP_WIDTH`] if it is not a terminal.
+static TERM_WIDTH: LazyLock<usize> = LazyLock::new(|| {
+    terminal_size_of(io::stdout()).map_or(DEFAULT_WRAP_WIDTH, |(Width(w), _)| w.into())
+});
+
+/// The [`Theme`] of the output, which is the default one unless set otherwise
+/// with [`set_theme`].
+static THEME: OnceLock<Theme> = OnceLock::new();
+
+/// A shared writer capturing the command echoes of [`println_cmd`], see
+/// [`capture_echoes`].
+pub type EchoSink = Arc<Mutex<dyn io::Write + Send>>;
+
+/// A shared list collecting the [`Pkg`]s of [`println_pkgs`], see
+/// [`capture_pkgs`].
+pub type PkgSink = Arc<Mutex<Vec<Pkg>>>;
+
+tokio::task_local! {
+    /// The [`EchoSink`] of the current task, if any.
+    static ECHO_SINK: EchoSink;
+
+    /// The [`PkgSink`] of the current task, if any.
+    static PKG_SINK: PkgSink;
+}
+
 macro_rules! prompt_format {
     () => {
         "{:>indent$}"
@@ -43,34 +106,815 @@ pub fn write_err(f: &mut fmt::Formatter, prompt: impl Display, err: impl Debug)
     )
 }
 
-/// Prints out a message after the given prompt.
+/// Writes a (possibly multi-line) hint, indented under the previous line.
+#[allow(clippy::missing_errors_doc)]
+pub fn write_hint(f: &mut fmt::Formatter, hint: &str) -> fmt::Result {
+    hint.lines()
+        .try_for_each(|line| write!(f, "\n{:indent$}{line}", "", indent = PROMPT_INDENT + 1))
+}
+
+/// The number of lines at the end of the output of a failed command to be
+/// shown by [`write_cmd_failure`].
+const FAILURE_OUTPUT_TAIL: usize = 20;
+
+/// Writes the details of a failed command, i.e. the command itself, the mode
+/// it has been run in and the end of its captured `output`, indented under the
+/// previous line.
+#[allow(clippy::missing_errors_doc)]
+pub fn write_cmd_failure(
+    f: &mut fmt::Formatter,
+    cmd: &str,
+    mode: impl Display,
+    output: &[u8],
+) -> fmt::Result {
+    f.write_str(&format_cmd_failure(cmd, mode, output))
+}
+
+/// Formats the details of a failed command of [`write_cmd_failure`].
+fn format_cmd_failure(cmd: &str, mode: impl Display, output: &[u8]) -> String {
+    let indent = PROMPT_INDENT + 1;
+    let text = String::from_utf8_lossy(output);
+    let text = console::strip_ansi_codes(&text);
+    let lines = text.lines().collect_vec();
+    let tail = &lines[lines.len().saturating_sub(FAILURE_OUTPUT_TAIL)..];
+    let mut res = format!(
+        "\n{:indent$}command: `{cmd}`\n{:indent$}mode: {mode}",
+        "", ""
+    );
+    if tail.is_empty() {
+        return res;
+    }
+    let head = if tail.len() < lines.len() {
+        format!("output (last {} of {} lines):", tail.len(), lines.len())
+    } else {
+        "output:".into()
+    };
+    _ = write!(res, "\n{:indent$}{head}", "");
+    for line in tail {
+        _ = write!(res, "\n{:indent$}| {line}", "");
+    }
+    res
+}
+
+/// Prints out `text` as is with the [`OutputSink`](context::OutputSink) of the
+/// current [`Context`](context::Context), i.e. to `stdout` by default.
+pub fn print_raw(text: impl Display) {
+    context::current().output().print(&text.to_string());
+}
+
+/// Prints out a message after the given prompt, reflowed to [`wrap_width`].
 pub fn println(prompt: impl Display, msg: impl Display) {
-    println!(
-        plain_format!(),
+    print_raw(format_args!(
+        concat!(plain_format!(), "\n"),
         style::MESSAGE.apply_to(prompt),
-        msg,
+        reflow(&msg.to_string(), wrap_width(), PROMPT_INDENT + 1),
         indent = PROMPT_INDENT,
-    );
+    ));
 }
 
-/// Prints out an error message.
+/// Prints out an error message, reflowed to [`wrap_width`].
 pub fn println_err(msg: impl Display) {
-    println!(
-        plain_format!(),
+    print_raw(format_args!(
+        concat!(plain_format!(), "\n"),
         &*prompt::ERROR,
-        msg,
+        reflow(&msg.to_string(), wrap_width(), PROMPT_INDENT + 1),
         indent = PROMPT_INDENT,
-    );
+    ));
 }
 
 /// Prints out a backtick-quoted message after the given prompt.
 pub fn println_quoted(prompt: impl Display, msg: impl Display) {
-    println!(
-        quoted_format!(),
+    print_raw(format_args!(
+        concat!(quoted_format!(), "\n"),
         style::MESSAGE.apply_to(prompt),
         msg,
         indent = PROMPT_INDENT,
+    ));
+}
+
+/// Sets the width to reflow the output to. `None` means the width of the
+/// current terminal.
+pub fn set_wrap_width(width: Option<usize>) {
+    // This is a standalone setting, so `Ordering::Relaxed` is fine.
+    WRAP_WIDTH.store(width.unwrap_or_default(), Ordering::Relaxed);
+}
+
+/// Returns the width to reflow the output to.
+///
+/// This is the one set with [`set_wrap_width`] if any, or the width of the
+/// terminal behind `stdout` (queried only once), or [`DEFAULT_WRAP_WIDTH`] if
+/// `stdout` is not a terminal, even if `stderr` or `stdin` is.
+#[must_use]
+pub fn wrap_width() -> usize {
+    match WRAP_WIDTH.load(Ordering::Relaxed) {
+        0 => *TERM_WIDTH,
+        width => width,
+    }
+}
+
+/// Sets the maximum number of keywords to be shown in a command echo before
+/// the ones in the middle get elided. `None` means no limit.
+pub fn set_cmd_echo_limit(limit: Option<usize>) {
+    // This is a standalone setting, so `Ordering::Relaxed` is fine.
+    CMD_ECHO_LIMIT.store(limit.unwrap_or_default(), Ordering::Relaxed);
+}
+
+/// Prints out a command after the given prompt, backtick-quoted.
+///
+/// The command is given in `head-keywords` form, where the keywords might be
+/// elided according to [`set_cmd_echo_limit`]. The command will also be wrapped
+/// at argument boundaries to fit in [`wrap_width`].
+///
+/// If the command is going to be run as another `user` (e.g. `root`), it will
+/// be marked with a `[user]` tag.
+pub fn println_cmd(
+    prompt: impl Display,
+    user: Option<&str>,
+    head: impl IntoIterator<Item = impl AsRef<str>>,
+    kws: &[impl AsRef<str>],
+) {
+    let limit = Some(CMD_ECHO_LIMIT.load(Ordering::Relaxed)).filter(|&n| n > 0);
+    let words = head
+        .into_iter()
+        .map(|s| Cow::Owned(s.as_ref().into()))
+        .chain(elide_kws(kws, limit))
+        .collect::<Vec<_>>();
+    let Ok(sink) = ECHO_SINK.try_with(Arc::clone) else {
+        print_raw(format_args!(
+            "{}\n",
+            format_cmd(prompt, user, &words, wrap_width())
+        ));
+        return;
+    };
+    let echo = format_cmd(prompt, user, &words, usize::MAX);
+    _ = writeln!(
+        sink.lock().unwrap_or_else(PoisonError::into_inner),
+        "{}",
+        console::strip_ansi_codes(&echo)
+    );
+}
+
+/// Runs `fut` with the command echoes of [`println_cmd`] written to `sink`
+/// instead of `stdout`, e.g. to check the commands printed out in a dry run.
+///
+/// The captured echoes are neither styled nor wrapped. Also, the commands
+/// asking for `sudo` are always echoed as such regardless of the current user,
+/// so that the captured output is reproducible.
+pub async fn capture_echoes<F: Future>(sink: EchoSink, fut: F) -> F::Output {
+    ECHO_SINK.scope(sink, fut).await
+}
+
+/// Checks if the command echoes of the current task are being captured by
+/// [`capture_echoes`].
+pub(crate) fn is_capturing_echoes() -> bool {
+    ECHO_SINK.try_with(|_| ()).is_ok()
+}
+
+/// Sets the [`Theme`] of the output.
+///
+/// This should be done before anything is printed out, as the styles cannot
+/// be changed once they are used.
+pub fn set_theme(theme: Theme) {
+    // The theme has already been used if this fails, so it's too late anyway.
+    _ = THEME.set(theme);
+}
+
+/// Returns the current [`Theme`] of the output.
+pub fn theme() -> &'static Theme {
+    THEME.get_or_init(Theme::default)
+}
+
+/// Formats a command echo of [`println_cmd`] to fit in `width` columns.
+fn format_cmd(
+    prompt: impl Display,
+    user: Option<&str>,
+    words: &[impl AsRef<str>],
+    width: usize,
+) -> String {
+    let tag = user.map_or_else(String::new, |user| {
+        format!("{} ", style::PRIVILEGED.apply_to(format_args!("[{user}]")))
+    });
+    let start = PROMPT_INDENT + 2 + measure_text_width(&tag);
+    format!(
+        concat!(prompt_format!(), " {}`{}`"),
+        style::MESSAGE.apply_to(prompt),
+        tag,
+        style::COMMAND.apply_to(wrap_words(words, width, start, CMD_HANGING_INDENT)),
+        indent = PROMPT_INDENT,
+    )
+}
+
+/// Prints out a list of package updates in the `name old -> new` format of
+/// `pacman -Qu`.
+///
+/// The lines that could not be parsed are printed out as is, followed by a
+/// warning.
+pub fn println_updates(updates: &[Result<Update, String>]) {
+    for update in updates {
+        match update {
+            Ok(update) => print_raw(format_args!("{}\n", format_update(update))),
+            Err(line) => print_raw(format_args!("{line}\n")),
+        }
+    }
+    let unparsed = updates.iter().filter(|update| update.is_err()).count();
+    if unparsed > 0 {
+        println(
+            &*prompt::WARNING,
+            format_args!("{unparsed} line(s) could not be parsed and are shown as is"),
+        );
+    }
+}
+
+/// Formats an [`Update`] with the old version dimmed and the new version
+/// highlighted.
+fn format_update(update: &Update) -> String {
+    format!(
+        "{} {} -> {}",
+        update.name,
+        style::DIMMED.apply_to(&update.old),
+        style::HIGHLIGHT.apply_to(&update.new),
+    )
+}
+
+/// The format of the results of query operations.
+///
+/// See [`pkg`](crate::pkg) for more info.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    /// The output of the package manager as is.
+    #[default]
+    Plain,
+
+    /// One package per line as `name<TAB>version<TAB>source`.
+    Porcelain,
+
+    /// Aligned columns of name, version and source.
+    Table,
+
+    /// A JSON array of objects with the `name`, `version` and `source` keys.
+    Json,
+
+    /// One JSON object per line with the `name`, `version` and `source` keys,
+    /// where each package is printed out as soon as it is parsed (if
+    /// possible).
+    #[serde(rename = "json-lines")]
+    JsonLines,
+}
+
+impl OutputFormat {
+    /// Checks if this format requires the output of the package manager to be
+    /// parsed.
+    #[must_use]
+    pub const fn is_structured(self) -> bool {
+        !matches!(self, Self::Plain)
+    }
+}
+
+/// Prints out a list of [`Pkg`]s in the given [`OutputFormat`], unless they
+/// are being collected by [`capture_pkgs`].
+pub fn println_pkgs(pkgs: &[Pkg], format: OutputFormat) {
+    if let Ok(sink) = PKG_SINK.try_with(Arc::clone) {
+        (sink.lock().unwrap_or_else(PoisonError::into_inner)).extend_from_slice(pkgs);
+        return;
+    }
+    print_raw(format_pkgs(pkgs, format));
+}
+
+/// Runs `fut` with the [`Pkg`]s of [`println_pkgs`] collected in `sink`
+/// instead of being printed out, e.g. to merge the results of several package
+/// managers.
+pub async fn capture_pkgs<F: Future>(sink: PkgSink, fut: F) -> F::Output {
+    PKG_SINK.scope(sink, fut).await
+}
+
+/// Prints out a hint to reinstall or remove the installed packages `names`
+/// listed by `-Qm`, as they are not available in any repository.
+pub fn println_unavailable_hint(names: &[impl AsRef<str>]) {
+    let names = names.iter().map(AsRef::as_ref).join(" ");
+    println(
+        &*prompt::INFO,
+        "the packages above are not available in any repository, so they will never be upgraded",
+    );
+    println(
+        &*prompt::INFO,
+        format_args!(
+            "consider reinstalling them from a repository with `pacaptr -S {names}`, or \
+             removing them with `pacaptr -R {names}`"
+        ),
+    );
+}
+
+/// Formats a list of [`Pkg`]s of [`println_pkgs`].
+fn format_pkgs(pkgs: &[Pkg], format: OutputFormat) -> String {
+    match format {
+        // The raw output has no counterpart here, so it falls back to the porcelain one.
+        OutputFormat::Plain | OutputFormat::Porcelain => {
+            pkgs.iter().map(|pkg| format!("{pkg}\n")).join("")
+        }
+        OutputFormat::Table => format_table(
+            &pkgs
+                .iter()
+                .map(|pkg| [&*pkg.name, &*pkg.version, &*pkg.source])
+                .collect_vec(),
+        ),
+        OutputFormat::Json => format_json(pkgs),
+        OutputFormat::JsonLines => format_json_lines(pkgs),
+    }
+}
+
+/// Formats the given rows into left-aligned columns.
+fn format_table<const N: usize>(rows: &[[&str; N]]) -> String {
+    /// The number of spaces between two columns.
+    const GAP: usize = 2;
+
+    let widths = (0..N)
+        .map(|i| {
+            rows.iter()
+                .map(|row| measure_text_width(row[i]))
+                .max()
+                .unwrap_or_default()
+        })
+        .collect_vec();
+    rows.iter()
+        .map(|row| {
+            let line = row
+                .iter()
+                .zip(&widths)
+                .map(|(cell, width)| {
+                    let pad = width - measure_text_width(cell);
+                    format!("{cell}{:pad$}", "")
+                })
+                .join(&" ".repeat(GAP));
+            format!("{}\n", line.trim_end())
+        })
+        .join("")
+}
+
+/// Formats the given value as pretty-printed JSON, followed by a newline.
+fn format_json(value: &(impl Serialize + ?Sized)) -> String {
+    let json = serde_json::to_string_pretty(value).expect("value should be serializable to JSON");
+    format!("{json}\n")
+}
+
+/// Formats the given values as JSON, one per line.
+fn format_json_lines<T: Serialize>(values: impl IntoIterator<Item = T>) -> String {
+    values
+        .into_iter()
+        .map(|value| {
+            serde_json::to_string(&value).expect("value should be serializable to JSON") + "\n"
+        })
+        .collect()
+}
+
+/// Prints out a list of names in the given [`OutputFormat`].
+///
+/// In the [`OutputFormat::Plain`] format, if `stdout` is a terminal, the names
+/// will be packed into columns to fit in [`wrap_width`] just like `ls`.
+/// Otherwise, one name is printed per line so that the output can be easily
+/// piped, unless a JSON array is requested.
+pub fn println_names(names: &[impl AsRef<str>], format: OutputFormat) {
+    match format {
+        OutputFormat::Json => {
+            print_raw(format_json(&names.iter().map(AsRef::as_ref).collect_vec()));
+            return;
+        }
+        OutputFormat::JsonLines => {
+            print_raw(format_json_lines(names.iter().map(AsRef::as_ref)));
+            return;
+        }
+        _ => (),
+    }
+    let columns = format == OutputFormat::Plain;
+    let width = (columns && console::Term::stdout().is_term()).then(wrap_width);
+    print_raw(format_names(names, width));
+}
+
+/// Formats a list of names of [`println_names`], packing them into columns in
+/// column-major order to fit in `width` if it is given.
+fn format_names(names: &[impl AsRef<str>], width: Option<usize>) -> String {
+    /// The number of spaces between two columns.
+    const GAP: usize = 2;
+
+    let lens = names
+        .iter()
+        .map(|name| measure_text_width(name.as_ref()))
+        .collect_vec();
+    // Finds the layout with the least rows where all columns fit in `width`.
+    let layout = width.and_then(|width| {
+        (1..=names.len()).find_map(|rows| {
+            let col_widths = lens
+                .chunks(rows)
+                .map(|col| col.iter().copied().max().unwrap_or_default())
+                .collect_vec();
+            let total = col_widths.iter().sum::<usize>() + GAP * (col_widths.len() - 1);
+            (total <= width).then_some((rows, col_widths))
+        })
+    });
+    let Some((rows, col_widths)) = layout else {
+        return names
+            .iter()
+            .map(|name| format!("{}\n", name.as_ref()))
+            .join("");
+    };
+    let mut res = String::new();
+    for row in 0..rows {
+        let line = (row..names.len())
+            .step_by(rows)
+            .zip(&col_widths)
+            .map(|(i, &col_width)| {
+                let pad = col_width - lens[i];
+                format!("{}{:pad$}", names[i].as_ref(), "")
+            })
+            .join(&" ".repeat(GAP));
+        res.push_str(line.trim_end());
+        res.push('\n');
+    }
+    res
+}
+
+/// A handle reporting the progress of a loop over multiple items, e.g. the
+/// keywords of an operation.
+///
+/// Each step prints out a whole `[i/n] item` line right before the step
+/// itself, so that it never gets mixed up with the live output of the child
+/// processes that follow.
+#[must_use]
+#[derive(Debug)]
+pub struct Progress {
+    /// The total number of steps.
+    total: usize,
+
+    /// The number of steps started so far.
+    started: AtomicUsize,
+
+    /// Whether the progress should be hidden, see [`Config::quiet`].
+    quiet: bool,
+}
+
+impl Progress {
+    /// Makes a new [`Progress`] instance for a loop of `total` steps.
+    ///
+    /// The progress is only shown when there is more than one step, and it is
+    /// hidden altogether if [`Config::quiet`] holds for `cfg`.
+    pub fn new(total: usize, cfg: &Config) -> Self {
+        Self {
+            total,
+            started: AtomicUsize::new(0),
+            quiet: cfg.quiet(),
+        }
+    }
+
+    /// Reports the start of the next step, which works on `item`.
+    pub fn step(&self, item: impl Display) {
+        // This is a standalone counter, so `Ordering::Relaxed` is fine.
+        let curr = self.started.fetch_add(1, Ordering::Relaxed) + 1;
+        if !self.quiet && self.total > 1 {
+            print_raw(format_args!(
+                "{}\n",
+                format_progress(curr, self.total, item)
+            ));
+        }
+    }
+}
+
+/// Formats a progress line of [`Progress::step`].
+fn format_progress(curr: usize, total: usize, item: impl Display) -> String {
+    format!(
+        plain_format!(),
+        style::MESSAGE.apply_to(format!("[{curr}/{total}]")),
+        item,
+        indent = PROMPT_INDENT,
+    )
+}
+
+/// Prints out the summary of an operation `op` done by the package manager
+/// `pm`, in which `cmds` commands have been executed in `elapsed`.
+///
+/// The summary is colored according to the exit status `code`, where `None`
+/// means that the operation has failed without one.
+pub fn println_summary(op: &str, pm: &str, cmds: usize, elapsed: Duration, code: Option<i32>) {
+    print_raw(format_args!(
+        "{}\n",
+        format_summary(op, pm, cmds, elapsed, code)
+    ));
+}
+
+/// Formats an operation summary of [`println_summary`].
+fn format_summary(op: &str, pm: &str, cmds: usize, elapsed: Duration, code: Option<i32>) -> String {
+    let (style, status) = match code {
+        Some(0) => (&*style::SUCCESS, "exited with code 0".into()),
+        Some(code) => (&*style::FAILURE, format!("exited with code {code}")),
+        None => (&*style::FAILURE, "failed".into()),
+    };
+    let plural = if cmds == 1 { "" } else { "s" };
+    let msg = format!(
+        "`{op}` with `{pm}`: {cmds} command{plural} in {:.1}s, {status}",
+        elapsed.as_secs_f64(),
+    );
+    format!(
+        plain_format!(),
+        &*prompt::SUMMARY,
+        style.apply_to(msg),
+        indent = PROMPT_INDENT,
+    )
+}
+
+/// Elides the keywords in the middle if there are more than `limit` of them,
+/// replacing them with a single `… (+n more)` marker.
+fn elide_kws(kws: &[impl AsRef<str>], limit: Option<usize>) -> Vec<Cow<'_, str>> {
+    let kws = kws.iter().map(|s| Cow::Borrowed(s.as_ref()));
+    match limit {
+        Some(limit) if kws.len() > limit => {
+            let (head, tail) = (limit - limit / 2, limit / 2);
+            let marker = format!("… (+{} more)", kws.len() - limit);
+            let skipped = kws.len() - tail;
+            kws.clone()
+                .take(head)
+                .chain([Cow::Owned(marker)])
+                .chain(kws.skip(skipped))
+                .collect()
+        }
+        _ => kws.collect(),
+    }
+}
+
+/// Joins the given words with spaces, wrapping the result at word boundaries
+/// so that no line exceeds `width` columns.
+///
+/// The first line is supposed to start at column `start`, and the following
+/// ones are indented by `start + hanging` spaces. A word that is too long to
+/// fit in a line is put on a line of its own.
+fn wrap_words(words: &[impl AsRef<str>], width: usize, start: usize, hanging: usize) -> String {
+    let indent = start + hanging;
+    let mut res = String::new();
+    // The column of the cursor, and whether the current line is still empty.
+    let (mut col, mut empty) = (start, true);
+    for word in words {
+        let word = word.as_ref();
+        let len = measure_text_width(word);
+        if !empty && col.saturating_add(1 + len) > width {
+            res.push('\n');
+            res.extend(std::iter::repeat_n(' ', indent));
+            (col, empty) = (indent, true);
+        }
+        if !empty {
+            res.push(' ');
+            col += 1;
+        }
+        res.push_str(word);
+        (col, empty) = (col + len, false);
+    }
+    res
+}
+
+/// Reflows each line of `text` to fit in `width` columns with [`wrap_words`],
+/// lining up all the resulting lines at column `start`.
+fn reflow(text: &str, width: usize, start: usize) -> String {
+    text.lines()
+        .map(|ln| wrap_words(&ln.split_whitespace().collect_vec(), width, start, 0))
+        .join(&format!("\n{:start$}", ""))
+}
+
+/// Formats a size in bytes in a human-readable way, e.g. `1.5 MiB`.
+#[must_use]
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["KiB", "MiB", "GiB", "TiB", "PiB"];
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+    #[allow(clippy::cast_precision_loss)]
+    let mut size = bytes as f64 / 1024.;
+    let mut unit = UNITS[0];
+    for next in &UNITS[1..] {
+        if size < 1024. {
+            break;
+        }
+        size /= 1024.;
+        unit = next;
+    }
+    format!("{size:.1} {unit}")
+}
+
+/// The width of the size column of [`println_file_sizes`], enough for sizes
+/// like `1023.9 KiB`.
+const SIZE_WIDTH: usize = 10;
+
+/// Prints out the given files along with their sizes, where `None` means that
+/// the file is missing, followed by the total size.
+pub fn println_file_sizes(files: &[(impl AsRef<str>, Option<u64>)]) {
+    print_raw(format_file_sizes(files));
+}
+
+/// Formats a file list of [`println_file_sizes`].
+fn format_file_sizes(files: &[(impl AsRef<str>, Option<u64>)]) -> String {
+    let lines = files.iter().map(|(path, size)| {
+        let size = size.map_or_else(
+            || style::FAILURE.apply_to(format!("{:>SIZE_WIDTH$}", "missing")),
+            |size| Style::new().apply_to(format!("{:>SIZE_WIDTH$}", format_size(size))),
+        );
+        format!("{size}  {}\n", path.as_ref())
+    });
+    let total = files.iter().filter_map(|(_, size)| *size).sum();
+    let missing = files.iter().filter(|(_, size)| size.is_none()).count();
+    let plural = if files.len() == 1 { "" } else { "s" };
+    let missing = if missing == 0 {
+        String::new()
+    } else {
+        format!(", {missing} missing")
+    };
+    let total = format!(
+        "{:>SIZE_WIDTH$}  total of {} file{plural}{missing}\n",
+        format_size(total),
+        files.len(),
     );
+    lines
+        .chain([style::HIGHLIGHT.apply_to(total).to_string()])
+        .collect()
+}
+
+/// Prints out the installed sizes of the given packages as `(name, bytes)`,
+/// followed by the total size.
+pub fn println_pkg_sizes(sizes: &[(impl AsRef<str>, u64)]) {
+    print_raw(format_pkg_sizes(sizes));
+}
+
+/// Formats the package sizes of [`println_pkg_sizes`], aligned as in
+/// [`println_file_sizes`].
+fn format_pkg_sizes(sizes: &[(impl AsRef<str>, u64)]) -> String {
+    let lines = sizes
+        .iter()
+        .map(|(name, size)| format!("{:>SIZE_WIDTH$}  {}\n", format_size(*size), name.as_ref()));
+    let total = sizes.iter().map(|(_, size)| size).sum();
+    let plural = if sizes.len() == 1 { "" } else { "s" };
+    let total = format!(
+        "{:>SIZE_WIDTH$}  total of {} package{plural}\n",
+        format_size(total),
+        sizes.len(),
+    );
+    lines
+        .chain([style::HIGHLIGHT.apply_to(total).to_string()])
+        .collect()
+}
+
+/// Prints out the dependency conflicts explained by `--explain-conflicts`.
+pub fn println_conflicts(conflicts: &[Conflict]) {
+    if conflicts.is_empty() {
+        println(
+            &*prompt::WARNING,
+            "no dependency conflict could be found in the output of the resolver",
+        );
+        return;
+    }
+    println(&*prompt::INFO, "found the following dependency conflicts:");
+    print_raw(format_conflicts(conflicts));
+}
+
+/// Formats the dependency conflicts of [`println_conflicts`], one per line
+/// and aligned with the prompts.
+fn format_conflicts(conflicts: &[Conflict]) -> String {
+    conflicts.iter().fold(String::new(), |mut res, conflict| {
+        _ = writeln!(res, "{:>indent$} {conflict}", "-", indent = PROMPT_INDENT);
+        res
+    })
+}
+
+/// Prints out the dependency trees of the packages `roots` in the dependency
+/// graph `deps`, as shown with `--tree`.
+pub fn println_dep_tree(roots: &[impl AsRef<str>], deps: &DepGraph) {
+    print_raw(format_dep_tree(roots, deps));
+}
+
+/// Formats the dependency trees of [`println_dep_tree`].
+///
+/// The dependencies of each package are only expanded the first time it is
+/// shown, after which it is marked with `(*)`. A dependency on one of its own
+/// ancestors is marked with `(cycle)` instead.
+fn format_dep_tree<'a>(roots: &'a [impl AsRef<str>], deps: &'a DepGraph) -> String {
+    /// Writes the dependencies of `name` below it, where `path` holds its
+    /// ancestors (including itself) and `prefix` is the indentation of its
+    /// children.
+    fn write_deps<'a>(
+        res: &mut String,
+        deps: &'a DepGraph,
+        name: &'a str,
+        path: &mut Vec<&'a str>,
+        seen: &mut HashSet<&'a str>,
+        prefix: &str,
+    ) {
+        let children = deps.get(name).map_or(&[][..], Vec::as_slice);
+        for (i, child) in children.iter().enumerate() {
+            let last = i + 1 == children.len();
+            let (branch, indent) = if last {
+                ("└── ", "    ")
+            } else {
+                ("├── ", "│   ")
+            };
+            let has_deps = deps.get(child).is_some_and(|d| !d.is_empty());
+            let mark = if path.contains(&child.as_str()) {
+                " (cycle)"
+            } else if has_deps && !seen.insert(child) {
+                " (*)"
+            } else {
+                ""
+            };
+            _ = writeln!(res, "{prefix}{branch}{child}{mark}");
+            if mark.is_empty() {
+                path.push(child);
+                write_deps(res, deps, child, path, seen, &(prefix.to_owned() + indent));
+                path.pop();
+            }
+        }
+    }
+
+    let mut res = String::new();
+    let mut seen = HashSet::new();
+    for root in roots {
+        let root = root.as_ref();
+        _ = writeln!(res, "{}", style::HIGHLIGHT.apply_to(root));
+        seen.insert(root);
+        write_deps(&mut res, deps, root, &mut vec![root], &mut seen, "");
+    }
+    res
+}
+
+/// Prints out the results of verifying packages in the given
+/// [`OutputFormat`].
+///
+/// Only [`OutputFormat::Json`] and [`OutputFormat::JsonLines`] are treated
+/// specially, while all the other formats print a human-readable report.
+pub fn println_verifications(verifications: &[Verification], format: OutputFormat) {
+    print_raw(format_verifications(verifications, format));
+}
+
+/// Formats the verification results of [`println_verifications`].
+fn format_verifications(verifications: &[Verification], format: OutputFormat) -> String {
+    match format {
+        OutputFormat::Json => return format_json(verifications),
+        OutputFormat::JsonLines => return format_json_lines(verifications),
+        _ => (),
+    }
+    verifications.iter().map(format_verification).collect()
+}
+
+/// Formats a single [`Verification`] as a summary line, followed by one line
+/// per changed file in the style of `rpm -V`.
+fn format_verification(verification: &Verification) -> String {
+    let files = match verification.changes.len() {
+        0 => style::SUCCESS.apply_to("no changed files".to_owned()),
+        1 => style::FAILURE.apply_to("1 changed file".to_owned()),
+        n => style::FAILURE.apply_to(format!("{n} changed files")),
+    };
+    let repo = match verification.repo {
+        RepoStatus::Match => style::SUCCESS.apply_to("matches the repositories"),
+        RepoStatus::Mismatch => style::FAILURE.apply_to("differs from the repositories"),
+        RepoStatus::NotFound => style::DIMMED.apply_to("not found in the repositories"),
+    };
+    let mut res = format!(
+        "{} {}: {files}, version {repo}\n",
+        verification.name, verification.version
+    );
+    for change in &verification.changes {
+        let kind = if change.config { 'c' } else { ' ' };
+        _ = writeln!(res, "    {:<9} {kind} {}", change.attrs, change.path);
+    }
+    res
+}
+
+/// Prints out the results of checking the files of installed packages, with one
+/// line per altered file if `details` is set.
+pub fn println_file_checks(checks: &[FileCheck], details: bool, format: OutputFormat) {
+    print_raw(format_file_checks(checks, details, format));
+}
+
+/// Formats the file checks of [`println_file_checks`].
+fn format_file_checks(checks: &[FileCheck], details: bool, format: OutputFormat) -> String {
+    match format {
+        OutputFormat::Json => return format_json(checks),
+        OutputFormat::JsonLines => return format_json_lines(checks),
+        _ => (),
+    }
+    let mut res = String::new();
+    for check in checks {
+        let plural = if check.total == 1 { "" } else { "s" };
+        let altered = match check.altered.len() {
+            0 => style::SUCCESS.apply_to("0 altered".to_owned()),
+            n => style::FAILURE.apply_to(format!("{n} altered")),
+        };
+        _ = writeln!(
+            res,
+            "{}: {} file{plural} checked, {altered}",
+            check.name, check.total,
+        );
+        if !details {
+            continue;
+        }
+        for change in &check.altered {
+            let kind = if change.config { 'c' } else { ' ' };
+            _ = writeln!(res, "    {:<9} {kind} {}", change.attrs, change.path);
+        }
+    }
+    res
 }
 
 /// Returns a [`dialoguer`] theme with the given prompt.
@@ -82,7 +926,7 @@ pub(crate) fn question_theme(prompt: impl Display) -> impl dialoguer::theme::The
     ));
     ColorfulTheme {
         success_prefix: prompt_prefix.clone(),
-        error_prefix: prompt_prefix.clone().red(),
+        error_prefix: style::ERROR.apply_to(prompt_prefix.to_string()),
         prompt_prefix,
         prompt_style: Style::new(),
         prompt_suffix: style(String::new()),
@@ -92,3 +936,457 @@ pub(crate) fn question_theme(prompt: impl Display) -> impl dialoguer::theme::The
         ..ColorfulTheme::default()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use indoc::indoc;
+
+    use super::*;
+    use crate::pkg::FileChange;
+
+    fn pkgs(n: usize) -> Vec<String> {
+        (1..=n).map(|i| format!("pkg{i}")).collect()
+    }
+
+    #[test]
+    fn elide_kws_under_limit() {
+        let kws = pkgs(3);
+        assert_eq!(elide_kws(&kws, Some(3)), ["pkg1", "pkg2", "pkg3"]);
+        assert_eq!(elide_kws(&kws, None), ["pkg1", "pkg2", "pkg3"]);
+    }
+
+    #[test]
+    fn elide_kws_over_limit() {
+        let kws = pkgs(40);
+        assert_eq!(
+            elide_kws(&kws, Some(8)),
+            [
+                "pkg1",
+                "pkg2",
+                "pkg3",
+                "pkg4",
+                "… (+32 more)",
+                "pkg37",
+                "pkg38",
+                "pkg39",
+                "pkg40"
+            ],
+        );
+        assert_eq!(
+            elide_kws(&kws, Some(3)),
+            ["pkg1", "pkg2", "… (+37 more)", "pkg40"]
+        );
+        assert_eq!(elide_kws(&kws, Some(1)), ["pkg1", "… (+39 more)"]);
+    }
+
+    #[test]
+    fn format_cmd_unprivileged() {
+        let words = ["brew", "install", "curl", "fish", "wget"];
+        let got = format_cmd("Running", None, &words, usize::MAX);
+        assert_eq!(
+            console::strip_ansi_codes(&got),
+            "  Running `brew install curl fish wget`"
+        );
+        let got = format_cmd("Running", None, &words, 30);
+        assert_eq!(
+            console::strip_ansi_codes(&got),
+            "  Running `brew install curl\n               fish wget`"
+        );
+    }
+
+    #[test]
+    fn format_cmd_privileged() {
+        let words = ["sudo", "-S", "apt", "install", "curl", "fish"];
+        let got = format_cmd("Pending", Some("root"), &words, usize::MAX);
+        assert_eq!(
+            console::strip_ansi_codes(&got),
+            "  Pending [root] `sudo -S apt install curl fish`"
+        );
+        let got = format_cmd("Pending", Some("postgres"), &words, 42);
+        assert_eq!(
+            console::strip_ansi_codes(&got),
+            "  Pending [postgres] `sudo -S apt install\n                          curl fish`"
+        );
+    }
+
+    #[test]
+    fn reflow_lines() {
+        let text = "package `foo` was not found\nplease check the spelling";
+        assert_eq!(
+            reflow(text, 30, 10),
+            indoc! {"
+                package `foo` was
+                          not found
+                          please check the
+                          spelling"
+            }
+        );
+        assert_eq!(
+            reflow(text, usize::MAX, 10),
+            text.replace('\n', "\n          ")
+        );
+    }
+
+    #[test]
+    fn format_names_columns() {
+        let names = [
+            "apt",
+            "brew",
+            "choco",
+            "conda",
+            "dnf",
+            "stack",
+            "xbps-install",
+        ];
+        let format = |width| format_names(&names, Some(width));
+        assert_eq!(
+            format(80),
+            "apt  brew  choco  conda  dnf  stack  xbps-install\n"
+        );
+        assert_eq!(
+            format(40),
+            indoc! {"
+                apt   choco  dnf    xbps-install
+                brew  conda  stack
+            "}
+        );
+        assert_eq!(
+            format(20),
+            indoc! {"
+                apt    dnf
+                brew   stack
+                choco  xbps-install
+                conda
+            "}
+        );
+        assert_eq!(format(5), names.iter().map(|s| format!("{s}\n")).join(""));
+        assert_eq!(format_names(&[] as &[&str], Some(80)), "");
+    }
+
+    #[test]
+    fn format_names_piped() {
+        let names = ["apt", "brew", "choco"];
+        assert_eq!(format_names(&names, None), "apt\nbrew\nchoco\n");
+    }
+
+    #[test]
+    fn format_pkgs_in_each_format() {
+        let pkgs = [
+            Pkg::new("curl", "8.5.0-2ubuntu10.6", "noble-updates"),
+            Pkg::new("wget", "1.21.4-1ubuntu4.1", "noble"),
+        ];
+        let porcelain = "curl\t8.5.0-2ubuntu10.6\tnoble-updates\nwget\t1.21.4-1ubuntu4.1\tnoble\n";
+        assert_eq!(format_pkgs(&pkgs, OutputFormat::Porcelain), porcelain);
+        let table = indoc! {"
+            curl  8.5.0-2ubuntu10.6  noble-updates
+            wget  1.21.4-1ubuntu4.1  noble
+        "};
+        assert_eq!(format_pkgs(&pkgs, OutputFormat::Table), table);
+        let json = indoc! {r#"
+            [
+              {
+                "name": "curl",
+                "version": "8.5.0-2ubuntu10.6",
+                "source": "noble-updates"
+              },
+              {
+                "name": "wget",
+                "version": "1.21.4-1ubuntu4.1",
+                "source": "noble"
+              }
+            ]
+        "#};
+        assert_eq!(format_pkgs(&pkgs, OutputFormat::Json), json);
+        assert_eq!(format_pkgs(&[], OutputFormat::Json), "[]\n");
+        let json_lines = indoc! {r#"
+            {"name":"curl","version":"8.5.0-2ubuntu10.6","source":"noble-updates"}
+            {"name":"wget","version":"1.21.4-1ubuntu4.1","source":"noble"}
+        "#};
+        assert_eq!(format_pkgs(&pkgs, OutputFormat::JsonLines), json_lines);
+        assert_eq!(format_pkgs(&[], OutputFormat::JsonLines), "");
+    }
+
+    #[test]
+    fn format_updates() {
+        let got = format_update(&Update::new("curl", "8.6.0-8", "8.6.0-10"));
+        assert_eq!(console::strip_ansi_codes(&got), "curl 8.6.0-8 -> 8.6.0-10");
+    }
+
+    #[test]
+    fn format_progresses() {
+        let got = format_progress(3, 7, "curl");
+        assert_eq!(console::strip_ansi_codes(&got), "    [3/7] curl");
+        let got = format_progress(12, 120, "wget");
+        assert_eq!(console::strip_ansi_codes(&got), " [12/120] wget");
+    }
+
+    #[test]
+    fn progress_steps() {
+        let progress = Progress::new(3, &Config::default());
+        progress.step("foo");
+        progress.step("bar");
+        assert_eq!(progress.started.load(Ordering::Relaxed), 2);
+        let cfg = Config {
+            quiet: Some(true),
+            ..Config::default()
+        };
+        assert!(Progress::new(3, &cfg).quiet);
+    }
+
+    #[test]
+    fn format_summaries() {
+        let summary = |cmds, code| {
+            let got = format_summary("-Suy", "apt", cmds, Duration::from_millis(12345), code);
+            console::strip_ansi_codes(&got).into_owned()
+        };
+        assert_eq!(
+            summary(2, Some(0)),
+            "  Summary `-Suy` with `apt`: 2 commands in 12.3s, exited with code 0"
+        );
+        assert_eq!(
+            summary(1, Some(100)),
+            "  Summary `-Suy` with `apt`: 1 command in 12.3s, exited with code 100"
+        );
+        assert_eq!(
+            summary(0, None),
+            "  Summary `-Suy` with `apt`: 0 commands in 12.3s, failed"
+        );
+    }
+
+    #[test]
+    fn format_sizes() {
+        assert_eq!(format_size(0), "0 B");
+        assert_eq!(format_size(1023), "1023 B");
+        assert_eq!(format_size(1024), "1.0 KiB");
+        assert_eq!(format_size(1536 * 1024), "1.5 MiB");
+        assert_eq!(format_size(5 << 30), "5.0 GiB");
+    }
+
+    #[test]
+    fn format_cmd_failures() {
+        let output = (1..=25)
+            .map(|i| format!("\x1b[1mline {i}\x1b[0m\n"))
+            .join("");
+        let got = format_cmd_failure(
+            "apt install 'foo bar'",
+            "capturing stderr",
+            output.as_bytes(),
+        );
+        let expected = itertools::chain!(
+            [
+                "",
+                "          command: `apt install 'foo bar'`",
+                "          mode: capturing stderr",
+                "          output (last 20 of 25 lines):",
+            ]
+            .map(String::from),
+            (6..=25).map(|i| format!("          | line {i}")),
+        )
+        .join("\n");
+        assert_eq!(got, expected);
+        assert_eq!(
+            format_cmd_failure("brew update", "muted, capturing stdout and stderr", b""),
+            "\n          command: `brew update`\n          mode: muted, capturing stdout and stderr"
+        );
+    }
+
+    #[test]
+    fn format_conflict_list() {
+        let conflicts = [
+            Conflict::new("foo", "depends on", "libbar (>= 2.0)").detail("found 1.5-1"),
+            Conflict::new("foo", "conflicts with", "baz"),
+        ];
+        assert_eq!(
+            format_conflicts(&conflicts),
+            concat!(
+                "        - foo depends on libbar (>= 2.0) (found 1.5-1)\n",
+                "        - foo conflicts with baz\n",
+            )
+        );
+    }
+
+    #[test]
+    fn format_dep_trees() {
+        let deps: DepGraph = [
+            ("flask", &["werkzeug", "jinja2", "click"][..]),
+            ("werkzeug", &["markupsafe"]),
+            ("jinja2", &["markupsafe"]),
+            ("markupsafe", &[]),
+            ("click", &["colorama"]),
+            ("a", &["b"]),
+            ("b", &["a"]),
+        ]
+        .into_iter()
+        .map(|(name, deps)| (name.into(), deps.iter().map(|&d| d.into()).collect()))
+        .collect();
+        assert_eq!(
+            console::strip_ansi_codes(&format_dep_tree(&["flask", "a", "missing"], &deps)),
+            indoc! {"
+                flask
+                ├── werkzeug
+                │   └── markupsafe
+                ├── jinja2
+                │   └── markupsafe
+                └── click
+                    └── colorama
+                a
+                └── b
+                    └── a (cycle)
+                missing
+            "}
+        );
+    }
+
+    #[test]
+    fn format_verifications_as_report() {
+        let verifications = [
+            Verification {
+                name: "curl".into(),
+                version: "8.6.0-8.fc40".into(),
+                repo: RepoStatus::Match,
+                changes: vec![],
+            },
+            Verification {
+                name: "openssh-server".into(),
+                version: "9.6p1-1.fc40".into(),
+                repo: RepoStatus::Mismatch,
+                changes: vec![
+                    FileChange {
+                        path: "/etc/ssh/sshd_config".into(),
+                        attrs: "S.5....T.".into(),
+                        config: true,
+                    },
+                    FileChange {
+                        path: "/usr/sbin/sshd".into(),
+                        attrs: "missing".into(),
+                        config: false,
+                    },
+                ],
+            },
+        ];
+        let got = format_verifications(&verifications, OutputFormat::Plain);
+        assert_eq!(
+            console::strip_ansi_codes(&got),
+            concat!(
+                "curl 8.6.0-8.fc40: no changed files, version matches the repositories\n",
+                "openssh-server 9.6p1-1.fc40: 2 changed files, version differs from the repositories\n",
+                "    S.5....T. c /etc/ssh/sshd_config\n",
+                "    missing     /usr/sbin/sshd\n",
+            )
+        );
+        let json = format_verifications(&verifications[..1], OutputFormat::Json);
+        assert!(json.contains(r#""repo": "match""#));
+        assert!(json.contains(r#""changes": []"#));
+    }
+
+    #[test]
+    fn format_file_checks_as_summary() {
+        let checks = [
+            FileCheck {
+                name: "curl".into(),
+                total: 1,
+                altered: vec![],
+            },
+            FileCheck {
+                name: "openssh-server".into(),
+                total: 42,
+                altered: vec![FileChange {
+                    path: "/etc/ssh/sshd_config".into(),
+                    attrs: "S.5....T.".into(),
+                    config: true,
+                }],
+            },
+        ];
+        let summary = concat!(
+            "curl: 1 file checked, 0 altered\n",
+            "openssh-server: 42 files checked, 1 altered\n",
+        );
+        let got = format_file_checks(&checks, false, OutputFormat::Plain);
+        assert_eq!(console::strip_ansi_codes(&got), summary);
+        let got = format_file_checks(&checks, true, OutputFormat::Plain);
+        assert_eq!(
+            console::strip_ansi_codes(&got),
+            format!("{summary}    S.5....T. c /etc/ssh/sshd_config\n"),
+        );
+        let json = format_file_checks(&checks, false, OutputFormat::Json);
+        assert!(json.contains(r#""total": 42"#));
+    }
+
+    #[test]
+    fn format_file_sizes_with_total() {
+        let files = [
+            ("/usr/bin/curl", Some(256 * 1024)),
+            ("/usr/share/doc/curl/README", Some(12)),
+            ("/usr/share/man/man1/curl.1.gz", None),
+        ];
+        let got = format_file_sizes(&files);
+        assert_eq!(
+            console::strip_ansi_codes(&got),
+            concat!(
+                " 256.0 KiB  /usr/bin/curl\n",
+                "      12 B  /usr/share/doc/curl/README\n",
+                "   missing  /usr/share/man/man1/curl.1.gz\n",
+                " 256.0 KiB  total of 3 files, 1 missing\n",
+            )
+        );
+        let empty: [(&str, _); 0] = [];
+        assert_eq!(
+            console::strip_ansi_codes(&format_file_sizes(&empty)),
+            "       0 B  total of 0 files\n"
+        );
+    }
+
+    #[test]
+    fn format_pkg_sizes_with_total() {
+        let sizes = [("linux-firmware", 512 << 20), ("curl", 500 * 1024)];
+        assert_eq!(
+            console::strip_ansi_codes(&format_pkg_sizes(&sizes)),
+            concat!(
+                " 512.0 MiB  linux-firmware\n",
+                " 500.0 KiB  curl\n",
+                " 512.5 MiB  total of 2 packages\n",
+            )
+        );
+    }
+
+    #[test]
+    fn wrap_words_unlimited() {
+        let words = pkgs(40);
+        assert_eq!(
+            wrap_words(&words, usize::MAX, PROMPT_INDENT + 2, 4),
+            words.join(" ")
+        );
+    }
+
+    #[test]
+    fn wrap_words_narrow() {
+        let words = ["apt", "install", "curl", "fish", "wget", "zsh"];
+        assert_eq!(
+            wrap_words(&words, 20, 2, 4),
+            "apt install curl\n      fish wget zsh",
+        );
+        assert_eq!(
+            wrap_words(&words, 13, 2, 2),
+            "apt install\n    curl fish\n    wget zsh",
+        );
+    }
+
+    #[test]
+    fn wrap_words_too_long() {
+        let words = ["brew", "install", "a-very-long-package-name", "x"];
+        assert_eq!(
+            wrap_words(&words, 16, 0, 2),
+            "brew install\n  a-very-long-package-name\n  x",
+        );
+        assert_eq!(wrap_words(&["a-long-word"], 4, 0, 2), "a-long-word");
+    }
+
+    #[test]
+    fn wrap_words_unicode() {
+        let words = ["apt", "install", "… (+32 more)", "pkg"];
+        assert_eq!(
+            wrap_words(&words, 24, 0, 2),
+            "apt install … (+32 more)\n  pkg",
+        );
+    }
+}
@@ -6,6 +6,10 @@ type StyledStr<'a> = console::StyledObject<&'a str>;
 
 pub static CANCELED: LazyLock<StyledStr> = LazyLock::new(|| style::MESSAGE.apply_to("Canceled"));
 pub static PENDING: LazyLock<StyledStr> = LazyLock::new(|| style::MESSAGE.apply_to("Pending"));
+pub static PREVIEW: LazyLock<StyledStr> = LazyLock::new(|| style::MESSAGE.apply_to("Preview"));
+pub static SUMMARY: LazyLock<StyledStr> = LazyLock::new(|| style::MESSAGE.apply_to("Summary"));
 pub static RUNNING: LazyLock<StyledStr> = LazyLock::new(|| style::MESSAGE.apply_to("Running"));
+pub static CACHED: LazyLock<StyledStr> = LazyLock::new(|| style::MESSAGE.apply_to("Cached"));
 pub static INFO: LazyLock<StyledStr> = LazyLock::new(|| style::MESSAGE.apply_to("Info"));
+pub static WARNING: LazyLock<StyledStr> = LazyLock::new(|| style::WARNING.apply_to("Warning"));
 pub static ERROR: LazyLock<StyledStr> = LazyLock::new(|| style::ERROR.apply_to("Error"));
@@ -2,6 +2,26 @@ use std::sync::LazyLock;
 
 use console::Style;
 
-pub static MESSAGE: LazyLock<Style> = LazyLock::new(|| Style::new().green().bold());
-pub static ERROR: LazyLock<Style> = LazyLock::new(|| Style::new().bright().red().bold());
-pub static QUESTION: LazyLock<Style> = LazyLock::new(|| Style::new().yellow().bold());
+use super::theme;
+
+/// Defines the styles taken from the current [`Theme`](super::theme::Theme).
+macro_rules! styles {
+    ($( $name:ident => $role:ident, )*) => {
+        $(
+            pub static $name: LazyLock<Style> = LazyLock::new(|| theme().$role.clone());
+        )*
+    };
+}
+
+styles! {
+    MESSAGE => prompt,
+    COMMAND => command,
+    ERROR => error,
+    WARNING => warning,
+    PRIVILEGED => privileged,
+    SUCCESS => success,
+    FAILURE => failure,
+    HIGHLIGHT => highlight,
+    DIMMED => dimmed,
+    QUESTION => question,
+}
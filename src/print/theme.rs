@@ -0,0 +1,349 @@
+//! Color themes of the output of [`pacaptr`](crate).
+//!
+//! A theme maps each semantic role of the output (e.g. warnings) to a
+//! [`Style`], which is written as a simple string of space-separated words in
+//! the `[theme]` section of the config file:
+//!
+//! ```toml
+//! [theme]
+//! preset = "light"
+//! warning = "bold magenta"
+//! command = "bright cyan"
+//! success = "none"
+//! ```
+//!
+//! Each word is either an attribute (`bold`, `dim`, `italic`, `underlined`,
+//! `blink`, `reverse`, `hidden`) or a color (`black`, `red`, `green`,
+//! `yellow`, `blue`, `magenta`, `cyan`, `white`, optionally preceded by
+//! `bright`), and `none` stands for no style at all.
+
+use std::fmt::{self, Display};
+
+use console::Style;
+use serde::{Deserialize, Serialize};
+
+/// A [`Style`] parsed from its string representation in the config file.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct StyleSpec {
+    spec: String,
+    style: Style,
+}
+
+impl StyleSpec {
+    /// Returns the parsed [`Style`].
+    #[must_use]
+    pub const fn style(&self) -> &Style {
+        &self.style
+    }
+}
+
+impl TryFrom<String> for StyleSpec {
+    type Error = String;
+
+    fn try_from(spec: String) -> Result<Self, Self::Error> {
+        let style = parse_style(&spec)?;
+        Ok(Self { spec, style })
+    }
+}
+
+impl From<StyleSpec> for String {
+    fn from(spec: StyleSpec) -> Self {
+        spec.spec
+    }
+}
+
+impl Display for StyleSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.spec)
+    }
+}
+
+/// Parses a style string like `bright yellow` or `bold red`.
+fn parse_style(spec: &str) -> Result<Style, String> {
+    let mut style = Style::new();
+    let mut bright = false;
+    let mut words = spec.split_whitespace().peekable();
+    if words.peek().is_none() {
+        return Err("empty style, use `none` for no style".into());
+    }
+    for word in words {
+        if bright && !is_color(word) {
+            return Err(format!("expected a color after `bright`, found `{word}`"));
+        }
+        style = match word.to_lowercase().as_str() {
+            "none" => style,
+            "bright" => {
+                bright = true;
+                continue;
+            }
+            "bold" => style.bold(),
+            "dim" => style.dim(),
+            "italic" => style.italic(),
+            "underlined" | "underline" => style.underlined(),
+            "blink" => style.blink(),
+            "reverse" => style.reverse(),
+            "hidden" => style.hidden(),
+            "black" => style.black(),
+            "red" => style.red(),
+            "green" => style.green(),
+            "yellow" => style.yellow(),
+            "blue" => style.blue(),
+            "magenta" => style.magenta(),
+            "cyan" => style.cyan(),
+            "white" => style.white(),
+            _ => return Err(format!("unknown color or attribute `{word}` in `{spec}`")),
+        };
+        if bright {
+            style = style.bright();
+            bright = false;
+        }
+    }
+    if bright {
+        return Err(format!("expected a color after `bright` in `{spec}`"));
+    }
+    Ok(style)
+}
+
+/// Checks if `word` is the name of a color.
+fn is_color(word: &str) -> bool {
+    [
+        "black", "red", "green", "yellow", "blue", "magenta", "cyan", "white",
+    ]
+    .contains(&word.to_lowercase().as_str())
+}
+
+/// A built-in [`Theme`].
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Preset {
+    /// The default palette, designed for dark terminals.
+    #[default]
+    Default,
+
+    /// No colors at all, only attributes like `bold`.
+    Mono,
+
+    /// A palette designed for light terminals.
+    Light,
+}
+
+/// The `[theme]` section of the config file, where each role can be
+/// overridden on top of the given preset.
+#[derive(Clone, Default, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ThemeConfig {
+    /// The preset to start from.
+    pub preset: Option<Preset>,
+
+    /// The style of prompts like `Running` and `Info`.
+    pub prompt: Option<StyleSpec>,
+
+    /// The style of echoed commands.
+    pub command: Option<StyleSpec>,
+
+    /// The style of warnings.
+    pub warning: Option<StyleSpec>,
+
+    /// The style of errors.
+    pub error: Option<StyleSpec>,
+
+    /// The style of successful outcomes.
+    pub success: Option<StyleSpec>,
+
+    /// The style of failed outcomes.
+    pub failure: Option<StyleSpec>,
+
+    /// The style of the `[root]` tag of privileged commands.
+    pub privileged: Option<StyleSpec>,
+
+    /// The style of questions.
+    pub question: Option<StyleSpec>,
+
+    /// The style of highlighted parts, e.g. new versions in `-Qu`.
+    pub highlight: Option<StyleSpec>,
+
+    /// The style of dimmed parts, e.g. old versions in `-Qu`.
+    pub dimmed: Option<StyleSpec>,
+}
+
+impl ThemeConfig {
+    /// Performs a left-biased join of two `ThemeConfig`s.
+    #[must_use]
+    pub fn join(&self, other: Self) -> Self {
+        Self {
+            preset: self.preset.or(other.preset),
+            prompt: self.prompt.clone().or(other.prompt),
+            command: self.command.clone().or(other.command),
+            warning: self.warning.clone().or(other.warning),
+            error: self.error.clone().or(other.error),
+            success: self.success.clone().or(other.success),
+            failure: self.failure.clone().or(other.failure),
+            privileged: self.privileged.clone().or(other.privileged),
+            question: self.question.clone().or(other.question),
+            highlight: self.highlight.clone().or(other.highlight),
+            dimmed: self.dimmed.clone().or(other.dimmed),
+        }
+    }
+}
+
+/// The styles of each semantic role of the output.
+///
+/// See [`ThemeConfig`] for the meaning of each role.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[allow(missing_docs)]
+pub struct Theme {
+    pub prompt: Style,
+    pub command: Style,
+    pub warning: Style,
+    pub error: Style,
+    pub success: Style,
+    pub failure: Style,
+    pub privileged: Style,
+    pub question: Style,
+    pub highlight: Style,
+    pub dimmed: Style,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self::preset(Preset::Default)
+    }
+}
+
+impl Theme {
+    /// Returns the [`Theme`] of the given preset.
+    #[must_use]
+    pub fn preset(preset: Preset) -> Self {
+        let s = Style::new;
+        match preset {
+            Preset::Default => Self {
+                prompt: s().green().bold(),
+                command: s(),
+                warning: s().yellow().bold(),
+                error: s().bright().red().bold(),
+                success: s().green(),
+                failure: s().red(),
+                privileged: s().red().bold(),
+                question: s().yellow().bold(),
+                highlight: s().green().bold(),
+                dimmed: s().dim(),
+            },
+            Preset::Mono => Self {
+                prompt: s().bold(),
+                command: s(),
+                warning: s().bold(),
+                error: s().bold(),
+                success: s(),
+                failure: s().bold(),
+                privileged: s().bold(),
+                question: s().bold(),
+                highlight: s().bold(),
+                dimmed: s().dim(),
+            },
+            Preset::Light => Self {
+                prompt: s().blue().bold(),
+                command: s(),
+                warning: s().magenta().bold(),
+                error: s().red().bold(),
+                success: s().green(),
+                failure: s().red(),
+                privileged: s().red().bold(),
+                question: s().magenta().bold(),
+                highlight: s().blue().bold(),
+                dimmed: s().dim(),
+            },
+        }
+    }
+}
+
+impl From<&ThemeConfig> for Theme {
+    fn from(cfg: &ThemeConfig) -> Self {
+        let base = Self::preset(cfg.preset.unwrap_or_default());
+        let pick = |spec: &Option<StyleSpec>, default: Style| {
+            spec.as_ref().map_or(default, |spec| spec.style().clone())
+        };
+        Self {
+            prompt: pick(&cfg.prompt, base.prompt),
+            command: pick(&cfg.command, base.command),
+            warning: pick(&cfg.warning, base.warning),
+            error: pick(&cfg.error, base.error),
+            success: pick(&cfg.success, base.success),
+            failure: pick(&cfg.failure, base.failure),
+            privileged: pick(&cfg.privileged, base.privileged),
+            question: pick(&cfg.question, base.question),
+            highlight: pick(&cfg.highlight, base.highlight),
+            dimmed: pick(&cfg.dimmed, base.dimmed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use figment::{
+        providers::{Format, Toml},
+        Figment,
+    };
+
+    use super::*;
+
+    fn render(style: &Style) -> String {
+        style.apply_to("x").force_styling(true).to_string()
+    }
+
+    fn parse(toml: &str) -> Result<ThemeConfig, figment::Error> {
+        Figment::from(Toml::string(toml)).extract()
+    }
+
+    #[test]
+    fn custom_theme() {
+        let cfg = parse(
+            r#"
+            preset = "mono"
+            prompt = "bright yellow"
+            command = "underlined cyan"
+            warning = "bold red"
+            error = "none"
+            success = "Bold Green"
+            highlight = "reverse"
+            "#,
+        )
+        .unwrap();
+        let theme = Theme::from(&cfg);
+        let cases = [
+            (&theme.prompt, "\x1b[38;5;11mx\x1b[0m"),
+            (&theme.command, "\x1b[36m\x1b[4mx\x1b[0m"),
+            (&theme.warning, "\x1b[31m\x1b[1mx\x1b[0m"),
+            (&theme.error, "x"),
+            (&theme.success, "\x1b[32m\x1b[1mx\x1b[0m"),
+            (&theme.highlight, "\x1b[7mx\x1b[0m"),
+            // From the `mono` preset.
+            (&theme.failure, "\x1b[1mx\x1b[0m"),
+            (&theme.privileged, "\x1b[1mx\x1b[0m"),
+            (&theme.question, "\x1b[1mx\x1b[0m"),
+            (&theme.dimmed, "\x1b[2mx\x1b[0m"),
+        ];
+        for (style, expected) in cases {
+            assert_eq!(render(style), expected);
+        }
+    }
+
+    #[test]
+    fn default_theme() {
+        let theme = Theme::from(&ThemeConfig::default());
+        assert_eq!(theme, Theme::preset(Preset::Default));
+        assert_eq!(render(&theme.error), "\x1b[38;5;9m\x1b[1mx\x1b[0m");
+    }
+
+    #[test]
+    fn invalid_theme() {
+        for toml in [
+            r#"warning = "bold purple""#,
+            r#"warning = "bright bold""#,
+            r#"warning = "red bright""#,
+            r#"warning = """#,
+            r#"preset = "solarized""#,
+        ] {
+            assert!(parse(toml).is_err(), "`{toml}` should be rejected");
+        }
+    }
+}
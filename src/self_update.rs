@@ -0,0 +1,279 @@
+//! Updating the `pacaptr` executable itself from the latest GitHub release.
+//!
+//! This is meant for standalone installations of `pacaptr` that are not owned
+//! by any package manager. The release is downloaded with `curl` and unpacked
+//! with `tar`, which ship with all the supported platforms, and the archive is
+//! checked against the `checksums.txt` of the release before the current
+//! executable gets replaced.
+//!
+//! See [`notify`] for the periodic notification of new releases.
+
+pub mod notify;
+
+use std::{
+    env,
+    fmt::Write as _,
+    fs,
+    path::{Path, PathBuf},
+    process,
+};
+
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    config::Config,
+    error::{Error, Result},
+    exec::{Cmd, Mode},
+    print::{self, prompt, Progress},
+};
+
+/// The GitHub API endpoint of the latest release of `pacaptr`.
+const LATEST_RELEASE_URL: &str = "https://api.github.com/repos/rami3l/pacaptr/releases/latest";
+
+/// The name of the release asset listing the SHA-256 checksums of the others.
+const CHECKSUMS_ASSET: &str = "checksums.txt";
+
+/// A GitHub release, as returned by the releases API.
+#[derive(Debug, Deserialize)]
+struct Release {
+    tag_name: String,
+    assets: Vec<Asset>,
+}
+
+/// A file attached to a [`Release`].
+#[derive(Debug, Deserialize)]
+struct Asset {
+    name: String,
+    browser_download_url: String,
+}
+
+impl Release {
+    /// Returns the download URL of the asset called `name`.
+    fn asset_url(&self, name: &str) -> Result<&str> {
+        (self.assets.iter())
+            .find(|asset| asset.name == name)
+            .map(|asset| asset.browser_download_url.as_str())
+            .ok_or_else(|| {
+                error(format!(
+                    "`{name}` is missing in release `{}`",
+                    self.tag_name
+                ))
+            })
+    }
+}
+
+/// Returns an [`Error::SelfUpdateError`] with the given message.
+fn error(msg: impl Into<String>) -> Error {
+    Error::SelfUpdateError { msg: msg.into() }
+}
+
+/// Returns the name of the release asset built for the current target.
+fn asset_name() -> Result<&'static str> {
+    Ok(match () {
+        () if cfg!(target_os = "macos") => "pacaptr-darwin-universal2.tar.gz",
+        () if cfg!(all(target_os = "linux", target_arch = "x86_64")) => {
+            "pacaptr-linux-amd64.tar.gz"
+        }
+        () if cfg!(all(target_os = "linux", target_arch = "aarch64")) => {
+            "pacaptr-linux-arm64.tar.gz"
+        }
+        () if cfg!(all(windows, target_arch = "x86_64")) => "pacaptr-windows-amd64.zip",
+        () if cfg!(all(windows, target_arch = "aarch64")) => "pacaptr-windows-arm64.zip",
+        () => {
+            return Err(error(
+                "no prebuilt binary is released for the current target",
+            ))
+        }
+    })
+}
+
+/// Parses a version like `v0.22.0` into its numeric components.
+///
+/// Any pre-release or build suffix is ignored.
+fn parse_version(version: &str) -> Option<Vec<u64>> {
+    let version = version.trim().trim_start_matches('v');
+    let core = version.split(['-', '+']).next()?;
+    core.split('.').map(|n| n.parse().ok()).collect()
+}
+
+/// Checks if the release `latest` is newer than the version `current`.
+fn is_newer(latest: &str, current: &str) -> bool {
+    parse_version(latest) > parse_version(current)
+}
+
+/// Looks up the checksum of the asset called `name` in the content of
+/// `checksums.txt`, where each line is in the form of `hash  name`.
+fn find_checksum<'a>(checksums: &'a str, name: &str) -> Option<&'a str> {
+    checksums.lines().find_map(|ln| {
+        let (hash, file) = ln.split_once(char::is_whitespace)?;
+        (file.trim_start().trim_start_matches('*') == name).then_some(hash)
+    })
+}
+
+/// Returns the SHA-256 checksum of the file at `path` in lowercase hex.
+fn sha256_file(path: &Path) -> Result<String> {
+    let digest = Sha256::digest(fs::read(path)?);
+    Ok(digest.iter().fold(String::new(), |mut hex, b| {
+        _ = write!(hex, "{b:02x}");
+        hex
+    }))
+}
+
+/// Runs `curl` with the given arguments, returning its output.
+async fn curl(args: &[&str], mode: Mode) -> Result<Vec<u8>> {
+    Cmd::new(["curl", "-fsSL"]).flags(args).exec(mode).await
+}
+
+/// Fetches the latest [`Release`] from GitHub, running `curl` with the extra
+/// `args` given.
+async fn latest_release(args: &[&str]) -> Result<Release> {
+    let args = [
+        args,
+        &[
+            "-H",
+            "Accept: application/vnd.github+json",
+            LATEST_RELEASE_URL,
+        ],
+    ]
+    .concat();
+    let json = curl(&args, Mode::Mute).await?;
+    serde_json::from_slice(&json)
+        .map_err(|e| error(format!("failed to parse the latest release: {e}")))
+}
+
+/// Replaces the executable at `exe` with the one at `new` in an atomic way.
+///
+/// The new executable is first copied next to the current one, so that the
+/// final rename never crosses filesystems. On Windows, the running executable
+/// cannot be overwritten but can be renamed, so it is moved out of the way
+/// first and left to be removed by the next update.
+fn replace_exe(new: &Path, exe: &Path) -> Result<()> {
+    let staged = exe.with_file_name(".pacaptr.new");
+    fs::copy(new, &staged)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&staged, fs::Permissions::from_mode(0o755))?;
+    }
+    if cfg!(windows) {
+        let old = exe.with_extension("old.exe");
+        _ = fs::remove_file(&old);
+        fs::rename(exe, &old)?;
+        if let Err(e) = fs::rename(&staged, exe) {
+            _ = fs::rename(&old, exe);
+            return Err(e.into());
+        }
+        return Ok(());
+    }
+    fs::rename(&staged, exe).map_err(Into::into)
+}
+
+/// Updates the running `pacaptr` executable to the latest release, if it is
+/// newer than the current version.
+///
+/// With [`Config::dry_run`], only the download that would happen is reported.
+///
+/// # Errors
+/// Returns an [`Error::SelfUpdateError`] if the release is unavailable for the
+/// current target or its checksum does not match, and any error from running
+/// `curl` and `tar` or replacing the executable otherwise.
+pub async fn self_update(cfg: &Config) -> Result<()> {
+    let current = env!("CARGO_PKG_VERSION");
+    let release = latest_release(&[]).await?;
+    let latest = &release.tag_name;
+    if !is_newer(latest, current) {
+        print::println(
+            &*prompt::INFO,
+            format_args!("pacaptr v{current} is already up to date"),
+        );
+        return Ok(());
+    }
+
+    let asset = asset_name()?;
+    let url = release.asset_url(asset)?;
+    let checksums_url = release.asset_url(CHECKSUMS_ASSET)?;
+    if cfg.dry_run {
+        print::println(
+            &*prompt::INFO,
+            format_args!("would update pacaptr v{current} to {latest} from {url}"),
+        );
+        return Ok(());
+    }
+
+    let exe = env::current_exe()?;
+    let dir = env::temp_dir().join(format!("pacaptr-self-update-{}", process::id()));
+    fs::create_dir_all(&dir)?;
+    let res = async {
+        let progress = Progress::new(3, cfg);
+        progress.step(format_args!("downloading {asset}"));
+        let archive = dir.join(asset);
+        let archive_str = archive.to_string_lossy();
+        let mode = Mode::CheckErr { quiet: cfg.quiet() };
+        curl(&["-o", &archive_str, url], mode).await?;
+
+        progress.step("verifying the checksum");
+        let checksums = curl(&[checksums_url], Mode::Mute).await?;
+        let expected = find_checksum(&String::from_utf8_lossy(&checksums), asset)
+            .map(str::to_lowercase)
+            .ok_or_else(|| error(format!("no checksum is found for `{asset}`")))?;
+        let actual = sha256_file(&archive)?;
+        if actual != expected {
+            return Err(error(format!(
+                "checksum mismatch for `{asset}`: expected {expected}, got {actual}"
+            )));
+        }
+
+        progress.step(format_args!("replacing `{}`", exe.display()));
+        let dir_str = dir.to_string_lossy();
+        Cmd::new(["tar", "-xf", &archive_str, "-C", &dir_str])
+            .exec(mode)
+            .await?;
+        let new: PathBuf = dir.join(format!("pacaptr{}", env::consts::EXE_SUFFIX));
+        replace_exe(&new, &exe)
+    }
+    .await;
+    _ = fs::remove_dir_all(&dir);
+    res?;
+
+    print::println(
+        &*prompt::INFO,
+        format_args!("pacaptr has been updated from v{current} to {latest}"),
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use indoc::indoc;
+
+    use super::*;
+
+    #[test]
+    fn versions() {
+        assert!(is_newer("v0.23.0", "0.22.0"));
+        assert!(is_newer("v0.22.10", "0.22.9"));
+        assert!(is_newer("v1.0.0-rc.1", "0.22.0"));
+        assert!(!is_newer("v0.22.0", "0.22.0"));
+        assert!(!is_newer("v0.21.3", "0.22.0"));
+        assert!(!is_newer("nightly", "0.22.0"));
+    }
+
+    #[test]
+    fn checksums() {
+        let text = indoc! {"
+            0a1b  pacaptr-darwin-universal2.tar.gz
+            2c3d  pacaptr-linux-amd64.tar.gz
+            4E5F *pacaptr-windows-amd64.zip
+        "};
+        assert_eq!(
+            find_checksum(text, "pacaptr-linux-amd64.tar.gz"),
+            Some("2c3d")
+        );
+        assert_eq!(
+            find_checksum(text, "pacaptr-windows-amd64.zip"),
+            Some("4E5F")
+        );
+        assert_eq!(find_checksum(text, "pacaptr-linux-arm64.tar.gz"), None);
+    }
+}
@@ -0,0 +1,198 @@
+//! Notifying the user of a new release of `pacaptr` once in a while.
+//!
+//! The check is throttled to once a day with a timestamp file in the cache
+//! directory. It is kicked off in the background before running the operation,
+//! and its result is only reported if it has arrived shortly after the
+//! operation succeeds, so that `pacaptr` never hangs on a slow network.
+
+use std::{
+    env, fs,
+    io::{self, IsTerminal},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use tokio::{task::JoinHandle, time};
+
+use super::{is_newer, latest_release};
+use crate::{
+    config::Config,
+    error::Result,
+    print::{self, prompt},
+};
+
+/// The minimum interval between two checks.
+const CHECK_INTERVAL: Duration = Duration::from_hours(24);
+
+/// The maximum time to wait for the result of the check after the operation.
+const WAIT_TIMEOUT: Duration = Duration::from_millis(300);
+
+/// The maximum time given to `curl`, in seconds.
+const CURL_MAX_TIME: &str = "5";
+
+/// The name of the file recording the time of the last check.
+const TIMESTAMP_FILE: &str = "last-update-check";
+
+/// A check for a new release running in the background.
+#[must_use]
+#[derive(Debug)]
+pub struct UpdateCheck(JoinHandle<Option<String>>);
+
+impl UpdateCheck {
+    /// Starts a check for a new release in the background, if it is enabled
+    /// and the last one was made more than a day ago.
+    ///
+    /// The check is disabled with [`Config::check_updates`], in CI (i.e. when
+    /// `$CI` is set) and when the output is not a TTY.
+    #[must_use]
+    pub fn spawn(cfg: &Config) -> Option<Self> {
+        let enabled = cfg.check_updates()
+            && env::var_os("CI").is_none()
+            && !cfg.quiet()
+            && io::stdout().is_terminal()
+            && io::stderr().is_terminal();
+        if !enabled {
+            return None;
+        }
+        let path = timestamp_path()?;
+        let now = SystemTime::now();
+        if !is_due(&path, now) {
+            return None;
+        }
+        // The timestamp is written beforehand, so that an unreachable GitHub
+        // is not retried on every run.
+        _ = mark_checked(&path, now);
+        Some(Self(tokio::spawn(async { latest_version().await.ok() })))
+    }
+
+    /// Waits briefly for the result of the check, then prints a notice if a
+    /// newer release is available, with the
+    /// [`OutputSink`](crate::exec::context::OutputSink) of the current
+    /// [`Context`](crate::exec::context::Context).
+    pub async fn notify(self) {
+        let Ok(Ok(Some(latest))) = time::timeout(WAIT_TIMEOUT, self.0).await else {
+            return;
+        };
+        if let Some(notice) = notice(&latest, env!("CARGO_PKG_VERSION")) {
+            print::println(&*prompt::INFO, notice);
+        }
+    }
+}
+
+/// Returns the path of the file recording the time of the last check.
+fn timestamp_path() -> Option<PathBuf> {
+    Some(dirs_next::cache_dir()?.join("pacaptr").join(TIMESTAMP_FILE))
+}
+
+/// Checks if the last check recorded at `path` was made more than
+/// [`CHECK_INTERVAL`] before `now`, or has never been made.
+fn is_due(path: &Path, now: SystemTime) -> bool {
+    let last = fs::read_to_string(path)
+        .ok()
+        .and_then(|secs| secs.trim().parse().ok())
+        .map(|secs| SystemTime::UNIX_EPOCH + Duration::from_secs(secs));
+    last.is_none_or(|last| {
+        now.duration_since(last)
+            .is_ok_and(|elapsed| elapsed >= CHECK_INTERVAL)
+    })
+}
+
+/// Records `now` as the time of the last check at `path`.
+fn mark_checked(path: &Path, now: SystemTime) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let secs = now
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    fs::write(path, secs.to_string())
+}
+
+/// Fetches the version of the latest release, e.g. `0.22.0`.
+async fn latest_version() -> Result<String> {
+    let release = latest_release(&["--max-time", CURL_MAX_TIME]).await?;
+    Ok(release.tag_name.trim_start_matches('v').to_owned())
+}
+
+/// Returns the notice to be shown if the version `latest` is newer than
+/// `current`.
+fn notice(latest: &str, current: &str) -> Option<String> {
+    is_newer(latest, current).then(|| format!("pacaptr {latest} is available (you have {current})"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process;
+
+    use super::*;
+    use crate::pm::mock::{capture_output, MockLog};
+
+    #[tokio::test]
+    async fn latest() {
+        let log = MockLog::new().output(
+            "curl",
+            r#"{"tag_name": "v0.23.0", "assets": []}"#.as_bytes().to_vec(),
+        );
+        assert_eq!(log.scope(latest_version()).await.unwrap(), "0.23.0");
+        assert_eq!(
+            log.lines(),
+            [format!(
+                "curl -fsSL --max-time 5 -H Accept: application/vnd.github+json {}",
+                super::super::LATEST_RELEASE_URL
+            )]
+        );
+
+        let log = MockLog::new().fail("curl", 22);
+        assert!(log.scope(latest_version()).await.is_err());
+        let log = MockLog::new().output("curl", b"<html>".to_vec());
+        assert!(log.scope(latest_version()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn notify() {
+        let check = UpdateCheck(tokio::spawn(async { Some("999.0.0".to_owned()) }));
+        let ((), out) = capture_output(check.notify()).await;
+        assert_eq!(
+            out,
+            format!(
+                "Info pacaptr 999.0.0 is available (you have {})",
+                env!("CARGO_PKG_VERSION")
+            )
+        );
+
+        let check = UpdateCheck(tokio::spawn(async { None }));
+        let ((), out) = capture_output(check.notify()).await;
+        assert_eq!(out, "");
+    }
+
+    #[test]
+    fn notices() {
+        assert_eq!(
+            notice("0.23.0", "0.22.1").as_deref(),
+            Some("pacaptr 0.23.0 is available (you have 0.22.1)")
+        );
+        assert_eq!(notice("0.22.1", "0.22.1"), None);
+        assert_eq!(notice("0.21.0", "0.22.1"), None);
+    }
+
+    #[test]
+    fn throttle() {
+        let dir = env::temp_dir().join(format!("pacaptr-notify-{}", process::id()));
+        let path = dir.join("pacaptr").join(TIMESTAMP_FILE);
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let hours = Duration::from_hours;
+
+        assert!(is_due(&path, now));
+        mark_checked(&path, now).unwrap();
+        assert!(!is_due(&path, now));
+        assert!(!is_due(&path, now + hours(23)));
+        assert!(is_due(&path, now + hours(24)));
+        // A clock going backwards should not trigger a check.
+        assert!(!is_due(&path, now - hours(1)));
+
+        fs::write(&path, "garbage").unwrap();
+        assert!(is_due(&path, now));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
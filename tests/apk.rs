@@ -4,11 +4,10 @@ mod common;
 use common::*;
 
 #[test]
-#[should_panic(expected = "failed with pattern `Why not use curl instead?`")]
 fn apk_fail() {
     test_dsl! { r##"
         in -Si wget
-        ou Why not use curl instead?
+        ou ! Why not use curl instead?
     "## }
 }
 
@@ -58,6 +57,22 @@ fn apk_r_s() {
     "## }
 }
 
+#[test]
+#[ignore]
+fn apk_rs() {
+    test_dsl! { r##"
+        # `libidn2` is only required by `wget`.
+        in -S wget --yes
+        in -Q
+        ou ^libidn2$
+
+        in -Rs wget --yes
+        ou Purging libidn2
+        in -Q
+        ou ! ^(wget|libidn2)$
+    "## }
+}
+
 #[test]
 fn apk_si() {
     test_dsl! { r##"
@@ -4,11 +4,10 @@ mod common;
 use common::*;
 
 #[test]
-#[should_panic(expected = "failed with pattern `^Package: wget$`")]
 fn apt_fail() {
     test_dsl! { r##"
         in -Si fish
-        ou ^Package: wget$
+        ou ! ^Package: wget$
     "## }
 }
 
@@ -20,6 +19,17 @@ fn apt_q() {
     "## }
 }
 
+#[cfg(target_os = "linux")]
+#[test]
+fn apt_qu() {
+    test_dsl! { r##"
+        in -Qu
+        ec 0
+        in -Si no-such-package-xyz
+        ec 5
+    "## }
+}
+
 #[cfg(target_os = "linux")]
 #[test]
 fn apt_qc() {
@@ -49,6 +59,27 @@ fn apt_qi() {
     "## }
 }
 
+#[test]
+fn apt_qk() {
+    test_dsl! { r##"
+        in -Qk apt
+        ou ^apt: \d+ files checked, \d+ altered$
+        in -Qk apt --output-format json
+        ou "total": \d+
+    "## }
+}
+
+#[test]
+fn apt_ql() {
+    test_dsl! { r##"
+        in -Ql apt
+        ou ^/usr/bin/apt$
+        in -Ql apt --size
+        ou ^\s*[\d.]+ [KM]?i?B  /usr/bin/apt$
+        ou ^\s*[\d.]+ [KMG]?i?B  total of \d+ files
+    "## }
+}
+
 #[test]
 fn apt_qo() {
     test_dsl! { r##"
@@ -95,6 +126,54 @@ fn apt_r_s() {
     "## }
 }
 
+#[test]
+#[ignore]
+fn apt_s_noninteractive() {
+    test_dsl! { r##"
+        in -Sy
+
+        # `tzdata` asks for the time zone with `debconf` unless installed
+        # non-interactively, which would hang until the timeout.
+        to 300
+        in -S tzdata --yes
+        in ! dpkg-query -W -f '${Status}\n' tzdata
+        ou ^install ok installed$
+    "## }
+}
+
+#[test]
+#[ignore]
+fn apt_rs() {
+    test_dsl! { r##"
+        # Update package databases
+        in -Sy
+
+        # `fish-common` is only required by `fish`.
+        in -S fish --yes
+        in -Q
+        ou ^fish-common/
+
+        in -Rs fish --yes
+        ou ^Removing fish-common
+        in -Q
+        ou ! ^fish(-common)?/
+    "## }
+}
+
+#[test]
+#[ignore]
+fn apt_sg() {
+    test_dsl! { r##"
+        # Ubuntu defines its tasks for `tasksel` only.
+        in -Sy
+        in -S tasksel --yes
+        in -Sg
+        ou openssh-server
+        in -Sg openssh-server
+        ou ^openssh-server$
+    "## }
+}
+
 #[test]
 fn apt_si() {
     test_dsl! { r##"
@@ -119,3 +198,37 @@ fn apt_ss() {
         ou commandline package manager
     "## }
 }
+
+#[test]
+fn apt_su_ignore_dry_run() {
+    test_dsl! { r##"
+        in -Su --dry-run --ignore apt,dpkg
+        ou ignoring upgrades of apt, dpkg
+        ou apt-mark hold apt dpkg
+        ou apt upgrade
+        ou apt-mark unhold apt dpkg
+        # Nothing should have been held in a dry run.
+        in ! apt-mark showhold
+        ou \A\s*\z
+    "## }
+}
+
+#[test]
+fn apt_repair_dry_run() {
+    test_dsl! { r##"
+        in repair --dry-run
+        ou dpkg --configure -a
+        ou apt --fix-broken install
+    "## }
+}
+
+#[test]
+fn apt_target_release_dry_run() {
+    test_dsl! { r##"
+        in -S fish --dry-run --target-release bookworm-backports
+        ou apt install --reinstall -t bookworm-backports fish
+        in -Su --dry-run --target-release bookworm-backports
+        ou apt upgrade -t bookworm-backports
+        ou apt dist-upgrade -t bookworm-backports
+    "## }
+}
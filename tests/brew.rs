@@ -4,11 +4,10 @@ mod common;
 use common::*;
 
 #[test]
-#[should_panic(expected = "failed with pattern `curl is not keg-only`")]
 fn brew_fail() {
     test_dsl! { r##"
         in --using brew -Si curl
-        ou curl is not keg-only
+        ou ! curl is not keg-only
     "## }
 }
 
@@ -1,10 +1,18 @@
 #![cfg(feature = "test")]
 #![allow(clippy::missing_panics_doc)]
 
+use std::{
+    env,
+    io::{Read, Write},
+    process::{Child, Command, ExitStatus, Stdio},
+    sync::{Arc, Mutex, PoisonError},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
 use itertools::{chain, Itertools};
 pub use pacaptr_macros::test_dsl;
-use regex::RegexBuilder;
-use xshell::{cmd, Shell};
+use regex::{Regex, RegexBuilder};
 
 #[derive(Debug)]
 enum Input<'i> {
@@ -19,6 +27,73 @@ enum Input<'i> {
     },
 }
 
+/// An output stream of an [`Input`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Stream {
+    #[default]
+    Stdout,
+    #[allow(dead_code)]
+    Stderr,
+}
+
+/// A regex pattern to check the output of an [`Input`] against, as written
+/// in an `ou` item of the Test DSL.
+///
+/// The modifiers of the item are written right after `ou`: `^` for `line`,
+/// then `/` followed by `i` for `case_insensitive` and/or `e` for `stream`,
+/// e.g. `ou^/i Done` or `ou/e ! warning`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Pattern<'p> {
+    /// The regex itself.
+    pub regex: &'p str,
+    /// If the pattern must match none of the lines of the output (`ou !`),
+    /// instead of matching the output.
+    pub forbid: bool,
+    /// If the pattern must match a whole line (`ou^`).
+    pub line: bool,
+    /// If the pattern ignores case (`ou/i`).
+    pub case_insensitive: bool,
+    /// The stream the pattern is checked against, where `ou/e` stands for
+    /// [`Stream::Stderr`].
+    pub stream: Stream,
+}
+
+impl Pattern<'_> {
+    fn regex(&self) -> Regex {
+        let re = if self.line {
+            format!("^(?:{})$", self.regex)
+        } else {
+            self.regex.to_owned()
+        };
+        RegexBuilder::new(&re)
+            .multi_line(true)
+            .case_insensitive(self.case_insensitive)
+            .build()
+            .unwrap()
+    }
+
+    /// Checks the pattern against the given stdout and stderr.
+    fn check(&self, stdout: &str, stderr: &str) {
+        let (out, stream) = match self.stream {
+            Stream::Stdout => (stdout, "stdout"),
+            Stream::Stderr => (stderr, "stderr"),
+        };
+        let p = self.regex;
+        let re = self.regex();
+        if !self.forbid {
+            assert!(
+                re.is_match(out),
+                "failed with pattern `{p}`, got {stream} `{out}`"
+            );
+        } else if let Some((i, ln)) = out.lines().enumerate().find(|(_, ln)| re.is_match(ln)) {
+            panic!(
+                "forbidden pattern `{p}` matched {stream} line {}: `{ln}`",
+                i + 1
+            );
+        }
+    }
+}
+
 /// Returns the platform specific prefix of calling a command encoded as string.
 const fn cmd_prefix() -> (&'static str, &'static [&'static str]) {
     match () {
@@ -27,10 +102,164 @@ const fn cmd_prefix() -> (&'static str, &'static [&'static str]) {
     }
 }
 
+/// The environment variable to override the `pacaptr` executable under test,
+/// e.g. when running the tests in a container without `cargo`.
+const PACAPTR_BIN_ENV: &str = "PACAPTR_BIN";
+
+/// Returns the command running `pacaptr`, which is the executable given by
+/// [`PACAPTR_BIN_ENV`] if set, or `cargo run` otherwise.
+fn pacaptr_cmd() -> String {
+    env::var(PACAPTR_BIN_ENV).unwrap_or_else(|_| "cargo run --quiet --".into())
+}
+
+/// The time allowed for each [`Input`] unless specified otherwise with
+/// [`Test::timeout`].
+const DEFAULT_TIMEOUT: Duration = Duration::from_mins(5);
+
+/// The interval at which a running [`Input`] is checked for completion.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// An [`Input`] along with the checks on its result.
+#[derive(Debug)]
+struct Step<'s> {
+    input: Input<'s>,
+    /// The environment variables to be set (or unset if `None`) for the input.
+    env: Vec<Env<'s>>,
+    /// The time allowed for the input, after which it is killed.
+    timeout: Duration,
+    patterns: Vec<Pattern<'s>>,
+    /// The expected exit code, or `None` if the input should simply succeed.
+    code: Option<i32>,
+    /// The lines to be written to the stdin of the input, or `None` if the
+    /// stdin should be inherited.
+    stdin: Option<String>,
+}
+
+/// An environment variable to be set, or unset if the value is `None`.
+type Env<'e> = (&'e str, Option<&'e str>);
+
+/// The number of lines at the end of the output to be shown when the exit
+/// code is unexpected.
+const OUTPUT_TAIL: usize = 20;
+
+/// Returns the last [`OUTPUT_TAIL`] lines of the given outputs combined.
+fn output_tail(stdout: &str, stderr: &str) -> String {
+    let lines = chain!(stdout.lines(), stderr.lines()).collect_vec();
+    lines[lines.len().saturating_sub(OUTPUT_TAIL)..].join("\n")
+}
+
+/// The result of a command run by [`run_shell`].
+#[derive(Debug)]
+pub struct ShellOutput {
+    /// The exit status, or `None` if the command has been killed on timeout.
+    pub status: Option<ExitStatus>,
+    /// The `stdout` collected, which is partial on timeout.
+    pub stdout: String,
+    /// The `stderr` collected, which is partial on timeout.
+    pub stderr: String,
+}
+
+/// Collects everything read from `src` into the returned buffer in the
+/// background, so that a partial output is available at any time.
+fn drain(mut src: impl Read + Send + 'static) -> (Arc<Mutex<Vec<u8>>>, JoinHandle<()>) {
+    let buf = Arc::new(Mutex::new(Vec::new()));
+    let sink = Arc::clone(&buf);
+    let handle = thread::spawn(move || {
+        let mut chunk = [0; 4096];
+        while let Ok(n @ 1..) = src.read(&mut chunk) {
+            (sink.lock().unwrap_or_else(PoisonError::into_inner)).extend_from_slice(&chunk[..n]);
+        }
+    });
+    (buf, handle)
+}
+
+/// Kills `child` along with all of its descendants, e.g. `pacaptr` spawned by
+/// `cargo run`.
+fn kill_tree(child: &mut Child) {
+    let pid = child.id().to_string();
+    let killed = if cfg!(windows) {
+        Command::new("taskkill")
+            .args(["/T", "/F", "/PID", &pid])
+            .status()
+    } else {
+        // `child` leads its own process group, see `run_shell`.
+        Command::new("kill")
+            .args(["-KILL", "--", &format!("-{pid}")])
+            .status()
+    };
+    if !killed.is_ok_and(|status| status.success()) {
+        _ = child.kill();
+    }
+    _ = child.wait();
+}
+
+/// Runs `cmd` with the system shell and the environment variables in `env` set
+/// or unset, killing it if it is still running after `timeout`.
+///
+/// If `stdin` is given, it is written to the stdin of `cmd`, which is then
+/// closed.
+#[must_use]
+pub fn run_shell(cmd: &str, env: &[Env], stdin: Option<&str>, timeout: Duration) -> ShellOutput {
+    let (sh, sh_args) = cmd_prefix();
+    let mut command = Command::new(sh);
+    command
+        .args(sh_args)
+        .arg(cmd)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    if stdin.is_some() {
+        command.stdin(Stdio::piped());
+    }
+    for &(key, val) in env {
+        match val {
+            Some(val) => command.env(key, val),
+            None => command.env_remove(key),
+        };
+    }
+    #[cfg(unix)]
+    std::os::unix::process::CommandExt::process_group(&mut command, 0);
+    let mut child = command.spawn().unwrap();
+    if let Some(input) = stdin {
+        let mut sink = child.stdin.take().unwrap();
+        let input = input.to_owned();
+        // Written in the background, as `cmd` might not read everything.
+        thread::spawn(move || _ = sink.write_all(input.as_bytes()));
+    }
+    let (stdout, stdout_handle) = drain(child.stdout.take().unwrap());
+    let (stderr, stderr_handle) = drain(child.stderr.take().unwrap());
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        if let Some(status) = child.try_wait().unwrap() {
+            // The pipes are closed by now, unless held by an orphaned descendant.
+            _ = (stdout_handle.join(), stderr_handle.join());
+            break Some(status);
+        }
+        if Instant::now() >= deadline {
+            kill_tree(&mut child);
+            break None;
+        }
+        thread::sleep(POLL_INTERVAL);
+    };
+    let read = |buf: &Mutex<Vec<u8>>| {
+        let buf = buf.lock().unwrap_or_else(PoisonError::into_inner);
+        String::from_utf8_lossy(&buf).into_owned()
+    };
+    ShellOutput {
+        status,
+        stdout: read(&stdout),
+        stderr: read(&stderr),
+    }
+}
+
 #[derive(Debug, Default)]
 pub struct Test<'t> {
-    sequence: Vec<(Input<'t>, Vec<&'t str>)>,
-    pending_input: Option<Input<'t>>,
+    sequence: Vec<Step<'t>>,
+    /// The last input, as a step yet to be checked.
+    pending_input: Option<Step<'t>>,
+    /// The timeout of the next input, if specified with [`Test::timeout`].
+    next_timeout: Option<Duration>,
+    /// The environment variables set so far with [`Test::env`].
+    env: Vec<Env<'t>>,
 }
 
 impl<'t> Test<'t> {
@@ -40,46 +269,115 @@ impl<'t> Test<'t> {
     }
 
     #[must_use]
-    pub fn pacaptr(mut self, args: &'t [&str], flags: &'t [&str]) -> Self {
-        // Guard against consecutive inputs without calling `self.output()`.
-        if self.pending_input.is_some() {
-            self = self.output(&[]);
-        }
-        self.pending_input = Some(Input::Pacaptr { args, flags });
+    pub fn pacaptr(self, args: &'t [&str], flags: &'t [&str]) -> Self {
+        self.input(Input::Pacaptr { args, flags })
+    }
+
+    #[allow(dead_code)]
+    #[must_use]
+    pub fn exec(self, cmd: &'t [&str], kws: &'t [&str]) -> Self {
+        self.input(Input::Exec { cmd, kws })
+    }
+
+    /// Sets the time allowed for the next input, in seconds.
+    #[allow(dead_code)]
+    #[must_use]
+    pub const fn timeout(mut self, secs: u64) -> Self {
+        self.next_timeout = Some(Duration::from_secs(secs));
         self
     }
 
+    /// Sets the environment variable `key` to `val` (or unsets it if `val` is
+    /// `None`) for all the inputs below, as done by the `ev KEY=value` and
+    /// `ev -KEY` items of the Test DSL.
+    ///
+    /// Only the spawned processes are affected, not the test process itself.
     #[allow(dead_code)]
     #[must_use]
-    pub fn exec(mut self, cmd: &'t [&str], kws: &'t [&str]) -> Self {
+    pub fn env(mut self, key: &'t str, val: Option<&'t str>) -> Self {
+        self.env.push((key, val));
+        self
+    }
+
+    fn input(mut self, input: Input<'t>) -> Self {
         // Guard against consecutive inputs without calling `self.output()`.
         if self.pending_input.is_some() {
             self = self.output(&[]);
         }
-        self.pending_input = Some(Input::Exec { cmd, kws });
+        self.pending_input = Some(Step {
+            input,
+            env: self.env.clone(),
+            timeout: self.next_timeout.take().unwrap_or(DEFAULT_TIMEOUT),
+            patterns: vec![],
+            code: None,
+            stdin: None,
+        });
         self
     }
 
     #[must_use]
-    pub fn output(mut self, out: &'t [&str]) -> Self {
-        if let Some(cmd) = self.pending_input.take() {
-            self.sequence.push((cmd, out.into()));
-        } else if let Some((_cmd, outs)) = self.sequence.last_mut() {
-            outs.extend(out);
-        } else {
-            panic!("expect an input before an output");
-        }
+    pub fn output(self, out: &'t [&str]) -> Self {
+        self.patterns(out.iter().map(|&regex| Pattern {
+            regex,
+            ..Pattern::default()
+        }))
+    }
+
+    #[allow(dead_code)]
+    #[must_use]
+    pub fn output_none(self, out: &'t [&str]) -> Self {
+        self.patterns(out.iter().map(|&regex| Pattern {
+            regex,
+            forbid: true,
+            ..Pattern::default()
+        }))
+    }
+
+    #[allow(dead_code)]
+    #[must_use]
+    pub fn exit_code(mut self, code: i32) -> Self {
+        self.last_step().code = Some(code);
         self
     }
 
-    pub fn run(&self) {
-        let try_match = |out: &str, patterns: &[&str]| {
-            for &p in patterns {
-                let re = RegexBuilder::new(p).multi_line(true).build().unwrap();
-                let is_match = re.is_match(out);
-                assert!(is_match, "failed with pattern `{p}`, got `{out}`");
-            }
-        };
+    /// Writes `line` to the stdin of the last input, e.g. to answer a prompt,
+    /// as done by the `si` item of the Test DSL.
+    ///
+    /// The lines are written in order and the stdin is closed afterwards, so
+    /// that any further read gets an EOF.
+    #[allow(dead_code)]
+    #[must_use]
+    pub fn stdin(mut self, line: &str) -> Self {
+        let stdin = self.last_step().stdin.get_or_insert_with(String::new);
+        stdin.push_str(line);
+        stdin.push('\n');
+        self
+    }
+
+    #[must_use]
+    pub fn pattern(self, pattern: Pattern<'t>) -> Self {
+        self.patterns([pattern])
+    }
+
+    fn patterns(mut self, patterns: impl IntoIterator<Item = Pattern<'t>>) -> Self {
+        self.last_step().patterns.extend(patterns);
+        self
+    }
+
+    /// Returns the last step of the sequence, after turning the pending input
+    /// (if any) into a new step.
+    fn last_step(&mut self) -> &mut Step<'t> {
+        if let Some(step) = self.pending_input.take() {
+            self.sequence.push(step);
+        }
+        (self.sequence.last_mut()).expect("expect an input before an output")
+    }
+
+    pub fn run(mut self) {
+        // Run the last input even if nothing is checked against its output.
+        if self.pending_input.is_some() {
+            self = self.output(&[]);
+        }
 
         // Prevent running the test before `self.sequence` is configured.
         assert!(
@@ -87,24 +385,46 @@ impl<'t> Test<'t> {
             "Test sequence not yet configured"
         );
 
-        let s = Shell::new().unwrap();
-        for (input, patterns) in &self.sequence {
-            let (sh, sh_args) = cmd_prefix();
+        for Step {
+            input,
+            env,
+            timeout,
+            patterns,
+            code: expected,
+            stdin,
+        } in &self.sequence
+        {
             let cmd = match *input {
                 Input::Exec { cmd, kws } => chain!(cmd, kws).join(" "),
                 Input::Pacaptr { args, flags } => {
-                    format!("cargo run --quiet -- {}", chain!(args, flags).join(" "))
+                    format!("{} {}", pacaptr_cmd(), chain!(args, flags).join(" "))
                 }
             };
-            let cmd = cmd!(s, "{sh}").args(sh_args).arg(dbg!(&cmd));
-            let output = cmd.ignore_status().output().unwrap();
-            let got = String::from_utf8_lossy(&output.stdout);
+            let output = run_shell(dbg!(&cmd), env, stdin.as_deref(), *timeout);
+            let (got, got_stderr) = (&output.stdout, &output.stderr);
             println!("{got}");
-            try_match(&got, patterns);
-            let code = output.status.code();
-            let got_stderr = String::from_utf8_lossy(&output.stderr);
+            let Some(status) = output.status else {
+                panic!(
+                    "timed out after {}s, with the output ending with:\n{}",
+                    timeout.as_secs(),
+                    output_tail(got, got_stderr)
+                );
+            };
+            for p in patterns {
+                p.check(got, got_stderr);
+            }
+            let code = status.code();
+            if let Some(expected) = *expected {
+                assert_eq!(
+                    code,
+                    Some(expected),
+                    "expected exit code {expected}, got {code:?}, with the output ending with:\n{}",
+                    output_tail(got, got_stderr)
+                );
+                continue;
+            }
             assert!(
-                output.status.success(),
+                status.success(),
                 "failed with exit code {code:?} and the following stderr: {got_stderr}"
             );
         }
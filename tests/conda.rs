@@ -4,11 +4,10 @@ mod common;
 use common::*;
 
 #[test]
-#[should_panic(expected = "failed with pattern `Why not use curl instead?`")]
 fn conda_fail() {
     test_dsl! { r##"
         in --using conda -Si sympy
-        ou Why not use curl instead?
+        ou ! Why not use curl instead?
     "## }
 }
 
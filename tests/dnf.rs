@@ -4,11 +4,10 @@ mod common;
 use common::*;
 
 #[test]
-#[should_panic(expected = "failed with pattern `Why not use curl instead?`")]
 fn dnf_fail() {
     test_dsl! { r##"
         in -Si wget2
-        ou Why not use curl instead?
+        ou ! Why not use curl instead?
     "## }
 }
 
@@ -20,6 +19,16 @@ fn dnf_q() {
     "## }
 }
 
+#[test]
+fn dnf_qu() {
+    test_dsl! { r##"
+        in -Qu
+        ec 0
+        in -Si no-such-package-xyz
+        ec 5
+    "## }
+}
+
 #[test]
 fn dnf_qc() {
     test_dsl! { r##"
@@ -44,6 +53,16 @@ fn dnf_qi() {
     "## }
 }
 
+#[test]
+fn dnf_qk() {
+    test_dsl! { r##"
+        in -Qk dnf5
+        ou ^dnf5: \d+ files checked, \d+ altered$
+        in -Qk dnf5 --output-format json
+        ou "total": \d+
+    "## }
+}
+
 #[test]
 fn dnf_ql() {
     test_dsl! { r##"
@@ -92,6 +111,22 @@ fn dnf_r_s() {
     "## }
 }
 
+#[test]
+#[ignore]
+fn dnf_rs() {
+    test_dsl! { r##"
+        # `wget2-libs` is only required by `wget2`.
+        in -S wget2 --yes
+        in -Q
+        ou ^wget2-libs\b
+
+        in -Rs wget2 --yes
+        ou Removing
+        in -Q
+        ou ! ^wget2(-libs)?\b
+    "## }
+}
+
 #[test]
 fn dnf_si() {
     test_dsl! { r##"
@@ -135,3 +170,13 @@ fn dnf_ss() {
         ou An advanced file and recursive website downloader
     "## }
 }
+
+#[test]
+fn dnf_target_release_dry_run() {
+    test_dsl! { r##"
+        in -S wget2 --dry-run --target-release updates-testing
+        ou dnf install --enablerepo=updates-testing wget2
+        in -Su --dry-run --target-release updates-testing
+        ou dnf upgrade --enablerepo=updates-testing
+    "## }
+}
@@ -4,11 +4,10 @@ mod common;
 use common::*;
 
 #[test]
-#[should_panic(expected = "failed with pattern `Why not use curl instead?`")]
 fn emerge_fail() {
     test_dsl! { r##"
         in -Si wget
-        ou Why not use curl instead?
+        ou ! Why not use curl instead?
     "## }
 }
 
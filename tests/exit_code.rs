@@ -0,0 +1,162 @@
+//! Checks the exit codes of the `pacaptr` binary for each class of failures.
+//! See [`ExitKind`] for the full list.
+
+use std::{
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use pacaptr::error::ExitKind;
+
+/// Runs the built `pacaptr` binary with the given arguments and no config
+/// file, and returns its exit code.
+fn exit_code(args: &[&str], path: Option<&Path>) -> i32 {
+    run(args, path).0
+}
+
+/// Runs the built `pacaptr` binary with the given arguments and no config
+/// file, and returns its exit code and `stderr`.
+fn run(args: &[&str], path: Option<&Path>) -> (i32, String) {
+    let mut cmd = Command::new(env!("CARGO_BIN_EXE_pacaptr"));
+    cmd.args(args)
+        .env("PACAPTR_CONFIG", "/nonexistent/pacaptr.toml")
+        .env_remove("PACAPTR_DEFAULT_PM");
+    if let Some(path) = path {
+        cmd.env("PATH", path);
+    }
+    let out = cmd.output().expect("failed to run pacaptr");
+    let code = (out.status.code()).expect("pacaptr was terminated by a signal");
+    (code, String::from_utf8(out.stderr).unwrap())
+}
+
+/// Creates a directory containing a fake `stack` executable with the given
+/// `sh` script as its body, to be used as `PATH`.
+#[cfg(unix)]
+fn fake_stack(name: &str, body: &str) -> PathBuf {
+    use std::{fs, os::unix::fs::PermissionsExt};
+
+    let dir = std::env::temp_dir().join(format!("pacaptr-exit-code-{name}"));
+    fs::create_dir_all(&dir).unwrap();
+    let stack = dir.join("stack");
+    fs::write(&stack, format!("#!/bin/sh\n{body}\n")).unwrap();
+    fs::set_permissions(&stack, fs::Permissions::from_mode(0o755)).unwrap();
+    dir
+}
+
+#[test]
+fn help_lists_exit_codes() {
+    let out = Command::new(env!("CARGO_BIN_EXE_pacaptr"))
+        .arg("--help")
+        .output()
+        .unwrap();
+    assert!(out.status.success());
+    assert!(String::from_utf8(out.stdout)
+        .unwrap()
+        .contains(&ExitKind::table()));
+}
+
+#[test]
+fn usage_error() {
+    let code = i32::from(ExitKind::Usage.code());
+    assert_eq!(exit_code(&["--no-such-flag"], None), code);
+    let args = ["--using", "stack", "-Scs", "--dry-run"];
+    assert_eq!(exit_code(&args, None), code);
+}
+
+#[test]
+fn pm_not_found() {
+    let (code, stderr) = run(&["--using", "no-such-pm", "-Q"], None);
+    assert_eq!(code, i32::from(ExitKind::PmNotFound.code()));
+    assert!(stderr.contains("use `--using <pm>` with one of:"));
+}
+
+#[test]
+fn pm_not_installed() {
+    let (code, stderr) = run(&["--using", "stack", "-Q"], Some(Path::new("/nonexistent")));
+    assert_eq!(code, i32::from(ExitKind::PmNotFound.code()));
+    assert!(stderr.contains("install Stack from"));
+}
+
+#[test]
+fn operation_unsupported() {
+    let code = exit_code(&["--using", "stack", "-Si", "pandoc", "--dry-run"], None);
+    assert_eq!(code, i32::from(ExitKind::Unsupported.code()));
+}
+
+#[cfg(unix)]
+#[test]
+fn success() {
+    let path = fake_stack("success", "echo /nonexistent");
+    let code = exit_code(&["--using", "stack", "-Q"], Some(&path));
+    assert_eq!(code, i32::from(ExitKind::Success.code()));
+    // Invalid UTF-8 in the output is replaced instead of failing the operation.
+    let path = fake_stack("invalid-utf8", r"printf '/nonexistent\377'");
+    let code = exit_code(&["--using", "stack", "-Q"], Some(&path));
+    assert_eq!(code, i32::from(ExitKind::Success.code()));
+}
+
+#[cfg(unix)]
+#[test]
+fn generic_error() {
+    // The keyword is not a valid regex.
+    let path = fake_stack("generic", "echo /nonexistent");
+    let code = exit_code(&["--using", "stack", "-Q", "("], Some(&path));
+    assert_eq!(code, i32::from(ExitKind::Generic.code()));
+}
+
+#[cfg(unix)]
+#[test]
+fn cmd_failed() {
+    let path = fake_stack("failed", "echo 'something went wrong' >&2; exit 42");
+    let (code, stderr) = run(&["--using", "stack", "-Q"], Some(&path));
+    assert_eq!(code, i32::from(ExitKind::CmdFailed.code()));
+    // The failed command is reported along with the end of its output.
+    assert!(stderr.contains("command: `stack path --local-bin`"));
+    assert!(stderr.contains("| something went wrong"));
+    let code = exit_code(
+        &["--using", "stack", "-Q", "--propagate-exit-code"],
+        Some(&path),
+    );
+    assert_eq!(code, 42);
+}
+
+#[cfg(unix)]
+#[test]
+fn interrupted() {
+    let path = fake_stack("interrupted", "kill -INT $$");
+    let code = exit_code(&["--using", "stack", "-Q"], Some(&path));
+    assert_eq!(code, i32::from(ExitKind::Interrupted.code()));
+}
+
+#[cfg(unix)]
+#[test]
+fn partial_failure() {
+    use std::{env, fs};
+
+    let run_removing = |name: &str, extra: &[&str]| {
+        let bin = env::temp_dir().join(format!("pacaptr-exit-code-{name}-bin"));
+        fs::create_dir_all(&bin).unwrap();
+        for pkg in ["foo", "baz"] {
+            fs::write(bin.join(pkg), "").unwrap();
+        }
+        let dir = fake_stack(name, &format!("echo {}", bin.display()));
+        let path = env::join_paths([dir, "/usr/bin".into(), "/bin".into()]).unwrap();
+        let args = [
+            &["--using", "stack", "-R", "foo", "bar", "baz", "--yes"],
+            extra,
+        ]
+        .concat();
+        let (code, stderr) = run(&args, Some(Path::new(&path)));
+        (code, stderr, bin)
+    };
+
+    // `bar` doesn't stop `baz` from being removed.
+    let (code, stderr, bin) = run_removing("partial", &[]);
+    assert_eq!(code, i32::from(ExitKind::Partial.code()));
+    assert!(stderr.contains("failed for 1 of 3 keywords: `bar`"));
+    assert!(!bin.join("foo").exists() && !bin.join("baz").exists());
+
+    let (code, _, bin) = run_removing("fail-fast", &["--fail-fast"]);
+    assert_eq!(code, i32::from(ExitKind::Generic.code()));
+    assert!(!bin.join("foo").exists() && bin.join("baz").exists());
+}
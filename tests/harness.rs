@@ -0,0 +1,123 @@
+//! Checks the test harness itself.
+
+#![cfg(all(unix, feature = "test"))]
+
+mod common;
+use std::time::{Duration, Instant};
+
+use common::*;
+
+#[test]
+fn harness_run_shell() {
+    let output = run_shell("sleep 0.1; echo foo", &[], None, Duration::from_secs(10));
+    assert!(output.status.unwrap().success());
+    assert_eq!(output.stdout, "foo\n");
+}
+
+#[test]
+fn harness_run_shell_timeout() {
+    let start = Instant::now();
+    let output = run_shell(
+        "echo foo; sleep 10; echo bar",
+        &[],
+        None,
+        Duration::from_secs(1),
+    );
+    assert!(start.elapsed() < Duration::from_secs(5));
+    assert!(output.status.is_none());
+    assert_eq!(output.stdout, "foo\n");
+}
+
+#[test]
+fn harness_timeout_ok() {
+    test_dsl! { r##"
+        to 120
+        in --version
+        ou/i ^pacaptr
+    "## }
+}
+
+#[test]
+#[should_panic(expected = "timed out after 1s, with the output ending with:\nfoo")]
+fn harness_timeout() {
+    test_dsl! { r##"
+        to 1
+        in ! echo foo; sleep 10
+    "## }
+}
+
+#[test]
+fn harness_env() {
+    test_dsl! { r##"
+        in ! echo "[$PACAPTR_TEST_FOO]"
+        ou ^\[\]$
+
+        ev PACAPTR_TEST_FOO=foo bar
+        in ! echo "[$PACAPTR_TEST_FOO]"
+        ou ^\[foo bar\]$
+
+        ev -HOME
+        in ! echo "[$PACAPTR_TEST_FOO] [$HOME]"
+        ou ^\[foo bar\] \[\]$
+    "## }
+    assert!(std::env::var_os("PACAPTR_TEST_FOO").is_none());
+}
+
+#[test]
+fn harness_no_color() {
+    test_dsl! { r##"
+        ev -CLICOLOR_FORCE
+        ev NO_COLOR=1
+        in -Sy --dry-run --using apt
+        ou Canceled
+        ou ! \x1b\[
+    "## }
+}
+
+#[test]
+fn harness_stdin() {
+    test_dsl! { r##"
+        in ! read a; read b; read c; echo "[$a] [$b] [$c]"
+        si foo bar
+        si
+        si baz
+        ou ^\[foo bar\] \[\] \[baz\]$
+
+        in ! read a || echo eof
+        si
+        ou ! eof
+    "## }
+}
+
+#[test]
+#[should_panic(expected = "forbidden pattern `^bar$` matched stdout line 2: `bar`")]
+fn harness_forbidden_pattern() {
+    test_dsl! { r##"
+        in ! printf 'foo\nbar\n'
+        ou foo
+        ou ! ^baz$
+        ou ! ^bar$
+    "## }
+}
+
+#[test]
+fn harness_pattern_modifiers() {
+    test_dsl! { r##"
+        in ! printf 'Foo bar\n'; printf 'Warning: baz\n' >&2
+        ou/i ^foo
+        ou^ Foo bar
+        ou^ ! Foo
+        ou/e ^Warning: baz$
+        ou/e ! Foo
+        ou ! Warning
+    "## }
+}
+
+#[test]
+#[should_panic(expected = "expected exit code 4, got Some(3)")]
+fn harness_unexpected_exit_code() {
+    test_dsl! { r##"
+        in ! echo foo; exit 3
+        ec 4
+    "## }
+}
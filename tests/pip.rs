@@ -4,11 +4,10 @@ mod common;
 use common::*;
 
 #[test]
-#[should_panic(expected = "failed with pattern `Here comes the sun`")]
 fn pip_fail() {
     test_dsl! { r##"
         in --using pip -Qi wheel
-        ou Here comes the sun
+        ou ! Here comes the sun
     "## }
 }
 
@@ -4,11 +4,10 @@ mod common;
 use common::*;
 
 #[test]
-#[should_panic(expected = "failed with pattern `^Package: wget$`")]
 fn pkcon_fail() {
     test_dsl! { r##"
         in --using pkcon -Si fish
-        ou ^Package: wget$
+        ou ! ^Package: wget$
     "## }
 }
 
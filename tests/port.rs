@@ -6,11 +6,10 @@ mod common;
 use common::*;
 
 #[test]
-#[should_panic(expected = "failed with pattern `curl is not keg-only`")]
 fn port_fail() {
     test_dsl! { r##"
         in --using port -Si curl
-        ou curl is not keg-only
+        ou ! curl is not keg-only
     "## }
 }
 
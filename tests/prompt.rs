@@ -0,0 +1,167 @@
+//! Checks the prompt flows, with the answers piped into `pacaptr`.
+
+#![cfg(all(unix, feature = "test"))]
+
+mod common;
+use std::{env, fs, os::unix::fs::PermissionsExt, path::PathBuf};
+
+use common::*;
+
+/// The question asked before running a command, as written to stderr without
+/// a terminal.
+const QUESTION: &str = r"Proceed with the previous command\? \[Yes/all/no\]";
+
+/// Prevents every question from being answered with yes when the tests are
+/// run in a container without a TTY.
+const NO_CONTAINER: &[&str] = &["--container-mode", "off"];
+
+/// Makes a fake `stack` in a new temporary directory named after `name`, with
+/// the given `tools` installed in its `bin` subdirectory.
+///
+/// Returns the temporary directory, and the `PATH` with it prepended.
+fn fake_stack(name: &str, tools: &[&str]) -> (PathBuf, String) {
+    let dir = env::temp_dir().join(format!("pacaptr-{name}-{}", std::process::id()));
+    let bin = dir.join("bin");
+    fs::create_dir_all(&bin).unwrap();
+    for tool in tools {
+        fs::write(bin.join(tool), "").unwrap();
+    }
+    // Only `stack path --local-bin` is needed to remove the tools.
+    let stack = dir.join("stack");
+    fs::write(&stack, format!("#!/bin/sh\necho '{}'\n", bin.display())).unwrap();
+    fs::set_permissions(&stack, fs::Permissions::from_mode(0o755)).unwrap();
+    let path = format!("{}:{}", dir.display(), env::var("PATH").unwrap());
+    (dir, path)
+}
+
+#[test]
+fn prompt_each_step() {
+    let (dir, path) = fake_stack("step", &[]);
+    // Record the subcommands run other than `stack path`.
+    let log = dir.join("log");
+    let stack = dir.join("stack");
+    let script = fs::read_to_string(&stack).unwrap();
+    let script = script.replacen(
+        '\n',
+        &format!(
+            "\n[ \"$1\" = path ] || echo \"$1\" >> '{}'\n",
+            log.display()
+        ),
+        1,
+    );
+    fs::write(&stack, script).unwrap();
+    let cat = format!("cat {}", log.display());
+    // The refresh is confirmed, then the upgrade is declined.
+    Test::new()
+        .env("PATH", Some(&path))
+        .pacaptr(&["--using", "stack", "-Suy", "--step", "foo"], NO_CONTAINER)
+        .stdin("yes\nno")
+        .exit_code(6)
+        .pattern(Pattern {
+            regex: &format!("(?s){QUESTION} yes\n.*{QUESTION} no\n"),
+            stream: Stream::Stderr,
+            ..Pattern::default()
+        })
+        .exec(&[&cat], &[])
+        .output(&["^update$"])
+        .output_none(&["install"])
+        .run();
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn prompt_declined_dsl() {
+    test_dsl! { r##"
+        in --using pip -S foo --container-mode off
+        si n
+        ec 6
+        ou Pending `pip3? install foo`
+        ou ! Running
+        ou/e Proceed with the previous command\? \[Yes/all/no\] n$
+    "## }
+}
+
+#[test]
+fn prompt_declined() {
+    let (dir, path) = fake_stack("declined", &["foo"]);
+    let ls = format!("ls {}/bin", dir.display());
+    Test::new()
+        .env("PATH", Some(&path))
+        .pacaptr(&["--using", "stack", "-R", "foo"], NO_CONTAINER)
+        .stdin("no")
+        .exit_code(6)
+        .pattern(Pattern {
+            regex: QUESTION,
+            stream: Stream::Stderr,
+            ..Pattern::default()
+        })
+        .exec(&[&ls], &[])
+        .output(&["^foo$"])
+        .run();
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn prompt_default_no() {
+    let (dir, path) = fake_stack("default-no", &["foo"]);
+    let ls = format!("ls {}/bin", dir.display());
+    // An empty line stands for the default answer.
+    Test::new()
+        .env("PATH", Some(&path))
+        .pacaptr(
+            &["--using", "stack", "-R", "foo", "--prompt-default", "no"],
+            NO_CONTAINER,
+        )
+        .stdin("\n")
+        .exit_code(6)
+        .pattern(Pattern {
+            regex: r"Proceed with the previous command\? \[yes/all/No\] \n",
+            stream: Stream::Stderr,
+            ..Pattern::default()
+        })
+        .exec(&[&ls], &[])
+        .output(&["^foo$"])
+        .run();
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn prompt_all() {
+    let (dir, path) = fake_stack("all", &["bar", "foo"]);
+    let ls = format!("ls {}/bin", dir.display());
+    // Any further prompt would fail on the closed stdin.
+    Test::new()
+        .env("PATH", Some(&path))
+        .pacaptr(&["--using", "stack", "-R", "foo", "bar"], NO_CONTAINER)
+        .stdin("a")
+        .output(&["removed `.*/foo`", "removed `.*/bar`"])
+        .exec(&[&ls], &[])
+        .output_none(&["foo", "bar"])
+        .run();
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn prompt_eof() {
+    let (dir, path) = fake_stack("eof", &["foo"]);
+    let ls = format!("ls {}/bin", dir.display());
+    Test::new()
+        .env("PATH", Some(&path))
+        .pacaptr(&["--using", "stack", "-R", "foo"], NO_CONTAINER)
+        .stdin("maybe")
+        .exit_code(1)
+        .pattern(Pattern {
+            regex: &format!("(?s){QUESTION} maybe\n.*{QUESTION} \n"),
+            stream: Stream::Stderr,
+            ..Pattern::default()
+        })
+        .pattern(Pattern {
+            regex: "stdin closed before answering the prompt",
+            stream: Stream::Stderr,
+            ..Pattern::default()
+        })
+        .exec(&[&ls], &[])
+        .output(&["^foo$"])
+        .run();
+    fs::remove_dir_all(dir).unwrap();
+}
@@ -4,11 +4,10 @@ mod common;
 use common::*;
 
 #[test]
-#[should_panic(expected = "failed with pattern `GNU Wget is not a free software package`")]
 fn scoop_fail() {
     test_dsl! { r##"
         in --using scoop -Si wget
-        ou GNU Wget is not a free software package
+        ou ! GNU Wget is not a free software package
     "## }
 }
 
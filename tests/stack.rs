@@ -0,0 +1,32 @@
+#![cfg(feature = "test")]
+
+mod common;
+use common::*;
+
+#[test]
+fn stack_s_dry_run() {
+    test_dsl! { r##"
+        in --using stack -S hlint --dry-run
+        ou stack install hlint
+    "## }
+}
+
+#[test]
+fn stack_sy_dry_run() {
+    test_dsl! { r##"
+        in --using stack -Sy --dry-run
+        ou stack update
+    "## }
+}
+
+#[test]
+#[ignore]
+fn stack_r_s() {
+    test_dsl! { r##"
+        in --using stack -S hello --yes
+        in --using stack -Q
+        ou hello
+        in --using stack -R hello --yes
+        ou removed `.*hello`
+    "## }
+}
@@ -4,11 +4,10 @@ mod common;
 use common::*;
 
 #[test]
-#[should_panic(expected = "failed with pattern `^pkgname: wget$`")]
 fn xbps_fail() {
     test_dsl! {"
         in -Si fish-shell
-        ou ^pkgname: wget$
+        ou ! ^pkgname: wget$
     "}
 }
 
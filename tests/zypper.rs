@@ -4,11 +4,10 @@ mod common;
 use common::*;
 
 #[test]
-#[should_panic(expected = "failed with pattern `Why not use curl instead?`")]
 fn zypper_fail() {
     test_dsl! { r##"
         in -Si wget
-        ou Why not use curl instead?
+        ou ! Why not use curl instead?
     "## }
 }
 
@@ -77,6 +76,22 @@ fn zypper_r_s() {
     "## }
 }
 
+#[test]
+#[ignore]
+fn zypper_rs() {
+    test_dsl! { r##"
+        # `libutempter0` is only required by `tmux`.
+        in -S tmux --yes
+        in -Q
+        ou ^libutempter0\b
+
+        in -Rs tmux --yes
+        ou Removing
+        in -Q
+        ou ! ^(tmux|libutempter0)\b
+    "## }
+}
+
 #[test]
 fn zypper_sg() {
     test_dsl! { r##"